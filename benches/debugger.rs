@@ -0,0 +1,47 @@
+//! How much slower a program runs under the debugger with only a TRAP breakpoint set, against
+//! running it without. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use lc3::{assemble, RunOptions, Verbosity};
+use std::env;
+use std::fs;
+
+// 30000 trips round a three-instruction loop, then HALT.
+const LOOP: &str = "
+        .ORIG x3000
+        LD R1, COUNT
+LOOP    ADD R0, R0, #1
+        ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+COUNT   .FILL #30000
+        .END
+";
+
+fn debugged(c: &mut Criterion) {
+    let program = assemble(LOOP).unwrap().to_bytes();
+    let script = env::temp_dir().join(format!("lc3-bench-{}.script", std::process::id()));
+    fs::write(&script, "set auto-delta off\nbreak-trap 0x25\ncontinue\n").unwrap();
+
+    let mut group = c.benchmark_group("90k instructions");
+    group.sample_size(20);
+    for (name, script) in [("run", None), ("debugged", Some(script.clone()))] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                lc3::run(RunOptions {
+                    program_bytes: Some(program.clone()),
+                    script: script.clone(),
+                    verbosity: Verbosity::Quiet,
+                    ..RunOptions::default()
+                })
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+
+    fs::remove_file(&script).unwrap();
+}
+
+criterion_group!(benches, debugged);
+criterion_main!(benches);
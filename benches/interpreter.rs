@@ -0,0 +1,100 @@
+//! The interpreter's hot paths one at a time: arithmetic, memory access, printing through a trap
+//! and decoding on its own. Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lc3::{Instruction, ReaderConsole, Vm};
+use std::io;
+
+const ARITHMETIC_LOOP: &str = "
+        .ORIG x3000
+LOOP    ADD R1, R1, #1
+        AND R2, R1, #15
+        NOT R3, R2
+        ADD R4, R3, R1
+        BRnzp LOOP
+        .END
+";
+
+// Increments each word of a buffer in turn, over and over.
+const MEMORY_LOOP: &str = "
+        .ORIG x3000
+START   LEA R1, BUFFER
+        LD R2, COUNT
+NEXT    LDR R3, R1, #0
+        ADD R3, R3, #1
+        STR R3, R1, #0
+        ADD R1, R1, #1
+        ADD R2, R2, #-1
+        BRp NEXT
+        BRnzp START
+COUNT   .FILL #256
+BUFFER  .BLKW #256
+        .END
+";
+
+// Prints 100 lines of 100 characters.
+fn printer() -> String {
+    format!(
+        "
+        .ORIG x3000
+        LD R1, COUNT
+LOOP    LEA R0, LINE
+        PUTS
+        ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+COUNT   .FILL #100
+LINE    .STRINGZ \"{}\\n\"
+        .END
+",
+        "x".repeat(99)
+    )
+}
+
+// `source` loaded into a machine whose console has no keys and throws output away, so no
+// terminal is involved.
+fn machine(source: &str) -> Vm {
+    let mut vm = Vm::new();
+    vm.set_console(ReaderConsole::new(io::empty(), io::sink()));
+    vm.load_assembly(source).unwrap();
+    vm
+}
+
+fn loops(c: &mut Criterion) {
+    for (name, source) in [
+        ("10000 steps of arithmetic", ARITHMETIC_LOOP),
+        ("10000 steps of loads and stores", MEMORY_LOOP),
+    ] {
+        let mut vm = machine(source);
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..10000 {
+                    black_box(vm.step());
+                }
+            })
+        });
+    }
+}
+
+fn puts(c: &mut Criterion) {
+    let mut vm = machine(&printer());
+    c.bench_function("10k characters through PUTS", |b| {
+        b.iter(|| {
+            vm.reset();
+            vm.run().unwrap();
+        })
+    });
+}
+
+fn decode(c: &mut Criterion) {
+    c.bench_function("decoding every word", |b| {
+        b.iter(|| {
+            for word in 0..=u16::MAX {
+                black_box(Instruction::decode(black_box(word)));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, loops, puts, decode);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+//! How fast a program polling the keyboard status register runs while no key comes, and how
+//! often the console underneath gets asked. Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lc3::{Console, Polling, Throttled, Vm};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+const POLLING_LOOP: &str = "
+        .ORIG x3000
+POLL    LDI R0, KBSR
+        BRzp POLL
+        HALT
+KBSR    .FILL xFE00
+        .END
+";
+
+// Nobody typing, with each poll making a syscall like checking a terminal would.
+struct Untouched(Arc<AtomicU64>);
+
+impl Console for Untouched {
+    fn poll_key(&mut self) -> Option<u8> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        std::thread::yield_now();
+        None
+    }
+
+    fn read_key_blocking(&mut self, _deadline: Option<Instant>) -> Option<u8> {
+        self.poll_key()
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {}
+}
+
+fn polling_loop(c: &mut Criterion) {
+    for (name, throttled) in [
+        ("10000 steps polling KBSR", false),
+        ("10000 steps polling KBSR, throttled", true),
+    ] {
+        let polls = Arc::new(AtomicU64::new(0));
+        let console = Untouched(polls.clone());
+        let mut vm = Vm::new();
+        if throttled {
+            // Never idling, which would measure the waits instead.
+            let polling = Polling {
+                idle_after: u32::MAX,
+                ..Polling::default()
+            };
+            vm.set_console(Throttled::with_polling(console, polling));
+        } else {
+            vm.set_console(console);
+        }
+        vm.load_assembly(POLLING_LOOP).unwrap();
+
+        let mut reads = 0;
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..10000 {
+                    black_box(vm.step());
+                }
+                reads += 5000;
+            })
+        });
+
+        let polls = polls.load(Ordering::Relaxed);
+        println!("{}: {} KBSR reads per poll", name, reads / polls.max(1));
+    }
+}
+
+criterion_group!(benches, polling_loop);
+criterion_main!(benches);
@@ -0,0 +1,58 @@
+//! How fast output-heavy programs print, through the built-in traps and through an OS writing
+//! the display a character at a time. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use lc3::{StdConsole, Vm, BUNDLED_OS};
+use std::fs::File;
+
+// Prints 1000 lines of 100 characters.
+fn printer() -> String {
+    format!(
+        "
+        .ORIG x3000
+        LD R1, COUNT
+LOOP    LEA R0, LINE
+        PUTS
+        ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+COUNT   .FILL #1000
+LINE    .STRINGZ \"{}\\n\"
+        .END
+",
+        "x".repeat(99)
+    )
+}
+
+// Writes really go somewhere, so each flush costs what it would on a terminal or file.
+#[cfg(unix)]
+const NULL: &str = "/dev/null";
+#[cfg(windows)]
+const NULL: &str = "NUL";
+
+fn printing(c: &mut Criterion) {
+    let source = printer();
+    let mut group = c.benchmark_group("100k characters");
+    group.sample_size(10);
+
+    for (name, os) in [("built-in traps", false), ("bundled OS", true)] {
+        let mut vm = Vm::new();
+        vm.set_console(StdConsole::with_output(File::create(NULL).unwrap()));
+        if os {
+            vm.load_os(BUNDLED_OS).unwrap();
+        }
+        vm.load_assembly(&source).unwrap();
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                vm.reset();
+                vm.run().unwrap();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, printing);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+//! How fast the machine steps through a tight loop, the shape of most of a program's run time.
+//! Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lc3::Vm;
+
+const COUNTING_LOOP: &str = "
+        .ORIG x3000
+LOOP    ADD R1, R1, #1
+        BRnzp LOOP
+        .END
+";
+
+fn counting_loop(c: &mut Criterion) {
+    for (name, decode_cache, predecode) in [
+        ("10000 steps of a counting loop", false, false),
+        ("10000 steps of a counting loop, decode cache", true, false),
+        ("10000 steps of a counting loop, predecoded", false, true),
+    ] {
+        let mut vm = Vm::new();
+        vm.set_decode_cache(decode_cache);
+        vm.load_assembly(COUNTING_LOOP).unwrap();
+        vm.set_predecode(predecode);
+
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..10000 {
+                    black_box(vm.step());
+                }
+            })
+        });
+    }
+}
+
+criterion_group!(benches, counting_loop);
+criterion_main!(benches);
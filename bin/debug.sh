@@ -1,3 +1,3 @@
 #!/bin/sh
 
-nc 127.0.0.1 6379
+nc "${1:-127.0.0.1}" "${2:-6379}"
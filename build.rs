@@ -0,0 +1,27 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    generate_header();
+}
+
+// Writes the C header for `src/ffi.rs`.
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        include_guard: Some("LC3_H".to_string()),
+        usize_is_size_t: true,
+        autogen_warning: Some(
+            "/* Generated by cbindgen from src/ffi.rs, don't edit by hand. */".to_string(),
+        ),
+        ..Default::default()
+    };
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(format!("{}/src/ffi.rs", crate_dir))
+        .generate()
+        .expect("Unable to generate the C header")
+        .write_to_file(format!("{}/include/lc3.h", crate_dir));
+}
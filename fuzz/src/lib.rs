@@ -0,0 +1,59 @@
+//! What each fuzz target does with its input. `tests/fuzz.rs` includes this file too, so an input
+//! that once made a target crash, saved in `tests/fuzz/TARGET/`, is run on every `cargo test`.
+//!
+//! Each of them passes as long as it returns: errors are fine, panics aren't.
+
+use lc3::{analyzed_listing, disassemble_program, program_listing};
+use lc3::{Instruction, Program, ReaderConsole, SymbolTable, Vm};
+use std::io;
+
+/// How many instructions [`execute`] runs a program for, so one that never halts still returns.
+pub const MAX_STEPS: u64 = 10_000;
+
+/// Decodes and disassembles the big-endian words in `data`, each on its own and all of them as an
+/// object file image.
+pub fn decode(data: &[u8]) {
+    let words: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    let symbols = SymbolTable::default();
+
+    for (address, word) in (0..=u16::MAX).zip(&words) {
+        let instruction = Instruction::decode(*word);
+        instruction.to_string();
+        instruction.disassemble(address, &symbols);
+    }
+
+    program_listing(&words, None, &symbols);
+    analyzed_listing(&words, None, &symbols);
+    if let Some((origin, rest)) = words.split_first() {
+        let program = Program {
+            origin: *origin,
+            words: rest.to_vec(),
+            symbols: SymbolTable::default(),
+        };
+        disassemble_program(&program, None);
+    }
+}
+
+/// Loads `data` every way a machine can be given a file: as a program, an OS, a symbol table and
+/// assembly source.
+pub fn load(data: &[u8]) {
+    let _ = Vm::new().load_program(data);
+    let _ = Vm::new().load_os(data);
+
+    let text = String::from_utf8_lossy(data);
+    SymbolTable::parse(&text);
+    let _ = Vm::new().load_assembly(&text);
+}
+
+/// Runs `data` as an object file for up to [`MAX_STEPS`] instructions, with no keys to read and
+/// the output thrown away.
+pub fn execute(data: &[u8]) {
+    let mut vm = Vm::new();
+    vm.set_console(ReaderConsole::new(io::empty(), io::sink()));
+    if vm.load_program(data).is_ok() {
+        let _ = vm.run_with_limit(MAX_STEPS);
+    }
+}
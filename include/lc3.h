@@ -0,0 +1,129 @@
+#ifndef LC3_H
+#define LC3_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * A null `Lc3Vm` or buffer was passed.
+ */
+#define LC3_ERROR_NULL -1
+
+/**
+ * The program couldn't be loaded, e.g. it's shorter than its origin.
+ */
+#define LC3_ERROR_LOAD -2
+
+/**
+ * The register number isn't 0 to 7.
+ */
+#define LC3_ERROR_REGISTER -3
+
+/**
+ * The program failed, e.g. on an illegal opcode.
+ */
+#define LC3_ERROR_EXECUTION -4
+
+/**
+ * A machine owned by C code, created with [`lc3_vm_new`] and destroyed with [`lc3_vm_free`].
+ */
+typedef struct Lc3Vm Lc3Vm;
+
+/**
+ * Called with each write the program makes to the console, and the `user_data` it was
+ * registered with. Null discards the output.
+ */
+typedef void (*Lc3OutputCallback)(const uint8_t *bytes, size_t len, void *user_data);
+
+/**
+ * A new machine with no program loaded and output discarded until a callback is set.
+ */
+struct Lc3Vm *lc3_vm_new(void);
+
+/**
+ * # Safety
+ *
+ * `vm` must come from [`lc3_vm_new`] and not have been freed already. Null is ignored.
+ */
+void lc3_vm_free(struct Lc3Vm *vm);
+
+/**
+ * Loads an object file: a big-endian origin followed by the words to store there. Returns 0 on
+ * success.
+ *
+ * # Safety
+ *
+ * `vm` must be a live machine from [`lc3_vm_new`], and `bytes` must point to `len` readable
+ * bytes.
+ */
+int lc3_vm_load(struct Lc3Vm *vm, const uint8_t *bytes, size_t len);
+
+/**
+ * Runs up to `n` instructions. Returns 1 while the machine is still running, 0 once it has
+ * halted. A program waiting for a key retries until one is pushed.
+ *
+ * # Safety
+ *
+ * `vm` must be a live machine from [`lc3_vm_new`].
+ */
+int lc3_vm_step(struct Lc3Vm *vm, uint32_t n);
+
+/**
+ * The value of register R`index`, 0 to 7.
+ *
+ * # Safety
+ *
+ * `vm` must be a live machine from [`lc3_vm_new`].
+ */
+int lc3_vm_read_register(struct Lc3Vm *vm, int index);
+
+/**
+ * Sets register R`index`, 0 to 7. Returns 0 on success.
+ *
+ * # Safety
+ *
+ * `vm` must be a live machine from [`lc3_vm_new`].
+ */
+int lc3_vm_write_register(struct Lc3Vm *vm, int index, uint16_t value);
+
+/**
+ * The word at `address`, without any device side effects.
+ *
+ * # Safety
+ *
+ * `vm` must be a live machine from [`lc3_vm_new`].
+ */
+int lc3_vm_read_memory(struct Lc3Vm *vm, uint16_t address);
+
+/**
+ * Queues a key for GETC, IN or the keyboard registers. Returns 0 on success.
+ *
+ * # Safety
+ *
+ * `vm` must be a live machine from [`lc3_vm_new`].
+ */
+int lc3_vm_push_key(struct Lc3Vm *vm, uint8_t key);
+
+/**
+ * Sends the program's output to `callback` from now on, or discards it when `callback` is null.
+ * Returns 0 on success.
+ *
+ * # Safety
+ *
+ * `vm` must be a live machine from [`lc3_vm_new`]. `callback` is called with `user_data` on
+ * whichever thread steps the machine.
+ */
+int lc3_vm_set_output_callback(struct Lc3Vm *vm, Lc3OutputCallback callback, void *user_data);
+
+/**
+ * Why the last call on this thread to return an error failed, or null if none has. Valid until
+ * the next error on this thread.
+ */
+const char *lc3_last_error_message(void);
+
+#endif  /* LC3_H */
@@ -0,0 +1,406 @@
+;
+; The OS lc3 loads with --os, bundled as lc3os.obj.
+;
+; It carries out the standard TRAPs through the trap vector table, like the built-in routines
+; do, printing exactly what they print. HALT clears the clock enable bit of the machine control
+; register (MCR), which stops the machine. A TRAP with any other vector prints a message and
+; halts.
+;
+; The routines return with RET and restore every register they use, apart from R7, the R0 that
+; GETC and IN return and the R0 HALT stops with. The saved registers are kept in the image, so
+; it isn't write-protected by --protect-text.
+;
+; Rebuild lc3os.obj after changing this with:
+;
+;     lc3 asm os/lc3os.asm --no-sym
+;
+
+        .ORIG x0000
+
+; The trap vector table, x0000-x00FF.
+        .FILL BAD_TRAP        ; x00
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL TRAP_GETC        ; x20
+        .FILL TRAP_OUT        ; x21
+        .FILL TRAP_PUTS        ; x22
+        .FILL TRAP_IN        ; x23
+        .FILL TRAP_PUTSP        ; x24
+        .FILL TRAP_HALT        ; x25
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+        .FILL BAD_TRAP
+
+; The interrupt vector table, x0100-x01FF. There are no interrupts yet.
+        .BLKW x100
+
+; x0200
+
+; R0 <- a key from the keyboard, without echoing it.
+TRAP_GETC
+        LDI R0, KBSR
+        BRzp TRAP_GETC
+        LDI R0, KBDR
+        RET
+
+; Writes the character in R0 to the display.
+TRAP_OUT
+        ST R1, OUT_R1
+OUT_WAIT
+        LDI R1, DSR
+        BRzp OUT_WAIT
+        STI R0, DDR
+        LD R1, OUT_R1
+        RET
+
+; Writes the string at R0, one character per word, up to a x0000.
+TRAP_PUTS
+        ST R0, PUTS_R0
+        ST R1, PUTS_R1
+        ST R7, PUTS_R7
+        ADD R1, R0, #0
+PUTS_LOOP
+        LDR R0, R1, #0
+        BRz PUTS_DONE
+        JSR TRAP_OUT
+        ADD R1, R1, #1
+        BRnzp PUTS_LOOP
+PUTS_DONE
+        LD R0, PUTS_R0
+        LD R1, PUTS_R1
+        LD R7, PUTS_R7
+        RET
+
+; Prompts for a key, echoes it and leaves it in R0.
+TRAP_IN
+        ST R7, IN_R7
+        LEA R0, IN_PROMPT
+        JSR TRAP_PUTS
+        JSR TRAP_GETC
+        JSR TRAP_OUT
+        LD R7, IN_R7
+        RET
+
+; Writes the string at R0, two characters per word with the low byte first, up to a x0000. A
+; x00 high byte isn't written.
+TRAP_PUTSP
+        ST R0, PUTSP_R0
+        ST R1, PUTSP_R1
+        ST R2, PUTSP_R2
+        ST R3, PUTSP_R3
+        ST R7, PUTSP_R7
+        ADD R1, R0, #0
+PUTSP_LOOP
+        LDR R2, R1, #0
+        BRz PUTSP_DONE
+        LD R3, LOW_BYTE
+        AND R0, R2, R3
+        JSR TRAP_OUT
+        ; Shifts the high byte down into R0 a bit at a time.
+        AND R0, R0, #0
+        AND R3, R3, #0
+        ADD R3, R3, #8
+PUTSP_SHIFT
+        ADD R0, R0, R0
+        ADD R2, R2, #0
+        BRzp PUTSP_ZERO
+        ADD R0, R0, #1
+PUTSP_ZERO
+        ADD R2, R2, R2
+        ADD R3, R3, #-1
+        BRp PUTSP_SHIFT
+        ADD R0, R0, #0
+        BRz PUTSP_NEXT
+        JSR TRAP_OUT
+PUTSP_NEXT
+        ADD R1, R1, #1
+        BRnzp PUTSP_LOOP
+PUTSP_DONE
+        LD R0, PUTSP_R0
+        LD R1, PUTSP_R1
+        LD R2, PUTSP_R2
+        LD R3, PUTSP_R3
+        LD R7, PUTSP_R7
+        RET
+
+; Stops the clock by clearing the MCR, leaving R0 x0000. If it's started again R0 is restored
+; and the program carries on after the HALT.
+TRAP_HALT
+        ST R0, HALT_R0
+        AND R0, R0, #0
+        STI R0, MCR
+        LD R0, HALT_R0
+        RET
+
+BAD_TRAP
+        LEA R0, BAD_TRAP_MESSAGE
+        JSR TRAP_PUTS
+        BRnzp TRAP_HALT
+
+KBSR    .FILL xFE00
+KBDR    .FILL xFE02
+DSR     .FILL xFE04
+DDR     .FILL xFE06
+MCR     .FILL xFFFE
+LOW_BYTE .FILL x00FF
+
+IN_PROMPT .STRINGZ "Enter a character: "
+BAD_TRAP_MESSAGE .STRINGZ "\nA TRAP was executed with an unknown vector, halting.\n"
+
+OUT_R1  .BLKW #1
+PUTS_R0 .BLKW #1
+PUTS_R1 .BLKW #1
+PUTS_R7 .BLKW #1
+IN_R7   .BLKW #1
+PUTSP_R0 .BLKW #1
+PUTSP_R1 .BLKW #1
+PUTSP_R2 .BLKW #1
+PUTSP_R3 .BLKW #1
+PUTSP_R7 .BLKW #1
+HALT_R0 .BLKW #1
+
+        .END
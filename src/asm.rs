@@ -0,0 +1,959 @@
+//! LC-3 assembly parsing.
+//!
+//! Single statements are enough to patch an instruction in the debugger, and [`assemble`] puts
+//! them together into a whole program. PC-relative operands are labels, explicit offsets (`#-3`)
+//! or absolute addresses (`x3010`), the last two resolved against the address the instruction
+//! will be stored at.
+
+use crate::instruction::{Condition, Instruction, Register, TrapVector};
+use crate::symbols::SymbolTable;
+use std::error::Error;
+use std::fmt;
+use std::iter;
+
+/// An assembled program, see [`assemble`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub origin: u16,
+    pub words: Vec<u16>,
+    /// Every label and the address it names.
+    pub symbols: SymbolTable,
+}
+
+impl Program {
+    /// The object file format: the origin followed by the words, all big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        iter::once(self.origin)
+            .chain(self.words.iter().copied())
+            .flat_map(u16::to_be_bytes)
+            .collect()
+    }
+}
+
+/// A problem with one line of the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    /// Counting from 1.
+    pub line: usize,
+    /// Counting from 1, in characters.
+    pub column: usize,
+    pub message: String,
+}
+
+// Prefixed with the file name, this is the usual `file:line:column: message`.
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+// Where a statement starts in the source, so errors can point into it.
+#[derive(Clone, Copy)]
+struct Position<'a> {
+    line: usize,
+    text: &'a str,
+    start: usize,
+}
+
+impl Position<'_> {
+    fn error(&self, offset: usize, message: String) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.text[..self.start + offset].chars().count() + 1,
+            message,
+        }
+    }
+}
+
+// A word that can only be encoded once the address of every label is known.
+enum Unresolved<'a> {
+    // The operand and where it starts in the statement.
+    Fill(&'a str, usize),
+    Instruction(&'a str),
+}
+
+/// Assembles a whole program: `.ORIG` first, then instructions and the `.FILL`, `.BLKW` and
+/// `.STRINGZ` directives, up to an optional `.END`. A label starts a line, either on its own or
+/// before the statement it names, `.ORIG` included. Comments start with `;`. Every line is
+/// checked, so all the errors come back at once.
+///
+/// [`Program::symbols`] has every label and the `.ORIG` address, and displays as the `.sym` file
+/// `lc3as` would write, with an `Origin:` line added.
+///
+/// The first pass lays out the words and finds the address of every label, the second encodes
+/// the instructions and `.FILL`s, so labels can be used before they're defined.
+pub fn assemble(source: &str) -> Result<Program, Vec<AsmError>> {
+    let mut origin: Option<u16> = None;
+    let mut words = Vec::new();
+    let mut symbols = SymbolTable::new();
+    // A label on the `.ORIG` line names the origin, once it's known.
+    let mut origin_label = None;
+    let mut unresolved = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let code = strip_comment(line);
+        let mut statement = code.trim();
+        if statement.is_empty() {
+            continue;
+        }
+        let mut position = Position {
+            line: index + 1,
+            text: line,
+            start: code.len() - code.trim_start().len(),
+        };
+
+        let (first, rest) = split_word(statement);
+        if !is_keyword(first) {
+            let rest_offset = statement.len() - rest.trim_start().len();
+            let (next, _) = split_word(rest.trim_start());
+            // `FOO R1` is a mistyped instruction rather than a label `FOO` on an `R1` one.
+            if !next.is_empty() && !is_keyword(next) && is_operand(next) {
+                errors.push(position.error(
+                    0,
+                    format!("Unknown mnemonic {:?}", first.to_ascii_uppercase()),
+                ));
+                continue;
+            }
+
+            let result = match origin {
+                _ if !is_label(first) => Err(format!("Invalid label {:?}", first)),
+                None if next.eq_ignore_ascii_case(".ORIG") => {
+                    origin_label = Some(first);
+                    Ok(())
+                }
+                None => Err(format!("Expected .ORIG before {:?}", first)),
+                Some(_) if symbols.address(first).is_some() => {
+                    Err(format!("Duplicate label {:?}", first))
+                }
+                Some(origin) => {
+                    symbols.insert(first, origin.wrapping_add(words.len() as u16));
+                    Ok(())
+                }
+            };
+            if let Err(message) = result {
+                errors.push(position.error(0, message));
+                continue;
+            }
+
+            statement = rest.trim();
+            position.start += rest_offset;
+            if statement.is_empty() {
+                continue;
+            }
+        }
+
+        let (keyword, operand) = split_word(statement);
+        let operand_offset = statement.len() - operand.trim_start().len();
+        let operand = operand.trim();
+
+        match (keyword.to_ascii_uppercase().as_str(), origin) {
+            (".END", _) => break,
+            (".ORIG", Some(_)) => {
+                errors.push(position.error(0, "Only one .ORIG is supported".to_string()))
+            }
+            (".ORIG", None) => match address(operand) {
+                Ok(address) => {
+                    origin = Some(address);
+                    symbols.set_origin(address);
+                    if let Some(label) = origin_label {
+                        symbols.insert(label, address);
+                    }
+                }
+                Err(message) => errors.push(position.error(operand_offset, message)),
+            },
+            (_, None) => {
+                errors.push(position.error(0, format!("Expected .ORIG before {:?}", statement)))
+            }
+            (".FILL", Some(_)) => {
+                unresolved.push((
+                    words.len(),
+                    position,
+                    Unresolved::Fill(operand, operand_offset),
+                ));
+                words.push(0);
+            }
+            (".BLKW", Some(_)) => match number(operand) {
+                Ok(count) if count > 0 => words.extend(iter::repeat_n(0, count as usize)),
+                Ok(_) => errors.push(position.error(
+                    operand_offset,
+                    format!(".BLKW needs a positive count, found {}", operand),
+                )),
+                Err(message) => errors.push(position.error(operand_offset, message)),
+            },
+            (".STRINGZ", Some(_)) => match string(operand) {
+                Ok(string) => {
+                    words.extend(string.chars().map(|c| c as u16));
+                    words.push(0);
+                }
+                Err(message) => errors.push(position.error(operand_offset, message)),
+            },
+            (_, Some(_)) => {
+                unresolved.push((words.len(), position, Unresolved::Instruction(statement)));
+                words.push(0);
+            }
+        }
+    }
+
+    if let Some(origin) = origin {
+        for (index, position, statement) in unresolved {
+            let address = origin.wrapping_add(index as u16);
+            let result = match statement {
+                Unresolved::Fill(operand, offset) => {
+                    fill(operand, &symbols).map_err(|message| position.error(offset, message))
+                }
+                Unresolved::Instruction(statement) => {
+                    parse_instruction(statement, address, &symbols)
+                        .map(|instruction| instruction.encode())
+                        .map_err(|message| position.error(0, message))
+                }
+            };
+            match result {
+                Ok(word) => words[index] = word,
+                Err(error) => errors.push(error),
+            }
+        }
+    }
+
+    match origin {
+        None if errors.is_empty() => errors.push(AsmError {
+            line: 1,
+            column: 1,
+            message: "Missing .ORIG".to_string(),
+        }),
+        Some(origin) if usize::from(origin) + words.len() > 0x10000 => errors.push(AsmError {
+            line: 1,
+            column: 1,
+            message: format!(
+                "{} words from {:#06x} don't fit in memory",
+                words.len(),
+                origin
+            ),
+        }),
+        _ => {}
+    }
+    // The second pass finds its errors after the first pass's, but they're reported in order.
+    errors.sort_by_key(|error| (error.line, error.column));
+
+    match origin {
+        Some(origin) if errors.is_empty() => Ok(Program {
+            origin,
+            words,
+            symbols,
+        }),
+        _ => Err(errors),
+    }
+}
+
+/// Parses one instruction to be stored at `address`, with labels looked up in `symbols`.
+pub fn parse_instruction(
+    statement: &str,
+    address: u16,
+    symbols: &SymbolTable,
+) -> Result<Instruction, String> {
+    let (mnemonic, operands) = split_word(statement.trim());
+    let operands: Vec<&str> = if operands.trim().is_empty() {
+        Vec::new()
+    } else {
+        operands.split(',').map(str::trim).collect()
+    };
+
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    let expect = |count: usize| {
+        if operands.len() == count {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} expects {} operand{}, found {}",
+                mnemonic,
+                count,
+                if count == 1 { "" } else { "s" },
+                operands.len()
+            ))
+        }
+    };
+    let pc_relative = |operand: &str, bits: u8| pc_offset(operand, address, bits, symbols);
+
+    let instruction = match mnemonic.as_str() {
+        "ADD" | "AND" => {
+            expect(3)?;
+            let r0 = register(operands[0])?;
+            let r1 = register(operands[1])?;
+            match operands[2].parse::<Register>() {
+                Ok(r2) if mnemonic == "ADD" => Instruction::ADD(r0, r1, r2),
+                Ok(r2) => Instruction::AND(r0, r1, r2),
+                Err(_) => {
+                    let value = immediate(operands[2], 5)?;
+                    if mnemonic == "ADD" {
+                        Instruction::ADDIMM(r0, r1, value)
+                    } else {
+                        Instruction::ANDIMM(value, r0, r1)
+                    }
+                }
+            }
+        }
+        "NOT" => {
+            expect(2)?;
+            Instruction::NOT(register(operands[0])?, register(operands[1])?)
+        }
+        "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+            expect(2)?;
+            let r0 = register(operands[0])?;
+            let offset = pc_relative(operands[1], 9)?;
+            match mnemonic.as_str() {
+                "LD" => Instruction::LD(r0, offset),
+                "LDI" => Instruction::LDI(r0, offset),
+                "LEA" => Instruction::LEA(r0, offset),
+                "ST" => Instruction::ST(r0, offset),
+                _ => Instruction::STI(r0, offset),
+            }
+        }
+        "LDR" | "STR" => {
+            expect(3)?;
+            let r0 = register(operands[0])?;
+            let r1 = register(operands[1])?;
+            let offset = immediate(operands[2], 6)?;
+            if mnemonic == "LDR" {
+                Instruction::LDR(r0, r1, offset)
+            } else {
+                Instruction::STR(r0, r1, offset)
+            }
+        }
+        "JSR" => {
+            expect(1)?;
+            Instruction::JSR(pc_relative(operands[0], 11)?)
+        }
+        "JSRR" => {
+            expect(1)?;
+            Instruction::JSRR(register(operands[0])?)
+        }
+        "JMP" => {
+            expect(1)?;
+            Instruction::JMP(register(operands[0])?)
+        }
+        "RET" => {
+            expect(0)?;
+            Instruction::JMP(Register::R7)
+        }
+        // Only valid in supervisor mode, which this machine doesn't have, so it fails when run.
+        "RTI" => {
+            expect(0)?;
+            Instruction::UNUSED
+        }
+        "TRAP" => {
+            expect(1)?;
+            trap(operands[0])?
+        }
+        "GETC" | "OUT" | "PUTS" | "IN" | "PUTSP" | "HALT" => {
+            expect(0)?;
+            trap(&format!("x{:02X}", alias_vector(&mnemonic)))?
+        }
+        "NOP" => {
+            expect(0)?;
+            Instruction::BR(
+                Condition {
+                    n: false,
+                    z: false,
+                    p: false,
+                },
+                0,
+            )
+        }
+        _ => match branch_condition(&mnemonic) {
+            Some(condition) => {
+                expect(1)?;
+                Instruction::BR(condition, pc_relative(operands[0], 9)?)
+            }
+            None => return Err(format!("Unknown mnemonic {:?}", mnemonic)),
+        },
+    };
+
+    Ok(instruction)
+}
+
+fn register(operand: &str) -> Result<Register, String> {
+    operand.parse()
+}
+
+// The first word of a statement and the rest of it.
+fn split_word(statement: &str) -> (&str, &str) {
+    match statement.find(char::is_whitespace) {
+        Some(index) => statement.split_at(index),
+        None => (statement, ""),
+    }
+}
+
+// A directive or a mnemonic, anything else starting a line is a label.
+fn is_keyword(word: &str) -> bool {
+    const MNEMONICS: [&str; 22] = [
+        "ADD", "AND", "NOT", "LD", "LDI", "LEA", "ST", "STI", "LDR", "STR", "JSR", "JSRR", "JMP",
+        "RET", "RTI", "TRAP", "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT",
+    ];
+    let word = word.to_ascii_uppercase();
+
+    word.starts_with('.')
+        || word == "NOP"
+        || MNEMONICS.contains(&word.as_str())
+        || branch_condition(&word).is_some()
+}
+
+// Letters, digits and underscores, not starting with a digit, that can't be read as a register
+// or a hex number.
+fn is_label(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !is_operand(word)
+}
+
+fn is_operand(word: &str) -> bool {
+    let word = word.trim_end_matches(',');
+    register(word).is_ok() || number(word).is_ok()
+}
+
+// `BR` on its own means unconditional, otherwise any of `n`, `z` and `p` in that order.
+fn branch_condition(mnemonic: &str) -> Option<Condition> {
+    let flags = mnemonic.strip_prefix("BR")?;
+    if flags.is_empty() {
+        return Some(Condition {
+            n: true,
+            z: true,
+            p: true,
+        });
+    }
+
+    let mut rest = flags;
+    let mut take = |flag: &str| match rest.strip_prefix(flag) {
+        Some(remainder) => {
+            rest = remainder;
+            true
+        }
+        None => false,
+    };
+    let condition = Condition {
+        n: take("N"),
+        z: take("Z"),
+        p: take("P"),
+    };
+
+    if rest.is_empty() {
+        Some(condition)
+    } else {
+        None
+    }
+}
+
+fn alias_vector(mnemonic: &str) -> u8 {
+    match mnemonic {
+        "GETC" => 0x20,
+        "OUT" => 0x21,
+        "PUTS" => 0x22,
+        "IN" => 0x23,
+        "PUTSP" => 0x24,
+        _ => 0x25,
+    }
+}
+
+// Any 8-bit vector, those without a built-in routine being left for an OS to handle.
+fn trap(operand: &str) -> Result<Instruction, String> {
+    let vector = number(operand)?;
+    if !(0..=0xff).contains(&vector) {
+        return Err(format!("TRAP vector {} out of range (x00 to xFF)", operand));
+    }
+
+    Ok(match TrapVector::try_decode(vector as u16) {
+        Some(trap_vector) => Instruction::TRAP(trap_vector),
+        None => Instruction::UNKNOWNTRAP(vector as u8),
+    })
+}
+
+// A signed immediate that fits in `bits` bits, returned masked to those bits.
+fn immediate(operand: &str, bits: u8) -> Result<u16, String> {
+    let value = number(operand)?;
+    let (min, max) = signed_range(bits);
+
+    if value < min || value > max {
+        return Err(format!(
+            "Immediate {} out of range for imm{} ({} to {})",
+            operand, bits, min, max
+        ));
+    }
+
+    Ok(value as u16 & mask(bits))
+}
+
+// `#n` is an explicit offset, a label or `xNNNN` an address relative to the next instruction.
+fn pc_offset(operand: &str, address: u16, bits: u8, symbols: &SymbolTable) -> Result<u16, String> {
+    let offset = if operand.starts_with('#') {
+        number(operand)?
+    } else {
+        let target = value(operand, symbols)?;
+        if !(0..=0xffff).contains(&target) {
+            return Err(format!("Address {} out of range", operand));
+        }
+        // The offset wraps around the 16-bit address space.
+        i32::from((target as u16).wrapping_sub(address.wrapping_add(1)) as i16)
+    };
+
+    let (min, max) = signed_range(bits);
+    if offset < min || offset > max {
+        return Err(format!(
+            "Offset to {} out of range for offset{} ({} to {}, found {})",
+            operand, bits, min, max, offset
+        ));
+    }
+
+    Ok(offset as u16 & mask(bits))
+}
+
+// Everything before a `;` that isn't inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn address(operand: &str) -> Result<u16, String> {
+    match number(operand)? {
+        value @ 0..=0xffff => Ok(value as u16),
+        _ => Err(format!("Address {} out of range", operand)),
+    }
+}
+
+// Negative values are stored as two's complement, a label as its address.
+fn fill(operand: &str, symbols: &SymbolTable) -> Result<u16, String> {
+    match value(operand, symbols)? {
+        value @ -0x8000..=0xffff => Ok(value as u16),
+        _ => Err(format!("Value {} doesn't fit in a word", operand)),
+    }
+}
+
+// A double-quoted string, with `\n`, `\t`, `\"` and `\\` escapes.
+fn string(operand: &str) -> Result<String, String> {
+    let contents = operand
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .filter(|_| operand.len() >= 2)
+        .ok_or_else(|| format!("Expected a quoted string, found {}", operand))?;
+
+    let mut string = String::new();
+    let mut chars = contents.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => string.push('\n'),
+            Some('t') => string.push('\t'),
+            Some(c @ ('"' | '\\')) => string.push(c),
+            Some(c) => return Err(format!("Unknown escape \\{}", c)),
+            None => return Err("Unfinished escape at the end of the string".to_string()),
+        }
+    }
+
+    Ok(string)
+}
+
+// A number, or the address of a label.
+fn value(operand: &str, symbols: &SymbolTable) -> Result<i32, String> {
+    if !is_label(operand) {
+        return number(operand);
+    }
+
+    symbols
+        .address(operand)
+        .map(i32::from)
+        .ok_or_else(|| format!("Unknown label {:?}", operand))
+}
+
+// `#` decimal (optionally negative) or `x` hex literals.
+fn number(operand: &str) -> Result<i32, String> {
+    let result = if let Some(decimal) = operand.strip_prefix('#') {
+        decimal.parse::<i32>()
+    } else if let Some(hex) = operand
+        .strip_prefix('x')
+        .or_else(|| operand.strip_prefix('X'))
+    {
+        i32::from_str_radix(hex, 16)
+    } else {
+        return Err(format!(
+            "Expected a number like #10 or x0A, found {:?}",
+            operand
+        ));
+    };
+
+    result.map_err(|_| format!("Invalid number {:?}", operand))
+}
+
+fn signed_range(bits: u8) -> (i32, i32) {
+    (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+}
+
+fn mask(bits: u8) -> u16 {
+    (1 << bits) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(statement: &str) -> Result<u16, String> {
+        parse_instruction(statement, 0x3000, &SymbolTable::new())
+            .map(|instruction| instruction.encode())
+    }
+
+    #[test]
+    fn test_operates() {
+        assert_eq!(encode("ADD R1, R2, R3"), Ok(0x1283));
+        assert_eq!(encode("add r1, r1, #-1"), Ok(0x127f));
+        assert_eq!(encode("AND R0, R0, #0"), Ok(0x5020));
+        assert_eq!(encode("AND R1, R2, xF"), Ok(0x52af));
+        assert_eq!(encode("NOT R1, R2"), Ok(0x92bf));
+    }
+
+    #[test]
+    fn test_memory() {
+        assert_eq!(encode("LDR R1, R6, #-2"), Ok(0x63be));
+        assert_eq!(encode("STR R1, R6, #31"), Ok(0x739f));
+        assert_eq!(encode("LD R3, #4"), Ok(0x2604));
+        assert_eq!(encode("LEA R0, x3010"), Ok(0xe00f));
+        assert_eq!(encode("STI R0, x2F01"), Ok(0xb100));
+    }
+
+    #[test]
+    fn test_control() {
+        assert_eq!(encode("BR #-1"), Ok(0x0fff));
+        assert_eq!(encode("BRnzp x3000"), Ok(0x0fff));
+        assert_eq!(encode("BRz #2"), Ok(0x0402));
+        assert_eq!(encode("brnp #2"), Ok(0x0a02));
+        assert_eq!(encode("NOP"), Ok(0x0000));
+        assert_eq!(encode("JSR x3400"), Ok(0x4bff));
+        assert_eq!(encode("JSRR R3"), Ok(0x40c0));
+        assert_eq!(encode("JMP R2"), Ok(0xc080));
+        assert_eq!(encode("RET"), Ok(0xc1c0));
+        assert_eq!(encode("RTI"), Ok(0x8000));
+    }
+
+    #[test]
+    fn test_traps() {
+        assert_eq!(encode("TRAP x25"), Ok(0xf025));
+        // Vectors without a built-in routine are left for an OS.
+        assert_eq!(encode("TRAP x30"), Ok(0xf030));
+        assert_eq!(encode("TRAP xFF"), Ok(0xf0ff));
+        assert_eq!(encode("GETC"), Ok(0xf020));
+        assert_eq!(encode("OUT"), Ok(0xf021));
+        assert_eq!(encode("puts"), Ok(0xf022));
+        assert_eq!(encode("IN"), Ok(0xf023));
+        assert_eq!(encode("PUTSP"), Ok(0xf024));
+        assert_eq!(encode("HALT"), Ok(0xf025));
+    }
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            ; Prints a greeting.
+            .ORIG x3000
+            LEA R0, x3004 ; the string
+            PUTS
+            ADD R1, R1, #-1
+            HALT
+            .STRINGZ \"Hi;\\n\"
+            .BLKW #2
+            .FILL #-1
+            .END
+            this isn't assembled
+        ";
+
+        let program = assemble(source).unwrap();
+        assert_eq!(program.origin, 0x3000);
+        assert_eq!(
+            program.words,
+            [0xe003, 0xf022, 0x127f, 0xf025, 0x48, 0x69, 0x3b, 0x0a, 0, 0, 0, 0xffff]
+        );
+        assert_eq!(
+            assemble(".ORIG x3000\nHALT").unwrap().to_bytes(),
+            [0x30, 0x00, 0xf0, 0x25]
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let source = "
+            .ORIG x3000
+            LD R1, COUNT    ; forward
+    LOOP    BRz DONE
+            ADD R0, R0, R1
+            ADD R1, R1, #-1
+            BRnzp loop      ; any case
+    DONE
+            ST R0, RESULT
+            HALT
+    COUNT   .FILL #5
+    RESULT  .BLKW #1
+    PTR     .FILL RESULT
+            .END
+        ";
+
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program.words,
+            [0x2206, 0x0403, 0x1001, 0x127f, 0x0ffc, 0x3002, 0xf025, 5, 0, 0x3008]
+        );
+        assert_eq!(
+            program
+                .symbols
+                .iter()
+                .cloned()
+                .collect::<Vec<(String, u16)>>(),
+            [
+                ("LOOP".to_string(), 0x3001),
+                ("DONE".to_string(), 0x3005),
+                ("COUNT".to_string(), 0x3007),
+                ("RESULT".to_string(), 0x3008),
+                ("PTR".to_string(), 0x3009),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mnemonics_are_not_labels() {
+        let program = assemble(".ORIG x3000\nRTI\nHALT\n.END").unwrap();
+        assert_eq!(program.words, [0x8000, 0xf025]);
+        assert_eq!(program.symbols.iter().count(), 0);
+        assert_eq!(program.symbols.origin(), Some(0x3000));
+
+        let errors = assemble(".ORIG x3000\nRTI HALT\n").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "2:1: RTI expects 0 operands, found 1"
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        let source = "
+            .ORIG x3000
+    TOP     BR TOP
+            BRn TOP
+            BRz TOP
+            BRp TOP
+            BRnz TOP
+            BRnp TOP
+            BRzp TOP
+            BRnzp TOP
+            bRnZp TOP
+            getc
+            Out
+            puts
+            IN
+            PUTSP
+            Halt
+            ret
+            .END
+        ";
+
+        // BR is 0000 n z p offset9, TRAP 1111 0000 trapvect8 and RET is JMP R7, 1100 000 111
+        // 000000.
+        assert_eq!(
+            assemble(source).unwrap().words,
+            [
+                0x0fff, // BR, n z p, -1
+                0x09fe, // n, -2
+                0x05fd, // z, -3
+                0x03fc, // p, -4
+                0x0dfb, // n z, -5
+                0x0bfa, // n p, -6
+                0x07f9, // z p, -7
+                0x0ff8, // n z p, -8
+                0x0ff7, // n z p, -9
+                0xf020, // GETC
+                0xf021, // OUT
+                0xf022, // PUTS
+                0xf023, // IN
+                0xf024, // PUTSP
+                0xf025, // HALT
+                0xc1c0, // RET
+            ]
+        );
+    }
+
+    #[test]
+    fn test_symbols_round_trip() {
+        let source = "
+    MAIN    .ORIG x3000
+            JSR PRINT
+            HALT
+    PRINT   LEA R0, GREETING
+            PUTS
+    PRINT_DONE
+            RET
+    GREETING .STRINGZ \"Hi\"
+    COUNT   .FILL #3
+    BUFFER  .BLKW #4
+    AFTER   .END
+        ";
+
+        let symbols = assemble(source).unwrap().symbols;
+        let parsed = SymbolTable::parse(&symbols.to_string());
+        assert_eq!(parsed, symbols);
+
+        for (name, address) in [
+            ("MAIN", 0x3000),
+            ("PRINT", 0x3002),
+            ("PRINT_DONE", 0x3004),
+            ("GREETING", 0x3005),
+            ("COUNT", 0x3008),
+            ("BUFFER", 0x3009),
+            ("AFTER", 0x300d),
+        ] {
+            assert_eq!(parsed.address(name), Some(address), "{}", name);
+        }
+        assert_eq!(parsed.origin(), Some(0x3000));
+    }
+
+    #[test]
+    fn test_symbols_unlabelled_origin() {
+        let source = "
+            .ORIG x4000
+            AND R0, R0, #0
+    LOOP    ADD R0, R0, #1
+            BR LOOP
+            .END
+        ";
+
+        let symbols = assemble(source).unwrap().symbols;
+        assert_eq!(
+            symbols.to_string(),
+            "// Symbol table
+// Origin: x4000
+// Scope level 0:
+//\tSymbol Name       Page Address
+//\t----------------  ------------
+//\tLOOP              4001
+"
+        );
+        let parsed = SymbolTable::parse(&symbols.to_string());
+        assert_eq!(parsed.origin(), Some(0x4000));
+        assert_eq!(parsed.address("LOOP"), Some(0x4001));
+        assert_eq!(parsed.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_bundled_os() {
+        let program = assemble(include_str!("../os/lc3os.asm")).unwrap();
+
+        assert_eq!(program.to_bytes(), crate::BUNDLED_OS);
+        assert_eq!(program.symbols.address("TRAP_GETC"), Some(0x0200));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let errors = |source: &str| {
+            assemble(source)
+                .unwrap_err()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            errors(".ORIG x3000\n  FOO R1\n  .FILL x10000\n.BLKW #0\n.STRINGZ hi\n"),
+            [
+                "2:3: Unknown mnemonic \"FOO\"",
+                "3:9: Value x10000 doesn't fit in a word",
+                "4:7: .BLKW needs a positive count, found #0",
+                "5:10: Expected a quoted string, found hi",
+            ]
+        );
+        assert_eq!(errors("HALT"), ["1:1: Expected .ORIG before \"HALT\""]);
+        assert_eq!(errors("; nothing"), ["1:1: Missing .ORIG"]);
+        assert_eq!(
+            errors(".ORIG x3000\n.ORIG x4000"),
+            ["2:1: Only one .ORIG is supported"]
+        );
+        assert_eq!(
+            errors(".ORIG xFFFF\nHALT\nHALT"),
+            ["1:1: 2 words from 0xffff don't fit in memory"]
+        );
+        assert_eq!(
+            errors("START\n.ORIG x3000"),
+            ["1:1: Expected .ORIG before \"START\""]
+        );
+        assert_eq!(
+            errors(
+                ".ORIG x3000
+LOOP ADD R1, R1, #1
+loop HALT
+  BR MISSING
+  .FILL NOWHERE
+R2 HALT
+1ST HALT
+  BRz FAR
+  .BLKW x200
+FAR HALT
+"
+            ),
+            [
+                "3:1: Duplicate label \"loop\"",
+                "4:3: Unknown label \"MISSING\"",
+                "5:9: Unknown label \"NOWHERE\"",
+                "6:1: Invalid label \"R2\"",
+                "7:1: Invalid label \"1ST\"",
+                "8:3: Offset to FAR out of range for offset9 (-256 to 255, found 512)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            encode("FOO R1"),
+            Err("Unknown mnemonic \"FOO\"".to_string())
+        );
+        assert_eq!(
+            encode("BRx #1"),
+            Err("Unknown mnemonic \"BRX\"".to_string())
+        );
+        assert_eq!(
+            encode("ADD R1, R1, #16"),
+            Err("Immediate #16 out of range for imm5 (-16 to 15)".to_string())
+        );
+        assert_eq!(
+            encode("ADD R1, R9, #1"),
+            Err("Unknown register \"R9\", expected R0-R7".to_string())
+        );
+        assert_eq!(
+            encode("ADD R1, R1"),
+            Err("ADD expects 3 operands, found 2".to_string())
+        );
+        assert_eq!(
+            encode("BR x3200"),
+            Err("Offset to x3200 out of range for offset9 (-256 to 255, found 511)".to_string())
+        );
+        assert_eq!(
+            encode("LD R1, 5"),
+            Err("Expected a number like #10 or x0A, found \"5\"".to_string())
+        );
+        assert_eq!(
+            encode("TRAP x100"),
+            Err("TRAP vector x100 out of range (x00 to xFF)".to_string())
+        );
+        assert_eq!(
+            encode("ADD R1, R1, #1x"),
+            Err("Invalid number \"#1x\"".to_string())
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Guest I/O. Every trap and keyboard/display device access goes through a [`Console`], so the
+//! machine can run without a terminal.
+
+#[cfg(feature = "std")]
+mod reader;
+#[cfg(feature = "native-term")]
+mod terminal;
+#[cfg(feature = "std")]
+mod throttled;
+
+#[cfg(feature = "std")]
+pub use self::reader::ReaderConsole;
+#[cfg(feature = "native-term")]
+pub(crate) use self::terminal::CaptureConsole;
+#[cfg(feature = "native-term")]
+pub use self::terminal::StdConsole;
+#[cfg(feature = "std")]
+pub use self::throttled::{Polling, Throttled};
+
+use crate::error::IoError;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU8, Ordering};
+#[cfg(not(feature = "std"))]
+use spin::{Mutex, MutexGuard};
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "std")]
+pub(crate) use std::time::Instant;
+
+/// Stands in for `std::time::Instant` without `std`. There's no clock to set a deadline by, so
+/// [`Console::read_key_blocking`] is never given one.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Instant {}
+
+/// The keyboard and display as seen by guest programs.
+pub trait Console: Send {
+    /// Returns the next key if one has been typed, without waiting.
+    fn poll_key(&mut self) -> Option<u8>;
+
+    /// Waits for the next key. `None` means giving up, e.g. because `deadline` passed, and the
+    /// instruction waiting for it will be executed again.
+    fn read_key_blocking(&mut self, deadline: Option<Instant>) -> Option<u8>;
+
+    fn write(&mut self, bytes: &[u8]);
+
+    /// Sends on any output `write` has buffered. Called at the end of each TRAP, when the machine
+    /// halts and whenever a run stops, and consoles that buffer should flush before waiting for
+    /// a key so a prompt shows first.
+    fn flush(&mut self) {}
+
+    /// The I/O error behind the last read or write coming up empty, if any. The step that ran
+    /// into it returns it, see [`crate::StepResult::Error`]. Consoles that can't fail can leave
+    /// this as `None`.
+    fn take_error(&mut self) -> Option<IoError> {
+        None
+    }
+}
+
+/// Which keys the program reads are written back to the console, see [`crate::Vm::set_echo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Echo {
+    /// As the traps are specified: IN echoes its key, GETC and KBDR reads don't.
+    #[default]
+    Default,
+    /// Every key read through GETC, IN or KBDR, once each.
+    On,
+    /// None at all, not even IN's.
+    Off,
+}
+
+/// The machine's console, shared by the traps and the keyboard and display devices. Replacing it
+/// through any handle replaces it for all of them, and so does changing its [`Echo`].
+#[derive(Clone)]
+pub struct SharedConsole(Arc<Mutex<Box<dyn Console>>>, Arc<AtomicU8>);
+
+impl SharedConsole {
+    pub fn new(console: Box<dyn Console>) -> Self {
+        SharedConsole(
+            Arc::new(Mutex::new(console)),
+            Arc::new(AtomicU8::new(Echo::Default as u8)),
+        )
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Box<dyn Console>> {
+        lock(&self.0)
+    }
+
+    /// Swaps in `console`, returning the one it replaces.
+    pub fn replace(&self, console: Box<dyn Console>) -> Box<dyn Console> {
+        core::mem::replace(&mut *self.lock(), console)
+    }
+
+    pub fn echo(&self) -> Echo {
+        match self.1.load(Ordering::Relaxed) {
+            x if x == Echo::On as u8 => Echo::On,
+            x if x == Echo::Off as u8 => Echo::Off,
+            _ => Echo::Default,
+        }
+    }
+
+    pub fn set_echo(&self, echo: Echo) {
+        self.1.store(echo as u8, Ordering::Relaxed);
+    }
+}
+
+// The terminal when there is one, otherwise a console nobody types into.
+impl Default for SharedConsole {
+    fn default() -> Self {
+        #[cfg(feature = "native-term")]
+        return Self::new(Box::new(Throttled::new(StdConsole::default())));
+        #[cfg(not(feature = "native-term"))]
+        return Self::new(Box::new(BufferConsole::default()));
+    }
+}
+
+/// Scripted input and recorded output, e.g. for tests.
+///
+/// Clones share the same buffers, so keep one to feed keys and inspect output after handing
+/// another to the machine. Once the input runs out the program waits for more, like a terminal
+/// nobody is typing into.
+#[derive(Debug, Clone, Default)]
+pub struct BufferConsole {
+    input: Arc<Mutex<VecDeque<u8>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl BufferConsole {
+    pub fn new(input: &[u8]) -> Self {
+        let console = Self::default();
+        console.push_input(input);
+        console
+    }
+
+    /// Queues more keys.
+    pub fn push_input(&self, input: &[u8]) {
+        lock(&self.input).extend(input);
+    }
+
+    /// Everything written so far.
+    pub fn output(&self) -> Vec<u8> {
+        lock(&self.output).clone()
+    }
+
+    /// Output written since the last call.
+    pub fn take_output(&self) -> Vec<u8> {
+        core::mem::take(&mut *lock(&self.output))
+    }
+}
+
+impl Console for BufferConsole {
+    fn poll_key(&mut self) -> Option<u8> {
+        lock(&self.input).pop_front()
+    }
+
+    fn read_key_blocking(&mut self, _deadline: Option<Instant>) -> Option<u8> {
+        self.poll_key()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        lock(&self.output).extend_from_slice(bytes);
+    }
+}
+
+#[cfg(feature = "std")]
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap()
+}
+
+#[cfg(not(feature = "std"))]
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_console() {
+        let mut console = BufferConsole::new(b"ab");
+        let handle = console.clone();
+
+        assert_eq!(console.poll_key(), Some(b'a'));
+        handle.push_input(b"c");
+        assert_eq!(console.read_key_blocking(None), Some(b'b'));
+        assert_eq!(console.poll_key(), Some(b'c'));
+        assert_eq!(console.poll_key(), None);
+
+        console.write(b"Hi");
+        console.write(b"!");
+        assert_eq!(handle.output(), b"Hi!");
+        assert_eq!(handle.take_output(), b"Hi!");
+        assert_eq!(handle.output(), b"");
+    }
+}
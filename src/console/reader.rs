@@ -0,0 +1,92 @@
+//! A console fed from a file or any other reader, for runs nobody types into.
+
+use super::{Console, Instant};
+use std::io::{self, Read, Write};
+
+/// Keys from any [`Read`], e.g. a file of scripted answers, and output to any [`Write`].
+///
+/// Every key is ready as soon as it's asked for, so the keyboard status register reports one
+/// waiting until the input is used up. Reading past the end is an error, like STDIN reaching
+/// EOF, as no more keys will come.
+///
+/// ```
+/// use lc3::{ReaderConsole, Vm};
+///
+/// // .ORIG x3000, GETC, HALT
+/// let program = [0x30, 0x00, 0xf0, 0x20, 0xf0, 0x25];
+///
+/// let mut vm = Vm::new();
+/// vm.set_console(ReaderConsole::new(&b"y"[..], std::io::sink()));
+/// vm.load_program(&program).unwrap();
+/// vm.run().unwrap();
+/// assert_eq!(vm.registers()[0], u16::from(b'y'));
+/// ```
+pub struct ReaderConsole {
+    input: Box<dyn Read + Send>,
+    output: Box<dyn Write + Send>,
+    error: Option<io::Error>,
+}
+
+impl ReaderConsole {
+    pub fn new(input: impl Read + Send + 'static, output: impl Write + Send + 'static) -> Self {
+        ReaderConsole {
+            input: Box::new(input),
+            output: Box::new(output),
+            error: None,
+        }
+    }
+}
+
+impl Console for ReaderConsole {
+    fn poll_key(&mut self) -> Option<u8> {
+        let mut buffer = [0; 1];
+        match self.input.read_exact(&mut buffer) {
+            Ok(()) => Some(buffer[0]),
+            Err(e) => {
+                self.error = Some(io::Error::new(
+                    e.kind(),
+                    format!("Unable to read input: {}", e),
+                ));
+                None
+            }
+        }
+    }
+
+    fn read_key_blocking(&mut self, _deadline: Option<Instant>) -> Option<u8> {
+        self.poll_key()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let output = &mut self.output;
+        if let Err(e) = output.write_all(bytes).and_then(|_| output.flush()) {
+            self.error = Some(io::Error::new(
+                e.kind(),
+                format!("Unable to write output: {}", e),
+            ));
+        }
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_until_the_end() {
+        let mut console = ReaderConsole::new(&b"ab"[..], io::sink());
+
+        assert_eq!(console.poll_key(), Some(b'a'));
+        assert_eq!(console.read_key_blocking(None), Some(b'b'));
+        assert!(console.take_error().is_none());
+
+        assert_eq!(console.poll_key(), None);
+        assert_eq!(
+            console.take_error().unwrap().to_string(),
+            "Unable to read input: failed to fill whole buffer"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! The console on a real terminal.
+
+use super::{BufferConsole, Console};
+use crate::interrupt;
+use crate::terminal::{Native, Terminal};
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// The terminal: keys from STDIN and output to STDOUT, or another writer, see
+/// [`StdConsole::with_output`]. Output is buffered until [`Console::flush`], or a key is waited
+/// for.
+pub struct StdConsole {
+    output: BufWriter<Box<dyn Write + Send>>,
+    // Whether `output` is something other than STDOUT.
+    redirected: bool,
+    error: Option<io::Error>,
+}
+
+impl Default for StdConsole {
+    fn default() -> Self {
+        StdConsole {
+            output: BufWriter::new(Box::new(io::stdout())),
+            redirected: false,
+            error: None,
+        }
+    }
+}
+
+impl fmt::Debug for StdConsole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StdConsole")
+            .field("redirected", &self.redirected)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+// How long a blocking read waits on STDIN at a time before checking for a deadline or Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+impl Console for StdConsole {
+    fn poll_key(&mut self) -> Option<u8> {
+        self.flush();
+        if Native::key_ready(Duration::ZERO) {
+            self.read_byte()
+        } else {
+            None
+        }
+    }
+
+    // Also gives up when Ctrl-C is pressed, so the run loop or debugger can stop.
+    fn read_key_blocking(&mut self, deadline: Option<Instant>) -> Option<u8> {
+        self.flush();
+        loop {
+            let mut wait = POLL_INTERVAL;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return None;
+                }
+                wait = wait.min(deadline - now);
+            }
+            if interrupt::REQUESTED.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            if Native::key_ready(wait) {
+                return self.read_byte();
+            }
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let result = self.output.write_all(bytes);
+        self.record_write_error(result);
+    }
+
+    fn flush(&mut self) {
+        let result = self.output.flush();
+        self.record_write_error(result);
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
+
+impl StdConsole {
+    /// Keys from STDIN, with the program's output sent to `output` instead of STDOUT, e.g. a
+    /// file so it can be compared without anything else mixed in.
+    pub fn with_output(output: impl Write + Send + 'static) -> Self {
+        StdConsole {
+            output: BufWriter::new(Box::new(output)),
+            redirected: true,
+            error: None,
+        }
+    }
+
+    fn record_write_error(&mut self, result: io::Result<()>) {
+        if let Err(e) = result {
+            let destination = if self.redirected { "output" } else { "STDOUT" };
+            self.error = Some(io::Error::new(
+                e.kind(),
+                format!("Unable to write to {}: {}", destination, e),
+            ));
+        }
+    }
+
+    // STDIN reaching EOF is an error too, as no more keys will come.
+    fn read_byte(&mut self) -> Option<u8> {
+        match Native::read_key() {
+            Ok(key) => Some(key),
+            Err(e) => {
+                self.error = Some(io::Error::new(
+                    e.kind(),
+                    format!("Unable to read from STDIN: {}", e),
+                ));
+                None
+            }
+        }
+    }
+}
+
+/// Keys from STDIN like [`StdConsole`], with output collected in a [`BufferConsole`] rather than
+/// printed, e.g. so the TUI can show it in its own pane.
+pub(crate) struct CaptureConsole {
+    output: BufferConsole,
+    input: StdConsole,
+}
+
+impl CaptureConsole {
+    pub fn new(output: BufferConsole) -> Self {
+        CaptureConsole {
+            output,
+            input: StdConsole::default(),
+        }
+    }
+}
+
+impl Console for CaptureConsole {
+    fn poll_key(&mut self) -> Option<u8> {
+        self.input.poll_key()
+    }
+
+    fn read_key_blocking(&mut self, deadline: Option<Instant>) -> Option<u8> {
+        self.input.read_key_blocking(deadline)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.output.write(bytes);
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.input.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::memory::DDR;
+    use crate::state::State;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_output_is_flushed_after_traps() {
+        let output = Shared::default();
+        let printed = || String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let mut state = State::new();
+        state
+            .console
+            .replace(Box::new(StdConsole::with_output(output.clone())));
+        // LD R0, #3; STI R0, #3; OUT; HALT; .FILL x41; .FILL DDR
+        state.pc = 0x3000;
+        for (address, word) in (0x3000..).zip([0x2003, 0xb003, 0xf021, 0xf025, 0x41, DDR]) {
+            state.memory.write(address, word);
+        }
+
+        state.step();
+        state.step();
+        // Written to the display, but nothing has flushed it yet.
+        assert_eq!(printed(), "");
+
+        state.step();
+        assert_eq!(printed(), "AA");
+
+        state.console.lock().write(b"!");
+        state.step();
+        assert_eq!(printed(), "AA!");
+    }
+}
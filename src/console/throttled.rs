@@ -0,0 +1,323 @@
+//! Keeping a program that busy-waits on the keyboard status register from checking for a key, a
+//! `select(2)` each time on a terminal, millions of times a second.
+
+use super::{Console, Instant};
+use crate::interrupt;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// How often [`Throttled`] asks the console underneath for a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Polling {
+    /// How long a poll that found no key stands for. Checking again any sooner gets the same
+    /// answer without asking, so a key can take this long to show up.
+    pub interval: Duration,
+    /// Polls in a row that find no key before each one waits for a key, rather than returning
+    /// straight away, so a program idling in a polling loop doesn't keep a host CPU busy. A key
+    /// typed during a wait still arrives as soon as it's typed.
+    pub idle_after: u32,
+    /// The longest a poll waits. The first wait is `interval`, and each one after that twice as
+    /// long as the last, up to this.
+    pub max_wait: Duration,
+}
+
+// Keys arrive within half a millisecond, and after a tenth of a second with none the program
+// idles.
+impl Default for Polling {
+    fn default() -> Self {
+        Polling {
+            interval: Duration::from_micros(500),
+            idle_after: 200,
+            max_wait: Duration::from_millis(2),
+        }
+    }
+}
+
+/// Another console with its [`Console::poll_key`] rate-limited, see [`Polling`]. The machine's
+/// terminal is wrapped in one unless another console is set.
+///
+/// Polls don't wait while Ctrl-C is waiting to be handled, so pausing in the debugger or ending
+/// the run isn't held up.
+///
+/// ```
+/// use lc3::{BufferConsole, Console, Polling, Throttled};
+/// use std::time::Duration;
+///
+/// let polling = Polling {
+///     interval: Duration::from_secs(1),
+///     ..Polling::default()
+/// };
+/// let mut console = Throttled::with_polling(BufferConsole::new(b"y"), polling);
+/// assert_eq!(console.poll_key(), Some(b'y'));
+/// assert_eq!(console.poll_key(), None);
+/// // Within a second of finding nothing, so the buffer isn't asked again.
+/// assert_eq!(console.poll_key(), None);
+/// assert_eq!(console.polls(), 2);
+/// ```
+#[derive(Debug)]
+pub struct Throttled<C> {
+    inner: C,
+    backoff: Backoff,
+    polls: u64,
+}
+
+impl<C: Console> Throttled<C> {
+    pub fn new(inner: C) -> Self {
+        Self::with_polling(inner, Polling::default())
+    }
+
+    pub fn with_polling(inner: C, polling: Polling) -> Self {
+        Throttled {
+            inner,
+            backoff: Backoff::new(polling),
+            polls: 0,
+        }
+    }
+
+    /// How many times the console underneath has been polled for a key.
+    pub fn polls(&self) -> u64 {
+        self.polls
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: Console> Console for Throttled<C> {
+    fn poll_key(&mut self) -> Option<u8> {
+        let inner = &mut self.inner;
+        let polls = &mut self.polls;
+        let interrupted = interrupt::REQUESTED.load(Ordering::Relaxed);
+        self.backoff.poll(Instant::now(), interrupted, |wait| {
+            *polls += 1;
+            match wait {
+                Some(wait) => inner.read_key_blocking(Some(Instant::now() + wait)),
+                None => inner.poll_key(),
+            }
+        })
+    }
+
+    fn read_key_blocking(&mut self, deadline: Option<Instant>) -> Option<u8> {
+        self.inner.read_key_blocking(deadline)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.inner.write(bytes);
+    }
+
+    fn flush(&mut self) {
+        self.inner.flush();
+    }
+
+    fn take_error(&mut self) -> Option<io::Error> {
+        self.inner.take_error()
+    }
+}
+
+// Whether and how to ask for a key, going by the polls so far. The time is passed in, so this
+// can be tested without a real clock.
+#[derive(Debug)]
+struct Backoff {
+    polling: Polling,
+    // When the last poll that found nothing stops standing, if it still does.
+    next_poll: Option<Instant>,
+    empty_polls: u32,
+    // How long the next idle poll waits.
+    wait: Duration,
+}
+
+impl Backoff {
+    fn new(polling: Polling) -> Self {
+        Backoff {
+            polling,
+            next_poll: None,
+            empty_polls: 0,
+            wait: polling.interval.min(polling.max_wait),
+        }
+    }
+
+    // Calls `check` for a key unless the last empty poll still stands, with how long to wait for
+    // one if the program's been idle a while. Never waits while `interrupted`.
+    fn poll(
+        &mut self,
+        now: Instant,
+        interrupted: bool,
+        check: impl FnOnce(Option<Duration>) -> Option<u8>,
+    ) -> Option<u8> {
+        if self.next_poll.is_some_and(|next| now < next) {
+            return None;
+        }
+
+        let idle = self.empty_polls >= self.polling.idle_after && !interrupted;
+        let key = check(Some(self.wait).filter(|_| idle));
+
+        if key.is_some() {
+            *self = Backoff::new(self.polling);
+        } else if idle {
+            // Waiting has already spaced this poll out from the next.
+            self.next_poll = None;
+            self.wait = (self.wait * 2).min(self.polling.max_wait);
+        } else {
+            self.empty_polls = self.empty_polls.saturating_add(1);
+            self.next_poll = Some(now + self.polling.interval);
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+    use crate::vm::Vm;
+    use std::sync::{Arc, Mutex};
+
+    // Keys from a buffer, noting how it was asked for each.
+    #[derive(Clone, Default)]
+    struct Recording {
+        keys: BufferConsole,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Console for Recording {
+        fn poll_key(&mut self) -> Option<u8> {
+            self.calls.lock().unwrap().push("poll");
+            self.keys.poll_key()
+        }
+
+        fn read_key_blocking(&mut self, deadline: Option<Instant>) -> Option<u8> {
+            assert!(deadline.is_some());
+            self.calls.lock().unwrap().push("wait");
+            self.keys.poll_key()
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.keys.write(bytes);
+        }
+    }
+
+    #[test]
+    fn test_polls_are_rate_limited() {
+        let recording = Recording::default();
+        let polling = Polling {
+            interval: Duration::from_secs(60),
+            ..Polling::default()
+        };
+        let mut console = Throttled::with_polling(recording.clone(), polling);
+
+        recording.keys.push_input(b"ab");
+        assert_eq!(console.poll_key(), Some(b'a'));
+        assert_eq!(console.poll_key(), Some(b'b'));
+        assert_eq!(console.poll_key(), None);
+        // Typed within the minute the empty poll stands for.
+        recording.keys.push_input(b"c");
+        assert_eq!(console.poll_key(), None);
+        assert_eq!(console.poll_key(), None);
+
+        assert_eq!(console.polls(), 3);
+        assert_eq!(*recording.calls.lock().unwrap(), ["poll"; 3]);
+    }
+
+    #[test]
+    fn test_idle_polls_wait_for_a_key() {
+        let recording = Recording::default();
+        let polling = Polling {
+            interval: Duration::ZERO,
+            idle_after: 2,
+            ..Polling::default()
+        };
+        let mut console = Throttled::with_polling(recording.clone(), polling);
+
+        assert_eq!(console.poll_key(), None);
+        assert_eq!(console.poll_key(), None);
+        assert_eq!(console.poll_key(), None);
+        recording.keys.push_input(b"a");
+        assert_eq!(console.poll_key(), Some(b'a'));
+        // A key ends the idling.
+        assert_eq!(console.poll_key(), None);
+
+        assert_eq!(
+            *recording.calls.lock().unwrap(),
+            ["poll", "poll", "wait", "wait", "poll"]
+        );
+    }
+
+    // Polls `ms` milliseconds after `start`, with `key` waiting. Returns how the console was asked:
+    // not at all, without waiting, or waiting so many milliseconds.
+    fn poll(
+        backoff: &mut Backoff,
+        start: Instant,
+        ms: u64,
+        interrupted: bool,
+        key: Option<u8>,
+    ) -> Option<Option<u128>> {
+        let mut asked = None;
+        let now = start + Duration::from_millis(ms);
+        let found = backoff.poll(now, interrupted, |wait| {
+            asked = Some(wait.map(|wait| wait.as_millis()));
+            key
+        });
+        assert_eq!(found, key.filter(|_| asked.is_some()));
+        asked
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Polling {
+            interval: Duration::from_millis(1),
+            idle_after: 2,
+            max_wait: Duration::from_millis(3),
+        });
+        let start = Instant::now();
+        let mut poll = |ms, interrupted, key| poll(&mut backoff, start, ms, interrupted, key);
+
+        assert_eq!(poll(0, false, None), Some(None));
+        assert_eq!(poll(0, false, Some(b'a')), None);
+        assert_eq!(poll(1, false, None), Some(None));
+        // Idle, so waiting longer each time, up to the most.
+        assert_eq!(poll(2, false, None), Some(Some(1)));
+        assert_eq!(poll(3, false, None), Some(Some(2)));
+        assert_eq!(poll(5, false, None), Some(Some(3)));
+        assert_eq!(poll(8, false, None), Some(Some(3)));
+        // Not while Ctrl-C is pending, and that poll stands like any other.
+        assert_eq!(poll(11, true, None), Some(None));
+        assert_eq!(poll(11, false, None), None);
+        assert_eq!(poll(12, false, Some(b'a')), Some(Some(3)));
+        // A key starts it all over.
+        assert_eq!(poll(15, false, None), Some(None));
+        assert_eq!(poll(16, false, None), Some(None));
+        assert_eq!(poll(17, false, None), Some(Some(1)));
+    }
+
+    #[test]
+    fn test_polling_loop_reads_every_key() {
+        // Echoes keys read by polling KBSR until a newline.
+        let source = "
+        .ORIG x3000
+POLL    LDI R0, KBSR
+        BRzp POLL
+        LDI R0, KBDR
+        OUT
+        ADD R0, R0, #-10
+        BRnp POLL
+        HALT
+KBSR    .FILL xFE00
+KBDR    .FILL xFE02
+        .END
+";
+        let keys = BufferConsole::new(b"hello\n");
+        let polling = Polling {
+            interval: Duration::from_micros(100),
+            idle_after: 10,
+            ..Polling::default()
+        };
+        let mut vm = Vm::new();
+        vm.set_console(Throttled::with_polling(keys.clone(), polling));
+        vm.load_assembly(source).unwrap();
+        vm.run().unwrap();
+
+        assert_eq!(keys.output(), b"hello\n");
+    }
+}
@@ -1,9 +1,10 @@
+use crate::console::Echo;
 use crate::instruction::Register::*;
 use crate::instruction::{Instruction, TrapVector};
-use crate::state::{Condition, State};
-use std::io::{self, Read, Write};
+use crate::state::{Condition, State, TrapMode};
+use alloc::vec::Vec;
 
-pub fn execute(mut state: State, instruction: Instruction) -> State {
+pub fn execute(state: &mut State, instruction: Instruction) {
     state.pc = state.pc.wrapping_add(1);
 
     match instruction {
@@ -144,7 +145,7 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
         Instruction::ST(r0, pc_offset) => {
             let address = state.pc.wrapping_add(sign_extend(pc_offset, 9));
 
-            state.memory.write(address, state.registers.read(r0));
+            state.memory.store(address, state.registers.read(r0));
         }
 
         // JSR - Jump to Subroutine
@@ -184,11 +185,19 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
             let temp = state.pc;
             state.pc = state.pc.wrapping_add(sign_extend(pc_offset, 11));
             state.registers.write(R7, temp);
+
+            if let Some(call_stack) = &mut state.call_stack {
+                call_stack.call(temp.wrapping_sub(1), state.pc, temp);
+            }
         }
         Instruction::JSRR(r0) => {
             let temp = state.pc;
             state.pc = state.registers.read(r0);
             state.registers.write(R7, temp);
+
+            if let Some(call_stack) = &mut state.call_stack {
+                call_stack.call(temp.wrapping_sub(1), state.pc, temp);
+            }
         }
 
         // AND - Bit-wise Logical AND
@@ -223,10 +232,12 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
         Instruction::AND(r0, r1, r2) => {
             let value = state.registers.read(r1) & state.registers.read(r2);
             state.registers.write(r0, value);
+            state.update_flags(r0);
         }
         Instruction::ANDIMM(immediate_value, r0, r1) => {
             let value = state.registers.read(r1) & sign_extend(immediate_value, 5);
             state.registers.write(r0, value);
+            state.update_flags(r0);
         }
 
         // LDR - Load Base+offset
@@ -288,11 +299,11 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
                 .wrapping_add(sign_extend(offset, 6));
             let value = state.registers.read(sr);
 
-            state.memory.write(address, value);
+            state.memory.store(address, value);
         }
 
         Instruction::UNUSED => {
-            panic!("unused");
+            unreachable!("UNUSED is rejected by State::step");
         }
 
         // NOT - Bit-Wise Complement
@@ -376,7 +387,7 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
             let address = state.pc.wrapping_add(sign_extend(pc_offset, 9));
             let address = state.memory.read(address);
 
-            state.memory.write(address, state.registers.read(r0));
+            state.memory.store(address, state.registers.read(r0));
         }
 
         // JMP - Jump
@@ -412,10 +423,18 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
         // subroutine call instruction.
         Instruction::JMP(r0) => {
             state.pc = state.registers.read(r0);
+
+            if let (R7, Some(call_stack)) = (r0, &mut state.call_stack) {
+                call_stack.ret(state.pc);
+            }
         }
 
         Instruction::RESERVED => {
-            panic!("reserved");
+            unreachable!("RESERVED is rejected by State::step");
+        }
+
+        Instruction::UNKNOWNTRAP(vector) => {
+            unreachable!("TRAP x{:02X} is rejected by State::step", vector);
         }
 
         // LEA - Load Effective Address
@@ -478,23 +497,43 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
         // addresses for system calls specified by their corresponding trap vectors. This region of
         // memory is called the Trap Vector Table. Table A.2 describes the functions performed
         // by the service routines corresponding to trap vectors x20 to x25.
+        Instruction::TRAP(trap_vector) if state.trap_mode == TrapMode::Os => {
+            let call_site = state.pc.wrapping_sub(1);
+            state.registers.write(R7, state.pc);
+            let routine = state.memory.read(u16::from(trap_vector.vector()));
+            if let Some(call_stack) = &mut state.call_stack {
+                call_stack.call(call_site, routine, state.pc);
+            }
+            state.pc = routine;
+        }
         Instruction::TRAP(trap_vector) => {
             match trap_vector {
                 // Read a single character from the keyboard. The character is not echoed
-                // onto the console. Its ASCII code is copied into R0. The high eight bits
-                // of R0 are cleared.
+                // onto the console, unless echo is turned on. Its ASCII code is copied into R0.
+                // The high eight bits of R0 are cleared.
                 TrapVector::GETC => {
-                    let mut buffer = [0; 1];
-                    io::stdin()
-                        .read_exact(&mut buffer)
-                        .expect("unable to read from STDIN");
-
-                    state.registers.write(R0, u16::from(buffer[0]));
+                    let mut console = state.console.lock();
+                    match console.read_key_blocking(state.deadline) {
+                        Some(character) => {
+                            if state.console.echo() == Echo::On {
+                                console.write(&[character]);
+                            }
+                            drop(console);
+                            state.registers.write(R0, u16::from(character));
+                        }
+                        // Gave up waiting, leave the PC on the TRAP so the run loop can report
+                        // where it stopped, or it can be executed again.
+                        None => {
+                            drop(console);
+                            state.pc = state.pc.wrapping_sub(1);
+                        }
+                    }
                 }
 
                 // Write a character in R0[7:0] to the console display.
                 TrapVector::OUT => {
-                    print!("{}", char::from(state.registers.read(R0) as u8));
+                    let character = state.registers.read(R0) as u8;
+                    state.console.lock().write(&[character]);
                 }
 
                 // Write a string of ASCII characters to the console display. The characters
@@ -503,18 +542,39 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
                 // the occurrence of x0000 in a memory location.
                 TrapVector::PUTS => {
                     let mut address = state.registers.read(R0);
-                    while state.memory.read(address) != 0 {
-                        print!("{}", char::from(state.memory.read(address) as u8));
-                        address += 1;
+                    let mut string = Vec::new();
+                    // Stops after going all the way around memory without finding the end.
+                    for _ in 0..=u16::MAX {
+                        let character = state.memory.read(address);
+                        if character == 0 {
+                            break;
+                        }
+                        string.push(character as u8);
+                        address = address.wrapping_add(1);
                     }
-                    io::stdout().flush().expect("unable to flush stdout");
+                    state.console.lock().write(&string);
                 }
 
                 // Print a prompt on the screen and read a single character from the keyboard.
-                // The character is echoed onto the console monitor, and its ASCII code is
-                // copied into R0. The high eight bits of R0 are cleared.
+                // The character is echoed onto the console monitor, unless echo is turned off,
+                // and its ASCII code is copied into R0. The high eight bits of R0 are cleared.
                 TrapVector::IN => {
-                    unimplemented!("TrapVector: IN");
+                    let mut console = state.console.lock();
+                    console.write(b"Enter a character: ");
+                    match console.read_key_blocking(state.deadline) {
+                        Some(character) => {
+                            if state.console.echo() != Echo::Off {
+                                console.write(&[character]);
+                            }
+                            drop(console);
+                            state.registers.write(R0, u16::from(character));
+                        }
+                        // Like GETC, executed again if it's resumed.
+                        None => {
+                            drop(console);
+                            state.pc = state.pc.wrapping_sub(1);
+                        }
+                    }
                 }
 
                 // Write a string of ASCII characters to the console. The characters are
@@ -527,7 +587,20 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
                 // location containing the last character to be written.) Writing terminates
                 // with the occurrence of x0000 in a memory location.
                 TrapVector::PUTSP => {
-                    unimplemented!("TrapVector: PUTSP");
+                    let mut address = state.registers.read(R0);
+                    let mut string = Vec::new();
+                    for _ in 0..=u16::MAX {
+                        let word = state.memory.read(address);
+                        if word == 0 {
+                            break;
+                        }
+                        string.push(word as u8);
+                        if word >> 8 != 0 {
+                            string.push((word >> 8) as u8);
+                        }
+                        address = address.wrapping_add(1);
+                    }
+                    state.console.lock().write(&string);
                 }
 
                 // Halt execution and print a message on the console.
@@ -537,11 +610,9 @@ pub fn execute(mut state: State, instruction: Instruction) -> State {
             }
         }
     }
-
-    state
 }
 
-fn sign_extend(n: u16, bit_count: u8) -> u16 {
+pub fn sign_extend(n: u16, bit_count: u8) -> u16 {
     if ((n >> (bit_count - 1)) & 1) == 1 {
         n | (0xFFFF << bit_count)
     } else {
@@ -553,14 +624,16 @@ fn sign_extend(n: u16, bit_count: u8) -> u16 {
 mod tests {
     use super::Instruction::*;
     use super::*;
+    use crate::console::{BufferConsole, Echo};
     use crate::instruction;
+    use crate::vm::Vm;
 
     #[test]
     fn process_addimm() {
         let mut state = new_state();
         state.registers.write(R1, 3);
 
-        state = execute(state, ADDIMM(R2, R1, 1));
+        execute(&mut state, ADDIMM(R2, R1, 1));
 
         assert_eq!(state.registers.read(R2), 4);
         assert_eq!(state.condition, Condition::P);
@@ -572,7 +645,7 @@ mod tests {
         state.registers.write(R0, 2);
         state.registers.write(R1, 3);
 
-        state = execute(state, ADD(R2, R1, R0));
+        execute(&mut state, ADD(R2, R1, R0));
 
         assert_eq!(state.registers.read(R2), 5);
         assert_eq!(state.condition, Condition::P);
@@ -580,15 +653,19 @@ mod tests {
 
     #[test]
     fn process_ldi() {
-        let mut state = new_state();
-        state.memory.write(0x3001, 0x3002);
-        state.memory.write(0x3002, 0x3003);
-        state.memory.write(0x3003, 42);
-
-        state = execute(state, LDI(R0, 1));
-
-        assert_eq!(state.registers.read(R0), 42);
-        assert_eq!(state.condition, Condition::P);
+        let mut vm = vm("
+                .ORIG x3000
+                LDI R0, POINTER
+                HALT
+        POINTER .FILL VALUE
+        VALUE   .FILL #42
+                .END
+        ");
+
+        vm.step();
+
+        assert_eq!(vm.register(R0), 42);
+        assert_eq!(vm.condition(), Condition::P);
     }
 
     #[test]
@@ -596,7 +673,7 @@ mod tests {
         let mut state = new_state();
         state.registers.write(R2, 5);
 
-        state = execute(state, JMP(R2));
+        execute(&mut state, JMP(R2));
 
         assert_eq!(state.pc, 5);
     }
@@ -606,7 +683,7 @@ mod tests {
         let mut state = new_state();
         state.registers.write(R7, 42);
 
-        state = execute(state, JMP(R7));
+        execute(&mut state, JMP(R7));
 
         assert_eq!(state.pc, 42);
     }
@@ -621,7 +698,7 @@ mod tests {
             z: false,
             p: false,
         };
-        state = execute(state, BR(condition, 5));
+        execute(&mut state, BR(condition, 5));
 
         // incremented pc + 5
         assert_eq!(state.pc, 0x3006);
@@ -637,7 +714,7 @@ mod tests {
             z: false,
             p: false,
         };
-        state = execute(state, BR(condition, 5));
+        execute(&mut state, BR(condition, 5));
 
         // incremented pc + 1 (ingores the pc_offset)
         assert_eq!(state.pc, 0x3001);
@@ -653,7 +730,7 @@ mod tests {
             z: false,
             p: false,
         };
-        state = execute(state, BR(condition, 5));
+        execute(&mut state, BR(condition, 5));
 
         // incremented pc + 1 (ingores the pc_offset)
         assert_eq!(state.pc, 0x3001);
@@ -661,25 +738,35 @@ mod tests {
 
     #[test]
     fn process_ld() {
-        let mut state = new_state();
-        state.condition = Condition::P;
-        state.memory.write(0x3000 + 1 + 5, 42);
-
-        state = execute(state, LD(R3, 5));
-
-        assert_eq!(state.registers.read(R3), 42);
-        assert_eq!(state.condition, Condition::P);
+        let mut vm = vm("
+                .ORIG x3000
+                LD R3, VALUE
+                HALT
+        VALUE   .FILL #-42
+                .END
+        ");
+
+        vm.step();
+
+        assert_eq!(vm.register(R3), -42_i16 as u16);
+        assert_eq!(vm.condition(), Condition::N);
     }
 
     #[test]
     fn process_st() {
-        let mut state = new_state();
-        state.registers.write(R3, 42);
-        state.condition = Condition::P;
-
-        state = execute(state, ST(R3, 5));
-
-        assert_eq!(state.memory.read(0x3000 + 1 + 5), 42);
+        let mut vm = vm("
+                .ORIG x3000
+                LD R3, VALUE
+                ST R3, RESULT
+                HALT
+        VALUE   .FILL #42
+        RESULT  .BLKW #1
+                .END
+        ");
+
+        assert_eq!(vm.run(), Ok(()));
+
+        assert_eq!(vm.read_memory(0x3004), 42);
     }
 
     #[test]
@@ -687,7 +774,7 @@ mod tests {
         let mut state = new_state();
         state.registers.write(R3, 42);
 
-        state = execute(state, JSRR(R3));
+        execute(&mut state, JSRR(R3));
 
         assert_eq!(state.pc, 42);
         assert_eq!(state.registers.read(R7), 0x3001);
@@ -697,21 +784,41 @@ mod tests {
     fn process_jsr() {
         let mut state = new_state();
 
-        state = execute(state, JSR(0b10000000011)); // 1027
+        execute(&mut state, JSR(0b10000000011)); // 1027
 
-        assert_eq!(state.pc, (0x3001 as u16).wrapping_add(0b11111100_00000011));
+        assert_eq!(state.pc, 0x3001_u16.wrapping_add(0b11111100_00000011));
         //                      `incremented pc           ^
         //                                                `sign extended 1027
         assert_eq!(state.registers.read(R7), 0x3001);
     }
 
+    #[test]
+    fn process_jsr_and_ret() {
+        let mut vm = vm("
+                .ORIG x3000
+                JSR SUBROUTINE
+                HALT
+                .BLKW #1000
+        SUBROUTINE
+                RET
+                .END
+        ");
+
+        vm.step();
+        assert_eq!(vm.pc(), 0x3002 + 1000);
+        assert_eq!(vm.register(R7), 0x3001);
+
+        vm.step();
+        assert_eq!(vm.pc(), 0x3001);
+    }
+
     #[test]
     fn process_and() {
         let mut state = new_state();
         state.registers.write(R2, 3);
         state.registers.write(R3, 5);
 
-        state = execute(state, AND(R1, R2, R3));
+        execute(&mut state, AND(R1, R2, R3));
 
         assert_eq!(state.registers.read(R1), 3 & 5);
     }
@@ -721,9 +828,16 @@ mod tests {
         let mut state = new_state();
         state.registers.write(R2, 3);
 
-        state = execute(state, ANDIMM(5, R1, R2));
+        execute(&mut state, ANDIMM(5, R1, R2));
 
         assert_eq!(state.registers.read(R1), 3 & 5);
+        assert_eq!(state.condition, Condition::P);
+
+        execute(&mut state, ANDIMM(0, R1, R1));
+        assert_eq!(state.condition, Condition::Z);
+        state.registers.write(R2, 0x8000);
+        execute(&mut state, AND(R1, R2, R2));
+        assert_eq!(state.condition, Condition::N);
     }
 
     #[test]
@@ -732,7 +846,7 @@ mod tests {
         state.registers.write(R2, 1);
         state.memory.write(1 + 3, 42);
 
-        state = execute(state, LDR(R1, R2, 3));
+        execute(&mut state, LDR(R1, R2, 3));
 
         assert_eq!(state.registers.read(R1), 42);
         assert_eq!(state.condition, Condition::P);
@@ -744,9 +858,9 @@ mod tests {
         state.registers.write(R1, 42);
         state.registers.write(R2, 2);
 
-        state = execute(state, STR(R1, R2, 3));
+        execute(&mut state, STR(R1, R2, 3));
 
-        assert_eq!(state.memory.read(2 + 3), 42);
+        assert_eq!(state.memory.peek(2 + 3), 42);
     }
 
     #[test]
@@ -755,7 +869,7 @@ mod tests {
         let a = 0b11111111_11010110; // -42
         state.registers.write(R2, a);
 
-        state = execute(state, NOT(R1, R2));
+        execute(&mut state, NOT(R1, R2));
 
         assert_eq!(state.registers.read(R1), !a);
         assert_eq!(state.registers.read(R1), 0b00000000_00101001);
@@ -769,16 +883,16 @@ mod tests {
         state.registers.write(R1, 42);
         state.memory.write(state.pc + 1 + 2, address);
 
-        state = execute(state, STI(R1, 2));
+        execute(&mut state, STI(R1, 2));
 
-        assert_eq!(state.memory.read(address), 42);
+        assert_eq!(state.memory.peek(address), 42);
     }
 
     #[test]
     fn process_lea() {
         let mut state = new_state();
 
-        state = execute(state, LEA(R1, 2));
+        execute(&mut state, LEA(R1, 2));
 
         assert_eq!(state.registers.read(R1), 0x3000 + 1 + 2);
     }
@@ -787,9 +901,96 @@ mod tests {
     fn process_trap_halt() {
         let mut state = new_state();
 
-        state = execute(state, TRAP(TrapVector::HALT));
+        execute(&mut state, TRAP(TrapVector::HALT));
 
-        assert_eq!(state.running, false);
+        assert!(!state.running);
+    }
+
+    #[test]
+    fn process_trap_output() {
+        let mut state = new_state();
+        let console = BufferConsole::default();
+        state.console.replace(Box::new(console.clone()));
+        state.registers.write(R0, u16::from(b'!'));
+        execute(&mut state, TRAP(TrapVector::OUT));
+
+        state.memory.write(0x4000, u16::from(b'H'));
+        state.memory.write(0x4001, u16::from(b'i'));
+        state.registers.write(R0, 0x4000);
+        execute(&mut state, TRAP(TrapVector::PUTS));
+
+        assert_eq!(console.output(), b"!Hi");
+    }
+
+    #[test]
+    fn process_trap_putsp_and_wrapping_puts() {
+        let mut state = new_state();
+        let console = BufferConsole::default();
+        state.console.replace(Box::new(console.clone()));
+
+        state.memory.write(0x4000, u16::from_le_bytes(*b"He"));
+        state.memory.write(0x4001, u16::from_le_bytes(*b"y!"));
+        state.memory.write(0x4002, u16::from(b'?'));
+        state.registers.write(R0, 0x4000);
+        execute(&mut state, TRAP(TrapVector::PUTSP));
+        assert_eq!(console.take_output(), b"Hey!?");
+
+        // A string running off the end of memory carries on from x0000.
+        state.memory.write(0xffff, u16::from(b'a'));
+        state.memory.write(0x0000, u16::from(b'b'));
+        state.registers.write(R0, 0xffff);
+        execute(&mut state, TRAP(TrapVector::PUTS));
+        assert_eq!(console.output(), b"ab");
+    }
+
+    #[test]
+    fn process_trap_in() {
+        let mut state = new_state();
+        let console = BufferConsole::new(b"x");
+        state.console.replace(Box::new(console.clone()));
+
+        execute(&mut state, TRAP(TrapVector::IN));
+        assert_eq!(state.registers.read(R0), u16::from(b'x'));
+        assert_eq!(console.output(), b"Enter a character: x");
+    }
+
+    #[test]
+    fn process_trap_getc() {
+        let mut state = new_state();
+        let console = BufferConsole::new(b"A");
+        state.console.replace(Box::new(console.clone()));
+        state.registers.write(R0, 0xffff);
+
+        execute(&mut state, TRAP(TrapVector::GETC));
+        assert_eq!(state.registers.read(R0), u16::from(b'A'));
+        assert_eq!(state.pc, 0x3001);
+
+        // Nothing left to read, so the TRAP waits to be executed again.
+        execute(&mut state, TRAP(TrapVector::GETC));
+        assert_eq!(state.pc, 0x3001);
+        console.push_input(b"B");
+        execute(&mut state, TRAP(TrapVector::GETC));
+        assert_eq!(state.registers.read(R0), u16::from(b'B'));
+        assert!(console.output().is_empty());
+    }
+
+    #[test]
+    fn process_trap_echo() {
+        let mut state = new_state();
+        let console = BufferConsole::new(b"abcd");
+        state.console.replace(Box::new(console.clone()));
+
+        // IN already echoes, so turning echo on doesn't repeat its key.
+        state.console.set_echo(Echo::On);
+        execute(&mut state, TRAP(TrapVector::GETC));
+        execute(&mut state, TRAP(TrapVector::IN));
+        assert_eq!(console.take_output(), b"aEnter a character: b");
+
+        state.console.set_echo(Echo::Off);
+        execute(&mut state, TRAP(TrapVector::GETC));
+        execute(&mut state, TRAP(TrapVector::IN));
+        assert_eq!(state.registers.read(R0), u16::from(b'd'));
+        assert_eq!(console.output(), b"Enter a character: ");
     }
 
     #[test]
@@ -802,6 +1003,13 @@ mod tests {
         assert_eq!(sign_extend(0b10101, 5), 0b1111_1111_1111_0101);
     }
 
+    // A machine with `source` assembled and loaded, at its first instruction.
+    fn vm(source: &str) -> Vm {
+        let mut vm = Vm::new();
+        vm.load_assembly(source).unwrap();
+        vm
+    }
+
     fn new_state() -> State {
         let mut state = State::new();
         state.pc = 0x3000;
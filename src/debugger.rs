@@ -1,75 +1,517 @@
-use crate::instruction::Instruction;
-use crate::state::State;
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::net::TcpListener;
+mod command_history;
+#[cfg(feature = "dap")]
+mod dap;
+mod delta;
+mod expression;
+mod format;
+mod transport;
+#[cfg(feature = "tui")]
+mod tui;
+
+use crate::asm;
+use crate::disasm;
+use crate::file;
+use crate::instruction::{Instruction, Register, TrapVector};
+use crate::interrupt;
+use crate::log;
+use crate::state::call_stack::CallStack;
+use crate::state::history::{self, History};
+use crate::state::undo::UndoLog;
+use crate::state::{HookAction, PreStepHook, State, StepResult, TrapMode};
+use crate::symbols::SymbolTable;
+use crate::vm::{self, throttle::Throttle};
+use command_history::CommandHistory;
+use delta::Snapshot;
+use format::Format;
+use std::convert::TryFrom;
+use std::error::Error;
+#[cfg(unix)]
+use std::fs;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::num::IntErrorKind;
+use std::ops::{Bound, Range};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use transport::{Listener, Transport};
+
+/// The address the TCP debugger listens on unless told otherwise.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:6379";
+
+/// How the debugger talks to its client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugTransport {
+    /// Listen for a single client on the given address. Port 0 picks a free port.
+    Tcp(SocketAddr),
+    /// Listen on a Unix domain socket at the given path, which is removed afterwards. Unix only.
+    Socket(PathBuf),
+    /// Read commands from STDIN and write responses to STDERR.
+    Local,
+    /// Listen for a single Debug Adapter Protocol client on the given address.
+    #[cfg(feature = "dap")]
+    Dap(SocketAddr),
+    /// Drive the debugger from a terminal UI.
+    #[cfg(feature = "tui")]
+    Tui,
+}
 
 pub struct Debugger {
     debug_continue: bool,
+    single_step: bool,
     break_address: Option<u16>,
+    breakpoints: Vec<u16>,
+    // Break before any TRAP (None) or one with a specific vector.
+    trap_breakpoints: Vec<Option<u8>>,
+    // The most recently executed instructions, recorded by the pre-step hook while resumed.
+    history: Option<Arc<Mutex<History>>>,
+    // Why the last resume stopped, when there's more to say than the PC.
+    stop_report: Option<String>,
+    symbols: SymbolTable,
+    // The program files loaded at startup, reloaded on reset.
+    programs: Vec<PathBuf>,
+    // The OS image loaded under them, if any.
+    os: Option<Vec<u8>>,
+    command_history: CommandHistory,
+    format: Format,
+    // Report changed registers each time execution stops.
+    auto_delta: bool,
+    // Set by `detach`, the session ends and the program carries on without the debugger.
+    detached: bool,
+    // Raised by Ctrl-C to pause a running program.
+    interrupt: &'static AtomicBool,
+    // Instructions per second while the program runs, unthrottled when `None`.
+    hz: Option<u64>,
+    // Time spent running the program since the last reset, not counting time stopped.
+    run_time: Duration,
+    // Instructions run between checks for Ctrl-C when nothing else needs checking after each.
+    batch_size: u32,
 }
 
+// What `find` searches memory for.
+#[derive(PartialEq, Debug)]
+enum Needle {
+    Value(u16),
+    // One character per word, as PUTS expects.
+    Text(String),
+}
+
+// How many matching addresses `find` prints before summarising the rest.
+const FIND_LIMIT: usize = 50;
+
 #[derive(PartialEq, Debug)]
 enum Command {
     Continue,
+    Step,
     Registers,
     Flags,
     Disassemble,
-    Read(u16),
-    BreakAddress(u16),
+    DisassembleAt(String, u16),
+    Read(String),
+    BreakAddress(String),
+    Break(String),
+    BreakTrap(Option<u8>),
+    Breakpoints,
+    Delete(usize),
+    Until(String),
+    Symbols,
+    Backtrace,
+    History(usize),
+    SetHistorySize(usize),
+    ReverseStep,
+    SetReverseDepth(usize),
+    SetAutoDelta(bool),
+    LoadSymbols(String),
+    SetRegister(Register, u16, bool),
+    SetPc(String),
+    Jump(String),
+    Dump(String, String),
+    Find(Needle, String, String),
+    SetFormat(Format),
+    Print(String),
+    Patch(String, String),
+    List(u16),
     Info,
     Help,
+    Reset,
+    Commands,
     Exit,
+    Detach,
     Unknown(String),
     Error(String),
 }
 
 impl Debugger {
-    pub fn new() -> Self {
+    pub fn new(programs: Vec<PathBuf>, symbols: SymbolTable) -> Self {
         Debugger {
             debug_continue: false,
+            single_step: false,
             break_address: None,
+            breakpoints: Vec::new(),
+            trap_breakpoints: Vec::new(),
+            history: None,
+            stop_report: None,
+            symbols,
+            programs,
+            os: None,
+            command_history: CommandHistory::new(),
+            format: Format::default(),
+            auto_delta: true,
+            detached: false,
+            interrupt: &interrupt::REQUESTED,
+            hz: None,
+            run_time: Duration::ZERO,
+            batch_size: vm::DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// The OS image the programs were loaded over, see [`crate::Vm::load_os`], loaded again on
+    /// reset.
+    pub fn set_os(&mut self, image: Vec<u8>) {
+        self.os = Some(image);
+    }
+
+    /// Paces the program to about `hz` instructions a second whenever it runs, as `--hz` does
+    /// outside the debugger. Stepping is unaffected.
+    pub fn throttle(&mut self, hz: Option<u64>) {
+        self.hz = hz.filter(|hz| *hz > 0);
+    }
+
+    /// How many instructions run between checks for Ctrl-C while nothing could stop the program
+    /// at a particular one, see [`crate::Vm::set_batch_size`].
+    pub fn set_batch_size(&mut self, batch_size: u32) {
+        self.batch_size = batch_size.max(1);
+    }
+
+    /// How long the program has run for, not counting time stopped in the debugger.
+    pub fn run_time(&self) -> Duration {
+        self.run_time
+    }
+
+    /// Runs `script` first if there is one, then hands over to `transport`. Without a transport
+    /// the script is all there is: a failing command is an error and the session ends with it.
+    pub fn run(
+        &mut self,
+        mut state: State,
+        transport: Option<DebugTransport>,
+        script: Option<&str>,
+    ) -> io::Result<State> {
+        state.call_stack = Some(CallStack::new());
+        self.history = Some(Arc::new(Mutex::new(History::default())));
+        state.undo = Some(UndoLog::default());
+
+        if let Some(path) = script {
+            let file = File::open(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("Unable to open {}: {}", path, e)))?;
+            let mut script = transport::Script::new(BufReader::new(file));
+            state = self.session(state, &mut script);
+
+            if let Some(error) = script.error {
+                let message = format!("{} {}", path, error);
+                if transport.is_none() {
+                    return Err(io::Error::other(message));
+                }
+                eprintln!("{}", message);
+            }
+        }
+
+        let transport = match transport {
+            Some(transport) if state.running && !self.detached => transport,
+            _ => return self.finish_detached(state),
+        };
+
+        let state = match transport {
+            DebugTransport::Tcp(address) => {
+                let listener = bind(address)?;
+                self.listen(state, listener)?
+            }
+            #[cfg(unix)]
+            DebugTransport::Socket(path) => {
+                let listener = UnixListener::bind(&path).map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("Unable to bind to {}: {}", path.display(), e),
+                    )
+                })?;
+                // Only the user running the VM gets to drive it.
+                let permissions = fs::set_permissions(&path, fs::Permissions::from_mode(0o600));
+                let result = permissions.and_then(|()| self.listen(state, listener));
+                fs::remove_file(&path)?;
+                result?
+            }
+            #[cfg(not(unix))]
+            DebugTransport::Socket(path) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "Unable to bind to {}: Unix domain sockets aren't supported here",
+                        path.display()
+                    ),
+                ));
+            }
+            DebugTransport::Local => self.session(state, &mut transport::Local),
+            #[cfg(feature = "dap")]
+            DebugTransport::Dap(address) => {
+                let listener = bind(address)?;
+                log::note(format_args!(
+                    "Waiting for DAP client on {}...",
+                    listener.local_addr()?
+                ));
+
+                let (stream, client) = listener.accept()?;
+                log::note(format_args!("DAP client connected: {:?}", client));
+                self.dap_session(state, &stream)?
+            }
+            #[cfg(feature = "tui")]
+            DebugTransport::Tui => self.tui_session(state)?,
+        };
+
+        self.finish_detached(state)
+    }
+
+    // After `detach` the program runs to completion on its own, without any of the debugger's
+    // tracking or break checks. The listener is gone by now, so there's no re-attaching.
+    fn finish_detached(&mut self, mut state: State) -> io::Result<State> {
+        if !self.detached {
+            return Ok(state);
+        }
+
+        state.call_stack = None;
+        state.undo = None;
+        self.interrupt.store(false, Ordering::SeqCst);
+        let started = Instant::now();
+        let result = self.run_detached(&mut state);
+        self.run_time += started.elapsed();
+
+        result.map(|()| state)
+    }
+
+    fn run_detached(&self, state: &mut State) -> io::Result<()> {
+        let mut throttle = self.hz.map(Throttle::new);
+
+        while state.running {
+            if self.interrupt.load(Ordering::Relaxed) {
+                return Err(io::Error::other(format!(
+                    "Interrupted at PC {:#06x}",
+                    state.pc
+                )));
+            }
+            for _ in 0..self.batch_size {
+                if let Some(throttle) = &mut throttle {
+                    throttle.take();
+                }
+                let result = state.step();
+                if let StepResult::Error(e) = result {
+                    return Err(io::Error::other(e));
+                }
+                if !state.running {
+                    break;
+                }
+            }
         }
+
+        Ok(())
     }
 
-    pub fn step(&mut self, mut state: State) {
-        let listener = TcpListener::bind("127.0.0.1:6379").expect("unable to bind to port 6379");
+    // Waits for a client on an already bound listener and runs the session with it. When the
+    // client goes away the program stays paused until the next one connects.
+    fn listen<L: Listener>(&mut self, mut state: State, listener: L) -> io::Result<State>
+    where
+        for<'a> &'a L::Stream: Read + Write,
+    {
+        let address = listener.address()?;
+        log::note(format_args!("Waiting for connection on {}...", address));
+
+        loop {
+            let (stream, client) = listener.accept()?;
+            log::note(format_args!("Debug client connected: {}", client));
+
+            state = self.session(state, &mut transport::Stream::new(&stream, &stream));
+            if !state.running || self.detached {
+                return Ok(state);
+            }
 
-        eprintln!("Waiting for connection...");
+            log::note(format_args!(
+                "Client disconnected, waiting for reconnection at {}",
+                address
+            ));
+        }
+    }
 
-        match listener.accept() {
-            Ok((stream, address)) => {
-                eprintln!("Debug client connected: {:?}", address);
+    // Reads and handles commands until the program stops running or the client goes away, in
+    // which case the returned state is still running.
+    fn session(&mut self, mut state: State, transport: &mut impl Transport) -> State {
+        while state.running && !self.detached {
+            let mut line = String::new();
+            let command = match transport.read_line(&mut line) {
+                Ok(0) | Err(_) => return state,
+                Ok(_) => self.parse_line(&line),
+            };
 
-                let mut should_break = true;
-                while state.running {
-                    while state.running && !self.debug_continue && should_break {
-                        self.debug_continue = false;
+            let response = self.execute(&mut state, command);
+            // The program's output comes before what the debugger says about it.
+            state.console.lock().flush();
+            let written = match response {
+                Ok(response) => transport.write_response(&response),
+                Err(message) => transport.write_error(&message),
+            };
 
-                        let mut line = String::new();
-                        let command = match BufReader::new(&stream).read_line(&mut line) {
-                            Ok(_) => parse(line.trim().as_ref()),
-                            Err(_) => Command::Error("Unable to read line".to_string()),
-                        };
+            if written.is_err() {
+                return state;
+            }
 
-                        let response = self.handle_command(&mut state, command);
+            if self.debug_continue {
+                self.debug_continue = false;
+                self.interrupt.store(false, Ordering::SeqCst);
+                state = self.resume(state);
 
-                        BufWriter::new(&stream)
-                            .write_all(format!("{}\n", response).as_bytes())
-                            .expect("unable to write to socket");
+                if let Some(report) = self.stop_report.take() {
+                    if transport.write_response(&report).is_err() {
+                        return state;
                     }
+                }
+            }
+        }
+
+        state
+    }
+
+    // Turns an input line into a command, repeating previous commands for empty lines and `!n`.
+    fn parse_line(&mut self, line: &str) -> Command {
+        match self.command_history.expand(line) {
+            Ok(line) => parse(&line),
+            Err(message) => Command::Error(message),
+        }
+    }
 
-                    self.debug_continue = false;
+    // Executes at least one instruction, then keeps going until a break condition is met, the
+    // program halts or Ctrl-C is pressed. The interrupt stays raised while paused, so pressing it
+    // again at the prompt exits.
+    fn resume(&mut self, mut state: State) -> State {
+        let before = Snapshot::of(&state);
+        state.pre_step = self.pre_step_hook();
+        // Even when stopped on a TRAP breakpoint, the first instruction goes ahead.
+        state.paused = state.pre_step.is_some();
+        let mut throttle = self.hz.map(Throttle::new);
+        let started = Instant::now();
+        let batch = if self.stops_precisely() {
+            1
+        } else {
+            self.batch_size
+        };
 
-                    state = state.step();
+        loop {
+            let mut result = StepResult::Continue;
+            for _ in 0..batch {
+                if let Some(throttle) = &mut throttle {
+                    throttle.take();
+                }
+                result = state.step();
+                if !state.running || matches!(result, StepResult::Breakpoint | StepResult::Error(_))
+                {
+                    break;
+                }
+            }
 
-                    should_break = self.should_break(state.pc);
+            let stop = match result {
+                StepResult::Breakpoint => Some(format!(
+                    "Stopped at TRAP {}, PC {:#06x}",
+                    trap_name(state.memory.peek(state.pc) as u8),
+                    state.pc
+                )),
+                StepResult::Error(e) => Some(e.to_string()),
+                _ => None,
+            };
+            let interrupted = self.interrupt.load(Ordering::Relaxed);
+            if !state.running
+                || std::mem::take(&mut self.single_step)
+                || stop.is_some()
+                || self.should_break(&state)
+                || interrupted
+            {
+                self.run_time += started.elapsed();
+                state.pre_step = None;
+                if let Some(stop) = stop {
+                    self.report(stop);
+                }
+                if interrupted {
+                    self.report(format!("Interrupted at PC {:#06x}", state.pc));
                 }
+                if self.auto_delta {
+                    if let Some(delta) = delta::delta(&before, &Snapshot::of(&state)) {
+                        self.report(delta);
+                    }
+                }
+
+                return state;
             }
-            Err(e) => eprintln!("Couldn't get client: {:?}", e),
         }
     }
 
-    fn should_break(&mut self, pc: u16) -> bool {
+    // Records the history and stops before TRAPs with a breakpoint, when either is wanted.
+    fn pre_step_hook(&self) -> Option<PreStepHook> {
+        if self.history.is_none() && self.trap_breakpoints.is_empty() {
+            return None;
+        }
+
+        let history = self.history.clone();
+        let trap_breakpoints = self.trap_breakpoints.clone();
+
+        Some(Box::new(move |state, word, instruction| {
+            let pause = match instruction {
+                Instruction::TRAP(trap_vector) => {
+                    let vector = trap_vector.vector();
+                    // A paused step executes regardless.
+                    !state.paused
+                        && trap_breakpoints
+                            .iter()
+                            .any(|filter| filter.is_none_or(|filter| filter == vector))
+                }
+                _ => false,
+            };
+            if pause {
+                return HookAction::Pause;
+            }
+
+            if let Some(history) = &history {
+                let mut history = history.lock().unwrap();
+                history.record(state.pc, word, instruction.clone());
+            }
+            HookAction::Continue
+        }))
+    }
+
+    fn report(&mut self, message: String) {
+        self.stop_report = Some(match self.stop_report.take() {
+            Some(report) => format!("{}\n{}", report, message),
+            None => message,
+        });
+    }
+
+    // Whether `should_break` has to be checked after every instruction rather than once a batch.
+    // Only TRAP breakpoints can be left to the batch, they stop the step itself through the
+    // pre-step hook.
+    fn stops_precisely(&self) -> bool {
+        let only_trap_breakpoints = !self.trap_breakpoints.is_empty()
+            && self.breakpoints.is_empty()
+            && self.break_address.is_none();
+
+        self.single_step || !only_trap_breakpoints
+    }
+
+    // Without any breakpoints set, continuing executes a single instruction.
+    fn should_break(&mut self, state: &State) -> bool {
+        let pc = state.pc;
+        if self.breakpoints.contains(&pc) {
+            return true;
+        }
+
         match self.break_address {
             Some(break_address) => {
                 if break_address == pc {
@@ -79,106 +521,655 @@ impl Debugger {
                     false
                 }
             }
-            None => true,
+            None => self.breakpoints.is_empty() && self.trap_breakpoints.is_empty(),
+        }
+    }
+
+    // Reloads the programs into a fresh machine. Debugger tracking stays enabled, but starts over.
+    fn reset(&mut self, state: &mut State) -> Result<(), Box<dyn Error>> {
+        let mut fresh = State::new();
+        if let Some(os) = &self.os {
+            let mut rom = file::from_bytes(os)?;
+            fresh.load_rom(&mut rom).map_err(|e| e.to_string())?;
+            fresh.trap_mode = TrapMode::Os;
+        }
+        for program in &self.programs {
+            let mut rom = file::read_rom(program)?;
+            fresh.load_rom(&mut rom).map_err(|e| e.to_string())?;
+        }
+
+        if state.call_stack.is_some() {
+            fresh.call_stack = Some(CallStack::new());
+        }
+        if let Some(history) = &self.history {
+            let mut history = history.lock().unwrap();
+            *history = History::new(history.capacity());
+        }
+        if let Some(undo) = &state.undo {
+            fresh.undo = Some(UndoLog::new(undo.depth()));
+        }
+
+        *state = fresh;
+        self.run_time = Duration::ZERO;
+
+        Ok(())
+    }
+
+    // Evaluates an address expression against the current state.
+    fn resolve(&self, state: &State, expression: &str) -> Result<u16, String> {
+        expression::evaluate(expression, state, &self.symbols)
+    }
+
+    // The addresses a `dump` or `find` covers, up to `end` but not including it. `end` is either an address
+    // or `+count` words past `start`, which can reach the end of memory.
+    fn address_range(&self, state: &State, start: &str, end: &str) -> Result<Range<u32>, String> {
+        let start = self.resolve(state, start)?;
+        let end = match end.strip_prefix('+') {
+            Some(count) => u32::from(start) + u32::from(self.resolve(state, count)?),
+            None => u32::from(self.resolve(state, end)?),
+        };
+
+        if end < u32::from(start) {
+            Err(format!(
+                "End address {:#06x} is below start address {:#06x}",
+                end, start
+            ))
+        } else if end > 0x10000 {
+            Err("Range crosses the end of memory (0xffff)".to_string())
+        } else {
+            Ok(u32::from(start)..end)
         }
     }
 
-    fn handle_command(&mut self, state: &mut State, command: Command) -> String {
-        match command {
+    fn all_breakpoints(&self) -> Vec<u16> {
+        self.breakpoints
+            .iter()
+            .cloned()
+            .chain(self.break_address)
+            .collect()
+    }
+
+    // The registers in the current format, `per_line` to a line.
+    fn registers(&self, state: &State, per_line: usize) -> String {
+        let registers: Vec<(Register, u16)> = state.registers.iter().collect();
+
+        registers
+            .chunks(per_line)
+            .map(|line| {
+                line.iter()
+                    .map(|(register, value)| {
+                        format!("{:?}: {}", register, self.format.value(*value))
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // The instruction at the PC, read without device side effects.
+    fn next_instruction(&self, state: &State) -> String {
+        disasm::disassemble_word(state.pc, state.memory.peek(state.pc), &self.symbols)
+    }
+
+    // Numbered breakpoints, addresses first, as `delete` expects.
+    fn breakpoint_list(&self) -> Vec<String> {
+        let addresses = self
+            .breakpoints
+            .iter()
+            .map(|address| format!("{:#06x}", address));
+        let traps = self.trap_breakpoints.iter().map(|filter| match filter {
+            Some(vector) => format!("TRAP {}", trap_name(*vector)),
+            None => "TRAP (any)".to_string(),
+        });
+
+        addresses
+            .chain(traps)
+            .enumerate()
+            .map(|(i, breakpoint)| format!("{}: {}", i + 1, breakpoint))
+            .collect()
+    }
+
+    // Failures are returned as errors so scripts can stop at them, most front ends just show the
+    // message either way.
+    fn execute(&mut self, state: &mut State, command: Command) -> Result<String, String> {
+        Ok(match command {
             Command::Continue => {
                 self.debug_continue = true;
                 format!("PC {:#04x}", state.pc)
             }
 
-            Command::Flags => format!("{:?}", state.condition),
+            Command::Step => {
+                self.debug_continue = true;
+                self.single_step = true;
+                format!("PC {:#04x}", state.pc)
+            }
+
+            Command::Flags => flags(state),
 
-            Command::Registers => state
-                .registers()
-                .iter()
-                .enumerate()
-                .map(|(i, register)| format!("R{}: {:#04x}", i, register))
-                .collect::<Vec<String>>()
-                .join("\n"),
+            Command::Registers => self.registers(state, 1),
 
+            // With the raw offset of a PC-relative operand as well as its target.
             Command::Disassemble => {
-                let instruction = state.memory.read(state.pc);
+                let word = state.memory.peek(state.pc);
+                let text = disasm::disassemble_word(state.pc, word, &self.symbols);
+                let text = match disasm::pc_offset(word) {
+                    Some(offset) => format!("{} (#{})", text, offset),
+                    None => text,
+                };
+
+                format!("{}, {:08b}_{:08b}", text, (word >> 8) & 0xff, word & 0xff)
+            }
+
+            Command::DisassembleAt(address, count) => {
+                let address = self.resolve(state, &address)?;
+                let breakpoints = self.all_breakpoints();
+                let end = u32::from(address) + u32::from(count.max(1)) - 1;
+                let range = address..=(end.min(u32::from(u16::MAX)) as u16);
+                let listing =
+                    disasm::listing(&state.memory, state.pc, range, &breakpoints, &self.symbols);
 
-                format!(
-                    "{:?}, {:08b}_{:08b}",
-                    Instruction::decode(instruction),
-                    (instruction >> 8) & 0xff,
-                    instruction & 0xff
-                )
+                if end > u32::from(u16::MAX) {
+                    format!("{}\nReached the end of memory", listing)
+                } else {
+                    listing
+                }
             }
 
-            Command::Read(address) => {
-                let value = state.memory.read(address);
-                format!("{:#04x}, {:#016b}", value, value)
+            Command::Read(address) => match self.resolve(state, &address) {
+                Ok(address) => {
+                    let value = state.memory.peek(address);
+                    format!("{}, {:#018b}", self.format.value(value), value)
+                }
+                Err(message) => return Err(message),
+            },
+
+            Command::Dump(start, end) => {
+                let range = self.address_range(state, &start, &end)?;
+                dump(state, range, self.format)
             }
 
-            Command::BreakAddress(address) => {
-                self.break_address = Some(address);
-                format!("Break address set to {:#04x}", address)
+            Command::Find(needle, start, end) => {
+                let range = self.address_range(state, &start, &end)?;
+                find(state, &needle, range)
             }
 
-            Command::Info => {
-                let instruction = Instruction::decode(state.memory.read(state.pc));
-                let registers = state
-                    .registers()
+            Command::SetFormat(format) => {
+                self.format = format;
+                format!("Format set to {:?}", format)
+            }
+
+            Command::Print(operand) => match self.resolve(state, &operand) {
+                Ok(value) => format::describe(value),
+                Err(message) => return Err(message),
+            },
+
+            Command::Patch(address, statement) => match self.resolve(state, &address) {
+                Ok(address) => match asm::parse_instruction(&statement, address, &self.symbols) {
+                    Ok(instruction) => {
+                        let old = state.memory.peek(address);
+                        let new = instruction.encode();
+                        state.memory.write(address, new);
+
+                        format!(
+                            "{:#06x}: {} -> {}",
+                            address,
+                            disasm::disassemble_word(address, old, &self.symbols),
+                            disasm::disassemble_word(address, new, &self.symbols)
+                        )
+                    }
+                    Err(message) => return Err(message),
+                },
+                Err(message) => return Err(message),
+            },
+
+            Command::List(n) => {
+                let breakpoints = self.all_breakpoints();
+                let range = state.pc.saturating_sub(n)..=state.pc.saturating_add(n);
+
+                disasm::listing(&state.memory, state.pc, range, &breakpoints, &self.symbols)
+            }
+
+            Command::BreakAddress(address) => match self.resolve(state, &address) {
+                Ok(address) => {
+                    self.break_address = Some(address);
+                    format!("Break address set to {:#04x}", address)
+                }
+                Err(message) => return Err(message),
+            },
+
+            Command::SetPc(address) => {
+                let address = self.resolve(state, &address)?;
+                let previous = state.pc;
+                state.pc = address;
+
+                match illegal_opcode_warning(state, address) {
+                    Some(warning) => {
+                        format!("PC {:#06x} -> {:#06x}\n{}", previous, address, warning)
+                    }
+                    None => format!("PC {:#06x} -> {:#06x}", previous, address),
+                }
+            }
+
+            Command::Jump(address) => {
+                let address = self.resolve(state, &address)?;
+                state.pc = address;
+                self.debug_continue = true;
+
+                match illegal_opcode_warning(state, address) {
+                    Some(warning) => format!("PC {:#04x}\n{}", state.pc, warning),
+                    None => format!("PC {:#04x}", state.pc),
+                }
+            }
+
+            Command::SetRegister(register, value, update_flags) => {
+                let previous = state.registers[register];
+                state.registers[register] = value;
+
+                if update_flags {
+                    state.update_flags(register);
+                    format!(
+                        "{:?}: {:#06x} -> {:#06x}, Flags: {:?}",
+                        register, previous, value, state.condition
+                    )
+                } else {
+                    format!("{:?}: {:#06x} -> {:#06x}", register, previous, value)
+                }
+            }
+
+            Command::Break(location) => match self.resolve(state, &location) {
+                Ok(address) => {
+                    if !self.breakpoints.contains(&address) {
+                        self.breakpoints.push(address);
+                    }
+                    format!("Breakpoint set at {:#06x}", address)
+                }
+                Err(message) => return Err(message),
+            },
+
+            Command::SetAutoDelta(enabled) => {
+                self.auto_delta = enabled;
+                format!("Auto delta {}", if enabled { "on" } else { "off" })
+            }
+
+            Command::BreakTrap(filter) => {
+                if !self.trap_breakpoints.contains(&filter) {
+                    self.trap_breakpoints.push(filter);
+                }
+                match filter {
+                    Some(vector) => format!("Breakpoint set on TRAP {}", trap_name(vector)),
+                    None => "Breakpoint set on all TRAPs".to_string(),
+                }
+            }
+
+            Command::Breakpoints => {
+                let breakpoints = self.breakpoint_list();
+                if breakpoints.is_empty() {
+                    "No breakpoints".to_string()
+                } else {
+                    breakpoints.join("\n")
+                }
+            }
+
+            // Numbered as in the `breakpoints` listing, addresses first.
+            Command::Delete(n) => {
+                let index = n.wrapping_sub(1);
+                if index < self.breakpoints.len() {
+                    self.breakpoints.remove(index);
+                    format!("Deleted breakpoint {}", n)
+                } else if index - self.breakpoints.len() < self.trap_breakpoints.len() {
+                    self.trap_breakpoints.remove(index - self.breakpoints.len());
+                    format!("Deleted breakpoint {}", n)
+                } else {
+                    return Err(format!("No breakpoint {}", n));
+                }
+            }
+
+            Command::Until(location) => match self.resolve(state, &location) {
+                Ok(address) => {
+                    self.break_address = Some(address);
+                    self.debug_continue = true;
+                    format!("Continuing until {:#06x}", address)
+                }
+                Err(message) => return Err(message),
+            },
+
+            Command::Symbols => {
+                if self.symbols.is_empty() {
+                    return Ok("No symbols loaded".to_string());
+                }
+
+                self.symbols
                     .iter()
+                    .map(|(name, address)| format!("{:#06x} {}", address, name))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+
+            Command::Backtrace => match &state.call_stack {
+                Some(call_stack) if call_stack.is_empty() => {
+                    "No active subroutine calls".to_string()
+                }
+                Some(call_stack) => call_stack
+                    .frames()
                     .enumerate()
-                    .map(|(i, register)| format!("R{}: {:#04x}", i, register))
+                    .map(|(i, frame)| {
+                        format!(
+                            "#{} {} called from {}, returns to {}{}",
+                            i,
+                            self.symbols.format_address(frame.entry),
+                            self.symbols.format_address(frame.call_site),
+                            self.symbols.format_address(frame.return_address),
+                            if frame.suspect {
+                                " (suspect, R7 was modified)"
+                            } else {
+                                ""
+                            }
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                None => "Call stack tracking is disabled".to_string(),
+            },
+
+            Command::History(n) => match &self.history {
+                Some(history) => history
+                    .lock()
+                    .unwrap()
+                    .last(n)
+                    .map(|entry| {
+                        format!(
+                            "x{:04X}  x{:04X}  {}",
+                            entry.pc,
+                            entry.word,
+                            entry.instruction.disassemble(entry.pc, &self.symbols)
+                        )
+                    })
                     .collect::<Vec<String>>()
-                    .join(", ");
-                let break_address = if let Some(a) = self.break_address {
-                    format!(", break-address {:#04x}", a)
+                    .join("\n"),
+                None => "History tracking is disabled".to_string(),
+            },
+
+            Command::SetHistorySize(capacity) => match &self.history {
+                Some(history) => {
+                    let mut history = history.lock().unwrap();
+                    let previous = history.capacity();
+                    history.resize(capacity);
+                    format!("History size: {} -> {}", previous, capacity)
+                }
+                None => "History tracking is disabled".to_string(),
+            },
+
+            Command::ReverseStep => {
+                let delta = match &mut state.undo {
+                    Some(undo) => undo.pop(),
+                    None => return Ok("Reverse stepping is disabled".to_string()),
+                };
+
+                match delta {
+                    Some(delta) => {
+                        delta.restore(state);
+
+                        if delta.irreversible {
+                            format!(
+                                "PC {:#04x}\nWarning: this step consumed keyboard input, \
+                                 executing it again won't replay the same character",
+                                state.pc
+                            )
+                        } else {
+                            format!("PC {:#04x}", state.pc)
+                        }
+                    }
+                    None => "No more history to reverse".to_string(),
+                }
+            }
+
+            Command::SetReverseDepth(depth) => match &mut state.undo {
+                Some(undo) => {
+                    let previous = undo.depth();
+                    undo.resize(depth);
+                    format!("Reverse depth: {} -> {}", previous, depth)
+                }
+                None => "Reverse stepping is disabled".to_string(),
+            },
+
+            Command::LoadSymbols(filename) => match file::read_symbols(&filename) {
+                Ok(symbols) => {
+                    self.symbols = symbols;
+                    format!("Loaded {} symbols", self.symbols.iter().count())
+                }
+                Err(e) => return Err(format!("Unable to load {}", e)),
+            },
+
+            Command::Info => {
+                let pc = match self.symbols.symbolize(state.pc) {
+                    Some(symbol) => format!("{:#06x} ({})", state.pc, symbol),
+                    None => format!("{:#06x}", state.pc),
+                };
+                let mut breakpoints = self.breakpoint_list();
+                if let Some(address) = self.break_address {
+                    breakpoints.push(format!("break-address {:#06x}", address));
+                }
+                let breakpoints = if breakpoints.is_empty() {
+                    "none".to_string()
                 } else {
-                    String::new()
+                    breakpoints.join(", ")
                 };
 
-                format!(
-                    "{:#04x}: {:?}, Flags: {:?}, [{}]{}",
-                    state.pc, instruction, state.condition, registers, break_address
-                )
+                [
+                    format!("PC: {}, {}", pc, self.next_instruction(state)),
+                    format!("Flags: {}", flags(state)),
+                    self.registers(state, 4),
+                    format!(
+                        "Executed: {} instructions in {:.3}s, {:.2} MIPS",
+                        state.instructions,
+                        self.run_time.as_secs_f64(),
+                        vm::mips(state.instructions, self.run_time)
+                    ),
+                    format!("Breakpoints: {}", breakpoints),
+                    format!(
+                        "Status: {}",
+                        if state.running { "running" } else { "halted" }
+                    ),
+                ]
+                .join("\n")
             }
 
             Command::Help => [
                 "c, continue               Continue execution.",
+                "i, info                   Summarise the machine: PC, registers, breakpoints.",
+                "s, step                   Execute a single instruction.",
+                "rs, reverse-step          Step back one instruction.",
                 "r, registers              Print registers.",
                 "f, flags                  Print flags.",
-                "d, disassemble            Disassemble current instruction.",
-                "   read <addr>            Read and display memory address. e.g. read 0x3000",
+                "d, disassemble [addr [n]] Disassemble current instruction, or n at addr.",
+                "   read <addr>            Read and display memory address. e.g. read r6+2",
                 "   break-address <addr>   Break at address. e.g. break-address 0x3000",
+                "   break <addr>           Break at address. e.g. break LOOP, break pc+4",
+                "   until <addr>           Continue until address. e.g. until DONE",
+                concat!(
+                    "   break-trap [vector]    Break before any TRAP, or a specific one. ",
+                    "e.g. break-trap 0x22"
+                ),
+                "   breakpoints            List breakpoints.",
+                "   delete <n>             Delete the nth breakpoint in the listing.",
+                "   symbols                List loaded symbols.",
+                "   bt                     Print the active subroutine calls.",
+                "   history [n]            Print the last n executed instructions. e.g. history 10",
+                "   sym load <file>        Load a symbol table. e.g. sym load program.sym",
+                "   reset                  Reload the program and reset the machine.",
+                "   history-cmd            List the commands entered this session.",
+                "   exit                   Stop the program and exit.",
+                concat!(
+                    "   detach, quit           Leave the program running to completion ",
+                    "without the debugger."
+                ),
+                "   !<n>                   Repeat the nth command. An empty line repeats the last.",
+                concat!(
+                    "   set <reg> <value>      Set a register, --flags updates flags. ",
+                    "e.g. set r3 0x00ff"
+                ),
+                "   set pc <addr>          Set the PC. e.g. set pc 0x3020",
+                concat!(
+                    "   set reverse-depth <n>  Set how many steps can be reversed. ",
+                    "e.g. set reverse-depth 100"
+                ),
+                concat!(
+                    "   set history-size <n>   Set how many instructions history keeps. ",
+                    "e.g. set history-size 128"
+                ),
+                "   set auto-delta on|off  Report changed registers whenever execution stops.",
+                "   jump <addr>            Set the PC and continue. e.g. jump 0x3020",
+                "   dump <addr> <end|+n>   Dump a memory range. e.g. dump 0x3000 +16",
+                "   list [n]               Disassemble n instructions around the PC. e.g. list 10",
+                concat!(
+                    "   format hex|dec|signed  Set how registers and memory are shown. ",
+                    "e.g. format signed"
+                ),
+                "   print <expr>           Show a value in every format. e.g. print r3",
+                concat!(
+                    "   find <value|\"text\"> <addr> <end|+n> Search memory. ",
+                    "e.g. find \"HELLO\" 0x3000 0x5000"
+                ),
+                concat!(
+                    "   patch <addr> <instr>   Assemble an instruction into memory. ",
+                    "e.g. patch pc ADD R1, R1, #1"
+                ),
             ]
             .join("\n"),
 
+            Command::Reset => match self.reset(state) {
+                Ok(()) => format!("Reset, PC {:#04x}", state.pc),
+                Err(e) => return Err(format!("Unable to reset: {}", e)),
+            },
+
+            Command::Commands => self
+                .command_history
+                .lines()
+                .iter()
+                .enumerate()
+                .map(|(i, line)| format!("{}: {}", i + 1, line))
+                .collect::<Vec<String>>()
+                .join("\n"),
+
             Command::Exit => {
                 state.running = false;
                 "Exiting...".to_string()
             }
 
-            Command::Unknown(line) => format!("Unknown command {:?}", line),
+            Command::Detach => {
+                self.detached = true;
+                "Detaching, the program continues without the debugger".to_string()
+            }
+
+            Command::Unknown(line) => return Err(format!("Unknown command {:?}", line)),
 
-            Command::Error(message) => message,
-        }
+            Command::Error(message) => return Err(message),
+        })
     }
 }
 
+fn bind(address: SocketAddr) -> io::Result<TcpListener> {
+    TcpListener::bind(address)
+        .map_err(|e| io::Error::new(e.kind(), format!("Unable to bind to {}: {}", address, e)))
+}
+
 fn parse(line: &str) -> Command {
     match line {
         "c" | "continue" => Command::Continue,
+        "s" | "step" => Command::Step,
         "f" | "flags" => Command::Flags,
         "r" | "registers" => Command::Registers,
+        "rs" | "reverse-step" => Command::ReverseStep,
         "d" | "disassemble" => Command::Disassemble,
         "i" | "info" => Command::Info,
         "h" | "help" => Command::Help,
+        "reset" => Command::Reset,
+        "history-cmd" => Command::Commands,
         "exit" => Command::Exit,
+        "detach" | "quit" => Command::Detach,
+        "list" => Command::List(5),
+        "symbols" => Command::Symbols,
+        "bt" | "backtrace" => Command::Backtrace,
+        "history" => Command::History(history::DEFAULT_CAPACITY),
+        "break-trap" => Command::BreakTrap(None),
+        "breakpoints" => Command::Breakpoints,
         line => {
-            if let Some(address) = parse_hex_after_pattern("read 0x", line) {
-                return Command::Read(address);
+            if let Some(vector) = line.strip_prefix("break-trap ") {
+                return match parse_value(vector.trim()) {
+                    Ok(vector) if vector <= 0xff => Command::BreakTrap(Some(vector as u8)),
+                    Ok(vector) => {
+                        Command::Error(format!("TRAP vector {:#x} is not 8 bits", vector))
+                    }
+                    Err(message) => Command::Error(message),
+                };
+            }
+            if let Some(n) = line.strip_prefix("delete ") {
+                return match parse_value(n.trim()) {
+                    Ok(n) => Command::Delete(usize::from(n)),
+                    Err(message) => Command::Error(message),
+                };
+            }
+            if let Some(location) = line.strip_prefix("break ") {
+                return Command::Break(location.trim().to_string());
+            }
+            if let Some(location) = line.strip_prefix("until ") {
+                return Command::Until(location.trim().to_string());
+            }
+            if let Some(filename) = line.strip_prefix("sym load ") {
+                return Command::LoadSymbols(filename.trim().to_string());
+            }
+            if line.starts_with("set ") {
+                return parse_set(line);
+            }
+            if line.starts_with("d ") || line.starts_with("disassemble ") {
+                return parse_disassemble(line);
+            }
+            if let Some(n) = line.strip_prefix("list ") {
+                return match parse_value(n.trim()) {
+                    Ok(n) => Command::List(n),
+                    Err(message) => Command::Error(message),
+                };
+            }
+            if let Some(n) = line.strip_prefix("history ") {
+                return match parse_value(n.trim()) {
+                    Ok(n) => Command::History(usize::from(n)),
+                    Err(message) => Command::Error(message),
+                };
+            }
+            if let Some(format) = line.strip_prefix("format ") {
+                return match format.trim().parse() {
+                    Ok(format) => Command::SetFormat(format),
+                    Err(message) => Command::Error(message),
+                };
+            }
+            if let Some(operand) = line.strip_prefix("print ") {
+                return Command::Print(operand.trim().to_string());
             }
-            if let Some(address) = parse_hex_after_pattern("break-address 0x", line) {
-                return Command::BreakAddress(address);
+            if let Some(rest) = line.strip_prefix("patch ") {
+                let rest = rest.trim_start();
+                return match rest.find(char::is_whitespace) {
+                    Some(index) => {
+                        let (address, statement) = rest.split_at(index);
+                        Command::Patch(address.to_string(), statement.trim().to_string())
+                    }
+                    None => Command::Error("Expected an address and an instruction".to_string()),
+                };
+            }
+            if line.starts_with("dump ") {
+                return parse_dump(line);
+            }
+            if let Some(args) = line.strip_prefix("find ") {
+                return parse_find(args.trim());
+            }
+            if let Some(address) = line.strip_prefix("jump ") {
+                return Command::Jump(address.trim().to_string());
+            }
+            if let Some(address) = line.strip_prefix("read ") {
+                return Command::Read(address.trim().to_string());
+            }
+            if let Some(address) = line.strip_prefix("break-address ") {
+                return Command::BreakAddress(address.trim().to_string());
             }
 
             Command::Unknown(line.trim().to_string())
@@ -186,34 +1177,1409 @@ fn parse(line: &str) -> Command {
     }
 }
 
-fn parse_hex_after_pattern(pattern: &str, line: &str) -> Option<u16> {
-    if line.starts_with(pattern) {
-        let (_, address) = line.split_at(pattern.len());
-        if !address.is_empty()
-            && address.len() <= 4
-            && address.bytes().all(|b| b.is_ascii_hexdigit())
-        {
-            return Some(u16::from_str_radix(address, 16).expect("unable to parse address"));
+fn flags(state: &State) -> String {
+    format!("{:?}", state.condition)
+}
+
+// The vector's name when it's one of the standard routines, e.g. `x22 (PUTS)`.
+fn trap_name(vector: u8) -> String {
+    match TrapVector::try_decode(u16::from(vector)) {
+        Some(trap_vector) => format!("x{:02X} ({:?})", vector, trap_vector),
+        None => format!("x{:02X}", vector),
+    }
+}
+
+fn illegal_opcode_warning(state: &State, address: u16) -> Option<String> {
+    let opcode = match state.memory.peek(address) >> 12 {
+        0x08 => "UNUSED",
+        0x0d => "RESERVED",
+        _ => return None,
+    };
+
+    Some(format!(
+        "Warning: {:#06x} decodes to {}, executing it will fail",
+        address, opcode
+    ))
+}
+
+fn parse_set(line: &str) -> Command {
+    let mut args: Vec<&str> = line.split_whitespace().skip(1).collect();
+    let update_flags = args.contains(&"--flags");
+    args.retain(|arg| *arg != "--flags");
+
+    match args.as_slice() {
+        ["reverse-depth", depth] => match parse_value(depth) {
+            Ok(depth) => Command::SetReverseDepth(usize::from(depth)),
+            Err(message) => Command::Error(message),
+        },
+        ["auto-delta", "on"] => Command::SetAutoDelta(true),
+        ["auto-delta", "off"] => Command::SetAutoDelta(false),
+        ["history-size", capacity] => match parse_value(capacity) {
+            Ok(capacity) => Command::SetHistorySize(usize::from(capacity)),
+            Err(message) => Command::Error(message),
+        },
+        [register, address] if register.eq_ignore_ascii_case("pc") => {
+            Command::SetPc(address.to_string())
         }
+        [register, value] => {
+            let register = match register.parse::<Register>() {
+                Ok(register) => register,
+                Err(message) => return Command::Error(message),
+            };
+
+            match parse_value(value) {
+                Ok(value) => Command::SetRegister(register, value, update_flags),
+                Err(message) => Command::Error(message),
+            }
+        }
+        _ => Command::Error("Usage: set <register> <value> [--flags]".to_string()),
     }
+}
 
-    None
+fn parse_disassemble(line: &str) -> Command {
+    let args: Vec<&str> = line.split_whitespace().skip(1).collect();
+
+    match args.as_slice() {
+        [address] => Command::DisassembleAt(address.to_string(), 1),
+        [address, count] => match parse_value(count) {
+            Ok(count) => Command::DisassembleAt(address.to_string(), count),
+            Err(message) => Command::Error(message),
+        },
+        _ => Command::Error("Usage: disassemble [addr [count]]".to_string()),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn parse_dump(line: &str) -> Command {
+    let args: Vec<&str> = line.split_whitespace().skip(1).collect();
 
-    #[test]
-    fn test_parse_hex_after_pattern() {
-        for command in vec!["read", "read 0x", "read 0x12345", "read 0x1z", "a read 0x1"] {
-            assert_eq!(parse_hex_after_pattern("read 0x", command), None);
+    match args.as_slice() {
+        [start, end] => Command::Dump(start.to_string(), end.to_string()),
+        _ => Command::Error("Usage: dump <addr> <end|+count>".to_string()),
+    }
+}
+
+fn parse_find(args: &str) -> Command {
+    let usage = || Command::Error("Usage: find <value|\"text\"> <addr> <end|+count>".to_string());
+
+    let (needle, rest) = if let Some(quoted) = args.strip_prefix('"') {
+        match quoted.find('"') {
+            Some(0) => return Command::Error("Search text is empty".to_string()),
+            Some(index) => (
+                Ok(Needle::Text(quoted[..index].to_string())),
+                &quoted[index + 1..],
+            ),
+            None => return Command::Error("Unterminated string in find".to_string()),
         }
+    } else {
+        match args.split_once(char::is_whitespace) {
+            Some((value, rest)) => (parse_value(value).map(Needle::Value), rest),
+            None => return usage(),
+        }
+    };
 
-        assert_eq!(parse_hex_after_pattern("read 0x", "read 0x1"), Some(1));
-        assert_eq!(
-            parse_hex_after_pattern("read 0x", "read 0x1234"),
-            Some(4660)
+    let range: Vec<&str> = rest.split_whitespace().collect();
+    let (start, end) = match range.as_slice() {
+        [start, end] => (start, end),
+        _ => return usage(),
+    };
+
+    match needle {
+        Ok(needle) => Command::Find(needle, start.to_string(), end.to_string()),
+        Err(message) => Command::Error(message),
+    }
+}
+
+// Lists the addresses in `range` where the needle begins. Text may run past its end as long as it
+// starts inside the range. Memory is peeked so device registers aren't consumed.
+fn find(state: &State, needle: &Needle, range: Range<u32>) -> String {
+    let words: Vec<u16> = match needle {
+        Needle::Value(value) => vec![*value],
+        Needle::Text(text) => text.bytes().map(u16::from).collect(),
+    };
+
+    let matches: Vec<u16> = range
+        .map(|address| address as u16)
+        .filter(|&address| {
+            words.iter().enumerate().all(|(i, word)| {
+                u16::try_from(usize::from(address) + i)
+                    .map(|address| state.memory.peek(address) == *word)
+                    .unwrap_or(false)
+            })
+        })
+        .collect();
+
+    if matches.is_empty() {
+        return "No matches".to_string();
+    }
+
+    let mut lines: Vec<String> = matches
+        .iter()
+        .take(FIND_LIMIT)
+        .map(|address| format!("{:#06x}", address))
+        .collect();
+    if matches.len() > FIND_LIMIT {
+        lines.push(format!("... {} more results", matches.len() - FIND_LIMIT));
+    }
+
+    lines.join("\n")
+}
+
+// A hexdump of `range` with the values in `format`, see `Memory::hexdump`.
+fn dump(state: &State, range: Range<u32>, format: Format) -> String {
+    let end = match u16::try_from(range.end) {
+        Ok(end) => Bound::Excluded(end),
+        Err(_) => Bound::Unbounded,
+    };
+
+    state
+        .memory
+        .hexdump_lines_with((Bound::Included(range.start as u16), end), move |word| {
+            format.value(word)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn parse_value(value: &str) -> Result<u16, String> {
+    let result = if let Some(hex) = value.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16)
+    } else {
+        value.parse::<u16>()
+    };
+
+    result.map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow => format!("Value {} does not fit in 16 bits", value),
+        _ => format!(
+            "Invalid value {:?}, expected hex (0x00ff) or decimal",
+            value
+        ),
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+    use crate::state::Condition;
+    use std::thread;
+
+    impl Debugger {
+        fn handle_command(&mut self, state: &mut State, command: Command) -> String {
+            self.execute(state, command)
+                .unwrap_or_else(|message| message)
+        }
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("0x00ff"), Ok(0xff));
+        assert_eq!(parse_value("42"), Ok(42));
+        assert!(parse_value("0x10000").is_err());
+        assert!(parse_value("65536").is_err());
+        assert!(parse_value("-1").is_err());
+        assert!(parse_value("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_set_register() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.registers.write(Register::R3, 1);
+
+        let response = debugger.handle_command(&mut state, parse("set r3 0x00ff"));
+
+        assert_eq!(response, "R3: 0x0001 -> 0x00ff");
+        assert_eq!(state.registers(), [0, 0, 0, 0xff, 0, 0, 0, 0]);
+        assert_eq!(state.condition, Condition::P);
+    }
+
+    #[test]
+    fn test_set_register_decimal_with_flags() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.registers.write(Register::R1, 7);
+
+        let response = debugger.handle_command(&mut state, parse("set R1 0 --flags"));
+
+        assert_eq!(response, "R1: 0x0007 -> 0x0000, Flags: Z");
+        assert_eq!(state.registers(), [0; 8]);
+        assert_eq!(state.condition, Condition::Z);
+    }
+
+    #[test]
+    fn test_set_register_errors() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+
+        for (line, expected) in &[
+            ("set r9 1", "Unknown register \"r9\", expected R0-R7"),
+            ("set r1 0x12345", "Value 0x12345 does not fit in 16 bits"),
+            ("set r1 70000", "Value 70000 does not fit in 16 bits"),
+            ("set r1", "Usage: set <register> <value> [--flags]"),
+        ] {
+            let response = debugger.handle_command(&mut state, parse(line));
+            assert_eq!(&response, expected);
+        }
+
+        assert_eq!(state.registers(), [0; 8]);
+    }
+
+    #[test]
+    fn test_set_pc() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+
+        let response = debugger.handle_command(&mut state, parse("set pc 0x3020"));
+
+        assert_eq!(response, "PC 0x3000 -> 0x3020");
+        assert_eq!(state.pc, 0x3020);
+        assert!(!debugger.debug_continue);
+    }
+
+    #[test]
+    fn test_set_pc_warns_on_illegal_opcode() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.memory.write(0x3020, 0xd000);
+
+        let response = debugger.handle_command(&mut state, parse("set pc 0x3020"));
+
+        assert_eq!(
+            response,
+            "PC 0x0000 -> 0x3020\nWarning: 0x3020 decodes to RESERVED, executing it will fail"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("set pc 0x10000")),
+            "Value 0x10000 does not fit in 16 bits"
+        );
+    }
+
+    #[test]
+    fn test_jump() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.memory.write(0x3020, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+
+        let response = debugger.handle_command(&mut state, parse("jump 0x3020"));
+        assert_eq!(response, "PC 0x3020");
+        assert!(debugger.debug_continue);
+
+        state = debugger.resume(state);
+
+        assert_eq!(state.pc, 0x3021);
+        assert_eq!(state.registers.read(Register::R1), 1);
+    }
+
+    #[test]
+    fn test_jump_onto_break_address() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.memory.write(0x3002, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        state.memory.write(0x3003, 0b0000_111_111111110); // BRnzp #-2
+
+        debugger.handle_command(&mut state, parse("break-address 0x3002"));
+        debugger.handle_command(&mut state, parse("jump 0x3002"));
+        state = debugger.resume(state);
+
+        // The instruction at the break address is executed before breaking on the way back round.
+        assert_eq!(state.pc, 0x3002);
+        assert_eq!(state.registers.read(Register::R1), 1);
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        for (i, c) in "Hello, World!".bytes().enumerate() {
+            state.memory.write(0x3000 + i as u16, u16::from(c));
+        }
+        state.memory.write(0x300e, 0xff41);
+
+        let response = debugger.handle_command(&mut state, parse("dump 0x3000 +20"));
+
+        assert_eq!(
+            response,
+            [
+                "0x3000: 0x0048 0x0065 0x006c 0x006c 0x006f 0x002c 0x0020 0x0057 |Hello, W|",
+                "0x3008: 0x006f 0x0072 0x006c 0x0064 0x0021 0x0000 0xff41 0x0000 |orld!.A.|",
+                "0x3010: 0x0000 0x0000 0x0000 0x0000                             |....|",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("dump 0x3000 0x3014")),
+            response
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.registers.write(Register::R0, 0x8000);
+        state.memory.write(0x3000, 0x8000);
+        state.memory.write(0x3001, 0x0041);
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("format signed")),
+            "Format set to Signed"
+        );
+        assert!(debugger
+            .handle_command(&mut state, parse("registers"))
+            .starts_with("R0: -32768\nR1: 0\n"));
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("read 0x3000")),
+            "-32768, 0b1000000000000000"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("dump 0x3000 +2")),
+            "0x3000: -32768     65                                           |.A|"
+        );
+
+        debugger.handle_command(&mut state, parse("format hex"));
+        assert!(debugger
+            .handle_command(&mut state, parse("registers"))
+            .starts_with("R0: 0x8000\nR1: 0x0000\n"));
+
+        assert_eq!(
+            parse("format octal"),
+            Command::Error("Unknown format \"octal\", expected hex, dec or signed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_address_expressions() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.registers.write(Register::R6, 0x4000);
+        state.memory.write(0x4002, 0x002a);
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("read r6+2")),
+            "0x002a, 0b0000000000101010"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("break pc+4")),
+            "Breakpoint set at 0x3004"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("break-address 0x3002")),
+            "Break address set to 0x3002"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("read r9")),
+            "Unknown identifier \"r9\""
+        );
+    }
+
+    #[test]
+    fn test_address_operands_take_symbols() {
+        let symbols = SymbolTable::parse("//\tLOOP 3001\n//\tDONE 3003\n");
+        let mut debugger = Debugger::new(Vec::new(), symbols);
+        let mut state = State::new();
+        state.pc = 0x3000;
+        for (i, word) in [0x1261, 0x0ffe, 0x0041, 0xf025].iter().enumerate() {
+            state.memory.write(0x3000 + i as u16, *word);
+        }
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("dump LOOP +2")),
+            "0x3001: 0x0ffe 0x0041                                           |.A|"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("dump LOOP DONE")),
+            "0x3001: 0x0ffe 0x0041                                           |.A|"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("d LOOP 2")),
+            "    LOOP    x0FFE  BRnzp x3000\n    LOOP+1  x0041  NOP"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("find 0x41 LOOP DONE")),
+            "0x3002"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("set pc LOOP")),
+            "PC 0x3000 -> 0x3001"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("jump DONE")),
+            "PC 0x3003"
+        );
+        assert_eq!(state.pc, 0x3003);
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("jump MISSING")),
+            "Unknown identifier \"MISSING\""
+        );
+    }
+
+    #[test]
+    fn test_break_trap() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.memory.write(0x3000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        state.memory.write(0x3001, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        state.memory.write(0x3002, 0b1111_0000_00100010); // PUTS
+        state.memory.write(0x3003, 0b1111_0000_00100101); // HALT
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("break-trap 0x22")),
+            "Breakpoint set on TRAP x22 (PUTS)"
+        );
+        debugger.handle_command(&mut state, parse("continue"));
+        state = debugger.resume(state);
+
+        assert_eq!(state.pc, 0x3002);
+        assert_eq!(state.registers.read(Register::R1), 2);
+        assert_eq!(
+            debugger.stop_report.take().as_deref(),
+            Some("Stopped at TRAP x22 (PUTS), PC 0x3002\nR1: x0000 -> x0002, PC: x3000 -> x3002")
+        );
+    }
+
+    #[test]
+    fn test_batches_stop_exactly() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        debugger.set_batch_size(4);
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.memory.write(0x3000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        state.memory.write(0x3001, 0b0001_010_001_1_10110); // ADD R2, R1, #-10
+        state.memory.write(0x3002, 0b0000_100_111111101); // BRn x3000
+        state.memory.write(0x3003, 0b1111_0000_00100101); // HALT
+
+        // Only a TRAP breakpoint, so the loop runs in batches and the hook stops it.
+        debugger.handle_command(&mut state, parse("set auto-delta off"));
+        debugger.handle_command(&mut state, parse("break-trap 0x25"));
+        debugger.handle_command(&mut state, parse("continue"));
+        state = debugger.resume(state);
+        assert_eq!(state.pc, 0x3003);
+        assert_eq!(state.instructions, 30);
+
+        // An address breakpoint, on an instruction no batch would end on.
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        debugger.set_batch_size(4);
+        state.pc = 0x3000;
+        state.instructions = 0;
+        state.registers.write(Register::R1, 0);
+        debugger.handle_command(&mut state, parse("break-trap 0x25"));
+        debugger.handle_command(&mut state, parse("break 0x3002"));
+        for instructions in [2, 5, 8] {
+            debugger.handle_command(&mut state, parse("continue"));
+            state = debugger.resume(state);
+            assert_eq!((state.pc, state.instructions), (0x3002, instructions));
+        }
+    }
+
+    #[test]
+    fn test_break_trap_composes_with_address_breakpoints() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.memory.write(0x3000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        state.memory.write(0x3001, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        state.memory.write(0x3002, 0b1111_0000_00100101); // HALT
+
+        debugger.handle_command(&mut state, parse("break-trap"));
+        debugger.handle_command(&mut state, parse("break 0x3001"));
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("breakpoints")),
+            "1: 0x3001\n2: TRAP (any)"
+        );
+
+        debugger.handle_command(&mut state, parse("set auto-delta off"));
+        debugger.handle_command(&mut state, parse("continue"));
+        state = debugger.resume(state);
+        assert_eq!(state.pc, 0x3001);
+        assert_eq!(debugger.stop_report, None);
+
+        debugger.handle_command(&mut state, parse("continue"));
+        state = debugger.resume(state);
+        assert_eq!(state.pc, 0x3002);
+        assert_eq!(
+            debugger.stop_report.take().as_deref(),
+            Some("Stopped at TRAP x25 (HALT), PC 0x3002")
+        );
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("delete 2")),
+            "Deleted breakpoint 2"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("delete 2")),
+            "No breakpoint 2"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("breakpoints")),
+            "1: 0x3001"
+        );
+        assert_eq!(
+            parse("break-trap 0x100"),
+            Command::Error("TRAP vector 0x100 is not 8 bits".to_string())
+        );
+    }
+
+    #[test]
+    fn test_auto_delta_after_step() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.memory.write(0x3000, 0b0001_011_011_1_11111); // ADD R3, R3, #-1
+        state.memory.write(0x3001, 0b0000_111_000000100); // BRnzp #4
+
+        debugger.handle_command(&mut state, parse("step"));
+        state = debugger.resume(state);
+        assert_eq!(
+            debugger.stop_report.take().as_deref(),
+            Some("R3: x0000 -> xFFFF, COND: N")
+        );
+
+        debugger.handle_command(&mut state, parse("step"));
+        state = debugger.resume(state);
+        assert_eq!(
+            debugger.stop_report.take().as_deref(),
+            Some("PC: x3001 -> x3006")
+        );
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("set auto-delta off")),
+            "Auto delta off"
+        );
+        state.memory.write(0x3006, 0b0001_011_011_1_11111); // ADD R3, R3, #-1
+        debugger.handle_command(&mut state, parse("step"));
+        debugger.resume(state);
+        assert_eq!(debugger.stop_report, None);
+    }
+
+    #[test]
+    fn test_print() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::parse("//\tSTART 3000\n"));
+        let mut state = State::new();
+        state.pc = 0x3001;
+        state.registers.write(Register::R3, 0x8000);
+
+        let mut print = |operand: &str| {
+            debugger.handle_command(&mut state, parse(&format!("print {}", operand)))
+        };
+
+        assert_eq!(print("r3"), "0x8000, unsigned 32768, signed -32768");
+        assert_eq!(print("0x41"), "0x0041, unsigned 65, signed 65, char 'A'");
+        assert_eq!(print("65"), "0x0041, unsigned 65, signed 65, char 'A'");
+        assert_eq!(print("pc"), "0x3001, unsigned 12289, signed 12289");
+        assert_eq!(print("START"), "0x3000, unsigned 12288, signed 12288");
+        assert_eq!(print("NOPE"), "Unknown identifier \"NOPE\"");
+    }
+
+    #[test]
+    fn test_patch() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+        // ADD R1, R1, #1
+        state.memory.write(0x3000, 0x1261);
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("patch pc ADD R1, R1, #5")),
+            "0x3000: ADD R1, R1, #1 -> ADD R1, R1, #5"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("patch 0x3001 BRp x3000")),
+            "0x3001: NOP -> BRp x3000"
+        );
+
+        debugger.handle_command(&mut state, parse("s"));
+        state = debugger.resume(state);
+        assert_eq!(state.registers.read(Register::R1), 5);
+
+        debugger.handle_command(&mut state, parse("s"));
+        state = debugger.resume(state);
+        assert_eq!(state.pc, 0x3000);
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("patch pc ADD R1, R1, #16")),
+            "Immediate #16 out of range for imm5 (-16 to 15)"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("patch pc MOV R1, R2")),
+            "Unknown mnemonic \"MOV\""
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("patch pc")),
+            "Expected an address and an instruction"
+        );
+        assert_eq!(state.memory.peek(0x3000), 0x1265);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        for (i, c) in "HELLO".bytes().enumerate() {
+            state.memory.write(0x3010 + i as u16, u16::from(c));
+        }
+        state.memory.write(0x3020, 0x0041);
+        state.memory.write(0x3030, 0x0041);
+        state.memory.write(0xffff, 0x0041);
+
+        let mut find =
+            |args: &str| debugger.handle_command(&mut state, parse(&format!("find {}", args)));
+
+        assert_eq!(find("0x0041 0x3000 0x3100"), "0x3020\n0x3030");
+        assert_eq!(find("0x0041 0x3000 0x3030"), "0x3020");
+        assert_eq!(find("\"HELLO\" 0x3000 0x3100"), "0x3010");
+        assert_eq!(find("\"LO\" 0x3000 0x3100"), "0x3013");
+        // Starts inside the range, finishes past its end.
+        assert_eq!(find("\"HELLO\" 0x3000 0x3011"), "0x3010");
+        assert_eq!(find("\"HELLO\" 0x3011 0x3100"), "No matches");
+        assert_eq!(find("0x1234 0x3000 0x3100"), "No matches");
+        // Device registers are peeked rather than read.
+        assert_eq!(find("0x8000 0xfe00 0xfe08"), "0xfe04");
+
+        // A count can reach the last word of memory.
+        assert_eq!(find("0x0041 0xfff0 +16"), "0xffff");
+        assert_eq!(find("0x0041 0xfff0 0xffff"), "No matches");
+
+        assert_eq!(find("\"HI 0x3000 0x3100"), "Unterminated string in find");
+        assert_eq!(
+            find("0x41 0x3000"),
+            "Usage: find <value|\"text\"> <addr> <end|+count>"
+        );
+        assert_eq!(
+            find("0x41 0x3100 0x3000"),
+            "End address 0x3000 is below start address 0x3100"
+        );
+    }
+
+    #[test]
+    fn test_find_limits_results() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+
+        let response = debugger.handle_command(&mut state, parse("find 0 0x3000 0x3040"));
+
+        let lines: Vec<&str> = response.lines().collect();
+        assert_eq!(lines.len(), FIND_LIMIT + 1);
+        assert_eq!(lines[0], "0x3000");
+        assert_eq!(lines[FIND_LIMIT], "... 14 more results");
+    }
+
+    #[test]
+    fn test_info() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::parse("//\tLOOP 3001\n"));
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.memory.write(0x3000, 0x1261); // ADD R1, R1, #1
+        state.memory.write(0x3001, 0x0ffe); // BRnzp x3000
+        state.registers.write(Register::R6, 0xfdff);
+
+        debugger.handle_command(&mut state, parse("s"));
+        state = debugger.resume(state);
+        debugger.handle_command(&mut state, parse("break 0x3000"));
+        debugger.handle_command(&mut state, parse("break-trap 0x25"));
+        debugger.handle_command(&mut state, parse("break-address 0x3010"));
+        assert!(debugger.run_time > Duration::ZERO);
+        debugger.run_time = Duration::from_millis(2);
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("info")),
+            [
+                "PC: 0x3001 (LOOP), BRnzp x3000",
+                "Flags: P",
+                "R0: 0x0000, R1: 0x0001, R2: 0x0000, R3: 0x0000",
+                "R4: 0x0000, R5: 0x0000, R6: 0xfdff, R7: 0x0000",
+                "Executed: 1 instructions in 0.002s, 0.00 MIPS",
+                "Breakpoints: 1: 0x3000, 2: TRAP x25 (HALT), break-address 0x3010",
+                "Status: running",
+            ]
+            .join("\n")
+        );
+
+        state.running = false;
+        debugger.handle_command(&mut state, parse("delete 1"));
+        debugger.handle_command(&mut state, parse("delete 1"));
+        debugger.break_address = None;
+        let info = debugger.handle_command(&mut state, parse("i"));
+        assert!(
+            info.ends_with("Breakpoints: none\nStatus: halted"),
+            "{}",
+            info
+        );
+    }
+
+    #[test]
+    fn test_interrupt_pauses_a_running_program() {
+        static INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        debugger.interrupt = &INTERRUPT;
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.memory.write(0x3000, 0b0000_111_111111111); // BRnzp #-1
+
+        debugger.handle_command(&mut state, parse("break 0x4000"));
+        debugger.handle_command(&mut state, parse("c"));
+        INTERRUPT.store(true, Ordering::SeqCst);
+        state = debugger.resume(state);
+
+        assert!(state.running);
+        assert_eq!(state.pc, 0x3000);
+        assert_eq!(
+            debugger.stop_report.take().as_deref(),
+            Some("Interrupted at PC 0x3000\nPC: x3000 -> x3000")
+        );
+        // Still raised while paused, so a second Ctrl-C exits.
+        assert!(INTERRUPT.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_read_does_not_consume_keyboard_input() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        // A key is waiting. Reading KBDR for real would take it from STDIN.
+        state.memory.write(0xfe00, 0x8000);
+        state.memory.write(0xfe02, u16::from(b'a'));
+
+        let response = debugger.handle_command(&mut state, parse("read 0xfe02"));
+
+        assert_eq!(response, "0x0061, 0b0000000001100001");
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("read 0xfe00")),
+            "0x8000, 0b1000000000000000"
+        );
+        assert_eq!(state.memory.peek(0xfe02), u16::from(b'a'));
+    }
+
+    #[test]
+    fn test_dump_does_not_trigger_device_reads() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+
+        let response = debugger.handle_command(&mut state, parse("dump 0xfe00 +8"));
+
+        assert_eq!(
+            response,
+            "0xfe00: 0x0000 0x0000 0x0000 0x0000 0x8000 0x0000 0x0000 0x0000 |........|"
+        );
+    }
+
+    #[test]
+    fn test_dump_to_the_end_of_memory() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.memory.write(0xfffe, u16::from(b'H'));
+        state.memory.write(0xffff, u16::from(b'I'));
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("dump 0xfff8 +8")),
+            "0xfff8: 0x0000 0x0000 0x0000 0x0000 0x0000 0x0000 0x0048 0x0049 |......HI|"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("dump 0xfffe +2")),
+            "0xfffe: 0x0048 0x0049                                           |HI|"
+        );
+    }
+
+    #[test]
+    fn test_dump_errors() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+
+        for (line, expected) in &[
+            ("dump 0x3000", "Usage: dump <addr> <end|+count>"),
+            (
+                "dump 0x3000 0x2000",
+                "End address 0x2000 is below start address 0x3000",
+            ),
+            (
+                "dump 0xfff0 +32",
+                "Range crosses the end of memory (0xffff)",
+            ),
+        ] {
+            assert_eq!(debugger.handle_command(&mut state, parse(line)), *expected);
+        }
+    }
+
+    #[test]
+    fn test_list() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3001;
+        state.memory.write(0x3000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        state.memory.write(0x3001, 0b0000_111_111111110); // BRnzp #-2
+        state.memory.write(0x3002, 0xf025); // HALT
+
+        debugger.handle_command(&mut state, parse("break-address 0x3002"));
+        let response = debugger.handle_command(&mut state, parse("list 1"));
+
+        assert_eq!(
+            response,
+            [
+                "    x3000  x1261  ADD R1, R1, #1",
+                "=>  x3001  x0FFE  BRnzp x3000",
+                "  * x3002  xF025  HALT",
+            ]
+            .join("\n")
+        );
+        assert_eq!(parse("list"), Command::List(5));
+    }
+
+    #[test]
+    fn test_disassemble_at() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.memory.write(0x3000, 0b1110_000_000000010); // LEA R0, #2
+        state.memory.write(0x3001, 0b1111_0000_00100010); // PUTS
+        state.memory.write(0x3002, 0b1111_0000_00100101); // HALT
+        state.memory.write(0x3003, 0xdead);
+        state.memory.write(0x3004, 0xf0ff);
+
+        let response = debugger.handle_command(&mut state, parse("d 0x3000 6"));
+
+        assert_eq!(
+            response,
+            [
+                "    x3000  xE002  LEA R0, x3003",
+                "    x3001  xF022  PUTS",
+                "    x3002  xF025  HALT",
+                "    x3003  xDEAD  .FILL xDEAD",
+                "    x3004  xF0FF  .FILL xF0FF",
+                "    x3005  x0000  NOP",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("disassemble 0x3002")),
+            "    x3002  xF025  HALT"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_at_end_of_memory() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+
+        let response = debugger.handle_command(&mut state, parse("d 0xfffe 4"));
+
+        assert_eq!(
+            response,
+            [
+                "    xFFFE  x8000  .FILL x8000",
+                "    xFFFF  x0000  NOP",
+                "Reached the end of memory",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_disassemble_current_instruction() {
+        let symbols = SymbolTable::parse("//\tLOOP 3000\n");
+        let mut debugger = Debugger::new(Vec::new(), symbols);
+        let mut state = State::new();
+        state.pc = 0x3001;
+        state.memory.write(0x3001, 0b0000_101_111111110); // BRnp #-2
+        state.memory.write(0x3002, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("d")),
+            "BRnp LOOP (#-2), 00001011_11111110"
+        );
+
+        state.pc = 0x3002;
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("d")),
+            "ADD R1, R1, #1, 00010010_01100001"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_every_opcode() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+
+        for (word, expected) in [
+            (0x0e02, "BRnzp x3003 (#2), 00001110_00000010"),
+            (0x0000, "NOP, 00000000_00000000"),
+            (0x1283, "ADD R1, R2, R3, 00010010_10000011"),
+            (0x12bf, "ADD R1, R2, #-1, 00010010_10111111"),
+            (0x25ff, "LD R2, x3000 (#-1), 00100101_11111111"),
+            (0x3601, "ST R3, x3002 (#1), 00110110_00000001"),
+            (0x4810, "JSR x3011 (#16), 01001000_00010000"),
+            (0x4140, "JSRR R5, 01000001_01000000"),
+            (0x5283, "AND R1, R2, R3, 01010010_10000011"),
+            (0x52a7, "AND R1, R2, #7, 01010010_10100111"),
+            (0x6283, "LDR R1, R2, #3, 01100010_10000011"),
+            (0x72bf, "STR R1, R2, #-1, 01110010_10111111"),
+            (0x8000, ".FILL x8000, 10000000_00000000"),
+            (0x96bf, "NOT R3, R2, 10010110_10111111"),
+            (0xa804, "LDI R4, x3005 (#4), 10101000_00000100"),
+            (0xbbfe, "STI R5, x2FFF (#-2), 10111011_11111110"),
+            (0xc080, "JMP R2, 11000000_10000000"),
+            (0xc1c0, "RET, 11000001_11000000"),
+            (0xd000, ".FILL xD000, 11010000_00000000"),
+            (0xed00, "LEA R6, x2F01 (#-256), 11101101_00000000"),
+            (0xf020, "GETC, 11110000_00100000"),
+            (0xf025, "HALT, 11110000_00100101"),
+            // Data the PC has wandered into.
+            (0xf0ff, ".FILL xF0FF, 11110000_11111111"),
+        ] {
+            state.memory.write(0x3000, word);
+            assert_eq!(debugger.handle_command(&mut state, parse("d")), expected);
+        }
+    }
+
+    #[test]
+    fn test_break_at_symbol() {
+        let symbols = SymbolTable::parse("//\tSTART 3000\n//\tLOOP 3002\n//\tDONE 3004\n");
+        let mut debugger = Debugger::new(Vec::new(), symbols);
+        let mut state = State::new();
+        state.pc = 0x3000;
+        for address in 0x3000..0x3004 {
+            state.memory.write(address, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        }
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("break loop")),
+            "Breakpoint set at 0x3002"
+        );
+        debugger.handle_command(&mut state, parse("c"));
+        state = debugger.resume(state);
+
+        assert_eq!(state.pc, 0x3002);
+        assert_eq!(state.registers.read(Register::R1), 2);
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("until DONE")),
+            "Continuing until 0x3004"
+        );
+        state = debugger.resume(state);
+
+        assert_eq!(state.pc, 0x3004);
+        assert_eq!(state.registers.read(Register::R1), 4);
+    }
+
+    #[test]
+    fn test_break_at_unknown_symbol() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("break MISSING")),
+            "Unknown identifier \"MISSING\""
+        );
+        assert!(debugger.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn test_symbols() {
+        let symbols = SymbolTable::parse("//\tDONE 3010\n//\tSTART 3000\n//\tLOOP 3002\n");
+        let mut debugger = Debugger::new(Vec::new(), symbols);
+        let mut state = State::new();
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("symbols")),
+            "0x3000 START\n0x3002 LOOP\n0x3010 DONE"
+        );
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let symbols = SymbolTable::parse("//\tMAIN 3000\n//\tA 3010\n//\tB 3020\n//\tC 3030\n");
+        let mut debugger = Debugger::new(Vec::new(), symbols);
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.call_stack = Some(CallStack::new());
+        state.memory.write(0x3000, 0b0100_1_00000001111); // JSR A
+        state.memory.write(0x3010, 0b0100_1_00000001111); // JSR B
+        state.memory.write(0x3020, 0b0100_1_00000001111); // JSR C
+
+        for _ in 0..3 {
+            state.step();
+        }
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("bt")),
+            [
+                "#0 C called from B, returns to B+1",
+                "#1 B called from A, returns to A+1",
+                "#2 A called from MAIN, returns to MAIN+1",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_backtrace_ret_without_jsr() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.call_stack = Some(CallStack::new());
+        state.registers.write(Register::R7, 0x3010);
+        state.memory.write(0x3000, 0b1100_000_111_000000); // RET
+
+        state.step();
+
+        assert_eq!(state.pc, 0x3010);
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("bt")),
+            "No active subroutine calls"
         );
     }
+
+    #[test]
+    fn test_history() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        debugger.history = Some(Arc::new(Mutex::new(History::new(4))));
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.memory.write(0x3000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        state.memory.write(0x3001, 0b0000_111_111111110); // BRnzp #-2
+
+        debugger.handle_command(&mut state, parse("set auto-delta off"));
+        for _ in 0..5 {
+            debugger.handle_command(&mut state, parse("step"));
+            state = debugger.resume(state);
+        }
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("history")),
+            [
+                "x3001  x0FFE  BRnzp x3000",
+                "x3000  x1261  ADD R1, R1, #1",
+                "x3001  x0FFE  BRnzp x3000",
+                "x3000  x1261  ADD R1, R1, #1",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("history 1")),
+            "x3000  x1261  ADD R1, R1, #1"
+        );
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("set history-size 2")),
+            "History size: 4 -> 2"
+        );
+    }
+
+    #[test]
+    fn test_reverse_step() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.undo = Some(UndoLog::default());
+        state.memory.write(0x3000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+
+        state.step();
+
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("rs")),
+            "PC 0x3000"
+        );
+        assert_eq!(state.registers.read(Register::R1), 0);
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("reverse-step")),
+            "No more history to reverse"
+        );
+    }
+
+    #[test]
+    fn test_reset() {
+        let path = std::env::temp_dir().join(format!("lc3-reset-{}.obj", std::process::id()));
+        std::fs::write(
+            &path,
+            [
+                0x30, 0x00, // .ORIG x3000
+                0x12, 0x61, // ADD R1, R1, #1
+                0x12, 0x61, // ADD R1, R1, #1
+                0xf0, 0x25, // HALT
+            ],
+        )
+        .unwrap();
+        let mut debugger = Debugger::new(vec![path.clone()], SymbolTable::new());
+        let mut state = State::new();
+        state.load_rom(&mut file::read_rom(&path).unwrap()).unwrap();
+
+        debugger.handle_command(&mut state, parse("break 0x3001"));
+        debugger.handle_command(&mut state, parse("c"));
+        state = debugger.resume(state);
+        state.memory.write(0x3001, 0xf025);
+
+        let response = debugger.handle_command(&mut state, parse("reset"));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(response, "Reset, PC 0x3000");
+        assert_eq!(state.memory.peek(0x3001), 0x1261);
+        assert_eq!(state.registers(), [0; 8]);
+
+        debugger.handle_command(&mut state, parse("c"));
+        state = debugger.resume(state);
+
+        assert_eq!(state.pc, 0x3001);
+        assert_eq!(state.registers.read(Register::R1), 1);
+    }
+
+    #[test]
+    fn test_empty_line_repeats_step() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+        state.pc = 0x3000;
+        for address in 0x3000..0x3003 {
+            state.memory.write(address, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        }
+        debugger.handle_command(&mut state, parse("break 0x3002"));
+
+        for line in &["step\n", "\n"] {
+            let command = debugger.parse_line(line);
+            debugger.handle_command(&mut state, command);
+            state = debugger.resume(state);
+        }
+
+        assert_eq!(state.pc, 0x3002);
+        assert_eq!(state.registers.read(Register::R1), 2);
+        assert_eq!(
+            debugger.handle_command(&mut state, parse("history-cmd")),
+            "1: step\n2: step"
+        );
+    }
+
+    #[test]
+    fn test_empty_first_line() {
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let mut state = State::new();
+
+        let command = debugger.parse_line("\n");
+
+        assert_eq!(
+            debugger.handle_command(&mut state, command),
+            "No previous command"
+        );
+    }
+
+    #[test]
+    fn test_listen_on_ephemeral_port() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpStream;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let session = thread::spawn(move || {
+            let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+            debugger.listen(State::new(), listener).unwrap()
+        });
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"exit\n").unwrap();
+
+        let mut response = String::new();
+        BufReader::new(&stream).read_line(&mut response).unwrap();
+        assert_eq!(response, "Exiting...\n");
+
+        let state = session.join().unwrap();
+        assert!(!state.running);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("lc3-debug-{}.sock", std::process::id()));
+        let transport = DebugTransport::Socket(path.clone());
+        let session = thread::spawn(move || {
+            let mut state = State::new();
+            state.pc = 0x3000;
+            state.memory.write(0x3000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+            let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+            debugger.run(state, Some(transport), None).map(|_| ())
+        });
+
+        let stream = loop {
+            match UnixStream::connect(&path) {
+                Ok(stream) => break stream,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        let mut client = BufReader::new(stream);
+        let mut send = |line: &str, lines: usize| {
+            client.get_mut().write_all(line.as_bytes()).unwrap();
+            let mut response = String::new();
+            for _ in 0..lines {
+                client.read_line(&mut response).unwrap();
+            }
+            response
+        };
+
+        assert_eq!(send("s\n", 2), "PC 0x3000\nR1: x0000 -> x0001\n");
+        assert_eq!(send("print r1\n", 1), "0x0001, unsigned 1, signed 1\n");
+        assert_eq!(send("exit\n", 1), "Exiting...\n");
+
+        session.join().unwrap().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_session_over_in_memory_stream() {
+        let mut output = Vec::new();
+        let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let input = io::Cursor::new("set r2 0x0042\nprint r2\nexit\nflags\n");
+
+        let state = debugger.session(
+            State::new(),
+            &mut transport::Stream::new(input, &mut output),
+        );
+
+        assert!(!state.running);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "R2: 0x0000 -> 0x0042\n0x0042, unsigned 66, signed 66, char 'B'\nExiting...\n"
+        );
+    }
+
+    #[test]
+    fn test_detach_closes_the_listener() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpStream;
+        use std::sync::mpsc;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (listened, returned) = mpsc::channel();
+
+        let session = thread::spawn(move || {
+            let mut state = State::new();
+            state.pc = 0x3000;
+            state.memory.write(0x3000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+            state.memory.write(0x3001, 0xf025); // HALT
+            state.console.replace(Box::new(BufferConsole::default()));
+            let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+            let state = debugger.listen(state, listener).unwrap();
+            assert!(state.running);
+            listened.send(()).unwrap();
+
+            let state = debugger.finish_detached(state).unwrap();
+            (state.running, state.registers.read(Register::R1))
+        });
+
+        let mut client = BufReader::new(TcpStream::connect(address).unwrap());
+        client.get_mut().write_all(b"detach\n").unwrap();
+        let mut response = String::new();
+        client.read_line(&mut response).unwrap();
+        assert_eq!(
+            response,
+            "Detaching, the program continues without the debugger\n"
+        );
+
+        returned.recv().unwrap();
+        assert!(TcpStream::connect(address).is_err());
+        assert_eq!(session.join().unwrap(), (false, 1));
+    }
+
+    #[test]
+    fn test_run_reports_bind_errors() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = taken.local_addr().unwrap();
+
+        let error = thread::spawn(move || {
+            let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+            debugger
+                .run(State::new(), Some(DebugTransport::Tcp(address)), None)
+                .map(|_| ())
+        })
+        .join()
+        .unwrap()
+        .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with(&format!("Unable to bind to {}: ", address)));
+    }
+
+    #[test]
+    fn test_reconnect_keeps_session() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpStream;
+
+        fn send(client: &mut BufReader<TcpStream>, line: &str) -> String {
+            client.get_mut().write_all(line.as_bytes()).unwrap();
+            receive(client)
+        }
+
+        fn receive(client: &mut BufReader<TcpStream>) -> String {
+            let mut response = String::new();
+            client.read_line(&mut response).unwrap();
+            response
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let session = thread::spawn(move || {
+            let mut state = State::new();
+            state.pc = 0x3000;
+            for address in 0x3000..0x3003 {
+                state.memory.write(address, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+            }
+            let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+            debugger.listen(state, listener).unwrap()
+        });
+
+        let mut first = BufReader::new(TcpStream::connect(address).unwrap());
+        assert_eq!(
+            send(&mut first, "break 0x3002\n"),
+            "Breakpoint set at 0x3002\n"
+        );
+        assert_eq!(send(&mut first, "s\n"), "PC 0x3000\n");
+        assert_eq!(receive(&mut first), "R1: x0000 -> x0001\n");
+        drop(first);
+
+        let mut second = BufReader::new(TcpStream::connect(address).unwrap());
+        assert_eq!(send(&mut second, "c\n"), "PC 0x3001\n");
+        assert_eq!(receive(&mut second), "R1: x0001 -> x0002\n");
+        assert_eq!(send(&mut second, "exit\n"), "Exiting...\n");
+
+        let state = session.join().unwrap();
+        assert_eq!(state.pc, 0x3002);
+        assert_eq!(state.registers.read(Register::R1), 2);
+    }
+
+    #[test]
+    fn test_several_commands_in_one_segment() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpStream;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let session = thread::spawn(move || {
+            let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+            debugger.listen(State::new(), listener).unwrap()
+        });
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut response = String::new();
+
+        stream.write_all(b"break 0x3000\nflags\n").unwrap();
+        reader.read_line(&mut response).unwrap();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response, "Breakpoint set at 0x3000\nP\n");
+
+        // A command split across writes is only run once the line is complete.
+        response.clear();
+        stream.write_all(b"ex").unwrap();
+        stream.flush().unwrap();
+        thread::sleep(Duration::from_millis(10));
+        stream.write_all(b"it\n").unwrap();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response, "Exiting...\n");
+
+        session.join().unwrap();
+    }
 }
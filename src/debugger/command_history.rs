@@ -0,0 +1,71 @@
+/// The lines entered in a debugger session, used to repeat commands.
+#[derive(Debug, Default)]
+pub struct CommandHistory {
+    lines: Vec<String>,
+}
+
+impl CommandHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expands an input line into the command line to run, recording it. An empty line repeats
+    /// the previous command and `!n` repeats the nth (starting at 1).
+    pub fn expand(&mut self, line: &str) -> Result<String, String> {
+        let line = line.trim();
+
+        let expanded = if line.is_empty() {
+            match self.lines.last() {
+                Some(previous) => previous.clone(),
+                None => return Err("No previous command".to_string()),
+            }
+        } else if let Some(n) = line.strip_prefix('!') {
+            match n.parse::<usize>().ok().and_then(|n| self.get(n)) {
+                Some(line) => line.to_string(),
+                None => return Err(format!("No command {} in history", line)),
+            }
+        } else {
+            line.to_string()
+        };
+
+        self.lines.push(expanded.clone());
+
+        Ok(expanded)
+    }
+
+    fn get(&self, n: usize) -> Option<&str> {
+        n.checked_sub(1)
+            .and_then(|i| self.lines.get(i))
+            .map(String::as_str)
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let mut history = CommandHistory::new();
+
+        assert_eq!(history.expand(""), Err("No previous command".to_string()));
+        assert_eq!(history.expand("step"), Ok("step".to_string()));
+        assert_eq!(history.expand(""), Ok("step".to_string()));
+        assert_eq!(history.expand("registers"), Ok("registers".to_string()));
+        assert_eq!(history.expand("!1"), Ok("step".to_string()));
+        assert_eq!(
+            history.expand("!9"),
+            Err("No command !9 in history".to_string())
+        );
+        assert_eq!(
+            history.expand("!0"),
+            Err("No command !0 in history".to_string())
+        );
+
+        assert_eq!(history.lines(), ["step", "step", "registers", "step"]);
+    }
+}
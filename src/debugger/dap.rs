@@ -0,0 +1,461 @@
+//! A Debug Adapter Protocol front end, so editors like VS Code can drive the debugger.
+//!
+//! LC-3 programs have no source mapping, so breakpoint and stack frame "lines" are memory
+//! addresses. There is a single thread, and its registers are exposed through one scope.
+
+use super::Debugger;
+use crate::instruction::Instruction;
+use crate::state::{State, StepResult};
+use crate::vm::throttle::Throttle;
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+
+/// Content-Length framed JSON messages over a stream.
+struct Connection<R, W> {
+    reader: R,
+    writer: W,
+    seq: u64,
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    fn new(reader: R, writer: W) -> Self {
+        Connection {
+            reader,
+            writer,
+            seq: 0,
+        }
+    }
+
+    // Returns `None` once the client has gone away.
+    fn read_message(&mut self) -> io::Result<Option<Value>> {
+        let mut length = None;
+
+        loop {
+            let mut header = String::new();
+            if self.reader.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+
+        let length = length.ok_or_else(|| invalid_data("Missing Content-Length header"))?;
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body)?;
+
+        serde_json::from_slice(&body)
+            .map(Some)
+            .map_err(|e| invalid_data(&e.to_string()))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+
+        let body = message.to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.writer.flush()
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn respond_error(&mut self, request: &Value, message: &str) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Debugger {
+    /// Runs a DAP session with a connected client until it disconnects or the program halts.
+    pub(super) fn dap_session(
+        &mut self,
+        mut state: State,
+        stream: &TcpStream,
+    ) -> io::Result<State> {
+        let mut connection = Connection::new(BufReader::new(stream), BufWriter::new(stream));
+
+        while state.running {
+            let request = match connection.read_message()? {
+                Some(request) => request,
+                None => break,
+            };
+
+            let reason = match request["command"].as_str().unwrap_or_default() {
+                "initialize" => {
+                    connection.respond(
+                        &request,
+                        json!({ "supportsConfigurationDoneRequest": true }),
+                    )?;
+                    connection.event("initialized", json!({}))?;
+                    continue;
+                }
+
+                // The program is loaded before the session starts and waits at its entry point.
+                "launch" | "attach" => {
+                    connection.respond(&request, json!({}))?;
+                    continue;
+                }
+
+                "configurationDone" => {
+                    connection.respond(&request, json!({}))?;
+                    "entry"
+                }
+
+                "setBreakpoints" => {
+                    let lines = request["arguments"]["breakpoints"]
+                        .as_array()
+                        .map(|breakpoints| {
+                            breakpoints
+                                .iter()
+                                .map(|breakpoint| breakpoint["line"].as_u64().unwrap_or_default())
+                                .collect::<Vec<u64>>()
+                        })
+                        .unwrap_or_default();
+
+                    self.breakpoints = lines
+                        .iter()
+                        .filter_map(|&line| u16::try_from(line).ok())
+                        .collect();
+
+                    let breakpoints = lines
+                        .iter()
+                        .map(|&line| json!({ "verified": line <= u64::from(u16::MAX), "line": line }))
+                        .collect::<Vec<Value>>();
+                    connection.respond(&request, json!({ "breakpoints": breakpoints }))?;
+                    continue;
+                }
+
+                "threads" => {
+                    connection.respond(
+                        &request,
+                        json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+                    )?;
+                    continue;
+                }
+
+                "stackTrace" => {
+                    let frames = self.stack_frames(&state);
+                    connection.respond(
+                        &request,
+                        json!({ "totalFrames": frames.len(), "stackFrames": frames }),
+                    )?;
+                    continue;
+                }
+
+                "scopes" => {
+                    connection.respond(
+                        &request,
+                        json!({ "scopes": [{
+                            "name": "Registers",
+                            "variablesReference": REGISTERS_REFERENCE,
+                            "expensive": false,
+                        }] }),
+                    )?;
+                    continue;
+                }
+
+                "variables" => {
+                    let variables =
+                        if request["arguments"]["variablesReference"] == REGISTERS_REFERENCE {
+                            registers(&state)
+                        } else {
+                            Vec::new()
+                        };
+                    connection.respond(&request, json!({ "variables": variables }))?;
+                    continue;
+                }
+
+                "continue" => {
+                    connection.respond(&request, json!({ "allThreadsContinued": true }))?;
+                    state = self.run_until(state, |_| false);
+                    "breakpoint"
+                }
+
+                "next" => {
+                    connection.respond(&request, json!({}))?;
+                    let next = state.pc.wrapping_add(1);
+                    state = match Instruction::decode(state.memory.peek(state.pc)) {
+                        Instruction::JSR(_) | Instruction::JSRR(_) => {
+                            self.run_until(state, |state| state.pc == next)
+                        }
+                        _ => self.run_until(state, |_| true),
+                    };
+                    "step"
+                }
+
+                "stepIn" => {
+                    connection.respond(&request, json!({}))?;
+                    state = self.run_until(state, |_| true);
+                    "step"
+                }
+
+                // Execution only happens in response to requests, so the program is always paused
+                // by the time this arrives.
+                "pause" => {
+                    connection.respond(&request, json!({}))?;
+                    "pause"
+                }
+
+                "disconnect" => {
+                    connection.respond(&request, json!({}))?;
+                    state.running = false;
+                    break;
+                }
+
+                command => {
+                    connection
+                        .respond_error(&request, &format!("Unsupported request {:?}", command))?;
+                    continue;
+                }
+            };
+
+            if state.running {
+                connection.event(
+                    "stopped",
+                    json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+                )?;
+            } else {
+                connection.event("exited", json!({ "exitCode": 0 }))?;
+                connection.event("terminated", json!({}))?;
+            }
+        }
+
+        Ok(state)
+    }
+
+    // Executes at least one instruction, stopping at a breakpoint, when the program halts or
+    // once `stop` returns true.
+    fn run_until(&self, mut state: State, stop: impl Fn(&State) -> bool) -> State {
+        let mut throttle = self.hz.map(Throttle::new);
+
+        loop {
+            if let Some(throttle) = &mut throttle {
+                throttle.take();
+            }
+            let result = state.step();
+
+            if !state.running
+                || matches!(result, StepResult::Error(_))
+                || self.breakpoints.contains(&state.pc)
+                || stop(&state)
+            {
+                return state;
+            }
+        }
+    }
+
+    // The current PC followed by the call site of each active subroutine, innermost first.
+    fn stack_frames(&self, state: &State) -> Vec<Value> {
+        let call_sites = state
+            .call_stack
+            .iter()
+            .flat_map(|call_stack| call_stack.frames().map(|frame| frame.call_site));
+
+        std::iter::once(state.pc)
+            .chain(call_sites)
+            .enumerate()
+            .map(|(id, address)| {
+                json!({
+                    "id": id,
+                    "name": self.symbols.format_address(address),
+                    "line": address,
+                    "column": 0,
+                    "instructionPointerReference": format!("{:#06x}", address),
+                })
+            })
+            .collect()
+    }
+}
+
+fn registers(state: &State) -> Vec<Value> {
+    let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+    state
+        .registers
+        .iter()
+        .map(|(register, value)| {
+            variable(
+                format!("{:?}", register),
+                format!("{:#06x} ({})", value, value as i16),
+            )
+        })
+        .chain(vec![
+            variable("PC".to_string(), format!("{:#06x}", state.pc)),
+            variable("COND".to_string(), format!("{:?}", state.condition)),
+        ])
+        .collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
+mod tests {
+    use super::*;
+    use crate::state::call_stack::CallStack;
+    use crate::symbols::SymbolTable;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // A scripted client that reads back responses and events in order.
+    struct Client {
+        connection: Connection<BufReader<TcpStream>, TcpStream>,
+    }
+
+    impl Client {
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.connection
+                .send(json!({ "type": "request", "command": command, "arguments": arguments }))
+                .unwrap();
+            let response = self.receive();
+            assert_eq!(response["type"], "response");
+            assert_eq!(response["command"], command);
+            assert_eq!(response["success"], true, "{}", response);
+            response["body"].clone()
+        }
+
+        fn receive(&mut self) -> Value {
+            self.connection.read_message().unwrap().unwrap()
+        }
+
+        fn expect_event(&mut self, event: &str) -> Value {
+            let message = self.receive();
+            assert_eq!(message["type"], "event");
+            assert_eq!(message["event"], event, "{}", message);
+            message["body"].clone()
+        }
+    }
+
+    #[test]
+    fn test_scripted_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let session = thread::spawn(move || {
+            let mut state = State::new();
+            state.pc = 0x3000;
+            state.call_stack = Some(CallStack::new());
+            state.memory.write(0x3000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+            state.memory.write(0x3001, 0b0100_1_00000000010); // JSR x3004
+            state.memory.write(0x3002, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+            state.memory.write(0x3003, 0b1111_0000_00100101); // HALT
+            state.memory.write(0x3004, 0b0001_010_010_1_00011); // ADD R2, R2, #3
+            state.memory.write(0x3005, 0b1100_000_111_000000); // RET
+
+            let (stream, _) = listener.accept().unwrap();
+            let mut debugger = Debugger::new(Vec::new(), SymbolTable::new());
+            debugger.dap_session(state, &stream).unwrap()
+        });
+
+        let stream = TcpStream::connect(address).unwrap();
+        let mut client = Client {
+            connection: Connection::new(BufReader::new(stream.try_clone().unwrap()), stream),
+        };
+
+        let capabilities = client.request("initialize", json!({ "adapterID": "lc3" }));
+        assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+        client.expect_event("initialized");
+
+        client.request("launch", json!({}));
+        let breakpoints = client.request(
+            "setBreakpoints",
+            json!({ "source": { "name": "program" }, "breakpoints": [{ "line": 0x3004 }] }),
+        );
+        assert_eq!(breakpoints["breakpoints"][0]["verified"], true);
+
+        client.request("configurationDone", json!({}));
+        assert_eq!(client.expect_event("stopped")["reason"], "entry");
+
+        let threads = client.request("threads", json!({}));
+        assert_eq!(threads["threads"][0]["id"], THREAD_ID);
+
+        client.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.expect_event("stopped")["reason"], "breakpoint");
+
+        let stack = client.request("stackTrace", json!({ "threadId": THREAD_ID }));
+        let lines: Vec<&Value> = stack["stackFrames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|frame| &frame["line"])
+            .collect();
+        assert_eq!(lines, [&json!(0x3004), &json!(0x3001)]);
+
+        client.request("stepIn", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.expect_event("stopped")["reason"], "step");
+
+        let scopes = client.request("scopes", json!({ "frameId": 0 }));
+        assert_eq!(scopes["scopes"][0]["name"], "Registers");
+
+        let variables = client.request(
+            "variables",
+            json!({ "variablesReference": REGISTERS_REFERENCE }),
+        );
+        assert_eq!(variables["variables"][1]["value"], "0x0001 (1)");
+        assert_eq!(variables["variables"][2]["value"], "0x0003 (3)");
+        assert_eq!(variables["variables"][8]["value"], "0x3005");
+
+        client.request("next", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.expect_event("stopped")["reason"], "step");
+        let stack = client.request("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(stack["stackFrames"][0]["line"], 0x3002);
+        assert_eq!(stack["totalFrames"], 1);
+
+        client.request("disconnect", json!({}));
+
+        let state = session.join().unwrap();
+        assert!(!state.running);
+        assert_eq!(state.pc, 0x3002);
+    }
+
+    #[test]
+    fn test_next_steps_over_subroutines() {
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.memory.write(0x3000, 0b0100_1_00000000001); // JSR x3002
+        state.memory.write(0x3001, 0b1111_0000_00100101); // HALT
+        state.memory.write(0x3002, 0b0001_010_010_1_00011); // ADD R2, R2, #3
+        state.memory.write(0x3003, 0b1100_000_111_000000); // RET
+
+        let debugger = Debugger::new(Vec::new(), SymbolTable::new());
+        let state = debugger.run_until(state, |state| state.pc == 0x3001);
+
+        assert_eq!(state.pc, 0x3001);
+        assert_eq!(state.registers()[2], 3);
+    }
+}
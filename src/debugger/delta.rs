@@ -0,0 +1,102 @@
+use crate::state::{Condition, State};
+
+/// The registers, flags and PC at the point execution resumed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub registers: [u16; 8],
+    pub condition: Condition,
+    pub pc: u16,
+}
+
+impl Snapshot {
+    pub fn of(state: &State) -> Self {
+        Snapshot {
+            registers: state.registers(),
+            condition: state.condition.clone(),
+            pc: state.pc,
+        }
+    }
+}
+
+/// A compact summary of what changed between two stops, e.g. `R3: x0005 -> x0006, COND: P`.
+///
+/// The PC is only included when it didn't simply advance by one, i.e. a branch was taken.
+/// Returns `None` when nothing worth reporting changed.
+pub fn delta(before: &Snapshot, after: &Snapshot) -> Option<String> {
+    let mut changes: Vec<String> = before
+        .registers
+        .iter()
+        .zip(&after.registers)
+        .enumerate()
+        .filter(|(_, (before, after))| before != after)
+        .map(|(i, (before, after))| format!("R{}: x{:04X} -> x{:04X}", i, before, after))
+        .collect();
+
+    if before.condition != after.condition {
+        changes.push(format!("COND: {:?}", after.condition));
+    }
+
+    if after.pc != before.pc.wrapping_add(1) {
+        changes.push(format!("PC: x{:04X} -> x{:04X}", before.pc, after.pc));
+    }
+
+    if changes.is_empty() {
+        None
+    } else {
+        Some(changes.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(registers: [u16; 8], condition: Condition, pc: u16) -> Snapshot {
+        Snapshot {
+            registers,
+            condition,
+            pc,
+        }
+    }
+
+    #[test]
+    fn test_register_and_condition() {
+        let before = snapshot([0, 0, 0, 5, 0, 0, 0, 0], Condition::Z, 0x3000);
+        let after = snapshot([0, 0, 0, 6, 0, 0, 0, 0], Condition::P, 0x3001);
+
+        assert_eq!(
+            delta(&before, &after),
+            Some("R3: x0005 -> x0006, COND: P".to_string())
+        );
+    }
+
+    #[test]
+    fn test_branch_taken() {
+        let before = snapshot([0; 8], Condition::P, 0x3000);
+        let after = snapshot([0; 8], Condition::P, 0x3010);
+
+        assert_eq!(
+            delta(&before, &after),
+            Some("PC: x3000 -> x3010".to_string())
+        );
+    }
+
+    #[test]
+    fn test_several_registers() {
+        let before = snapshot([1, 2, 0, 0, 0, 0, 0, 0x3001], Condition::P, 0x3000);
+        let after = snapshot([1, 3, 0, 0, 0, 0, 0, 0x3002], Condition::P, 0x3001);
+
+        assert_eq!(
+            delta(&before, &after),
+            Some("R1: x0002 -> x0003, R7: x3001 -> x3002".to_string())
+        );
+    }
+
+    #[test]
+    fn test_nothing_changed() {
+        let before = snapshot([0; 8], Condition::P, 0xffff);
+        let after = snapshot([0; 8], Condition::P, 0x0000);
+
+        assert_eq!(delta(&before, &after), None);
+    }
+}
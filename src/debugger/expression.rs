@@ -0,0 +1,124 @@
+use super::parse_value;
+use crate::instruction::Register;
+use crate::state::State;
+use crate::symbols::SymbolTable;
+
+/// Evaluates an address expression like `r6+2` or `LOOP - 1` against the live state.
+///
+/// Terms are register names, `pc`, hex (`0x`) or decimal literals and symbols, combined with `+`
+/// and `-` from left to right using wrapping 16-bit arithmetic.
+pub fn evaluate(expression: &str, state: &State, symbols: &SymbolTable) -> Result<u16, String> {
+    let mut result: Option<u16> = None;
+    let mut operator = None;
+    let mut rest = expression.trim_start();
+
+    while !rest.is_empty() {
+        if let Some(c) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+            if result.is_none() || operator.is_some() {
+                return Err(format!("Expected a value before {:?}", c));
+            }
+            operator = Some(c);
+            rest = rest[1..].trim_start();
+            continue;
+        }
+
+        let end = rest
+            .find(|c: char| c == '+' || c == '-' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let (term, remainder) = rest.split_at(end);
+        let value = term_value(term, state, symbols)?;
+
+        result = Some(match (result, operator.take()) {
+            (None, _) => value,
+            (Some(left), Some('+')) => left.wrapping_add(value),
+            (Some(left), Some(_)) => left.wrapping_sub(value),
+            (Some(_), None) => return Err(format!("Expected + or - before {:?}", term)),
+        });
+        rest = remainder.trim_start();
+    }
+
+    match (result, operator) {
+        (Some(_), Some(c)) => Err(format!("Expected a value after {:?}", c)),
+        (Some(result), None) => Ok(result),
+        (None, _) => Err("Expected an address".to_string()),
+    }
+}
+
+fn term_value(term: &str, state: &State, symbols: &SymbolTable) -> Result<u16, String> {
+    if term.starts_with(|c: char| c.is_ascii_digit()) {
+        parse_value(term)
+    } else if term.eq_ignore_ascii_case("pc") {
+        Ok(state.pc)
+    } else if let Ok(register) = term.parse::<Register>() {
+        Ok(state.registers.read(register))
+    } else {
+        symbols
+            .address(term)
+            .ok_or_else(|| format!("Unknown identifier {:?}", term))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &str) -> Result<u16, String> {
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.registers.write(Register::R3, 0x4000);
+        state.registers.write(Register::R6, 0xfffe);
+        let symbols = SymbolTable::parse("//\tLOOP 3010\n");
+
+        evaluate(expression, &state, &symbols)
+    }
+
+    #[test]
+    fn test_terms() {
+        assert_eq!(eval("0x3000"), Ok(0x3000));
+        assert_eq!(eval("42"), Ok(42));
+        assert_eq!(eval("r3"), Ok(0x4000));
+        assert_eq!(eval("R3"), Ok(0x4000));
+        assert_eq!(eval("pc"), Ok(0x3000));
+        assert_eq!(eval("PC"), Ok(0x3000));
+        assert_eq!(eval("loop"), Ok(0x3010));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("pc+4"), Ok(0x3004));
+        assert_eq!(eval("r3 - 0x10"), Ok(0x3ff0));
+        assert_eq!(eval("LOOP-1+2"), Ok(0x3011));
+        // Left to right, no precedence or grouping.
+        assert_eq!(eval("10 - 2 - 3"), Ok(5));
+    }
+
+    #[test]
+    fn test_wrapping() {
+        assert_eq!(eval("r6+2"), Ok(0x0000));
+        assert_eq!(eval("0-1"), Ok(0xffff));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(eval("foo"), Err("Unknown identifier \"foo\"".to_string()));
+        assert_eq!(
+            eval("pc+bar"),
+            Err("Unknown identifier \"bar\"".to_string())
+        );
+        assert_eq!(eval(""), Err("Expected an address".to_string()));
+        assert_eq!(eval("pc+"), Err("Expected a value after '+'".to_string()));
+        assert_eq!(eval("-1"), Err("Expected a value before '-'".to_string()));
+        assert_eq!(
+            eval("pc + - 1"),
+            Err("Expected a value before '-'".to_string())
+        );
+        assert_eq!(
+            eval("pc 1"),
+            Err("Expected + or - before \"1\"".to_string())
+        );
+        assert_eq!(
+            eval("0x10000"),
+            Err("Value 0x10000 does not fit in 16 bits".to_string())
+        );
+    }
+}
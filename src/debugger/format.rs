@@ -0,0 +1,81 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// How the debugger displays register and memory values.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+    #[default]
+    Hex,
+    Dec,
+    Signed,
+}
+
+impl Format {
+    pub fn value(self, value: u16) -> String {
+        match self {
+            Format::Hex => format!("{:#06x}", value),
+            Format::Dec => value.to_string(),
+            Format::Signed => (value as i16).to_string(),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(Format::Hex),
+            "dec" => Ok(Format::Dec),
+            "signed" => Ok(Format::Signed),
+            _ => Err(format!(
+                "Unknown format {:?}, expected hex, dec or signed",
+                s
+            )),
+        }
+    }
+}
+
+/// Every interpretation of a value at once, with the character if the low byte is printable.
+pub fn describe(value: u16) -> String {
+    let mut description = format!(
+        "{:#06x}, unsigned {}, signed {}",
+        value, value, value as i16
+    );
+
+    let c = char::from(value as u8);
+    if c.is_ascii_graphic() || c == ' ' {
+        write!(description, ", char {:?}", c).unwrap();
+    }
+
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value() {
+        assert_eq!(Format::Hex.value(0x8000), "0x8000");
+        assert_eq!(Format::Dec.value(0x8000), "32768");
+        assert_eq!(Format::Signed.value(0x8000), "-32768");
+        assert_eq!(Format::Signed.value(0x7fff), "32767");
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("signed".parse(), Ok(Format::Signed));
+        assert_eq!(
+            "octal".parse::<Format>(),
+            Err("Unknown format \"octal\", expected hex, dec or signed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(describe(0x41), "0x0041, unsigned 65, signed 65, char 'A'");
+        assert_eq!(describe(0x8000), "0x8000, unsigned 32768, signed -32768");
+        assert_eq!(describe(0x0a), "0x000a, unsigned 10, signed 10");
+    }
+}
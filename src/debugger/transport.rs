@@ -0,0 +1,160 @@
+use crate::terminal::{Native, Terminal};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Where debugger commands come from and where responses go.
+pub trait Transport {
+    /// Reads the next command line, returning `Ok(0)` once the client has gone away.
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize>;
+
+    fn write_response(&mut self, response: &str) -> io::Result<()>;
+
+    /// Reports a command that failed. Returning an error ends the session.
+    fn write_error(&mut self, message: &str) -> io::Result<()> {
+        self.write_response(message)
+    }
+}
+
+/// A debug client on the other end of a stream, e.g. a TCP or Unix socket connection. The buffers
+/// live as long as the connection so that input sent ahead of a response (e.g. several pasted
+/// lines) isn't lost.
+pub struct Stream<R: Read, W: Write> {
+    reader: BufReader<R>,
+    writer: BufWriter<W>,
+}
+
+impl<R: Read, W: Write> Stream<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Stream {
+            reader: BufReader::new(reader),
+            writer: BufWriter::new(writer),
+        }
+    }
+}
+
+impl<R: Read, W: Write> Transport for Stream<R, W> {
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        self.reader.read_line(line)
+    }
+
+    fn write_response(&mut self, response: &str) -> io::Result<()> {
+        writeln!(self.writer, "{}", response)?;
+        self.writer.flush()
+    }
+}
+
+/// Something debug clients connect to, yielding a stream per client.
+pub trait Listener {
+    type Stream;
+
+    /// Where clients should connect, for display.
+    fn address(&self) -> io::Result<String>;
+
+    /// Waits for the next client, returning its stream and a description of the peer.
+    fn accept(&self) -> io::Result<(Self::Stream, String)>;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn address(&self) -> io::Result<String> {
+        Ok(self.local_addr()?.to_string())
+    }
+
+    fn accept(&self) -> io::Result<(TcpStream, String)> {
+        let (stream, client) = TcpListener::accept(self)?;
+        Ok((stream, client.to_string()))
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    fn address(&self) -> io::Result<String> {
+        let address = self.local_addr()?;
+        Ok(match address.as_pathname() {
+            Some(path) => path.display().to_string(),
+            None => format!("{:?}", address),
+        })
+    }
+
+    fn accept(&self) -> io::Result<(UnixStream, String)> {
+        let (stream, _) = UnixListener::accept(self)?;
+        Ok((stream, "local socket client".to_string()))
+    }
+}
+
+/// Commands read from the VM's own STDIN with responses written to STDERR.
+///
+/// The terminal is switched back to canonical mode with echo while waiting at the prompt, and
+/// returned to raw mode before the program continues so that GETC keeps working.
+pub struct Local;
+
+impl Transport for Local {
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        eprint!("(lc3) ");
+
+        Native::set_line_buffering(true)?;
+        let result = io::stdin().read_line(line);
+        Native::set_line_buffering(false)?;
+
+        result
+    }
+
+    fn write_response(&mut self, response: &str) -> io::Result<()> {
+        writeln!(io::stderr(), "{}", response)
+    }
+}
+
+/// Commands read from a script file with responses written to STDOUT, so a session can be
+/// replayed and its output captured. Blank lines and `#` comments are skipped, and the first
+/// failing command ends the script.
+pub struct Script<R> {
+    reader: R,
+    line_number: usize,
+    pub error: Option<String>,
+}
+
+impl<R: BufRead> Script<R> {
+    pub fn new(reader: R) -> Self {
+        Script {
+            reader,
+            line_number: 0,
+            error: None,
+        }
+    }
+}
+
+impl<R: BufRead> Transport for Script<R> {
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        loop {
+            line.clear();
+            let read = self.reader.read_line(line)?;
+            if read == 0 {
+                return Ok(0);
+            }
+
+            self.line_number += 1;
+            let command = line.trim();
+            if !command.is_empty() && !command.starts_with('#') {
+                return Ok(read);
+            }
+        }
+    }
+
+    fn write_response(&mut self, response: &str) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        writeln!(stdout, "{}", response)?;
+        stdout.flush()
+    }
+
+    fn write_error(&mut self, message: &str) -> io::Result<()> {
+        let error = format!("line {}: {}", self.line_number, message);
+        self.error = Some(error.clone());
+
+        Err(io::Error::other(error))
+    }
+}
@@ -0,0 +1,383 @@
+//! A terminal UI front end with panes for the registers, disassembly, memory and console output.
+//!
+//! Keys: `s` step, `n` next (steps over subroutine calls), `c` continue, `b` toggle a breakpoint
+//! at the PC, PageUp/PageDown scroll memory, `:` opens a command line taking the usual debugger
+//! commands, and `q` quits.
+
+use super::format::Format;
+use super::{dump, Debugger};
+use crate::console::{BufferConsole, CaptureConsole, StdConsole, Throttled};
+use crate::disasm;
+use crate::instruction::Instruction;
+use crate::state::State;
+use crate::symbols::SymbolTable;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+use std::io;
+
+// How far PageUp/PageDown move the memory pane, a screenful at 8 words per line.
+const MEMORY_SCROLL: u16 = 0x40;
+
+struct Tui {
+    memory_address: u16,
+    previous_registers: [u16; 8],
+    // The command being typed after `:`.
+    command_line: Option<String>,
+    message: String,
+    // The program's output, shown in the console pane rather than printed under the UI.
+    output: BufferConsole,
+}
+
+impl Debugger {
+    /// Runs the terminal UI until the program halts or the user quits.
+    pub(super) fn tui_session(&mut self, state: State) -> io::Result<State> {
+        let output = BufferConsole::default();
+        state
+            .console
+            .replace(Box::new(CaptureConsole::new(output.clone())));
+
+        let mut tui = Tui {
+            memory_address: state.pc,
+            previous_registers: state.registers(),
+            command_line: None,
+            message: "s step, n next, c continue, b breakpoint, : command, q quit".to_string(),
+            output,
+        };
+
+        let mut terminal = ratatui::init();
+        let result = self.tui_loop(&mut terminal, state, &mut tui);
+        ratatui::restore();
+
+        let state = result?;
+        if self.detached {
+            // From here on output goes straight to STDOUT, starting with what was captured.
+            state
+                .console
+                .replace(Box::new(Throttled::new(StdConsole::default())));
+            state.console.lock().write(&tui.output.take_output());
+        }
+
+        Ok(state)
+    }
+
+    fn tui_loop(
+        &mut self,
+        terminal: &mut ratatui::DefaultTerminal,
+        mut state: State,
+        tui: &mut Tui,
+    ) -> io::Result<State> {
+        while state.running && !self.detached {
+            terminal.draw(|frame| self.draw(frame, &state, tui))?;
+
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+
+            if let Some(command_line) = &mut tui.command_line {
+                match key.code {
+                    KeyCode::Enter => {
+                        let line = command_line.clone();
+                        tui.command_line = None;
+                        state = self.run_line(state, tui, &line);
+                    }
+                    KeyCode::Esc => tui.command_line = None,
+                    KeyCode::Backspace => {
+                        command_line.pop();
+                    }
+                    KeyCode::Char(c) => command_line.push(c),
+                    _ => {}
+                }
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('s') => state = self.run_line(state, tui, "step"),
+                KeyCode::Char('n') => {
+                    let line = match Instruction::decode(state.memory.peek(state.pc)) {
+                        Instruction::JSR(_) | Instruction::JSRR(_) => {
+                            format!("until {:#06x}", state.pc.wrapping_add(1))
+                        }
+                        _ => "step".to_string(),
+                    };
+                    state = self.run_line(state, tui, &line);
+                }
+                KeyCode::Char('c') => state = self.run_line(state, tui, "continue"),
+                KeyCode::Char('b') => {
+                    let pc = state.pc;
+                    tui.message = match self.breakpoints.iter().position(|&a| a == pc) {
+                        Some(index) => {
+                            self.breakpoints.remove(index);
+                            format!("Breakpoint removed at {:#06x}", pc)
+                        }
+                        None => {
+                            self.breakpoints.push(pc);
+                            format!("Breakpoint set at {:#06x}", pc)
+                        }
+                    };
+                }
+                KeyCode::PageUp => {
+                    tui.memory_address = tui.memory_address.saturating_sub(MEMORY_SCROLL)
+                }
+                KeyCode::PageDown => {
+                    tui.memory_address = tui.memory_address.saturating_add(MEMORY_SCROLL)
+                }
+                KeyCode::Char(':') => tui.command_line = Some(String::new()),
+                KeyCode::Char('q') => state.running = false,
+                _ => {}
+            }
+        }
+
+        Ok(state)
+    }
+
+    // Runs a command line exactly as the text debugger would, keeping the response to display.
+    fn run_line(&mut self, mut state: State, tui: &mut Tui, line: &str) -> State {
+        tui.previous_registers = state.registers();
+
+        let command = self.parse_line(line);
+        tui.message = self
+            .execute(&mut state, command)
+            .unwrap_or_else(|message| message);
+
+        if self.debug_continue {
+            self.debug_continue = false;
+            state = self.resume(state);
+
+            if let Some(report) = self.stop_report.take() {
+                tui.message = report;
+            }
+        }
+
+        state
+    }
+
+    fn draw(&self, frame: &mut Frame, state: &State, tui: &Tui) {
+        let [top, memory, console, command] = Layout::vertical([
+            Constraint::Min(10),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [registers, disassembly] =
+            Layout::horizontal([Constraint::Length(22), Constraint::Min(0)]).areas(top);
+
+        let breakpoints = self.all_breakpoints();
+        let output = tui.output.output();
+
+        frame.render_widget(registers_pane(state, &tui.previous_registers), registers);
+        frame.render_widget(
+            disassembly_pane(
+                state,
+                &breakpoints,
+                &self.symbols,
+                inner_height(disassembly),
+            ),
+            disassembly,
+        );
+        frame.render_widget(
+            memory_pane(state, tui.memory_address, inner_height(memory), self.format),
+            memory,
+        );
+        frame.render_widget(console_pane(&output, inner_height(console)), console);
+
+        let command_line = match &tui.command_line {
+            Some(line) => format!(":{}", line),
+            None => tui.message.lines().last().unwrap_or_default().to_string(),
+        };
+        frame.render_widget(Paragraph::new(command_line), command);
+    }
+}
+
+fn inner_height(area: Rect) -> u16 {
+    area.height.saturating_sub(2)
+}
+
+/// The register file, with values changed by the last command highlighted.
+fn registers_pane(state: &State, previous: &[u16; 8]) -> Paragraph<'static> {
+    let changed = Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+
+    let mut lines: Vec<Line> = state
+        .registers
+        .iter()
+        .zip(previous)
+        .map(|((register, value), &previous)| {
+            let text = format!("{:?}  {:#06x} {:>6}", register, value, value as i16);
+            if value == previous {
+                Line::raw(text)
+            } else {
+                Line::styled(text, changed)
+            }
+        })
+        .collect();
+
+    lines.push(Line::raw(format!("PC  {:#06x}", state.pc)));
+    lines.push(Line::raw(format!("CC  {:?}", state.condition)));
+
+    Paragraph::new(lines).block(Block::bordered().title("Registers"))
+}
+
+/// A disassembly listing of `height` lines centered on the PC.
+fn disassembly_pane(
+    state: &State,
+    breakpoints: &[u16],
+    symbols: &SymbolTable,
+    height: u16,
+) -> Paragraph<'static> {
+    let start = state
+        .pc
+        .saturating_sub(height / 2)
+        .min(u16::MAX.saturating_sub(height.saturating_sub(1)));
+    let end = start.saturating_add(height.saturating_sub(1));
+
+    let current = Style::new().add_modifier(Modifier::REVERSED);
+    let breakpoint = Style::new().fg(Color::Red);
+
+    let lines: Vec<Line> =
+        disasm::listing(&state.memory, state.pc, start..=end, breakpoints, symbols)
+            .lines()
+            .map(|line| {
+                if line.starts_with("=>") {
+                    Line::styled(line.to_string(), current)
+                } else if line[2..].starts_with('*') {
+                    Line::styled(line.to_string(), breakpoint)
+                } else {
+                    Line::raw(line.to_string())
+                }
+            })
+            .collect();
+
+    Paragraph::new(lines).block(Block::bordered().title("Disassembly"))
+}
+
+/// `height` lines of hexdump starting at `address`.
+fn memory_pane(state: &State, address: u16, height: u16, format: Format) -> Paragraph<'static> {
+    let end = (u32::from(address) + u32::from(height) * 8).min(0x10000);
+
+    let lines: Vec<Line> = dump(state, u32::from(address)..end, format)
+        .lines()
+        .map(|line| Line::raw(line.to_string()))
+        .collect();
+
+    Paragraph::new(lines).block(Block::bordered().title(format!("Memory {:#06x}", address)))
+}
+
+/// The last `height` lines the program has written.
+fn console_pane(output: &[u8], height: u16) -> Paragraph<'static> {
+    let output = String::from_utf8_lossy(output);
+    let lines: Vec<&str> = output.lines().collect();
+
+    let lines: Vec<Line> = lines[lines.len().saturating_sub(height.into())..]
+        .iter()
+        .map(|line| Line::from(Span::raw(line.to_string())))
+        .collect();
+
+    Paragraph::new(lines).block(Block::bordered().title("Console"))
+}
+
+#[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
+mod tests {
+    use super::*;
+    use ratatui::buffer::Buffer;
+    use ratatui::widgets::Widget;
+
+    fn render(widget: impl Widget, width: u16, height: u16) -> Buffer {
+        let area = Rect::new(0, 0, width, height);
+        let mut buffer = Buffer::empty(area);
+        widget.render(area, &mut buffer);
+        buffer
+    }
+
+    fn lines(buffer: &Buffer) -> Vec<String> {
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_registers_pane() {
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.registers.restore([0, 0xfffd, 0, 0, 0, 0, 0, 0]);
+
+        let buffer = render(registers_pane(&state, &[0; 8]), 22, 12);
+
+        let lines = lines(&buffer);
+        assert_eq!(lines[1], "│R0  0x0000      0   │");
+        assert_eq!(lines[2], "│R1  0xfffd     -3   │");
+        assert_eq!(lines[9], "│PC  0x3000          │");
+        assert_eq!(lines[10], "│CC  P               │");
+
+        // Only the changed register is highlighted.
+        assert_eq!(buffer[(1, 2)].fg, Color::Yellow);
+        assert_eq!(buffer[(1, 1)].fg, Color::Reset);
+    }
+
+    #[test]
+    fn test_disassembly_pane() {
+        let mut state = State::new();
+        state.pc = 0x3002;
+        for address in 0x3000..0x3005 {
+            state.memory.write(address, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        }
+
+        let pane = disassembly_pane(&state, &[0x3003], &SymbolTable::new(), 3);
+        let lines = lines(&render(pane, 40, 5));
+
+        assert_eq!(
+            lines,
+            [
+                "┌Disassembly───────────────────────────┐",
+                "│    x3001  x1261  ADD R1, R1, #1      │",
+                "│=>  x3002  x1261  ADD R1, R1, #1      │",
+                "│  * x3003  x1261  ADD R1, R1, #1      │",
+                "└──────────────────────────────────────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassembly_pane_at_end_of_memory() {
+        let mut state = State::new();
+        state.pc = 0xffff;
+
+        let pane = disassembly_pane(&state, &[], &SymbolTable::new(), 2);
+        let lines = lines(&render(pane, 40, 4));
+
+        assert!(lines[1].contains("xFFFE"));
+        assert!(lines[2].starts_with("│=>  xFFFF"));
+    }
+
+    #[test]
+    fn test_memory_pane() {
+        let mut state = State::new();
+        state.memory.write(0x3000, 0x0048);
+        state.memory.write(0x3001, 0x0069);
+
+        let lines = lines(&render(memory_pane(&state, 0x3000, 1, Format::Hex), 80, 3));
+
+        assert!(lines[0].starts_with("┌Memory 0x3000"));
+        assert!(lines[1].starts_with("│0x3000: 0x0048 0x0069 0x0000"));
+        assert!(lines[1].contains("|Hi......|"));
+    }
+
+    #[test]
+    fn test_console_pane_shows_the_last_lines() {
+        let lines = lines(&render(console_pane(b"one\ntwo\nthree\n", 2), 10, 4));
+
+        assert_eq!(lines[1], "│two     │");
+        assert_eq!(lines[2], "│three   │");
+    }
+}
@@ -0,0 +1,697 @@
+#[cfg(feature = "std")]
+use crate::asm::Program;
+use crate::instruction::{Instruction, TrapVector};
+use crate::state::memory::Memory;
+use crate::symbols::SymbolTable;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::fmt::Write;
+use core::iter;
+use core::ops::RangeInclusive;
+
+// The fewest characters a run of data needs before it's taken for a string, so a lone number that
+// happens to be followed by a zero stays a `.FILL`.
+const MIN_STRING: usize = 2;
+
+/// Formats a single word as it would appear in a listing. Words that aren't valid instructions
+/// are rendered as `.FILL` directives.
+pub fn disassemble_word(address: u16, word: u16, symbols: &SymbolTable) -> String {
+    match Instruction::decode(word) {
+        Instruction::UNUSED | Instruction::RESERVED | Instruction::UNKNOWNTRAP(_) => {
+            format!(".FILL x{:04X}", word)
+        }
+        instruction => instruction.disassemble(address, symbols),
+    }
+}
+
+/// Writes `program` out as assembly source that assembles back to exactly the same words: a
+/// `.ORIG`, one instruction or `.FILL` per word and `.END`. PC-relative operands that point into
+/// the program refer to a label there, from `symbols` if it has one at that address and made up
+/// otherwise, see [`program_listing`]. Words that wouldn't assemble back to themselves, like a TRAP with an unknown
+/// vector or an ADD with stray bits set, are written as `.FILL`s.
+#[cfg(feature = "std")]
+pub fn disassemble_program(program: &Program, symbols: Option<&SymbolTable>) -> String {
+    let origin = program.origin;
+    let end = usize::from(origin) + program.words.len();
+    let inside = |address: u16| (usize::from(origin)..end).contains(&usize::from(address));
+    let words: Vec<(u16, u16, Option<Instruction>)> = (origin..=u16::MAX)
+        .zip(&program.words)
+        .map(|(address, word)| (address, *word, reassemblable(*word)))
+        .collect();
+
+    let mut labels = BTreeMap::new();
+    for (name, address) in symbols.into_iter().flat_map(SymbolTable::iter) {
+        if inside(*address) {
+            labels.entry(*address).or_insert_with(|| name.clone());
+        }
+    }
+    let program_words: Vec<u16> = words.iter().map(|(_, word, _)| *word).collect();
+    for (address, name) in
+        generated_labels(origin, &program_words, |index| words[index].2.is_some())
+    {
+        labels.entry(address).or_insert(name);
+    }
+
+    let mut source = format!("        .ORIG x{:04X}\n", origin);
+    for (address, word, instruction) in &words {
+        let text = match instruction {
+            Some(instruction) => instruction.assembly(&|offset| match labels
+                .get(&address.wrapping_add(1).wrapping_add(offset))
+            {
+                Some(label) => label.clone(),
+                None => format!("#{}", offset as i16),
+            }),
+            None => format!(".FILL x{:04X}", word),
+        };
+        let label = labels.get(address).map_or("", String::as_str);
+        // Labels too long for the column go on a line of their own.
+        if label.len() > 7 {
+            writeln!(source, "{}", label).expect("writing to a string");
+            writeln!(source, "{:<7} {}", "", text).expect("writing to a string");
+        } else {
+            writeln!(source, "{:<7} {}", label, text).expect("writing to a string");
+        }
+    }
+    source.push_str("        .END\n");
+
+    source
+}
+
+// The word decoded, if assembling the instruction gives the same word back.
+#[cfg(feature = "std")]
+fn reassemblable(word: u16) -> Option<Instruction> {
+    match Instruction::decode(word) {
+        Instruction::UNUSED | Instruction::RESERVED => None,
+        // A branch on no conditions is a NOP to the assembler, which has no offset.
+        Instruction::BR(condition, _) if !(condition.n || condition.z || condition.p) => {
+            Some(Instruction::decode(word)).filter(|_| word == 0)
+        }
+        instruction => Some(instruction).filter(|instruction| instruction.encode() == word),
+    }
+}
+
+/// One word of a listing, see [`program_listing`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingEntry {
+    pub address: u16,
+    pub word: u16,
+    /// The symbol at this address, if there is one.
+    pub label: Option<String>,
+    /// The word disassembled, with PC-relative targets resolved, or a `.FILL`.
+    pub text: String,
+    /// The offset the target was resolved from, for an instruction with a PC-relative operand.
+    pub offset: Option<i16>,
+}
+
+/// Lists every word of an object file image, origin first, without running it. Only the words
+/// inside `range` are listed if it's given.
+///
+/// The targets of PC-relative operands inside the program that have no symbol are given one named
+/// for how they're used: `SUB_3050` for a subroutine called with `JSR`, `L_3010` for a branch and
+/// `DATA_3100` for anything loaded, stored or taken the address of with `LEA`.
+pub fn program_listing(
+    image: &[u16],
+    range: Option<RangeInclusive<u16>>,
+    symbols: &SymbolTable,
+) -> Vec<ListingEntry> {
+    let (origin, words) = match split_image(image) {
+        Some(image) => image,
+        None => return Vec::new(),
+    };
+    let symbols = &labeled(symbols, generated_labels(origin, words, |_| true));
+
+    words
+        .iter()
+        .zip(origin..=u16::MAX)
+        .filter(|(_, address)| range.as_ref().is_none_or(|range| range.contains(address)))
+        .map(|(word, address)| entry(address, *word, symbols))
+        .collect()
+}
+
+/// Like [`program_listing`], but only the words that can be reached from the origin, following
+/// branches and calls, are disassembled, and only their operands are given labels. The rest are data: a `.STRINGZ` on the first word of a
+/// zero-terminated run of printable characters, with the words after it left blank, and `.FILL`s
+/// otherwise. Computed jumps like `JMP R1` and `RET` end a trace, as where they go isn't known
+/// without running the program.
+pub fn analyzed_listing(
+    image: &[u16],
+    range: Option<RangeInclusive<u16>>,
+    symbols: &SymbolTable,
+) -> Vec<ListingEntry> {
+    let (origin, words) = match split_image(image) {
+        Some(image) => image,
+        None => return Vec::new(),
+    };
+    let code = reachable(origin, words);
+    let symbols = &labeled(
+        symbols,
+        generated_labels(origin, words, |index| code[index]),
+    );
+
+    let mut texts: Vec<(String, Option<i16>)> = Vec::with_capacity(words.len());
+    while texts.len() < words.len() {
+        let index = texts.len();
+        let (address, word) = (origin.wrapping_add(index as u16), words[index]);
+
+        if code[index] {
+            texts.push((disassemble_word(address, word, symbols), pc_offset(word)));
+        } else if let Some(length) = string_length(&words[index..], &code[index..]) {
+            texts.push((stringz(&words[index..index + length]), None));
+            // The rest of the characters and the terminator.
+            texts.extend(iter::repeat_n((String::new(), None), length));
+        } else {
+            texts.push((format!(".FILL x{:04X}", word), None));
+        }
+    }
+
+    words
+        .iter()
+        .zip(origin..=u16::MAX)
+        .zip(texts)
+        .filter(|((_, address), _)| range.as_ref().is_none_or(|range| range.contains(address)))
+        .map(|((word, address), (text, offset))| ListingEntry {
+            address,
+            word: *word,
+            label: symbols.label(address).map(ToString::to_string),
+            text,
+            offset,
+        })
+        .collect()
+}
+
+// The origin and the words loaded after it. Anything past the end of memory isn't loaded.
+fn split_image(image: &[u16]) -> Option<(u16, &[u16])> {
+    let (origin, words) = image.split_first()?;
+    Some((
+        *origin,
+        &words[..words.len().min(0x10000 - usize::from(*origin))],
+    ))
+}
+
+// The order made-up labels are chosen in when an address is used more than one way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    Data,
+    Branch,
+    Subroutine,
+}
+
+// Labels for the targets of the PC-relative operands in the words that are code, where they land
+// inside the program. Named as described on `program_listing`, with a subroutine taking precedence
+// over a branch, and a branch over data.
+fn generated_labels(
+    origin: u16,
+    words: &[u16],
+    is_code: impl Fn(usize) -> bool,
+) -> BTreeMap<u16, String> {
+    let inside = |address: u16| usize::from(address.wrapping_sub(origin)) < words.len();
+    let mut targets = BTreeMap::new();
+
+    for (index, (word, address)) in words.iter().zip(origin..=u16::MAX).enumerate() {
+        let offset = match pc_offset(*word) {
+            Some(offset) if is_code(index) => offset,
+            _ => continue,
+        };
+        let target = address.wrapping_add(1).wrapping_add(offset as u16);
+        if !inside(target) {
+            continue;
+        }
+
+        let usage = match Instruction::decode(*word) {
+            Instruction::JSR(_) => Target::Subroutine,
+            Instruction::BR(..) => Target::Branch,
+            _ => Target::Data,
+        };
+        let chosen = targets.entry(target).or_insert(usage);
+        *chosen = usage.max(*chosen);
+    }
+
+    targets
+        .into_iter()
+        .map(|(address, usage)| {
+            let prefix = match usage {
+                Target::Data => "DATA",
+                Target::Branch => "L",
+                Target::Subroutine => "SUB",
+            };
+            (address, format!("{}_{:04X}", prefix, address))
+        })
+        .collect()
+}
+
+// `symbols` with the generated labels added, where there isn't a symbol at the address already.
+fn labeled(symbols: &SymbolTable, generated: BTreeMap<u16, String>) -> SymbolTable {
+    let mut symbols = symbols.clone();
+    for (address, name) in generated {
+        if symbols.label(address).is_none() {
+            symbols.insert(&name, address);
+        }
+    }
+
+    symbols
+}
+
+// Which of the words can be reached as code from the first one. Only branches and calls to targets
+// inside the program are followed.
+fn reachable(origin: u16, words: &[u16]) -> Vec<bool> {
+    let mut code = vec![false; words.len()];
+    let mut pending = vec![origin];
+
+    while let Some(address) = pending.pop() {
+        let index = usize::from(address.wrapping_sub(origin));
+        if index >= words.len() || code[index] {
+            continue;
+        }
+        let instruction = match Instruction::decode(words[index]) {
+            Instruction::UNUSED | Instruction::RESERVED | Instruction::UNKNOWNTRAP(_) => continue,
+            instruction => instruction,
+        };
+        code[index] = true;
+
+        let next = address.wrapping_add(1);
+        let target = instruction
+            .pc_offset()
+            .map(|offset| next.wrapping_add(offset));
+        match instruction {
+            Instruction::BR(condition, _) => {
+                if condition.n || condition.z || condition.p {
+                    pending.extend(target);
+                }
+                if !(condition.n && condition.z && condition.p) {
+                    pending.push(next);
+                }
+            }
+            Instruction::JSR(_) => {
+                pending.extend(target);
+                pending.push(next);
+            }
+            Instruction::JMP(_) | Instruction::JSRR(_) | Instruction::TRAP(TrapVector::HALT) => {}
+            _ => pending.push(next),
+        }
+    }
+
+    code
+}
+
+// The number of characters in the string at the start of `words`, if they're a long enough run of
+// printable characters followed by a zero, and none of it is code.
+fn string_length(words: &[u16], code: &[bool]) -> Option<usize> {
+    let length = words
+        .iter()
+        .zip(code)
+        .take_while(|(word, code)| !**code && is_character(**word))
+        .count();
+    let terminated = words.get(length) == Some(&0) && code.get(length) == Some(&false);
+
+    Some(length).filter(|length| terminated && *length >= MIN_STRING)
+}
+
+fn is_character(word: u16) -> bool {
+    matches!(word, 0x20..=0x7e | 0x09 | 0x0a)
+}
+
+// A `.STRINGZ` of the characters, escaped the way the assembler reads them.
+fn stringz(words: &[u16]) -> String {
+    let mut text = String::from(".STRINGZ \"");
+    for word in words {
+        match *word as u8 {
+            b'\n' => text.push_str("\\n"),
+            b'\t' => text.push_str("\\t"),
+            b'"' => text.push_str("\\\""),
+            b'\\' => text.push_str("\\\\"),
+            c => text.push(char::from(c)),
+        }
+    }
+    text.push('"');
+
+    text
+}
+
+fn entry(address: u16, word: u16, symbols: &SymbolTable) -> ListingEntry {
+    ListingEntry {
+        address,
+        word,
+        label: symbols.label(address).map(ToString::to_string),
+        text: disassemble_word(address, word, symbols),
+        offset: pc_offset(word),
+    }
+}
+
+/// The offset of the word's PC-relative operand, if it's an instruction with one.
+pub fn pc_offset(word: u16) -> Option<i16> {
+    match Instruction::decode(word) {
+        // Shown as a NOP, which has no operand.
+        Instruction::BR(condition, _) if !(condition.n || condition.z || condition.p) => None,
+        instruction => instruction.pc_offset().map(|offset| offset as i16),
+    }
+}
+
+/// Disassembles `range`, marking the line at `pc` with `=>` and any breakpoints with `*`.
+pub fn listing(
+    memory: &Memory,
+    pc: u16,
+    range: RangeInclusive<u16>,
+    breakpoints: &[u16],
+    symbols: &SymbolTable,
+) -> String {
+    let addresses: Vec<String> = range
+        .clone()
+        .map(|address| symbols.format_address(address))
+        .collect();
+    let width = addresses.iter().map(String::len).max().unwrap_or(0);
+
+    range
+        .zip(addresses)
+        .map(|(address, formatted_address)| {
+            let entry = entry(address, memory.peek(address), symbols);
+
+            format!(
+                "{}{} {:<width$}  x{:04X}  {}",
+                if address == pc { "=>" } else { "  " },
+                if breakpoints.contains(&address) {
+                    "*"
+                } else {
+                    " "
+                },
+                formatted_address,
+                entry.word,
+                entry.text,
+                width = width
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::console::SharedConsole;
+
+    #[test]
+    fn test_disassemble_program() {
+        let program = assemble(
+            "
+            .ORIG x3000
+            LD R1, COUNT
+    LOOP    ADD R0, R0, R1
+            ADD R1, R1, #-1
+            BRp LOOP
+            HALT
+    COUNT   .FILL #5
+            .END
+        ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            disassemble_program(&program, None),
+            "        .ORIG x3000
+        LD R1, DATA_3005
+L_3001  ADD R0, R0, R1
+        ADD R1, R1, #-1
+        BRp L_3001
+        HALT
+DATA_3005
+        .FILL x0005
+        .END
+"
+        );
+        assert!(disassemble_program(&program, Some(&program.symbols)).contains("BRp LOOP\n"));
+    }
+
+    #[test]
+    fn test_generated_labels() {
+        let program = assemble(include_str!("../tests/fixtures/countdown.asm")).unwrap();
+        let mut image = vec![program.origin];
+        image.extend(&program.words);
+
+        let listing: Vec<String> = program_listing(&image, None, &SymbolTable::new())
+            .into_iter()
+            .map(|entry| format!("{:<9}  {}", entry.label.unwrap_or_default(), entry.text))
+            .collect();
+        assert_eq!(
+            listing,
+            [
+                "           LD R1, DATA_300B",
+                "L_3001     JSR SUB_3006",
+                "           ADD R1, R1, #-1",
+                "           BRp L_3001",
+                "           ST R1, DATA_300B",
+                "           HALT",
+                "SUB_3006   LEA R0, DATA_300C",
+                "           LDR R0, R0, #0",
+                "           ADD R0, R0, R1",
+                "           OUT",
+                "           RET",
+                "DATA_300B  NOP",
+                "DATA_300C  NOP",
+            ]
+        );
+
+        assert_eq!(
+            disassemble_program(&program, None),
+            "        .ORIG x3000
+        LD R1, DATA_300B
+L_3001  JSR SUB_3006
+        ADD R1, R1, #-1
+        BRp L_3001
+        ST R1, DATA_300B
+        HALT
+SUB_3006
+        LEA R0, DATA_300C
+        LDR R0, R0, #0
+        ADD R0, R0, R1
+        OUT
+        RET
+DATA_300B
+        .FILL x0003
+DATA_300C
+        .FILL x0030
+        .END
+"
+        );
+
+        // Real names are used where there are some.
+        let symbols = SymbolTable::parse("//\tPRINT 3006\n//\tLOOP 3001\n");
+        let entries = program_listing(&image, None, &symbols);
+        assert_eq!(entries[1].label.as_deref(), Some("LOOP"));
+        assert_eq!(entries[1].text, "JSR PRINT");
+        assert_eq!(entries[4].text, "ST R1, DATA_300B");
+        assert_eq!(entries[6].label.as_deref(), Some("PRINT"));
+        assert!(
+            disassemble_program(&program, Some(&symbols)).contains("\nPRINT   LEA R0, DATA_300C\n")
+        );
+    }
+
+    #[test]
+    fn test_disassembly_reassembles() {
+        let round_trip = |program: &Program| {
+            let source = disassemble_program(program, Some(&program.symbols));
+            let reassembled = assemble(&source).unwrap_or_else(|errors| {
+                panic!("{:?} in\n{}", errors, source);
+            });
+            assert_eq!(reassembled.origin, program.origin);
+            assert_eq!(reassembled.words, program.words, "{}", source);
+        };
+
+        round_trip(&assemble(include_str!("../os/lc3os.asm")).unwrap());
+        round_trip(
+            &assemble(
+                "
+                .ORIG x3000
+                LEA R0, GREETING
+                PUTS
+                JSR DONE
+                LDI R1, POINTER
+                STI R1, POINTER
+                ST R1, #-6
+                BRnzp x2FF0
+        DONE    RET
+        POINTER .FILL x4000
+        GREETING .STRINGZ \"Hello, World!\"
+                .END
+            ",
+            )
+            .unwrap(),
+        );
+        // Words that can't be written as an instruction that assembles back to them.
+        round_trip(&Program {
+            origin: 0x3000,
+            words: vec![
+                0x0000, // NOP
+                0x0005, // a branch on no conditions, with an offset
+                0x1018, // ADD R0, R0, R0 with bits 3 and 4 set
+                0x8000, // UNUSED
+                0xd000, // RESERVED
+                0xf030, // an unknown TRAP vector
+                0xc1c1, // RET with a stray low bit
+                0x9040, // NOT with its low bits clear
+            ],
+            symbols: SymbolTable::new(),
+        });
+    }
+
+    #[test]
+    fn test_listing() {
+        let mut memory = Memory::new(&SharedConsole::default());
+        memory.write(0x2fff, 0x1261); // ADD R1, R1, #1
+        memory.write(0x3000, 0x0bfe); // BRnp #-2
+        memory.write(0x3001, 0xf025); // HALT
+        memory.write(0x3002, 0xf0ff); // unknown TRAP vector
+        memory.write(0x3003, 0xd000); // RESERVED
+        memory.write(0x3004, 0xe1fb); // LEA R0, #-5
+
+        let listing = listing(
+            &memory,
+            0x3000,
+            0x2ffe..=0x3004,
+            &[0x3001, 0x2000],
+            &SymbolTable::new(),
+        );
+
+        assert_eq!(
+            listing,
+            [
+                "    x2FFE  x0000  NOP",
+                "    x2FFF  x1261  ADD R1, R1, #1",
+                "=>  x3000  x0BFE  BRnp x2FFF",
+                "  * x3001  xF025  HALT",
+                "    x3002  xF0FF  .FILL xF0FF",
+                "    x3003  xD000  .FILL xD000",
+                "    x3004  xE1FB  LEA R0, x3000",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_program_listing() {
+        // .ORIG x3000
+        // LOOP  ADD R1, R1, #1
+        //       BRnp LOOP
+        //       .FILL xD000
+        let image = [0x3000, 0x1261, 0x0bfe, 0xd000];
+        let symbols = SymbolTable::parse("//\tLOOP 3000\n");
+
+        let entries = program_listing(&image, None, &symbols);
+        assert_eq!(
+            entries[..2],
+            [
+                ListingEntry {
+                    address: 0x3000,
+                    word: 0x1261,
+                    label: Some("LOOP".to_string()),
+                    text: "ADD R1, R1, #1".to_string(),
+                    offset: None,
+                },
+                ListingEntry {
+                    address: 0x3001,
+                    word: 0x0bfe,
+                    label: None,
+                    text: "BRnp LOOP".to_string(),
+                    offset: Some(-2),
+                },
+            ]
+        );
+        assert_eq!(entries[2].text, ".FILL xD000");
+        assert_eq!(entries[2].offset, None);
+
+        let entries = program_listing(&image, Some(0x3002..=0x4000), &symbols);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].address, 0x3002);
+        assert!(program_listing(&[], None, &symbols).is_empty());
+    }
+
+    #[test]
+    fn test_analyzed_listing() {
+        let program = assemble(
+            "
+            .ORIG x3000
+            LEA R0, GREETING
+            PUTS
+            LD R1, SIZE
+            BRz DONE
+            JSR SHOUT
+    DONE    HALT
+    SHOUT   JMP R1
+    AFTER   ADD R0, R0, #1
+    SIZE    .FILL #65
+            .FILL #0
+    GREETING .STRINGZ \"Hi \\\"you\\\"\\n\"
+            .FILL x0048
+            .END
+        ",
+        )
+        .unwrap();
+        let mut image = vec![program.origin];
+        image.extend(&program.words);
+
+        let entries = analyzed_listing(&image, None, &program.symbols);
+        let texts: Vec<&str> = entries.iter().map(|entry| entry.text.as_str()).collect();
+        assert_eq!(
+            texts[..11],
+            [
+                "LEA R0, GREETING",
+                "PUTS",
+                "LD R1, SIZE",
+                "BRz DONE",
+                "JSR SHOUT",
+                "HALT",
+                "JMP R1",
+                // Only reached through the computed jump.
+                ".FILL x1021",
+                // Too short for a string.
+                ".FILL x0041",
+                ".FILL x0000",
+                ".STRINGZ \"Hi \\\"you\\\"\\n\"",
+            ]
+        );
+        assert!(texts[11..20].iter().all(|text| text.is_empty()));
+        // Not terminated.
+        assert_eq!(texts[20..], [".FILL x0048"]);
+        assert_eq!(entries[3].offset, Some(1));
+        assert_eq!(entries[10].label.as_deref(), Some("GREETING"));
+
+        let entries = analyzed_listing(&image, Some(0x3005..=0x3006), &program.symbols);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].text, "JMP R1");
+        assert!(analyzed_listing(&[], None, &program.symbols).is_empty());
+    }
+
+    #[test]
+    fn test_symbolized_listing() {
+        let mut memory = Memory::new(&SharedConsole::default());
+        memory.write(0x3000, 0x5260); // AND R1, R1, #0
+        memory.write(0x3001, 0x2005); // LD R0, COUNT
+        memory.write(0x3002, 0x1261); // LOOP ADD R1, R1, #1
+        memory.write(0x3003, 0x103f); // ADD R0, R0, #-1
+        memory.write(0x3004, 0x03fd); // BRp LOOP
+        memory.write(0x3005, 0xe024); // LEA R0, x302A
+        memory.write(0x3006, 0xf025); // HALT
+        memory.write(0x3007, 0x0005); // COUNT .FILL 5
+        let symbols = SymbolTable::parse("//\tSTART 3000\n//\tLOOP 3002\n//\tCOUNT 3007\n");
+
+        let listing = listing(&memory, 0x3002, 0x3000..=0x3007, &[], &symbols);
+
+        assert_eq!(
+            listing,
+            [
+                "    START    x5260  AND R1, R1, #0",
+                "    START+1  x2005  LD R0, COUNT",
+                "=>  LOOP     x1261  ADD R1, R1, #1",
+                "    LOOP+1   x103F  ADD R0, R0, #-1",
+                "    LOOP+2   x03FD  BRp LOOP",
+                "    LOOP+3   xE024  LEA R0, x302A",
+                "    LOOP+4   xF025  HALT",
+                "    COUNT    x0005  NOP",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use crate::disasm;
+use crate::symbols::SymbolTable;
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+use alloc::string::ToString;
+use core::error::Error;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "std")]
+pub(crate) use std::io::Error as IoError;
+
+/// A console I/O failure without `std`, described by its message.
+#[cfg(not(feature = "std"))]
+#[derive(Debug)]
+pub struct IoError(pub String);
+
+#[cfg(not(feature = "std"))]
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Error for IoError {}
+
+/// Why the machine stopped before the program halted, or why [`crate::run`] couldn't run it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum VmError {
+    /// The instruction budget ran out. `word` is the next instruction, at `pc`.
+    StepLimitExceeded { limit: u64, pc: u16, word: u16 },
+    /// Ctrl-C was pressed, after `instructions` had been executed in all.
+    Interrupted { pc: u16, instructions: u64 },
+    /// The run went past its deadline, possibly while waiting for input.
+    Timeout { pc: u16 },
+    /// The instruction at `pc` uses the unused or reserved opcode.
+    IllegalOpcode { pc: u16, word: u16 },
+    /// The TRAP at `pc` has a vector with neither a built-in routine nor a registered handler.
+    BadTrapVector { pc: u16, vector: u8 },
+    /// The instruction at `pc` stores to `address`, which is write-protected. The store didn't
+    /// happen and the PC still points at the instruction.
+    WriteToProtected { address: u16, pc: u16 },
+    /// The machine couldn't be built, e.g. the program file is missing.
+    #[cfg(feature = "std")]
+    Build(BuildError),
+    /// Console I/O, reading the symbols or talking to the debugger failed.
+    Io(IoError),
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::StepLimitExceeded { limit, pc, word } => write!(
+                f,
+                "Step limit of {} reached at PC {:#06x}: {}",
+                limit,
+                pc,
+                disasm::disassemble_word(*pc, *word, &SymbolTable::new())
+            ),
+            VmError::Interrupted { pc, instructions } => write!(
+                f,
+                "Interrupted at PC {:#06x} after {} instructions",
+                pc, instructions
+            ),
+            VmError::Timeout { pc } => write!(f, "Timed out at PC {:#06x}", pc),
+            VmError::IllegalOpcode { pc, word } => {
+                write!(f, "Illegal opcode {:#06x} at PC {:#06x}", word, pc)
+            }
+            VmError::BadTrapVector { pc, vector } => {
+                write!(f, "Unknown TRAP vector {:#04x} at PC {:#06x}", vector, pc)
+            }
+            VmError::WriteToProtected { address, pc } => write!(
+                f,
+                "Write to protected address {:#06x} at PC {:#06x}",
+                address, pc
+            ),
+            #[cfg(feature = "std")]
+            VmError::Build(e) => write!(f, "{}", e),
+            VmError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for VmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            VmError::Build(e) => Some(e),
+            VmError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// io::Error can't be compared, so errors carrying one are equal when they read the same.
+impl PartialEq for VmError {
+    fn eq(&self, other: &Self) -> bool {
+        use VmError::*;
+
+        match (self, other) {
+            (
+                StepLimitExceeded { limit, pc, word },
+                StepLimitExceeded {
+                    limit: other_limit,
+                    pc: other_pc,
+                    word: other_word,
+                },
+            ) => limit == other_limit && pc == other_pc && word == other_word,
+            (
+                Interrupted { pc, instructions },
+                Interrupted {
+                    pc: other_pc,
+                    instructions: other_instructions,
+                },
+            ) => pc == other_pc && instructions == other_instructions,
+            (Timeout { pc }, Timeout { pc: other }) => pc == other,
+            (
+                IllegalOpcode { pc, word },
+                IllegalOpcode {
+                    pc: other_pc,
+                    word: other_word,
+                },
+            ) => pc == other_pc && word == other_word,
+            (
+                BadTrapVector { pc, vector },
+                BadTrapVector {
+                    pc: other_pc,
+                    vector: other_vector,
+                },
+            ) => pc == other_pc && vector == other_vector,
+            (
+                WriteToProtected { address, pc },
+                WriteToProtected {
+                    address: other_address,
+                    pc: other_pc,
+                },
+            ) => address == other_address && pc == other_pc,
+            #[cfg(feature = "std")]
+            (Build(_), Build(_)) => self.to_string() == other.to_string(),
+            (Io(e), Io(other_e)) => e.to_string() == other_e.to_string(),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<BuildError> for VmError {
+    fn from(e: BuildError) -> Self {
+        VmError::Build(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<FileError> for VmError {
+    fn from(e: FileError) -> Self {
+        VmError::Io(e.into())
+    }
+}
+
+impl From<IoError> for VmError {
+    fn from(e: IoError) -> Self {
+        VmError::Io(e)
+    }
+}
+
+/// Why a [`crate::VmBuilder`] couldn't build a machine.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// Both `program_bytes` and `program_file` were given.
+    ConflictingPrograms,
+    /// Neither a program nor an OS image was given.
+    NoProgram,
+    /// The entry point isn't inside any loaded image.
+    EntryOutsideProgram(u16),
+    /// A program or OS image couldn't be loaded.
+    Load(io::Error),
+    /// A program file couldn't be read or loaded.
+    File(FileError),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::ConflictingPrograms => {
+                write!(f, "Both program bytes and a program file were given")
+            }
+            BuildError::NoProgram => write!(f, "No program to load"),
+            BuildError::EntryOutsideProgram(entry) => write!(
+                f,
+                "Entry point {:#06x} is outside the loaded program",
+                entry
+            ),
+            BuildError::Load(e) => write!(f, "{}", e),
+            BuildError::File(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Load(e) => Some(e),
+            BuildError::File(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Reading a file failed. Unlike a bare [`io::Error`] the message says which file.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct FileError {
+    pub path: PathBuf,
+    pub source: io::Error,
+}
+
+#[cfg(feature = "std")]
+impl FileError {
+    pub fn new(path: impl AsRef<Path>, source: io::Error) -> Self {
+        FileError {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.source)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+// Keeps the kind, so callers that only deal in io::Error still see e.g. NotFound.
+#[cfg(feature = "std")]
+impl From<FileError> for io::Error {
+    fn from(e: FileError) -> Self {
+        io::Error::new(e.source.kind(), e)
+    }
+}
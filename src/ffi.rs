@@ -0,0 +1,343 @@
+//! A C ABI for embedding the machine in C or C++. The header, `include/lc3.h`, is generated by
+//! cbindgen when building with the `ffi` feature.
+//!
+//! Functions that can fail return a negative `LC3_ERROR_*` code, with the reason available from
+//! [`lc3_last_error_message`] on the same thread.
+
+use crate::console::{BufferConsole, Console, Instant};
+use crate::instruction::Register;
+use crate::vm::{StepResult, Vm};
+use std::cell::RefCell;
+use std::ffi::{c_void, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+/// A null `Lc3Vm` or buffer was passed.
+pub const LC3_ERROR_NULL: c_int = -1;
+/// The program couldn't be loaded, e.g. it's shorter than its origin.
+pub const LC3_ERROR_LOAD: c_int = -2;
+/// The register number isn't 0 to 7.
+pub const LC3_ERROR_REGISTER: c_int = -3;
+/// The program failed, e.g. on an illegal opcode.
+pub const LC3_ERROR_EXECUTION: c_int = -4;
+
+/// Called with each write the program makes to the console, and the `user_data` it was
+/// registered with. Null discards the output.
+pub type Lc3OutputCallback =
+    Option<extern "C" fn(bytes: *const u8, len: usize, user_data: *mut c_void)>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(code: c_int, message: impl ToString) -> c_int {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    code
+}
+
+/// A machine owned by C code, created with [`lc3_vm_new`] and destroyed with [`lc3_vm_free`].
+pub struct Lc3Vm {
+    vm: Vm,
+    keys: BufferConsole,
+}
+
+// Keys from `lc3_vm_push_key`, and output to the registered callback if there is one.
+struct FfiConsole {
+    keys: BufferConsole,
+    output: Lc3OutputCallback,
+    user_data: UserData,
+}
+
+struct UserData(*mut c_void);
+
+// The caller registering the callback vouches for it being usable from whichever thread steps
+// the machine.
+unsafe impl Send for UserData {}
+
+impl Console for FfiConsole {
+    fn poll_key(&mut self) -> Option<u8> {
+        self.keys.poll_key()
+    }
+
+    fn read_key_blocking(&mut self, deadline: Option<Instant>) -> Option<u8> {
+        self.keys.read_key_blocking(deadline)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if let Some(callback) = self.output {
+            callback(bytes.as_ptr(), bytes.len(), self.user_data.0);
+        }
+    }
+}
+
+// Null checked, for the functions taking a machine.
+unsafe fn vm_mut<'a>(vm: *mut Lc3Vm) -> Result<&'a mut Lc3Vm, c_int> {
+    vm.as_mut()
+        .ok_or_else(|| fail(LC3_ERROR_NULL, "The machine is null"))
+}
+
+fn register(index: c_int) -> Result<Register, c_int> {
+    match index {
+        0..=7 => Ok(Register::from(index as u16)),
+        _ => Err(fail(
+            LC3_ERROR_REGISTER,
+            format!("Unknown register {}, expected 0-7", index),
+        )),
+    }
+}
+
+/// A new machine with no program loaded and output discarded until a callback is set.
+#[no_mangle]
+pub extern "C" fn lc3_vm_new() -> *mut Lc3Vm {
+    let mut vm = Vm::new();
+    let keys = BufferConsole::default();
+    vm.set_console(FfiConsole {
+        keys: keys.clone(),
+        output: None,
+        user_data: UserData(ptr::null_mut()),
+    });
+
+    Box::into_raw(Box::new(Lc3Vm { vm, keys }))
+}
+
+/// # Safety
+///
+/// `vm` must come from [`lc3_vm_new`] and not have been freed already. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_free(vm: *mut Lc3Vm) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+/// Loads an object file: a big-endian origin followed by the words to store there. Returns 0 on
+/// success.
+///
+/// # Safety
+///
+/// `vm` must be a live machine from [`lc3_vm_new`], and `bytes` must point to `len` readable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_load(vm: *mut Lc3Vm, bytes: *const u8, len: usize) -> c_int {
+    let vm = match vm_mut(vm) {
+        Ok(vm) => vm,
+        Err(code) => return code,
+    };
+    if bytes.is_null() {
+        return fail(LC3_ERROR_NULL, "The program is null");
+    }
+
+    match vm.vm.load_program(slice::from_raw_parts(bytes, len)) {
+        Ok(()) => 0,
+        Err(e) => fail(LC3_ERROR_LOAD, e),
+    }
+}
+
+/// Runs up to `n` instructions. Returns 1 while the machine is still running, 0 once it has
+/// halted. A program waiting for a key retries until one is pushed.
+///
+/// # Safety
+///
+/// `vm` must be a live machine from [`lc3_vm_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_step(vm: *mut Lc3Vm, n: u32) -> c_int {
+    let vm = match vm_mut(vm) {
+        Ok(vm) => vm,
+        Err(code) => return code,
+    };
+
+    for _ in 0..n {
+        match vm.vm.step() {
+            StepResult::Halted { .. } => return 0,
+            StepResult::Error(e) => return fail(LC3_ERROR_EXECUTION, e),
+            _ => {}
+        }
+    }
+
+    c_int::from(!vm.vm.is_halted())
+}
+
+/// The value of register R`index`, 0 to 7.
+///
+/// # Safety
+///
+/// `vm` must be a live machine from [`lc3_vm_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_read_register(vm: *mut Lc3Vm, index: c_int) -> c_int {
+    let vm = match vm_mut(vm) {
+        Ok(vm) => vm,
+        Err(code) => return code,
+    };
+
+    match register(index) {
+        Ok(register) => c_int::from(vm.vm.register(register)),
+        Err(code) => code,
+    }
+}
+
+/// Sets register R`index`, 0 to 7. Returns 0 on success.
+///
+/// # Safety
+///
+/// `vm` must be a live machine from [`lc3_vm_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_write_register(vm: *mut Lc3Vm, index: c_int, value: u16) -> c_int {
+    let vm = match vm_mut(vm) {
+        Ok(vm) => vm,
+        Err(code) => return code,
+    };
+
+    match register(index) {
+        Ok(register) => {
+            vm.vm.set_register(register, value);
+            0
+        }
+        Err(code) => code,
+    }
+}
+
+/// The word at `address`, without any device side effects.
+///
+/// # Safety
+///
+/// `vm` must be a live machine from [`lc3_vm_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_read_memory(vm: *mut Lc3Vm, address: u16) -> c_int {
+    match vm_mut(vm) {
+        Ok(vm) => c_int::from(vm.vm.read_memory(address)),
+        Err(code) => code,
+    }
+}
+
+/// Queues a key for GETC, IN or the keyboard registers. Returns 0 on success.
+///
+/// # Safety
+///
+/// `vm` must be a live machine from [`lc3_vm_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_push_key(vm: *mut Lc3Vm, key: u8) -> c_int {
+    match vm_mut(vm) {
+        Ok(vm) => {
+            vm.keys.push_input(&[key]);
+            0
+        }
+        Err(code) => code,
+    }
+}
+
+/// Sends the program's output to `callback` from now on, or discards it when `callback` is null.
+/// Returns 0 on success.
+///
+/// # Safety
+///
+/// `vm` must be a live machine from [`lc3_vm_new`]. `callback` is called with `user_data` on
+/// whichever thread steps the machine.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_set_output_callback(
+    vm: *mut Lc3Vm,
+    callback: Lc3OutputCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let vm = match vm_mut(vm) {
+        Ok(vm) => vm,
+        Err(code) => return code,
+    };
+
+    vm.vm.set_console(FfiConsole {
+        keys: vm.keys.clone(),
+        output: callback,
+        user_data: UserData(user_data),
+    });
+    0
+}
+
+/// Why the last call on this thread to return an error failed, or null if none has. Valid until
+/// the next error on this thread.
+#[no_mangle]
+pub extern "C" fn lc3_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    // .ORIG x3000
+    // LOOP  GETC
+    //       OUT
+    //       ADD R1, R0, #-10
+    //       BRnp LOOP
+    //       HALT
+    const ECHO: [u8; 12] = [
+        0x30, 0x00, 0xf0, 0x20, 0xf0, 0x21, 0x12, 0x36, 0x0b, 0xfc, 0xf0, 0x25,
+    ];
+
+    extern "C" fn collect(bytes: *const u8, len: usize, user_data: *mut c_void) {
+        let output = unsafe { &mut *(user_data as *mut Vec<u8>) };
+        output.extend_from_slice(unsafe { slice::from_raw_parts(bytes, len) });
+    }
+
+    fn last_error() -> String {
+        let message = lc3_last_error_message();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_run_a_program() {
+        let mut output: Vec<u8> = Vec::new();
+
+        unsafe {
+            let vm = lc3_vm_new();
+            let user_data = &mut output as *mut Vec<u8> as *mut c_void;
+            assert_eq!(lc3_vm_set_output_callback(vm, Some(collect), user_data), 0);
+            assert_eq!(lc3_vm_load(vm, ECHO.as_ptr(), ECHO.len()), 0);
+            assert_eq!(lc3_vm_read_memory(vm, 0x3000), 0xf020);
+
+            // Waiting on the first key.
+            assert_eq!(lc3_vm_step(vm, 100), 1);
+            for key in b"ok\n" {
+                assert_eq!(lc3_vm_push_key(vm, *key), 0);
+            }
+            assert_eq!(lc3_vm_step(vm, 100), 0);
+            assert_eq!(lc3_vm_read_register(vm, 0), c_int::from(b'\n'));
+
+            assert_eq!(lc3_vm_write_register(vm, 3, 0xbeef), 0);
+            assert_eq!(lc3_vm_read_register(vm, 3), 0xbeef);
+
+            lc3_vm_free(vm);
+        }
+        assert!(output.starts_with(b"ok\n"));
+    }
+
+    #[test]
+    fn test_errors() {
+        unsafe {
+            assert_eq!(lc3_vm_step(ptr::null_mut(), 1), LC3_ERROR_NULL);
+            assert_eq!(last_error(), "The machine is null");
+
+            let vm = lc3_vm_new();
+            assert_eq!(lc3_vm_load(vm, [0x30].as_ptr(), 1), LC3_ERROR_LOAD);
+            assert_eq!(lc3_vm_read_register(vm, 8), LC3_ERROR_REGISTER);
+            assert_eq!(last_error(), "Unknown register 8, expected 0-7");
+
+            // .ORIG x3000
+            // .FILL xD000 ; RESERVED
+            let program = [0x30, 0x00, 0xd0, 0x00];
+            assert_eq!(lc3_vm_load(vm, program.as_ptr(), program.len()), 0);
+            assert_eq!(lc3_vm_step(vm, 1), LC3_ERROR_EXECUTION);
+            assert_eq!(last_error(), "Illegal opcode 0xd000 at PC 0x3000");
+
+            lc3_vm_free(vm);
+        }
+    }
+}
@@ -1,14 +1,28 @@
-use std::fs::File;
-use std::io::{Error, ErrorKind, Read};
+use crate::error::FileError;
+use crate::symbols::SymbolTable;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
 
-pub fn read_rom(filename: String) -> Result<Vec<u16>, Error> {
-    let mut data = Vec::new();
-    File::open(filename)?.read_to_end(&mut data)?;
-    from_bytes(&data)
+/// Reads an object file: its origin followed by the words to place there.
+pub fn read_rom(path: impl AsRef<Path>) -> Result<Vec<u16>, FileError> {
+    let path = path.as_ref();
+    fs::read(path)
+        .and_then(|data| from_bytes(&data))
+        .map_err(|e| FileError::new(path, e))
 }
 
-fn from_bytes(data: &[u8]) -> Result<Vec<u16>, Error> {
-    if data.len() % 2 != 0 {
+/// Reads a `.sym` file, see [`SymbolTable::parse`].
+pub fn read_symbols(path: impl AsRef<Path>) -> Result<SymbolTable, FileError> {
+    let path = path.as_ref();
+    match fs::read_to_string(path) {
+        Ok(source) => Ok(SymbolTable::parse(&source)),
+        Err(e) => Err(FileError::new(path, e)),
+    }
+}
+
+pub fn from_bytes(data: &[u8]) -> Result<Vec<u16>, Error> {
+    if !data.len().is_multiple_of(2) {
         return Err(Error::new(
             ErrorKind::InvalidData,
             "input must be a multiple of 2",
@@ -27,11 +41,30 @@ mod tests {
 
     #[test]
     fn test_from_bytes() {
-        let data = from_bytes(&vec![0xaa, 0xbb, 0xcc, 0xdd]).unwrap();
+        let data = from_bytes(&[0xaa, 0xbb, 0xcc, 0xdd]).unwrap();
         assert_eq!(data, vec![0xaabb, 0xccdd]);
 
-        let result = from_bytes(&vec![0xaa, 0xbb, 0xcc]).map_err(|e| e.kind());
+        let result = from_bytes(&[0xaa, 0xbb, 0xcc]).map_err(|e| e.kind());
         let expected = Err(ErrorKind::InvalidData);
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_errors_name_the_file() {
+        let path = std::env::temp_dir().join("lc3-missing.obj");
+        let e = read_rom(&path).unwrap_err();
+        assert_eq!(e.path, path);
+        assert_eq!(e.source.kind(), ErrorKind::NotFound);
+        assert!(e
+            .to_string()
+            .starts_with(&format!("{}: No such file", path.display())));
+
+        let e = read_symbols("missing.sym").unwrap_err();
+        assert!(e.to_string().starts_with("missing.sym: No such file"));
+
+        // Converting keeps both the kind and the file name.
+        let e = std::io::Error::from(e);
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert!(e.to_string().starts_with("missing.sym: "));
+    }
 }
@@ -1,6 +1,24 @@
+use crate::cpu::sign_extend;
+use crate::symbols::SymbolTable;
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
 /// These instruction types don't map directly to the 4-bit opcodes.
 /// Some have been split into multiple enum variants for better ergonimics.
-#[derive(Debug, PartialEq)]
+///
+/// ```
+/// use lc3::{Instruction, Register};
+///
+/// // ADD R0, R0, #7
+/// let instruction = Instruction::decode(0x1027);
+/// assert_eq!(instruction, Instruction::ADDIMM(Register::R0, Register::R0, 7));
+/// assert_eq!(instruction.to_string(), "ADD R0, R0, #7");
+/// ```
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Instruction {
     BR(Condition, u16),
     ADD(Register, Register, Register),
@@ -21,6 +39,8 @@ pub enum Instruction {
     RESERVED,
     LEA(Register, u16),
     TRAP(TrapVector),
+    /// A TRAP to a vector without a built-in routine, which only an OS can handle.
+    UNKNOWNTRAP(u8),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -51,14 +71,34 @@ impl Register {
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "R0" => Ok(Register::R0),
+            "R1" => Ok(Register::R1),
+            "R2" => Ok(Register::R2),
+            "R3" => Ok(Register::R3),
+            "R4" => Ok(Register::R4),
+            "R5" => Ok(Register::R5),
+            "R6" => Ok(Register::R6),
+            "R7" => Ok(Register::R7),
+            _ => Err(format!("Unknown register {:?}, expected R0-R7", s)),
+        }
+    }
+}
+
+/// The condition codes a BR tests, exported as `BranchCondition`.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Condition {
     pub p: bool,
     pub z: bool,
     pub n: bool,
 }
 
-#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TrapVector {
     GETC,
     OUT,
@@ -69,22 +109,33 @@ pub enum TrapVector {
 }
 
 impl TrapVector {
-    pub fn decode(instruction: u16) -> Self {
-        let value = instruction & 0xFF;
+    /// The 8-bit vector the TRAP instruction carries.
+    pub fn vector(&self) -> u8 {
+        match self {
+            TrapVector::GETC => 0x20,
+            TrapVector::OUT => 0x21,
+            TrapVector::PUTS => 0x22,
+            TrapVector::IN => 0x23,
+            TrapVector::PUTSP => 0x24,
+            TrapVector::HALT => 0x25,
+        }
+    }
 
-        match value {
-            0x20 => TrapVector::GETC,
-            0x21 => TrapVector::OUT,
-            0x22 => TrapVector::PUTS,
-            0x23 => TrapVector::IN,
-            0x24 => TrapVector::PUTSP,
-            0x25 => TrapVector::HALT,
-            _ => unreachable!("bad TRAP vector: {:#04x}", value),
+    pub fn try_decode(instruction: u16) -> Option<Self> {
+        match instruction & 0xFF {
+            0x20 => Some(TrapVector::GETC),
+            0x21 => Some(TrapVector::OUT),
+            0x22 => Some(TrapVector::PUTS),
+            0x23 => Some(TrapVector::IN),
+            0x24 => Some(TrapVector::PUTSP),
+            0x25 => Some(TrapVector::HALT),
+            _ => None,
         }
     }
 }
 
 impl Instruction {
+    /// Decodes any word, a TRAP to a vector without a built-in routine as `UNKNOWNTRAP`.
     pub fn decode(instruction: u16) -> Self {
         let value = instruction >> 12;
 
@@ -207,27 +258,406 @@ impl Instruction {
                 Instruction::LEA(r0, pc_offset)
             }
 
-            0x0f => {
-                let trap_vector = TrapVector::decode(instruction);
+            0x0f => match TrapVector::try_decode(instruction) {
+                Some(trap_vector) => Instruction::TRAP(trap_vector),
+                None => Instruction::UNKNOWNTRAP(instruction as u8),
+            },
+
+            _ => unreachable!("bad instruction: {}", value),
+        }
+    }
+
+    /// The inverse of `decode`. Bits `decode` ignores (e.g. the payload of UNUSED) are zero.
+    pub fn encode(&self) -> u16 {
+        let register = |r: &Register, shift: u16| (*r as u16) << shift;
 
-                Instruction::TRAP(trap_vector)
+        match self {
+            Instruction::BR(condition, pc_offset) => {
+                u16::from(condition.n) << 11
+                    | u16::from(condition.z) << 10
+                    | u16::from(condition.p) << 9
+                    | pc_offset & 0x1ff
+            }
+            Instruction::ADD(r0, r1, r2) => {
+                0x1000 | register(r0, 9) | register(r1, 6) | register(r2, 0)
+            }
+            Instruction::ADDIMM(r0, r1, value) => {
+                0x1000 | register(r0, 9) | register(r1, 6) | 1 << 5 | value & 0x1f
+            }
+            Instruction::LD(r0, pc_offset) => 0x2000 | register(r0, 9) | pc_offset & 0x1ff,
+            Instruction::ST(r0, pc_offset) => 0x3000 | register(r0, 9) | pc_offset & 0x1ff,
+            Instruction::JSR(pc_offset) => 0x4000 | 1 << 11 | pc_offset & 0x7ff,
+            Instruction::JSRR(r0) => 0x4000 | register(r0, 6),
+            Instruction::AND(r0, r1, r2) => {
+                0x5000 | register(r0, 9) | register(r1, 6) | register(r2, 0)
+            }
+            Instruction::ANDIMM(value, r0, r1) => {
+                0x5000 | register(r0, 9) | register(r1, 6) | 1 << 5 | value & 0x1f
+            }
+            Instruction::LDR(r0, r1, offset) => {
+                0x6000 | register(r0, 9) | register(r1, 6) | offset & 0x3f
+            }
+            Instruction::STR(r0, r1, offset) => {
+                0x7000 | register(r0, 9) | register(r1, 6) | offset & 0x3f
             }
+            Instruction::UNUSED => 0x8000,
+            Instruction::NOT(r0, r1) => 0x9000 | register(r0, 9) | register(r1, 6) | 0x3f,
+            Instruction::LDI(r0, pc_offset) => 0xa000 | register(r0, 9) | pc_offset & 0x1ff,
+            Instruction::STI(r0, pc_offset) => 0xb000 | register(r0, 9) | pc_offset & 0x1ff,
+            Instruction::JMP(r0) => 0xc000 | register(r0, 6),
+            Instruction::RESERVED => 0xd000,
+            Instruction::LEA(r0, pc_offset) => 0xe000 | register(r0, 9) | pc_offset & 0x1ff,
+            Instruction::TRAP(trap_vector) => 0xf000 | u16::from(trap_vector.vector()),
+            Instruction::UNKNOWNTRAP(vector) => 0xf000 | u16::from(*vector),
+        }
+    }
 
-            _ => unreachable!("bad instruction: {}", value),
+    /// Formats the instruction in assembly syntax, resolving PC-relative operands to absolute
+    /// addresses using the address the instruction is stored at. Addresses are shown as labels
+    /// when `symbols` has a nearby one.
+    pub fn disassemble(&self, address: u16, symbols: &SymbolTable) -> String {
+        self.assembly(&|offset| {
+            symbols.format_address(address.wrapping_add(1).wrapping_add(offset))
+        })
+    }
+
+    /// Formats the instruction in assembly syntax, with `operand` formatting any PC-relative
+    /// operand from its sign-extended offset.
+    pub(crate) fn assembly(&self, operand: &dyn Fn(u16) -> String) -> String {
+        let mut output = String::new();
+        self.write_assembly(&mut output, operand)
+            .expect("unable to format instruction");
+        output
+    }
+
+    /// The sign-extended offset of a PC-relative operand, from the address after the
+    /// instruction.
+    pub(crate) fn pc_offset(&self) -> Option<u16> {
+        match self {
+            Instruction::BR(_, offset)
+            | Instruction::LD(_, offset)
+            | Instruction::ST(_, offset)
+            | Instruction::LDI(_, offset)
+            | Instruction::STI(_, offset)
+            | Instruction::LEA(_, offset) => Some(sign_extend(*offset, 9)),
+            Instruction::JSR(offset) => Some(sign_extend(*offset, 11)),
+            _ => None,
         }
     }
+
+    fn write_assembly(
+        &self,
+        f: &mut impl fmt::Write,
+        operand: &dyn Fn(u16) -> String,
+    ) -> fmt::Result {
+        let pc_relative = |offset: u16, bit_count: u8| operand(sign_extend(offset, bit_count));
+        let immediate =
+            |value: u16, bit_count: u8| format!("#{}", sign_extend(value, bit_count) as i16);
+
+        match self {
+            Instruction::BR(condition, pc_offset) => {
+                if !(condition.n || condition.z || condition.p) {
+                    return write!(f, "NOP");
+                }
+
+                write!(
+                    f,
+                    "BR{}{}{} {}",
+                    if condition.n { "n" } else { "" },
+                    if condition.z { "z" } else { "" },
+                    if condition.p { "p" } else { "" },
+                    pc_relative(*pc_offset, 9)
+                )
+            }
+            Instruction::ADD(r0, r1, r2) => write!(f, "ADD {:?}, {:?}, {:?}", r0, r1, r2),
+            Instruction::ADDIMM(r0, r1, value) => {
+                write!(f, "ADD {:?}, {:?}, {}", r0, r1, immediate(*value, 5))
+            }
+            Instruction::LD(r0, pc_offset) => {
+                write!(f, "LD {:?}, {}", r0, pc_relative(*pc_offset, 9))
+            }
+            Instruction::ST(r0, pc_offset) => {
+                write!(f, "ST {:?}, {}", r0, pc_relative(*pc_offset, 9))
+            }
+            Instruction::JSR(pc_offset) => write!(f, "JSR {}", pc_relative(*pc_offset, 11)),
+            Instruction::JSRR(r0) => write!(f, "JSRR {:?}", r0),
+            Instruction::AND(r0, r1, r2) => write!(f, "AND {:?}, {:?}, {:?}", r0, r1, r2),
+            Instruction::ANDIMM(value, r0, r1) => {
+                write!(f, "AND {:?}, {:?}, {}", r0, r1, immediate(*value, 5))
+            }
+            Instruction::LDR(r0, r1, offset) => {
+                write!(f, "LDR {:?}, {:?}, {}", r0, r1, immediate(*offset, 6))
+            }
+            Instruction::STR(r0, r1, offset) => {
+                write!(f, "STR {:?}, {:?}, {}", r0, r1, immediate(*offset, 6))
+            }
+            Instruction::UNUSED => write!(f, "UNUSED"),
+            Instruction::NOT(r0, r1) => write!(f, "NOT {:?}, {:?}", r0, r1),
+            Instruction::LDI(r0, pc_offset) => {
+                write!(f, "LDI {:?}, {}", r0, pc_relative(*pc_offset, 9))
+            }
+            Instruction::STI(r0, pc_offset) => {
+                write!(f, "STI {:?}, {}", r0, pc_relative(*pc_offset, 9))
+            }
+            Instruction::JMP(Register::R7) => write!(f, "RET"),
+            Instruction::JMP(r0) => write!(f, "JMP {:?}", r0),
+            Instruction::RESERVED => write!(f, "RESERVED"),
+            Instruction::LEA(r0, pc_offset) => {
+                write!(f, "LEA {:?}, {}", r0, pc_relative(*pc_offset, 9))
+            }
+            Instruction::TRAP(trap_vector) => write!(f, "{:?}", trap_vector),
+            Instruction::UNKNOWNTRAP(vector) => write!(f, "TRAP x{:02X}", vector),
+        }
+    }
+}
+
+/// Displays the instruction in assembly syntax. PC-relative operands are shown as offsets, use
+/// `disassemble` to resolve them to addresses.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_assembly(f, &|offset| format!("#{}", offset as i16))
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
 mod tests {
     use super::Instruction::{self, *};
-    use super::Register::*;
+    use super::Register::{self, *};
     use super::{Condition, TrapVector};
+    use crate::symbols::SymbolTable;
+    use proptest::prelude::{any, prop_assert_eq, prop_oneof, proptest, Just, Strategy};
+
+    #[test]
+    fn encode_round_trips_through_decode() {
+        let words = [
+            0b0000_101_111111110,    // BRnp #-2
+            0b0001_001_010_0_00_011, // ADD R1, R2, R3
+            0b0001_010_001_1_11111,  // ADD R2, R1, #-1
+            0b0010_011_000000100,    // LD R3, #4
+            0b0011_011_111111100,    // ST R3, #-4
+            0b0100_1_10000000011,    // JSR #-1021
+            0b0100_0_00_011_000000,  // JSRR R3
+            0b0101_001_010_0_00_011, // AND R1, R2, R3
+            0b0101_001_010_1_00101,  // AND R1, R2, #5
+            0b0110_001_010_111110,   // LDR R1, R2, #-2
+            0b0111_001_010_000010,   // STR R1, R2, #2
+            0b1000_000000000000,     // UNUSED
+            0b1001_001_010_111111,   // NOT R1, R2
+            0b1010_100_000000001,    // LDI R4, #1
+            0b1011_100_000000001,    // STI R4, #1
+            0b1100_000_111_000000,   // RET
+            0b1101_000000000000,     // RESERVED
+            0b1110_101_000010000,    // LEA R5, #16
+            0b1111_0000_00100010,    // PUTS
+            // The ends of each field's range.
+            0b0000_000_000000000,   // BR with no condition
+            0b0000_111_100000000,   // BRnzp #-256
+            0b0001_111_111_1_10000, // ADD R7, R7, #-16
+            0b0100_1_10000000000,   // JSR #-1024
+            0b0110_111_111_100000,  // LDR R7, R7, #-32
+            0b1010_111_100000000,   // LDI R7, #-256
+            0b1010_000_011111111,   // LDI R0, #255
+        ];
+
+        for word in words.iter() {
+            assert_eq!(Instruction::decode(*word).encode(), *word, "{:#018b}", word);
+        }
+    }
+
+    fn register() -> impl Strategy<Value = Register> {
+        (0u16..8).prop_map(Register::from)
+    }
+
+    fn trap_vector() -> impl Strategy<Value = TrapVector> {
+        prop_oneof![
+            Just(TrapVector::GETC),
+            Just(TrapVector::OUT),
+            Just(TrapVector::PUTS),
+            Just(TrapVector::IN),
+            Just(TrapVector::PUTSP),
+            Just(TrapVector::HALT),
+        ]
+    }
+
+    // Any instruction `decode` can give, with each field in range.
+    fn instruction() -> impl Strategy<Value = Instruction> {
+        // Field widths: 5-bit immediates, 6-bit base offsets and 9-bit PC offsets.
+        let r = register;
+        let imm5 = || 0u16..0x20;
+        let offset6 = || 0u16..0x40;
+        let offset9 = || 0u16..0x200;
+        let condition = any::<(bool, bool, bool)>().prop_map(|(n, z, p)| Condition { n, z, p });
+
+        prop_oneof![
+            (condition, offset9()).prop_map(|(c, offset)| BR(c, offset)),
+            (r(), r(), r()).prop_map(|(a, b, c)| ADD(a, b, c)),
+            (r(), r(), imm5()).prop_map(|(a, b, value)| ADDIMM(a, b, value)),
+            (r(), offset9()).prop_map(|(a, offset)| LD(a, offset)),
+            (r(), offset9()).prop_map(|(a, offset)| ST(a, offset)),
+            (0u16..0x800).prop_map(JSR),
+            r().prop_map(JSRR),
+            (r(), r(), r()).prop_map(|(a, b, c)| AND(a, b, c)),
+            (imm5(), r(), r()).prop_map(|(value, a, b)| ANDIMM(value, a, b)),
+            (r(), r(), offset6()).prop_map(|(a, b, offset)| LDR(a, b, offset)),
+            (r(), r(), offset6()).prop_map(|(a, b, offset)| STR(a, b, offset)),
+            Just(UNUSED),
+            (r(), r()).prop_map(|(a, b)| NOT(a, b)),
+            (r(), offset9()).prop_map(|(a, offset)| LDI(a, offset)),
+            (r(), offset9()).prop_map(|(a, offset)| STI(a, offset)),
+            r().prop_map(JMP),
+            Just(RESERVED),
+            (r(), offset9()).prop_map(|(a, offset)| LEA(a, offset)),
+            trap_vector().prop_map(TRAP),
+            any::<u8>()
+                .prop_filter("a built-in vector", |v| !(0x20..=0x25).contains(v))
+                .prop_map(UNKNOWNTRAP),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn decode_inverts_encode(instruction in instruction()) {
+            prop_assert_eq!(Instruction::decode(instruction.encode()), instruction);
+        }
+    }
+
+    // The bits of `word` that `decode` reads, e.g. not the zeros between SR1 and SR2 of an ADD.
+    fn significant_bits(word: u16) -> u16 {
+        let immediate = word & 1 << 5 != 0;
+        match word >> 12 {
+            0x1 | 0x5 if !immediate => 0xffe7,
+            0x4 if word & 1 << 11 == 0 => 0xf9c0,
+            0x9 => 0xffc0,
+            0xc => 0xf1c0,
+            0xf => 0xf0ff,
+            _ => 0xffff,
+        }
+    }
+
+    // Every word, rather than a sample, as there are few enough.
+    #[test]
+    fn encode_inverts_decode_up_to_ignored_bits() {
+        for word in 0..=u16::MAX {
+            // UNUSED and RESERVED carry nothing to encode.
+            if matches!(word >> 12, 0x8 | 0xd) {
+                continue;
+            }
+            let instruction = Instruction::decode(word);
+
+            let mask = significant_bits(word);
+            assert_eq!(
+                instruction.encode() & mask,
+                word & mask,
+                "{:#018b}: {:?}",
+                word,
+                instruction
+            );
+        }
+    }
 
     fn assert_decode(instruction: u16, expected: Instruction) {
         assert_eq!(Instruction::decode(instruction), expected);
     }
 
+    #[test]
+    fn decode_unknown_trap_vector() {
+        assert_decode(0b1111_0000_00100101, TRAP(TrapVector::HALT));
+        assert_decode(0b1111_0000_11111111, UNKNOWNTRAP(0xff));
+    }
+
+    #[test]
+    fn display() {
+        let condition = Condition {
+            n: true,
+            z: false,
+            p: true,
+        };
+        assert_eq!(BR(condition, 0x1fe).to_string(), "BRnp #-2");
+        assert_eq!(ADDIMM(R2, R1, 0b11111).to_string(), "ADD R2, R1, #-1");
+        assert_eq!(ANDIMM(5, R1, R2).to_string(), "AND R1, R2, #5");
+        assert_eq!(LDR(R1, R2, 3).to_string(), "LDR R1, R2, #3");
+        assert_eq!(JMP(R2).to_string(), "JMP R2");
+        assert_eq!(JMP(R7).to_string(), "RET");
+        assert_eq!(TRAP(TrapVector::PUTS).to_string(), "PUTS");
+    }
+
+    #[test]
+    fn disassemble_resolves_pc_relative_operands() {
+        let condition = Condition {
+            n: true,
+            z: true,
+            p: true,
+        };
+        let symbols = SymbolTable::new();
+        assert_eq!(
+            BR(condition, 0x1fe).disassemble(0x3002, &symbols),
+            "BRnzp x3001"
+        );
+        assert_eq!(LEA(R0, 5).disassemble(0x3000, &symbols), "LEA R0, x3006");
+        assert_eq!(
+            JSR(0b10000000011).disassemble(0x3000, &symbols),
+            "JSR x2C04"
+        );
+        assert_eq!(
+            ADD(R0, R1, R2).disassemble(0x3000, &symbols),
+            "ADD R0, R1, R2"
+        );
+
+        let symbols = SymbolTable::parse("//\tDATA 3004\n");
+        assert_eq!(LEA(R0, 5).disassemble(0x3000, &symbols), "LEA R0, DATA+2");
+    }
+
+    #[test]
+    fn disassemble_wraps_around_memory() {
+        let symbols = SymbolTable::new();
+        let condition = Condition {
+            n: false,
+            z: true,
+            p: false,
+        };
+
+        // Forwards past the top of memory, and backwards past the bottom.
+        assert_eq!(BR(condition, 1).disassemble(0xffff, &symbols), "BRz x0001");
+        assert_eq!(LD(R0, 0x1fe).disassemble(0x0000, &symbols), "LD R0, xFFFF");
+        assert_eq!(JSR(0x400).disassemble(0x0100, &symbols), "JSR xFD01");
+        assert_eq!(STI(R1, 0xff).disassemble(0xff80, &symbols), "STI R1, x0080");
+    }
+
+    #[test]
+    fn pc_offsets_are_decoded_as_their_bits() {
+        // Sign extension happens when they're used, the same for every instruction.
+        assert_decode(
+            0b0000_111_111111110,
+            BR(
+                Condition {
+                    n: true,
+                    z: true,
+                    p: true,
+                },
+                0x1fe,
+            ),
+        );
+        assert_decode(0b0010_000_111111110, LD(R0, 0x1fe));
+        assert_decode(0b0011_000_111111110, ST(R0, 0x1fe));
+        assert_decode(0b1010_000_111111110, LDI(R0, 0x1fe));
+        assert_decode(0b1011_000_111111110, STI(R0, 0x1fe));
+        assert_decode(0b1110_000_111111110, LEA(R0, 0x1fe));
+        assert_decode(0b0100_1_11111111110, JSR(0x7fe));
+
+        assert_eq!(LDI(R0, 0x1fe).pc_offset(), Some(-2_i16 as u16));
+        assert_eq!(JSR(0x3ff).pc_offset(), Some(0x3ff));
+        assert_eq!(ADD(R0, R0, R0).pc_offset(), None);
+    }
+
+    #[test]
+    fn register_from_str() {
+        assert_eq!("r3".parse::<Register>(), Ok(R3));
+        assert_eq!("R7".parse::<Register>(), Ok(R7));
+        assert!("r8".parse::<Register>().is_err());
+        assert!("pc".parse::<Register>().is_err());
+    }
+
     #[test]
     fn process_add_immediate() {
         assert_decode(0b0001_010_001_1_00001, ADDIMM(R2, R1, 1));
@@ -0,0 +1,82 @@
+//! Ctrl-C handling. SIGINT, or Ctrl-C in a Windows console, only raises a flag, the execution
+//! loops check it between instructions and decide what stopping means: pausing in the debugger or
+//! ending the run.
+
+#[cfg(feature = "native-term")]
+use crate::terminal::{Native, Terminal};
+#[cfg(all(feature = "native-term", unix))]
+use nix::libc;
+#[cfg(all(feature = "native-term", unix))]
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+#[cfg(feature = "native-term")]
+use std::io;
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "native-term")]
+use std::sync::atomic::Ordering;
+#[cfg(all(feature = "native-term", windows))]
+use windows_sys::Win32::Foundation::BOOL;
+#[cfg(all(feature = "native-term", windows))]
+use windows_sys::Win32::System::Console::{SetConsoleCtrlHandler, CTRL_C_EVENT};
+
+/// Set by Ctrl-C, cleared when execution is resumed.
+pub static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// The shell's convention for a process ended by SIGINT.
+#[cfg(feature = "native-term")]
+const EXIT_CODE: i32 = 130;
+
+#[cfg(all(feature = "native-term", unix))]
+pub fn install() -> io::Result<()> {
+    let action = SigAction::new(
+        SigHandler::Handler(handle),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+
+    // The handler only touches an atomic, or restores the terminal and exits.
+    unsafe { sigaction(Signal::SIGINT, &action) }
+        .map(|_| ())
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+#[cfg(all(feature = "native-term", unix))]
+extern "C" fn handle(_: libc::c_int) {
+    if requested_twice() {
+        unsafe { libc::_exit(EXIT_CODE) }
+    }
+}
+
+#[cfg(all(feature = "native-term", windows))]
+pub fn install() -> io::Result<()> {
+    if unsafe { SetConsoleCtrlHandler(Some(handle), 1) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// Runs on a thread of its own, so unlike a signal handler it can exit normally.
+#[cfg(all(feature = "native-term", windows))]
+unsafe extern "system" fn handle(event: u32) -> BOOL {
+    if event != CTRL_C_EVENT {
+        // Closing the console or logging off ends the process as usual.
+        return 0;
+    }
+    if requested_twice() {
+        std::process::exit(EXIT_CODE);
+    }
+
+    1
+}
+
+// Nobody picked up the last one, e.g. the debugger is already paused waiting for a command, so
+// this one means business: the terminal is put back ready for the process to exit.
+#[cfg(feature = "native-term")]
+fn requested_twice() -> bool {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        let _ = Native::set_line_buffering(true);
+        true
+    } else {
+        false
+    }
+}
@@ -1,26 +1,381 @@
+//! An LC-3 virtual machine, with a debugger.
+//!
+//! [`run`] runs a program like the `lc3` binary does. [`Vm`] runs one from other code, with
+//! [`State`] underneath for direct access to the machine.
+//!
+//! The terminal console, Ctrl-C handling and the debugger need the default `native-term` feature.
+//! Without it the library builds for targets like `wasm32-unknown-unknown`, with I/O only through
+//! a [`Console`]. Without the default `std` feature either, the decode, execute and memory core
+//! builds with `#![no_std]` and `alloc`: [`State`] steps the machine, and [`Vm`], file loading and
+//! the run loops are left out.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+// Parts of the machine only the debugger inspects.
+#![cfg_attr(not(feature = "native-term"), allow(dead_code))]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod asm;
+mod console;
 mod cpu;
+#[cfg(feature = "native-term")]
 mod debugger;
+mod disasm;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
 mod file;
 mod instruction;
+#[cfg(feature = "std")]
+mod interrupt;
+#[cfg(feature = "std")]
+pub mod log;
+#[cfg(feature = "python")]
+mod python;
 mod state;
+mod symbols;
+#[cfg(feature = "native-term")]
+mod terminal;
+#[cfg(feature = "std")]
+mod vm;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(feature = "std")]
+pub use crate::asm::{assemble, AsmError, Program};
+#[cfg(not(feature = "std"))]
+pub use crate::console::Instant;
+#[cfg(feature = "std")]
+pub use crate::console::ReaderConsole;
+#[cfg(feature = "native-term")]
+pub use crate::console::StdConsole;
+pub use crate::console::{BufferConsole, Console, Echo, SharedConsole};
+#[cfg(feature = "std")]
+pub use crate::console::{Polling, Throttled};
+#[cfg(feature = "native-term")]
+pub use crate::debugger::{DebugTransport, DEFAULT_ADDRESS};
+#[cfg(feature = "std")]
+pub use crate::disasm::disassemble_program;
+pub use crate::disasm::{analyzed_listing, program_listing, ListingEntry};
+#[cfg(not(feature = "std"))]
+pub use crate::error::IoError;
+pub use crate::error::VmError;
+#[cfg(feature = "std")]
+pub use crate::error::{BuildError, FileError};
+#[cfg(feature = "std")]
+pub use crate::file::{read_rom, read_symbols};
+pub use crate::instruction::Condition as BranchCondition;
+pub use crate::instruction::{Instruction, Register, TrapVector};
+#[cfg(feature = "std")]
+pub use crate::log::Verbosity;
+pub use crate::state::memory::device::Device;
+pub use crate::state::memory::{HexdumpLines, Memory};
+pub use crate::state::registers::Registers;
+#[cfg(feature = "stats")]
+pub use crate::state::stats::ExecStats;
+pub use crate::state::{Condition, HookAction, State, TrapMode};
+#[cfg(not(feature = "std"))]
+pub use crate::state::{HaltReason, StepResult};
+pub use crate::symbols::SymbolTable;
+#[cfg(feature = "native-term")]
+pub use crate::terminal::RawMode;
+#[cfg(feature = "tokio")]
+pub use crate::vm::AsyncConsole;
+#[cfg(feature = "std")]
+pub use crate::vm::{
+    HaltReason, RunSummary, StepResult, Trace, TraceEntry, Vm, VmBuilder, VmHandle,
+    DEFAULT_BATCH_SIZE,
+};
+
+#[cfg(feature = "wasm")]
+pub use crate::wasm::WasmVm;
 
+/// An object file image of a small OS that carries out the standard TRAPs just as the built-in
+/// routines do, for [`VmBuilder::os_image`] or `lc3 --os`. Its source is `os/lc3os.asm`.
+pub const BUNDLED_OS: &[u8] = include_bytes!("../os/lc3os.obj");
+
+#[cfg(feature = "native-term")]
 use crate::debugger::Debugger;
-use crate::state::State;
+#[cfg(feature = "native-term")]
 use std::error::Error;
+#[cfg(feature = "native-term")]
+use std::fs::{self, File};
+#[cfg(feature = "native-term")]
+use std::io::{self, BufReader, BufWriter, Write};
+#[cfg(feature = "native-term")]
+use std::path::PathBuf;
+#[cfg(feature = "native-term")]
+use std::time::Duration;
 
-pub fn run(filename: String, debug: bool) -> Result<(), Box<dyn Error>> {
-    let mut rom = file::read_rom(filename)?;
-    let mut state = State::new();
-    state.load_rom(&mut rom)?;
+/// Which OS [`run`] loads under the program, if any.
+#[cfg(feature = "native-term")]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum OsImage {
+    /// None, TRAPs are carried out by the built-in routines.
+    #[default]
+    None,
+    /// [`BUNDLED_OS`].
+    Bundled,
+    /// An object file.
+    File(PathBuf),
+}
 
-    if debug {
-        let mut debugger = Debugger::new();
-        debugger.step(state)
-    } else {
-        while state.running {
-            state = state.step()
+/// What [`run`] runs and how. The defaults run without the debugger or any limits, so only
+/// `program` needs setting.
+///
+/// ```no_run
+/// use lc3::RunOptions;
+/// use std::time::Duration;
+///
+/// let summary = lc3::run(RunOptions {
+///     program: "program.obj".into(),
+///     timeout: Some(Duration::from_secs(5)),
+///     ..RunOptions::default()
+/// })
+/// .unwrap();
+/// println!("R0 = {}", summary.registers[0]);
+/// ```
+#[cfg(feature = "native-term")]
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// The object file to load and run.
+    pub program: PathBuf,
+    /// An object file image to run instead of reading `program`, e.g. one just assembled.
+    pub program_bytes: Option<Vec<u8>>,
+    /// An OS to carry out the program's TRAPs, see [`Vm::load_os`].
+    pub os: OsImage,
+    /// Runs under the debugger, controlled from here.
+    pub debug: Option<DebugTransport>,
+    /// A symbol table for the debugger.
+    pub symbols: Option<PathBuf>,
+    /// Debugger commands to run before any interactive session.
+    pub script: Option<PathBuf>,
+    /// Outside the debugger, how many instructions can run, including those in TRAP routines.
+    /// Zero is no limit, like `None`.
+    pub max_steps: Option<u64>,
+    /// Outside the debugger, how long the instructions can take.
+    pub timeout: Option<Duration>,
+    /// Paces the program to about this many instructions a second, in the debugger too. Zero is
+    /// unthrottled, like `None`.
+    pub hz: Option<u64>,
+    /// Where execution starts instead of the program's origin, e.g. a subroutine to test on its
+    /// own. An address outside the program is warned about on STDERR but still used.
+    pub entry: Option<u16>,
+    /// Keys for the program from this file instead of the terminal, see [`ReaderConsole`].
+    pub stdin_file: Option<PathBuf>,
+    /// Where the program's output goes instead of STDOUT. `-` is STDOUT.
+    pub stdout_file: Option<PathBuf>,
+    /// Which keys the program reads are written back to its output, see [`Vm::set_echo`].
+    pub echo: Echo,
+    /// Prints each executed instruction and the registers it wrote to STDERR.
+    pub trace: bool,
+    /// Writes the trace to this file instead of STDERR.
+    pub trace_file: Option<PathBuf>,
+    /// Makes the program storing into its own image an error, see [`Vm::protect_programs`].
+    pub protect_text: bool,
+    /// Keeps instructions decoded between steps, see [`Vm::set_decode_cache`].
+    pub decode_cache: bool,
+    /// Decodes the images as they're loaded, turning the decode cache on, see
+    /// [`Vm::set_predecode`].
+    pub predecode: bool,
+    /// How much is said on STDERR besides errors, see [`log`].
+    pub verbosity: Verbosity,
+    /// Prints how many instructions ran, how long they took and the rate, see
+    /// [`RunSummary::duration`], to STDERR when the run ends.
+    pub time: bool,
+    /// Prints what the program did, see [`Vm::stats`], to STDERR when the run ends.
+    #[cfg(feature = "stats")]
+    pub stats: bool,
+}
+
+/// Runs a program to completion, or under the debugger, and returns the final registers and
+/// flags.
+#[cfg(feature = "native-term")]
+pub fn run(options: RunOptions) -> Result<RunSummary, VmError> {
+    log::set_verbosity(options.verbosity);
+    let builder = match &options.program_bytes {
+        Some(bytes) => Vm::builder().program_bytes(bytes),
+        None => Vm::builder().program_file(&options.program),
+    };
+    let mut builder = builder
+        .protect_text(options.protect_text)
+        .decode_cache(options.decode_cache)
+        .predecode(options.predecode)
+        .echo(options.echo)
+        .trace(options.trace);
+    // `-` is STDOUT, as it is without the option.
+    let stdout_file = options
+        .stdout_file
+        .as_ref()
+        .filter(|path| path.as_os_str() != "-");
+    let output: Option<Box<dyn Write + Send>> = match stdout_file {
+        Some(path) => Some(Box::new(
+            File::create(path).map_err(|e| BuildError::File(FileError::new(path, e)))?,
+        )),
+        None => None,
+    };
+    if let Some(path) = &options.stdin_file {
+        let file = File::open(path).map_err(|e| BuildError::File(FileError::new(path, e)))?;
+        let output = output.unwrap_or_else(|| Box::new(io::stdout()));
+        builder = builder.console(Box::new(ReaderConsole::new(BufReader::new(file), output)));
+    } else if let Some(output) = output {
+        builder = builder.console(Box::new(Throttled::new(StdConsole::with_output(output))));
+    }
+    if let Some(path) = &options.trace_file {
+        let file = File::create(path).map_err(|e| BuildError::File(FileError::new(path, e)))?;
+        builder = builder.trace_output(Box::new(BufWriter::new(file)));
+    }
+    if let Some(max_steps) = options.max_steps.filter(|max_steps| *max_steps > 0) {
+        builder = builder.max_steps(max_steps);
+    }
+    if let Some(timeout) = options.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(hz) = options.hz {
+        builder = builder.hz(hz);
+    }
+    let os = match &options.os {
+        OsImage::None => None,
+        OsImage::Bundled => Some(BUNDLED_OS.to_vec()),
+        OsImage::File(path) => {
+            Some(fs::read(path).map_err(|e| BuildError::File(FileError::new(path, e)))?)
+        }
+    };
+    if let Some(os) = &os {
+        builder = builder.os_image(os);
+    }
+    let mut vm = builder.build()?;
+    for (i, rom) in vm.programs().iter().enumerate() {
+        let name = match &options.os {
+            OsImage::File(path) if i == 0 => path.display().to_string(),
+            OsImage::Bundled if i == 0 => "the bundled OS".to_string(),
+            _ => options.program.display().to_string(),
+        };
+        let words = rom.len() - 1;
+        log::detail(format_args!(
+            "Loaded {}: {} words at {:#06x}-{:#06x}",
+            name,
+            words,
+            rom[0],
+            rom[0].wrapping_add(words as u16).wrapping_sub(1)
+        ));
+    }
+    if let Some(entry) = options.entry {
+        if !vm.is_loaded(entry) {
+            log::note(format_args!(
+                "Warning: Starting at {:#06x}, outside the loaded program",
+                entry
+            ));
         }
+        vm.set_pc(entry);
     }
+    interrupt::install()?;
+
+    if options.debug.is_some() || options.script.is_some() {
+        let symbols = match &options.symbols {
+            Some(sym) => file::read_symbols(sym).map_err(BuildError::File)?,
+            None => SymbolTable::new(),
+        };
+        let script = options
+            .script
+            .as_ref()
+            .map(|script| script.to_string_lossy().into_owned());
+        // An image from memory can't be reloaded on reset.
+        let programs = match options.program_bytes {
+            Some(_) => Vec::new(),
+            None => vec![options.program],
+        };
+        let mut debugger = Debugger::new(programs, symbols);
+        debugger.throttle(options.hz);
+        debugger.set_batch_size(vm.batch_size());
+        if let Some(os) = os {
+            debugger.set_os(os);
+        }
+        let state = debugger.run(vm.into_state(), options.debug, script.as_deref())?;
+        let summary = RunSummary::of(&state, debugger.run_time());
+        report_time(&summary, options.time);
+        #[cfg(feature = "stats")]
+        if options.stats {
+            eprint!("{}", stats_report(&state.stats, &summary));
+        }
+        return Ok(summary);
+    }
+
+    let result = vm.run();
+    report_time(&vm.summary(), options.time);
+    #[cfg(feature = "stats")]
+    if options.stats {
+        eprint!("{}", stats_report(vm.stats(), &vm.summary()));
+    }
+    if let Err(VmError::StepLimitExceeded { .. }) = result {
+        log::note(format_args!("{}", register_file(&vm.registers())));
+    }
+    result?;
+
+    Ok(vm.summary())
+}
+
+// How many instructions ran and how fast, always with `--time` and otherwise only when verbose.
+#[cfg(feature = "native-term")]
+fn report_time(summary: &RunSummary, always: bool) {
+    let report = format!(
+        "Ran {} instructions in {:.3}s, {:.2} MIPS",
+        summary.instructions,
+        summary.duration.as_secs_f64(),
+        summary.mips()
+    );
+    if always {
+        eprintln!("{}", report);
+    } else {
+        log::detail(format_args!("{}", report));
+    }
+}
+
+// The `--stats` report: the totals for the run, then the counts.
+#[cfg(feature = "stats")]
+fn stats_report(stats: &ExecStats, summary: &RunSummary) -> String {
+    format!(
+        "Instructions: {}\nWall time: {:.3}s\nRate: {:.0} instructions/s\n{}",
+        summary.instructions,
+        summary.duration.as_secs_f64(),
+        summary.mips() * 1_000_000.0,
+        stats
+    )
+}
+
+// The registers, four to a line, for a run that stopped part way.
+#[cfg(feature = "native-term")]
+fn register_file(registers: &[u16; 8]) -> String {
+    registers
+        .chunks(4)
+        .enumerate()
+        .map(|(line, values)| {
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("R{}: x{:04X}", line * 4 + i, value))
+                .collect::<Vec<String>>()
+                .join("  ")
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The `run(filename, debug)` from before [`RunOptions`], debugging from STDIN.
+#[cfg(feature = "native-term")]
+#[deprecated(since = "0.1.0", note = "use `run` with `RunOptions`")]
+pub fn run_file(filename: String, debug: bool) -> Result<(), Box<dyn Error>> {
+    run(RunOptions {
+        program: filename.into(),
+        debug: if debug {
+            Some(DebugTransport::Local)
+        } else {
+            None
+        },
+        ..RunOptions::default()
+    })?;
 
     Ok(())
 }
@@ -0,0 +1,48 @@
+//! The messages the VM prints about itself on STDERR, like the debugger waiting for a connection,
+//! as opposed to the program's output and hard errors, which are always printed.
+//!
+//! The level is shared by the whole process, see [`set_verbosity`]. [`crate::run`] sets it from
+//! [`crate::RunOptions::verbosity`].
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much the VM says about what it's doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+    /// Only hard errors.
+    Quiet,
+    /// Notes and warnings too, e.g. where the debugger is listening.
+    #[default]
+    Normal,
+    /// Also what was loaded where, and how long the run took.
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        x if x == Verbosity::Quiet as u8 => Verbosity::Quiet,
+        x if x == Verbosity::Verbose as u8 => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    }
+}
+
+/// Prints a line unless [`Verbosity::Quiet`], e.g. `note(format_args!("Listening on {}", port))`.
+pub fn note(args: fmt::Arguments) {
+    if verbosity() >= Verbosity::Normal {
+        eprintln!("{}", args);
+    }
+}
+
+/// Prints a line only when [`Verbosity::Verbose`].
+pub fn detail(args: fmt::Arguments) {
+    if verbosity() >= Verbosity::Verbose {
+        eprintln!("{}", args);
+    }
+}
@@ -1,49 +1,646 @@
-use clap::{App, Arg};
-use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use lc3::{
+    log, DebugTransport, Echo, FileError, OsImage, RawMode, RunOptions, SymbolTable, Verbosity,
+    VmError, DEFAULT_ADDRESS,
+};
 use std::boxed::Box;
 use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
+
+// Exit codes, so scripts can tell how a run ended without reading the messages. A program that
+// halts exits with 0.
+const EXIT_ERROR: i32 = 1;
+// The program, an input file or an option couldn't be used.
+const EXIT_LOAD: i32 = 2;
+// The program did something the machine can't, e.g. an illegal opcode.
+const EXIT_GUEST: i32 = 3;
+const EXIT_STEP_LIMIT: i32 = 4;
+const EXIT_TIMEOUT: i32 = 5;
+// The shell's convention for a process ended by Ctrl-C.
+const EXIT_INTERRUPTED: i32 = 130;
 
 fn main() {
     if let Err(e) = run() {
-        println!("Error: {}", e);
-        process::exit(1);
+        eprintln!("Error: {}", e);
+        process::exit(exit_code(e.as_ref()));
+    }
+}
+
+fn exit_code(e: &(dyn Error + 'static)) -> i32 {
+    let usage = e.is::<UsageError>();
+    match (e.downcast_ref::<VmError>(), e.downcast_ref::<AsmFailure>()) {
+        (Some(VmError::StepLimitExceeded { .. }), _) => EXIT_STEP_LIMIT,
+        (Some(VmError::Timeout { .. }), _) => EXIT_TIMEOUT,
+        (Some(VmError::Interrupted { .. }), _) => EXIT_INTERRUPTED,
+        (
+            Some(
+                VmError::IllegalOpcode { .. }
+                | VmError::BadTrapVector { .. }
+                | VmError::WriteToProtected { .. },
+            ),
+            _,
+        ) => EXIT_GUEST,
+        (Some(VmError::Build(_)), _) | (_, Some(AsmFailure::Io(_))) => EXIT_LOAD,
+        _ if usage => EXIT_LOAD,
+        // Including mistakes in assembly source.
+        _ => EXIT_ERROR,
+    }
+}
+
+// An option value that doesn't parse, which exits like clap's own usage errors.
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
     }
 }
 
+impl Error for UsageError {}
+
+// Why `lc3 asm` failed, which decides its exit code.
+#[derive(Debug)]
+enum AsmFailure {
+    // The errors themselves have already been printed.
+    Source { path: PathBuf, errors: usize },
+    Io(FileError),
+}
+
+impl fmt::Display for AsmFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmFailure::Source { path, errors } => write!(
+                f,
+                "Unable to assemble {}: {} error{}",
+                path.display(),
+                errors,
+                if *errors == 1 { "" } else { "s" }
+            ),
+            AsmFailure::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for AsmFailure {}
+
 fn run() -> Result<(), Box<dyn Error>> {
-    let matches = App::new("LC-3 VM")
+    let app = App::new("LC-3 VM")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("debug")
                 .short("d")
                 .long("debug")
                 .help("Runs in debug mode"),
         )
+        .arg(
+            Arg::with_name("debug-local")
+                .long("debug-local")
+                .help("Runs in debug mode, reading debugger commands from STDIN"),
+        )
+        .arg(
+            Arg::with_name("debug-addr")
+                .long("debug-addr")
+                .value_name("ADDR")
+                .help("Runs in debug mode, listening on ADDR (default 127.0.0.1:6379, port 0 picks one)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("debug-port")
+                .long("debug-port")
+                .value_name("PORT")
+                .help("Runs in debug mode, listening on PORT of 127.0.0.1 (0 picks one)")
+                .conflicts_with("debug-addr")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("debug-socket")
+                .long("debug-socket")
+                .value_name("PATH")
+                .help("Runs in debug mode, listening on a Unix domain socket at PATH")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("debug-script")
+                .long("debug-script")
+                .value_name("FILE")
+                .help("Runs debugger commands from FILE, then exits unless another debug mode is given")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-steps")
+                .long("max-steps")
+                .value_name("N")
+                .help("Stops with exit code 4 after N instructions if the program hasn't halted (0 for no limit)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("SECONDS")
+                .help("Stops with exit code 5 after SECONDS if the program hasn't halted")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("hz")
+                .long("hz")
+                .value_name("N")
+                .help("Runs about N instructions a second (0 for full speed)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("os")
+                .long("os")
+                .value_name("FILE")
+                .help("Loads an OS to carry out TRAPs, the bundled one or --os=FILE")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true),
+        )
+        .arg(
+            Arg::with_name("no-os")
+                .long("no-os")
+                .help("Carries out TRAPs with the built-in routines, the default")
+                .conflicts_with("os"),
+        )
+        .arg(
+            Arg::with_name("pc")
+                .long("pc")
+                .value_name("ADDR")
+                .help("Starts at ADDR (0x3010, x3010 or decimal) instead of the program's origin")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stdin-file")
+                .long("stdin-file")
+                .value_name("FILE")
+                .help("Reads the program's keys from FILE instead of the terminal")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stdout-file")
+                .long("stdout-file")
+                .value_name("FILE")
+                .help("Writes the program's output to FILE (- for STDOUT), apart from any errors")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("echo")
+                .long("echo")
+                .help("Shows every key the program reads with GETC, IN or KBDR")
+                .conflicts_with("no-echo"),
+        )
+        .arg(
+            Arg::with_name("no-echo")
+                .long("no-echo")
+                .help("Shows none of the keys the program reads, not even IN's"),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .help("Prints each executed instruction and the registers it wrote to STDERR"),
+        )
+        .arg(
+            Arg::with_name("trace-file")
+                .long("trace-file")
+                .value_name("FILE")
+                .help("Writes the trace to FILE instead of STDERR")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-raw-mode")
+                .long("no-raw-mode")
+                .help("Leaves the terminal as it is, so keys only arrive after Enter")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .help("Prints nothing on STDERR but errors, leaving the program's output alone")
+                .conflicts_with("verbose")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .help("Also prints what was loaded where and how long the run took")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("time")
+                .long("time")
+                .help("Prints how many instructions ran, how long they took and the MIPS to STDERR at exit"),
+        )
+        .arg(
+            Arg::with_name("protect-text")
+                .long("protect-text")
+                .help("Stops with an error when the program stores into its own image"),
+        )
+        .arg(
+            Arg::with_name("decode-cache")
+                .long("decode-cache")
+                .help("Keeps instructions decoded between steps, for a table the size of memory"),
+        )
+        .arg(
+            Arg::with_name("predecode")
+                .long("predecode")
+                .help("Decodes the program as it's loaded, turning on --decode-cache"),
+        )
+        .arg(
+            Arg::with_name("sym")
+                .long("sym")
+                .value_name("FILE")
+                .help("Loads a symbol table for use in the debugger")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("PROGRAM")
                 .help("The program to run.")
                 .required(true)
                 .index(1),
         )
-        .get_matches();
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .about("Lists a program's words and their disassembly without running it")
+                .arg(
+                    Arg::with_name("sym")
+                        .long("sym")
+                        .value_name("FILE")
+                        .help("Loads a symbol table for labels")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("range")
+                        .long("range")
+                        .value_name("START-END")
+                        .help("Only lists the words from START to END, e.g. x3000-x3050")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("no-addresses")
+                        .long("no-addresses")
+                        .help("Leaves out the address column, for diffing listings"),
+                )
+                .arg(
+                    Arg::with_name("analyze")
+                        .long("analyze")
+                        .help("Only disassembles the code reachable from the origin, the rest is data"),
+                )
+                .arg(
+                    Arg::with_name("PROGRAM")
+                        .help("The program to list.")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("asm")
+                .about("Assembles a program into an object file and a symbol table")
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .value_name("FILE")
+                        .help("Writes the object file to FILE instead of next to the source")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("no-sym")
+                        .long("no-sym")
+                        .help("Doesn't write a .sym file next to the object file"),
+                )
+                .arg(
+                    Arg::with_name("run")
+                        .long("run")
+                        .help("Runs the program once assembled, only writing files given with -o"),
+                )
+                .arg(
+                    Arg::with_name("SOURCE")
+                        .help("The assembly source.")
+                        .required(true)
+                        .index(1),
+                ),
+        );
+
+    #[cfg(feature = "dap")]
+    let app = app.arg(
+        Arg::with_name("dap")
+            .long("dap")
+            .value_name("ADDR")
+            .help("Runs in debug mode, serving the Debug Adapter Protocol on ADDR")
+            .takes_value(true),
+    );
+
+    #[cfg(feature = "tui")]
+    let app = app.arg(
+        Arg::with_name("tui")
+            .long("tui")
+            .help("Runs in debug mode with a terminal UI"),
+    );
+
+    #[cfg(feature = "stats")]
+    let app = app.arg(
+        Arg::with_name("stats")
+            .long("stats")
+            .help("Prints opcode, register and memory access counts to STDERR at exit"),
+    );
+
+    // `--help` and `--version` still exit with 0.
+    let matches = app.get_matches_safe().unwrap_or_else(|e| {
+        if e.use_stderr() {
+            eprintln!("{}", e.message);
+            process::exit(EXIT_LOAD);
+        }
+        e.exit()
+    });
+    if let Some(matches) = matches.subcommand_matches("disasm") {
+        return disasm(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("asm") {
+        return asm(matches);
+    }
+
+    let debug = if let Some(transport) = optional_transport(&matches)? {
+        Some(transport)
+    } else if matches.is_present("debug-local") {
+        Some(DebugTransport::Local)
+    } else if let Some(path) = matches.value_of("debug-socket") {
+        Some(DebugTransport::Socket(PathBuf::from(path)))
+    } else if let Some(address) = matches.value_of("debug-addr") {
+        Some(DebugTransport::Tcp(parse_address(address)?))
+    } else if let Some(port) = matches.value_of("debug-port") {
+        let mut address: SocketAddr = DEFAULT_ADDRESS.parse()?;
+        address.set_port(
+            port.parse()
+                .map_err(|_| UsageError(format!("Invalid --debug-port {:?}", port)))?,
+        );
+        Some(DebugTransport::Tcp(address))
+    } else if matches.is_present("debug") {
+        Some(DebugTransport::Tcp(DEFAULT_ADDRESS.parse()?))
+    } else {
+        None
+    };
+
+    let max_steps = match matches.value_of("max-steps") {
+        Some(n) => Some(
+            n.parse::<u64>()
+                .map_err(|_| UsageError(format!("Invalid --max-steps {:?}", n)))?,
+        ),
+        None => None,
+    };
 
-    disable_input_buffering()?;
+    let timeout = match matches.value_of("timeout") {
+        Some(seconds) => Some(
+            seconds
+                .parse::<f64>()
+                .ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .ok_or_else(|| UsageError(format!("Invalid --timeout {:?}", seconds)))?,
+        ),
+        None => None,
+    };
 
-    lc3::run(
-        matches.value_of("PROGRAM").unwrap().to_string(),
-        matches.is_present("debug"),
-    )?;
+    let hz = match matches.value_of("hz") {
+        Some(n) => Some(
+            n.parse::<u64>()
+                .map_err(|_| UsageError(format!("Invalid --hz {:?}", n)))?,
+        ),
+        None => None,
+    };
+
+    let entry = match matches.value_of("pc") {
+        Some(address) => Some(parse_pc(address)?),
+        None => None,
+    };
+
+    let echo = if matches.is_present("echo") {
+        Echo::On
+    } else if matches.is_present("no-echo") {
+        Echo::Off
+    } else {
+        Echo::Default
+    };
+
+    let raw_mode = !matches.is_present("no-raw-mode");
+    run_in_terminal(
+        RunOptions {
+            program: PathBuf::from(matches.value_of("PROGRAM").unwrap()),
+            program_bytes: None,
+            os: match matches.value_of("os") {
+                Some(path) => OsImage::File(PathBuf::from(path)),
+                None if matches.is_present("os") => OsImage::Bundled,
+                None => OsImage::None,
+            },
+            debug,
+            symbols: matches.value_of("sym").map(PathBuf::from),
+            script: matches.value_of("debug-script").map(PathBuf::from),
+            max_steps,
+            timeout,
+            hz,
+            entry,
+            stdin_file: matches.value_of("stdin-file").map(PathBuf::from),
+            stdout_file: matches.value_of("stdout-file").map(PathBuf::from),
+            echo,
+            verbosity: verbosity(&matches),
+            trace: matches.is_present("trace"),
+            trace_file: matches.value_of("trace-file").map(PathBuf::from),
+            protect_text: matches.is_present("protect-text"),
+            decode_cache: matches.is_present("decode-cache"),
+            predecode: matches.is_present("predecode"),
+            time: matches.is_present("time"),
+            #[cfg(feature = "stats")]
+            stats: matches.is_present("stats"),
+        },
+        raw_mode,
+    )
+}
+
+// Runs with the terminal in raw mode, so keys arrive as they're pressed, and puts it back
+// afterwards. Keys from a file leave the terminal alone, as does turning `raw_mode` off.
+fn run_in_terminal(options: RunOptions, raw_mode: bool) -> Result<(), Box<dyn Error>> {
+    log::set_verbosity(options.verbosity);
+    let terminal = if raw_mode && options.stdin_file.is_none() {
+        disable_input_buffering()?
+    } else {
+        None
+    };
+
+    let result = lc3::run(options);
+
+    if let Some(raw_mode) = terminal {
+        raw_mode.restore()?;
+    }
+
+    result?;
 
     Ok(())
 }
 
-fn disable_input_buffering() -> Result<(), nix::Error> {
-    const STDIN_FILENO: i32 = 0;
+// Assembles a source file, printing each error as `file:line:column: message`.
+fn asm(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(matches.value_of("SOURCE").unwrap());
+    let source = fs::read_to_string(path).map_err(|e| AsmFailure::Io(FileError::new(path, e)))?;
+    let program = lc3::assemble(&source).map_err(|errors| {
+        for error in &errors {
+            eprintln!("{}:{}", path.display(), error);
+        }
+        AsmFailure::Source {
+            path: path.to_path_buf(),
+            errors: errors.len(),
+        }
+    })?;
+    let bytes = program.to_bytes();
 
-    let mut termios = tcgetattr(STDIN_FILENO)?;
-    termios.local_flags &= !(LocalFlags::ICANON | LocalFlags::ECHO);
+    let output = match matches.value_of("output") {
+        Some(output) => Some(PathBuf::from(output)),
+        None if matches.is_present("run") => None,
+        None => Some(path.with_extension("obj")),
+    };
+    if let Some(output) = &output {
+        write_file(output, &bytes)?;
+        if !matches.is_present("no-sym") {
+            let symbols = program.symbols.to_string();
+            write_file(&output.with_extension("sym"), symbols.as_bytes())?;
+        }
+    }
 
-    tcsetattr(0, SetArg::TCSANOW, &termios)?;
+    if matches.is_present("run") {
+        run_in_terminal(
+            RunOptions {
+                program: path.to_path_buf(),
+                program_bytes: Some(bytes),
+                verbosity: verbosity(matches),
+                ..RunOptions::default()
+            },
+            !matches.is_present("no-raw-mode"),
+        )?;
+    }
 
     Ok(())
 }
+
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), AsmFailure> {
+    fs::write(path, contents).map_err(|e| AsmFailure::Io(FileError::new(path, e)))
+}
+
+// Debugger front ends that are only available when built with their feature.
+#[allow(unused_variables)]
+fn optional_transport(matches: &ArgMatches) -> Result<Option<DebugTransport>, UsageError> {
+    #[cfg(feature = "dap")]
+    if let Some(address) = matches.value_of("dap") {
+        return Ok(Some(DebugTransport::Dap(parse_address(address)?)));
+    }
+
+    #[cfg(feature = "tui")]
+    if matches.is_present("tui") {
+        return Ok(Some(DebugTransport::Tui));
+    }
+
+    Ok(None)
+}
+
+fn parse_address(address: &str) -> Result<SocketAddr, UsageError> {
+    address
+        .parse()
+        .map_err(|_| UsageError(format!("Invalid debugger address {:?}", address)))
+}
+
+// Prints the program's listing, one word per line, without running it.
+fn disasm(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let image = lc3::read_rom(matches.value_of("PROGRAM").unwrap())?;
+    let symbols = match matches.value_of("sym") {
+        Some(path) => lc3::read_symbols(path)?,
+        None => SymbolTable::new(),
+    };
+    let range = match matches.value_of("range") {
+        Some(range) => Some(parse_range(range)?),
+        None => None,
+    };
+    let addresses = !matches.is_present("no-addresses");
+    // The raw offsets of PC-relative operands too.
+    let offsets = matches.is_present("verbose");
+
+    let entries = if matches.is_present("analyze") {
+        lc3::analyzed_listing(&image, range, &symbols)
+    } else {
+        lc3::program_listing(&image, range, &symbols)
+    };
+    // Only given a column when there are labels to put in it.
+    let label_width = entries
+        .iter()
+        .filter_map(|entry| entry.label.as_ref().map(String::len))
+        .max();
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for entry in entries {
+        let mut line = String::new();
+        if addresses {
+            line.push_str(&format!("x{:04X}  ", entry.address));
+        }
+        line.push_str(&format!("x{:04X}  ", entry.word));
+        if let Some(width) = label_width {
+            let label = entry.label.as_deref().unwrap_or("");
+            line.push_str(&format!("{:<width$}  ", label, width = width));
+        }
+        line.push_str(&entry.text);
+        if let Some(offset) = entry.offset.filter(|_| offsets) {
+            line.push_str(&format!(" (#{})", offset));
+        }
+        // The words of a string after its first have no text.
+        writeln!(stdout, "{}", line.trim_end())?;
+    }
+
+    Ok(())
+}
+
+// An address as 0x3010, x3010 or decimal.
+fn parse_word(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix('x')) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn verbosity(matches: &ArgMatches) -> Verbosity {
+    if matches.is_present("quiet") {
+        Verbosity::Quiet
+    } else if matches.is_present("verbose") {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    }
+}
+
+fn parse_pc(address: &str) -> Result<u16, UsageError> {
+    parse_word(address).ok_or_else(|| UsageError(format!("Invalid --pc {:?}", address)))
+}
+
+fn parse_range(range: &str) -> Result<RangeInclusive<u16>, UsageError> {
+    let bounds = range
+        .split_once('-')
+        .and_then(|(start, end)| Some((parse_word(start)?, parse_word(end)?)));
+
+    match bounds {
+        Some((start, end)) if start <= end => Ok(start..=end),
+        _ => Err(UsageError(format!("Invalid --range {:?}", range))),
+    }
+}
+
+// Returns the original settings to restore on the way out, if there were any to change.
+fn disable_input_buffering() -> io::Result<Option<RawMode>> {
+    let raw_mode = RawMode::enable()?;
+    if raw_mode.is_none() {
+        log::note(format_args!("Note: STDIN isn't a terminal, leaving it in its normal mode (--no-raw-mode skips this check)"));
+    }
+
+    Ok(raw_mode)
+}
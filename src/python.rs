@@ -0,0 +1,299 @@
+//! A Python module, e.g. for autograding scripts. Build it with maturin, see `pyproject.toml`.
+//!
+//! ```python
+//! import lc3
+//!
+//! vm = lc3.Lc3Vm()
+//! vm.load(open("program.obj", "rb").read())
+//! vm.send_input("hello\n")
+//! vm.run(max_steps=1_000_000)
+//! assert vm.output == "hello\n"
+//! ```
+
+use crate::console::{BufferConsole, Console, Instant};
+use crate::error::VmError;
+use crate::vm::{StepResult, Vm};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyOSError, PyTimeoutError, PyValueError};
+use pyo3::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// How many instructions run between checks for Ctrl-C.
+const BATCH: u64 = 10_000;
+
+create_exception!(lc3, Lc3Error, PyException, "The machine failed.");
+create_exception!(
+    lc3,
+    StepLimitExceeded,
+    Lc3Error,
+    "The program ran for more than `max_steps` instructions."
+);
+create_exception!(
+    lc3,
+    IllegalInstruction,
+    Lc3Error,
+    "An illegal opcode or an unknown TRAP vector."
+);
+
+fn to_py_err(e: VmError) -> PyErr {
+    let message = e.to_string();
+    match e {
+        VmError::StepLimitExceeded { .. } => StepLimitExceeded::new_err(message),
+        VmError::IllegalOpcode { .. } | VmError::BadTrapVector { .. } => {
+            IllegalInstruction::new_err(message)
+        }
+        VmError::Timeout { .. } => PyTimeoutError::new_err(message),
+        VmError::Io(_) => PyOSError::new_err(message),
+        _ => Lc3Error::new_err(message),
+    }
+}
+
+/// A machine with scripted input and captured output. Hooks and devices make `Vm` unshareable,
+/// so it stays on the thread that created it.
+#[pyclass(module = "lc3", unsendable)]
+pub struct Lc3Vm {
+    vm: Vm,
+    console: BufferConsole,
+    starved: Arc<AtomicBool>,
+}
+
+#[pymethods]
+impl Lc3Vm {
+    #[new]
+    fn new() -> Self {
+        let mut vm = Vm::new();
+        let console = BufferConsole::default();
+        let starved = Arc::new(AtomicBool::new(false));
+        vm.set_console(PyConsole {
+            buffer: console.clone(),
+            starved: Arc::clone(&starved),
+        });
+
+        Lc3Vm {
+            vm,
+            console,
+            starved,
+        }
+    }
+
+    /// Loads an object file's bytes.
+    fn load(&mut self, program: &[u8]) -> PyResult<()> {
+        self.vm
+            .load_program(program)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Runs up to `n` instructions, returning whether the machine is still running.
+    #[pyo3(signature = (n = 1))]
+    fn step(&mut self, n: u64) -> PyResult<bool> {
+        for _ in 0..n {
+            match self.vm.step() {
+                StepResult::Halted { .. } => return Ok(false),
+                StepResult::Error(e) => return Err(to_py_err(e)),
+                _ => {}
+            }
+        }
+
+        Ok(!self.vm.is_halted())
+    }
+
+    /// Runs until the program halts, returning `True`, or waits for input that hasn't been sent,
+    /// returning `False`. Raises `StepLimitExceeded` after `max_steps` instructions.
+    #[pyo3(signature = (max_steps = None))]
+    fn run(&mut self, py: Python<'_>, max_steps: Option<u64>) -> PyResult<bool> {
+        let mut steps = 0;
+        self.starved.store(false, Ordering::Relaxed);
+
+        while !self.vm.is_halted() {
+            if Some(steps) == max_steps {
+                let pc = self.vm.pc();
+                return Err(to_py_err(VmError::StepLimitExceeded {
+                    limit: steps,
+                    pc,
+                    word: self.vm.read_memory(pc),
+                }));
+            }
+            if steps % BATCH == 0 {
+                py.check_signals()?;
+            }
+
+            if let StepResult::Error(e) = self.vm.step() {
+                return Err(to_py_err(e));
+            }
+            if self.starved.swap(false, Ordering::Relaxed) {
+                return Ok(false);
+            }
+            steps += 1;
+        }
+
+        Ok(true)
+    }
+
+    /// R0 to R7.
+    #[getter]
+    fn registers(&self) -> Vec<u16> {
+        self.vm.registers().to_vec()
+    }
+
+    #[getter]
+    fn pc(&self) -> u16 {
+        self.vm.pc()
+    }
+
+    #[getter]
+    fn halted(&self) -> bool {
+        self.vm.is_halted()
+    }
+
+    /// `len` words from `addr`, without any device side effects.
+    #[pyo3(signature = (addr, len = 1))]
+    fn read_mem(&self, addr: u16, len: usize) -> PyResult<Vec<u16>> {
+        if usize::from(addr) + len > 0x10000 {
+            return Err(PyValueError::new_err("Read goes past the end of memory"));
+        }
+
+        Ok((0..len)
+            .map(|i| self.vm.read_memory(addr + i as u16))
+            .collect())
+    }
+
+    /// Stores `values` from `addr` on.
+    fn write_mem(&mut self, addr: u16, values: Vec<u16>) -> PyResult<()> {
+        if usize::from(addr) + values.len() > 0x10000 {
+            return Err(PyValueError::new_err("Write goes past the end of memory"));
+        }
+
+        for (i, value) in values.into_iter().enumerate() {
+            self.vm.write_memory(addr + i as u16, value);
+        }
+        Ok(())
+    }
+
+    /// Queues keys for GETC, IN or the keyboard registers.
+    fn send_input(&mut self, text: &str) {
+        self.console.push_input(text.as_bytes());
+    }
+
+    /// Everything the program has written.
+    #[getter]
+    fn output(&self) -> String {
+        String::from_utf8_lossy(&self.console.output()).into_owned()
+    }
+}
+
+// A buffer console noting when the program waits for input that isn't there, so `run` can
+// return rather than spin.
+struct PyConsole {
+    buffer: BufferConsole,
+    starved: Arc<AtomicBool>,
+}
+
+impl Console for PyConsole {
+    fn poll_key(&mut self) -> Option<u8> {
+        self.buffer.poll_key()
+    }
+
+    fn read_key_blocking(&mut self, deadline: Option<Instant>) -> Option<u8> {
+        let key = self.buffer.read_key_blocking(deadline);
+        if key.is_none() {
+            self.starved.store(true, Ordering::Relaxed);
+        }
+        key
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.buffer.write(bytes);
+    }
+}
+
+#[pymodule]
+fn lc3(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Lc3Vm>()?;
+    m.add("Lc3Error", m.py().get_type::<Lc3Error>())?;
+    m.add("StepLimitExceeded", m.py().get_type::<StepLimitExceeded>())?;
+    m.add(
+        "IllegalInstruction",
+        m.py().get_type::<IllegalInstruction>(),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // .ORIG x3000
+    // LOOP  GETC
+    //       OUT
+    //       ADD R1, R0, #-10
+    //       BRnp LOOP
+    //       HALT
+    const ECHO: [u8; 12] = [
+        0x30, 0x00, 0xf0, 0x20, 0xf0, 0x21, 0x12, 0x36, 0x0b, 0xfc, 0xf0, 0x25,
+    ];
+
+    fn with_gil<T>(f: impl FnOnce(Python<'_>) -> T) -> T {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(f)
+    }
+
+    #[test]
+    fn test_run_with_input() {
+        with_gil(|py| {
+            let mut vm = Lc3Vm::new();
+            vm.load(&ECHO).unwrap();
+
+            vm.send_input("hi");
+            assert!(!vm.run(py, None).unwrap());
+            assert_eq!(vm.output(), "hi");
+            assert_eq!(vm.pc(), 0x3000);
+
+            vm.send_input("\n");
+            assert!(vm.run(py, None).unwrap());
+            assert!(vm.halted());
+            assert!(vm.output().starts_with("hi\n"));
+            assert_eq!(vm.registers()[0], u16::from(b'\n'));
+        });
+    }
+
+    #[test]
+    fn test_memory_and_steps() {
+        let mut vm = Lc3Vm::new();
+        vm.load(&ECHO).unwrap();
+        assert_eq!(vm.read_mem(0x3000, 2).unwrap(), [0xf020, 0xf021]);
+
+        // ADD R2, R2, #5
+        vm.write_mem(0x3000, vec![0x14a5, 0x14a5]).unwrap();
+        assert!(vm.step(2).unwrap());
+        assert_eq!(vm.registers()[2], 10);
+
+        assert!(vm.read_mem(0xffff, 2).is_err());
+        assert!(vm.write_mem(0xffff, vec![0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        with_gil(|py| {
+            let mut vm = Lc3Vm::new();
+            let e = vm.load(&[0x30]).unwrap_err();
+            assert!(e.is_instance_of::<PyValueError>(py));
+
+            // .ORIG x3000
+            // LOOP  BRnzp LOOP
+            vm.load(&[0x30, 0x00, 0x0f, 0xff]).unwrap();
+            let e = vm.run(py, Some(100)).unwrap_err();
+            assert!(e.is_instance_of::<StepLimitExceeded>(py));
+            assert!(e.is_instance_of::<Lc3Error>(py));
+            assert_eq!(
+                e.value(py).to_string(),
+                "Step limit of 100 reached at PC 0x3000: BRnzp x3000"
+            );
+
+            // .FILL xD000 ; RESERVED
+            vm.write_mem(0x3000, vec![0xd000]).unwrap();
+            let e = vm.step(1).unwrap_err();
+            assert!(e.is_instance_of::<IllegalInstruction>(py));
+        });
+    }
+}
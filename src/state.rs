@@ -1,31 +1,153 @@
+pub mod call_stack;
+#[cfg(feature = "native-term")]
+pub mod history;
 pub mod memory;
 pub mod registers;
+#[cfg(feature = "stats")]
+pub mod stats;
+pub mod undo;
 
-use crate::cpu::execute;
+use crate::console::Instant;
+use crate::console::SharedConsole;
+use crate::cpu::{execute, sign_extend};
+use crate::error::VmError;
 use crate::instruction::{Instruction, Register};
-use memory::Memory;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use call_stack::CallStack;
+use core::fmt;
+use memory::{Memory, Word, MCR};
 use registers::Registers;
+use undo::{Delta, UndoLog};
 
+/// Host code run for a TRAP vector, see [`crate::Vm::register_trap`].
+pub type TrapHandler = Box<dyn FnMut(&mut State) + Send>;
+
+/// Called before each instruction with the word at the PC and its decoding, see
+/// [`crate::Vm::set_pre_step_hook`].
+pub type PreStepHook = Box<dyn FnMut(&State, u16, &Instruction) -> HookAction + Send>;
+
+/// Called after each instruction with the machine, the address and word it executed and its
+/// decoding, see [`crate::Vm::set_post_step_hook`].
+pub type PostStepHook = Box<dyn FnMut(&State, u16, u16, &Instruction) + Send>;
+
+/// What a [`PreStepHook`] wants to happen to the instruction it was shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookAction {
+    /// Execute it.
+    Continue,
+    /// Stop before executing it.
+    Pause,
+}
+
+/// What happened during a step, see [`State::step`].
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum StepResult {
+    /// The instruction executed and the machine is ready for the next one.
+    Continue,
+    /// The machine has stopped.
+    Halted { reason: HaltReason },
+    /// A TRAP other than HALT executed, either the built-in routine or a registered handler.
+    Trapped { vector: u8 },
+    /// The pre-step hook paused before the instruction at the PC, which the next step executes.
+    Breakpoint,
+    /// The step failed. An instruction that can't be executed leaves the machine as it was, so
+    /// stepping again fails the same way. Console I/O errors come after the instruction ran.
+    Error(VmError),
+}
+
+/// Why the machine stopped, see [`StepResult::Halted`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HaltReason {
+    /// The program executed HALT, or cleared the clock enable bit of the MCR as an OS's HALT
+    /// routine does.
+    Halt,
+    /// A registered TRAP handler stopped the machine.
+    Host,
+}
+
+/// How TRAP instructions are carried out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrapMode {
+    /// By routines built into the VM, which return straight to the next instruction. Vectors
+    /// other than x20-x25 are an error.
+    #[default]
+    Builtin,
+    /// Through the trap vector table to an OS loaded in memory, setting R7 to the return address
+    /// for any vector.
+    Os,
+}
+
+/// The machine: memory, registers and flags.
+///
+/// ```
+/// use lc3::{Register, State, StepResult};
+///
+/// let mut state = State::new();
+/// // .ORIG x3000, ADD R0, R0, #7, HALT
+/// state.load_rom(&mut [0x3000, 0x1027, 0xf025]).unwrap();
+///
+/// assert_eq!(state.step(), StepResult::Continue);
+/// assert_eq!(state.registers.read(Register::R0), 7);
+/// assert_eq!(state.pc, 0x3001);
+/// ```
 pub struct State {
     pub memory: Memory,
     pub registers: Registers,
     pub pc: u16,
     pub condition: Condition,
     pub running: bool,
+    // How many instructions have been executed.
+    pub instructions: u64,
+    pub trap_mode: TrapMode,
+    // Only tracked when enabled (by the debugger), so normal runs don't pay for it.
+    pub(crate) call_stack: Option<CallStack>,
+    pub(crate) undo: Option<UndoLog>,
+    // Where guest programs read keys from and print to, also held by the keyboard and display.
+    pub console: SharedConsole,
+    // When set, GETC stops waiting for input at this point so a timed run can end.
+    pub(crate) deadline: Option<Instant>,
+    // Host-registered TRAP handlers by vector, checked before the built-in traps.
+    pub(crate) traps: BTreeMap<u8, TrapHandler>,
+    pub(crate) pre_step: Option<PreStepHook>,
+    // Set when the pre-step hook paused before the instruction at the PC. The next step executes
+    // it whatever the hook says.
+    pub(crate) paused: bool,
+    pub(crate) post_step: Option<PostStepHook>,
+    #[cfg(feature = "stats")]
+    pub(crate) stats: stats::ExecStats,
 }
 
 impl State {
     pub fn new() -> Self {
+        let console = SharedConsole::default();
+
         Self {
-            memory: Memory::new(),
+            memory: Memory::new(&console),
             registers: Registers::new(),
             pc: 0x0000,
             condition: Condition::P,
             running: true,
+            instructions: 0,
+            trap_mode: TrapMode::Builtin,
+            call_stack: None,
+            undo: None,
+            console,
+            deadline: None,
+            traps: BTreeMap::new(),
+            pre_step: None,
+            paused: false,
+            post_step: None,
+            #[cfg(feature = "stats")]
+            stats: stats::ExecStats::default(),
         }
     }
 
-    pub fn update_flags(&mut self, r: Register) -> &Self {
+    pub(crate) fn update_flags(&mut self, r: Register) -> &Self {
         if self.registers.read(r) == 0 {
             self.condition = Condition::Z;
         } else if (self.registers.read(r) >> 15) == 1 {
@@ -38,10 +160,197 @@ impl State {
         self
     }
 
-    pub fn step(mut self) -> Self {
-        let instruction = self.memory.read(self.pc);
-        let instruction = Instruction::decode(instruction);
-        execute(self, instruction)
+    /// Executes the instruction at the PC, returning what happened.
+    pub fn step(&mut self) -> StepResult {
+        let word = self.memory.read(self.pc);
+        if word >> 12 == 0xf && !self.traps.is_empty() {
+            let vector = word as u8;
+            if let Some(handler) = self.traps.remove(&vector) {
+                return self.host_trap(vector, handler);
+            }
+        }
+        let pc = self.pc;
+        let instruction = match self.memory.decode(pc, word) {
+            Instruction::UNUSED | Instruction::RESERVED => {
+                return StepResult::Error(VmError::IllegalOpcode { pc, word });
+            }
+            // An OS handles every vector, or at least decides what to do about it.
+            Instruction::UNKNOWNTRAP(vector) if self.trap_mode == TrapMode::Os => {
+                return self.os_trap(vector)
+            }
+            Instruction::UNKNOWNTRAP(vector) => {
+                return StepResult::Error(VmError::BadTrapVector { pc, vector });
+            }
+            instruction => instruction,
+        };
+
+        if let Some(mut hook) = self.pre_step.take() {
+            let action = hook(self, word, &instruction);
+            self.pre_step = Some(hook);
+
+            if !core::mem::take(&mut self.paused) && action == HookAction::Pause {
+                self.paused = true;
+                return StepResult::Breakpoint;
+            }
+        }
+
+        if let Some(address) = self.store_address(&instruction) {
+            if self.memory.is_protected(address) {
+                return StepResult::Error(VmError::WriteToProtected { address, pc });
+            }
+        }
+
+        #[cfg(feature = "stats")]
+        {
+            let mut stats = core::mem::take(&mut self.stats);
+            stats.record(self, word, &instruction);
+            self.stats = stats;
+        }
+
+        if self.undo.is_some() {
+            let delta = Delta::capture(self, &instruction);
+            if let Some(undo) = &mut self.undo {
+                undo.record(delta);
+            }
+        }
+
+        self.instructions += 1;
+        let vector = match &instruction {
+            Instruction::TRAP(trap_vector) => Some(trap_vector.vector()),
+            _ => None,
+        };
+        // Only copied when there's a hook to show it to afterwards.
+        let executed = self.post_step.as_ref().map(|_| instruction.clone());
+        execute(self, instruction);
+        let device_accessed = core::mem::take(&mut self.memory.device_accessed);
+        if device_accessed && self.memory.peek(MCR) >> 15 == 0 {
+            self.running = false;
+        }
+        if vector.is_some() || device_accessed {
+            let mut console = self.console.lock();
+            if vector.is_some() || !self.running {
+                console.flush();
+            }
+            if let Some(e) = console.take_error() {
+                return StepResult::Error(VmError::Io(e));
+            }
+        }
+
+        if let (Some(mut hook), Some(instruction)) = (self.post_step.take(), executed) {
+            hook(self, pc, word, &instruction);
+            self.post_step = Some(hook);
+        }
+
+        if !self.running {
+            StepResult::Halted {
+                reason: HaltReason::Halt,
+            }
+        } else if let Some(vector) = vector {
+            StepResult::Trapped { vector }
+        } else {
+            StepResult::Continue
+        }
+    }
+
+    // The address `instruction` stores to if it's executed next, found without device side
+    // effects.
+    pub(crate) fn store_address(&self, instruction: &Instruction) -> Option<u16> {
+        let pc = self.pc.wrapping_add(1);
+        match instruction {
+            Instruction::ST(_, pc_offset) => Some(pc.wrapping_add(sign_extend(*pc_offset, 9))),
+            Instruction::STR(_, base_r, offset) => Some(
+                self.registers
+                    .read(*base_r)
+                    .wrapping_add(sign_extend(*offset, 6)),
+            ),
+            Instruction::STI(_, pc_offset) => Some(
+                self.memory
+                    .peek(pc.wrapping_add(sign_extend(*pc_offset, 9))),
+            ),
+            _ => None,
+        }
+    }
+
+    // Runs a registered handler in place of the TRAP. The pre-step hook doesn't see it since the
+    // vector may not decode, and it can't be undone as there's no telling what it changed.
+    fn host_trap(&mut self, vector: u8, mut handler: TrapHandler) -> StepResult {
+        if self.undo.is_some() {
+            let delta = Delta::irreversible(self);
+            if let Some(undo) = &mut self.undo {
+                undo.record(delta);
+            }
+        }
+
+        #[cfg(feature = "stats")]
+        self.stats.record_host_trap(self.pc, vector);
+
+        self.instructions += 1;
+        self.pc = self.pc.wrapping_add(1);
+        self.registers.write(Register::R7, self.pc);
+        handler(self);
+        // Taken out while running so it can have the state, unless it replaced itself.
+        self.traps.entry(vector).or_insert(handler);
+
+        let mut console = self.console.lock();
+        console.flush();
+        if let Some(e) = console.take_error() {
+            return StepResult::Error(VmError::Io(e));
+        }
+        if self.running {
+            StepResult::Trapped { vector }
+        } else {
+            StepResult::Halted {
+                reason: HaltReason::Host,
+            }
+        }
+    }
+
+    // Jumps to the OS's routine for a vector that doesn't decode, which the pre-step hook doesn't
+    // see.
+    fn os_trap(&mut self, vector: u8) -> StepResult {
+        if self.undo.is_some() {
+            // Only the PC and R7 change.
+            let delta = Delta::irreversible(self);
+            if let Some(undo) = &mut self.undo {
+                undo.record(delta);
+            }
+        }
+
+        #[cfg(feature = "stats")]
+        self.stats.record_host_trap(self.pc, vector);
+
+        self.instructions += 1;
+        let call_site = self.pc;
+        self.pc = self.pc.wrapping_add(1);
+        self.registers.write(Register::R7, self.pc);
+        let routine = self.memory.read(u16::from(vector));
+        if let Some(call_stack) = &mut self.call_stack {
+            call_stack.call(call_site, routine, self.pc);
+        }
+        self.pc = routine;
+
+        StepResult::Trapped { vector }
+    }
+
+    /// A copy of the machine to go back to with [`State::restore_from`].
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    /// Puts the machine back the way `snapshot` had it, copying into the existing memory rather
+    /// than allocating. Devices, the console and host traps and hooks are left as they are.
+    pub fn restore_from(&mut self, snapshot: &State) {
+        self.memory.restore_from(&snapshot.memory);
+        self.registers.clone_from(&snapshot.registers);
+        self.pc = snapshot.pc;
+        self.condition = snapshot.condition.clone();
+        self.running = snapshot.running;
+        self.instructions = snapshot.instructions;
+        self.call_stack.clone_from(&snapshot.call_stack);
+        self.undo.clone_from(&snapshot.undo);
+        self.paused = snapshot.paused;
+        #[cfg(feature = "stats")]
+        self.stats.clone_from(&snapshot.stats);
     }
 
     pub fn registers(&self) -> [u16; 8] {
@@ -54,21 +363,420 @@ impl State {
             Some(a) => a,
             None => return Err("ROM must be at least 2 bytes."),
         };
-        let mut address = *address;
-        self.pc = address;
+        if rom.len() > 0x10000 - usize::from(*address) {
+            return Err("ROM doesn't fit between its origin and the end of memory.");
+        }
+        self.pc = *address;
 
-        for value in rom {
+        for (address, value) in (*address..=u16::MAX).zip(rom) {
             self.memory.write(address, *value);
-            address += 1;
         }
 
         Ok(())
     }
 }
 
-#[derive(Debug, PartialEq)]
+// A copy of the machine, sharing the console. Host traps and hooks can't be copied, so the clone
+// starts without them.
+impl Clone for State {
+    fn clone(&self) -> Self {
+        Self {
+            memory: self.memory.clone(),
+            registers: self.registers.clone(),
+            pc: self.pc,
+            condition: self.condition.clone(),
+            running: self.running,
+            instructions: self.instructions,
+            trap_mode: self.trap_mode,
+            call_stack: self.call_stack.clone(),
+            undo: self.undo.clone(),
+            console: self.console.clone(),
+            deadline: self.deadline,
+            traps: BTreeMap::new(),
+            pre_step: None,
+            paused: self.paused,
+            post_step: None,
+            #[cfg(feature = "stats")]
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+// The machine as the program sees it: registers, PC, flags, whether it's running and memory.
+impl PartialEq for State {
+    fn eq(&self, other: &Self) -> bool {
+        self.registers == other.registers
+            && self.pc == other.pc
+            && self.condition == other.condition
+            && self.running == other.running
+            && self.memory == other.memory
+    }
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers: Vec<Word> = self.registers().iter().map(|r| Word(*r)).collect();
+
+        f.debug_struct("State")
+            .field("registers", &registers)
+            .field("pc", &Word(self.pc))
+            .field("condition", &self.condition)
+            .field("running", &self.running)
+            .field("instructions", &self.instructions)
+            .field("memory", &self.memory)
+            .finish_non_exhaustive()
+    }
+}
+
+// Registers, flags and the rows of memory that aren't all zeros, 8 words to a row.
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "PC: {:#06x}, Flags: {:?}, {}",
+            self.pc,
+            self.condition,
+            if self.running { "running" } else { "halted" }
+        )?;
+        for (line, registers) in self.registers().chunks(4).enumerate() {
+            let registers: Vec<String> = registers
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("R{}: {:#06x}", line * 4 + i, value))
+                .collect();
+            writeln!(f, "{}", registers.join(", "))?;
+        }
+
+        let mut rows: Vec<u16> = self.memory.non_zero().map(|(a, _)| a & !7).collect();
+        rows.dedup();
+        for row in rows {
+            let words: Vec<String> = (row..=row + 7)
+                .map(|address| format!("{:#06x}", self.memory.peek(address)))
+                .collect();
+            writeln!(f, "{:#06x}: {}", row, words.join(" "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Condition {
     P,
     Z,
     N,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+
+    // .ORIG x3000
+    // ADD R0, R0, #1
+    // ST R0, #-2
+    // HALT
+    fn program() -> State {
+        let mut state = State::new();
+        state.console.replace(Box::new(BufferConsole::default()));
+        state
+            .load_rom(&mut [0x3000, 0x1021, 0x31fe, 0xf025])
+            .unwrap();
+        state
+    }
+
+    #[test]
+    fn test_runs_on_a_small_stack() {
+        // Memory lives on the heap, so building, cloning and moving the machine around take
+        // little stack.
+        let mut state = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| {
+                let mut state = program().clone();
+                while state.running {
+                    state.step();
+                }
+                state
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(state.registers.read(Register::R0), 1);
+        assert_eq!(state.memory.read(0x3000), 1);
+    }
+
+    #[test]
+    fn test_clones_are_isolated() {
+        let mut state = program();
+        let mut copy = state.clone();
+
+        state.step();
+        copy.registers.write(Register::R0, 41);
+        copy.step();
+        copy.step();
+
+        assert_eq!(state.registers.read(Register::R0), 1);
+        assert_eq!(state.pc, 0x3001);
+        assert_eq!(state.memory.peek(0x3000), 0x1021);
+        assert_eq!(copy.registers.read(Register::R0), 42);
+        assert_eq!(copy.pc, 0x3002);
+        assert_eq!(copy.memory.peek(0x3000), 42);
+        assert_eq!(copy.instructions, 2);
+
+        // The devices came along.
+        copy.memory.write(memory::KBSR, 1 << 15);
+        assert_eq!(copy.memory.peek(memory::KBSR), 1 << 15);
+        assert_eq!(state.memory.peek(memory::KBSR), 0);
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let mut state = program();
+        let snapshot = state.snapshot();
+
+        state.step();
+        state.step();
+        state.step();
+        assert!(!state.running);
+        assert_eq!(state.memory.peek(0x3000), 1);
+
+        state.restore_from(&snapshot);
+        assert!(state.running);
+        assert_eq!(state.pc, 0x3000);
+        assert_eq!(state.instructions, 0);
+        assert_eq!(state.registers(), [0; 8]);
+        assert_eq!(state.memory.peek(0x3000), 0x1021);
+
+        // Going again gets the same result.
+        state.step();
+        state.step();
+        assert_eq!(state.memory.peek(0x3000), 1);
+    }
+
+    #[test]
+    fn test_equality() {
+        assert_eq!(program(), program());
+
+        let mut state = program();
+        state.memory.write(0x4000, 1);
+        assert_ne!(state, program());
+
+        let mut state = program();
+        state.registers.write(Register::R3, 1);
+        assert_ne!(state, program());
+
+        // The hooks and bookkeeping don't count.
+        let mut state = program();
+        state.instructions = 10;
+        state.call_stack = Some(CallStack::new());
+        assert_eq!(state, program());
+    }
+
+    #[test]
+    fn test_formatting() {
+        let mut state = program();
+        state.memory.write(0x4009, 0xbeef);
+        state.step();
+
+        assert_eq!(
+            state.to_string(),
+            "PC: 0x3001, Flags: P, running\n\
+             R0: 0x0001, R1: 0x0000, R2: 0x0000, R3: 0x0000\n\
+             R4: 0x0000, R5: 0x0000, R6: 0x0000, R7: 0x0000\n\
+             0x3000: 0x1021 0x31fe 0xf025 0x0000 0x0000 0x0000 0x0000 0x0000\n\
+             0x4008: 0x0000 0xbeef 0x0000 0x0000 0x0000 0x0000 0x0000 0x0000\n"
+        );
+        assert_eq!(
+            format!("{:?}", state),
+            "State { registers: [0x0001, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000, 0x0000], \
+             pc: 0x3001, condition: P, running: true, instructions: 1, \
+             memory: {0x3000: 0x1021, 0x3001: 0x31fe, 0x3002: 0xf025, 0x4009: 0xbeef}, .. }"
+        );
+    }
+
+    #[test]
+    fn test_step_results() {
+        let mut state = program();
+        assert_eq!(state.step(), StepResult::Continue);
+
+        state.step();
+        let result = state.step();
+        assert_eq!(
+            result,
+            StepResult::Halted {
+                reason: HaltReason::Halt
+            }
+        );
+        assert!(!state.running);
+
+        // OUT
+        let mut state = program();
+        state.memory.write(0x3000, 0xf021);
+        let result = state.step();
+        assert_eq!(result, StepResult::Trapped { vector: 0x21 });
+
+        // A registered handler that stops the machine.
+        let mut state = program();
+        state.memory.write(0x3000, 0xf030);
+        state
+            .traps
+            .insert(0x30, Box::new(|state: &mut State| state.running = false));
+        let result = state.step();
+        assert_eq!(
+            result,
+            StepResult::Halted {
+                reason: HaltReason::Host
+            }
+        );
+    }
+
+    #[test]
+    fn test_illegal_opcode() {
+        for word in [0x8000, 0xd000] {
+            let mut state = program();
+            state.memory.write(0x3001, word);
+            state.step();
+            let result = state.step();
+
+            assert_eq!(
+                result,
+                StepResult::Error(VmError::IllegalOpcode { pc: 0x3001, word })
+            );
+            assert_eq!(state.pc, 0x3001);
+            assert_eq!(state.instructions, 1);
+        }
+    }
+
+    #[test]
+    fn test_bad_trap_vector() {
+        let mut state = program();
+        state.memory.write(0x3000, 0xf0ff);
+        let result = state.step();
+
+        assert_eq!(
+            result,
+            StepResult::Error(VmError::BadTrapVector {
+                pc: 0x3000,
+                vector: 0xff
+            })
+        );
+        assert_eq!(state.pc, 0x3000);
+    }
+
+    #[test]
+    fn test_os_traps() {
+        let mut state = program();
+        state.trap_mode = TrapMode::Os;
+        state.memory.write(0x0025, 0x0500);
+        // AND R0, R0, #0
+        // STI R0, #0
+        // .FILL xFFFE
+        state.memory.write(0x0500, 0x5020);
+        state.memory.write(0x0501, 0xb000);
+        state.memory.write(0x0502, 0xfffe);
+
+        state.step();
+        state.step();
+        let result = state.step();
+        assert_eq!(result, StepResult::Trapped { vector: 0x25 });
+        assert_eq!(
+            (state.pc, state.registers.read(Register::R7)),
+            (0x0500, 0x3003)
+        );
+        assert!(state.running);
+
+        // Clearing the MCR's clock enable bit is what stops the machine.
+        state.step();
+        let result = state.step();
+        assert_eq!(
+            result,
+            StepResult::Halted {
+                reason: HaltReason::Halt
+            }
+        );
+        assert_eq!((state.pc, state.instructions), (0x0502, 5));
+
+        // Vectors without a built-in routine go through the table too.
+        let mut state = program();
+        state.trap_mode = TrapMode::Os;
+        state.memory.write(0x3000, 0xf0ff);
+        state.memory.write(0x00ff, 0x0600);
+        let result = state.step();
+        assert_eq!(result, StepResult::Trapped { vector: 0xff });
+        assert_eq!(
+            (state.pc, state.registers.read(Register::R7)),
+            (0x0600, 0x3001)
+        );
+    }
+
+    // Fails every read and write.
+    struct BrokenConsole(Option<std::io::Error>);
+
+    impl crate::console::Console for BrokenConsole {
+        fn poll_key(&mut self) -> Option<u8> {
+            self.0 = Some(std::io::Error::other("unplugged"));
+            None
+        }
+
+        fn read_key_blocking(&mut self, _deadline: Option<Instant>) -> Option<u8> {
+            self.poll_key()
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {
+            self.0 = Some(std::io::Error::other("unplugged"));
+        }
+
+        fn take_error(&mut self) -> Option<std::io::Error> {
+            self.0.take()
+        }
+    }
+
+    #[test]
+    fn test_console_errors() {
+        // GETC, then LDI R0, KBSR
+        for word in [0xf020, 0xa001] {
+            let mut state = program();
+            state.console.replace(Box::new(BrokenConsole(None)));
+            state.memory.write(0x3000, word);
+            state.memory.write(0x3002, memory::KBSR);
+            let result = state.step();
+
+            match result {
+                StepResult::Error(VmError::Io(e)) => assert_eq!(e.to_string(), "unplugged"),
+                result => panic!("{:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_display_and_machine_control_reads() {
+        let mut state = program();
+        // LDI R0, DSR
+        state.memory.write(0x3000, 0xa001);
+        state.memory.write(0x3002, memory::DSR);
+        let result = state.step();
+
+        assert_eq!(result, StepResult::Continue);
+        assert_eq!(state.registers.read(Register::R0), 1 << 15);
+        assert_eq!(state.memory.clone().read(memory::MCR), 1 << 15);
+    }
+
+    #[test]
+    fn test_load_rom_at_the_end_of_memory() {
+        let mut state = State::new();
+        assert!(state.load_rom(&mut [0xfffe, 1, 2]).is_ok());
+        assert_eq!(state.memory.peek(0xffff), 2);
+
+        assert_eq!(
+            state.load_rom(&mut [0xfffe, 1, 2, 3]),
+            Err("ROM doesn't fit between its origin and the end of memory.")
+        );
+    }
+}
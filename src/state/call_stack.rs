@@ -0,0 +1,115 @@
+use alloc::vec::Vec;
+
+/// A shadow call stack maintained alongside execution. The LC-3 only keeps the return address
+/// in R7, so this is the only way to recover the chain of active subroutine calls. TRAPs handled
+/// by the host return immediately, so they don't create frames, but those vectored to an OS do.
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub call_site: u16,
+    pub entry: u16,
+    pub return_address: u16,
+    // Set when a return didn't match this frame, e.g. because the program modified R7 itself.
+    pub suspect: bool,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn call(&mut self, call_site: u16, entry: u16, return_address: u16) {
+        self.frames.push(Frame {
+            call_site,
+            entry,
+            return_address,
+            suspect: false,
+        });
+    }
+
+    // Pops frames up to and including the one returning to `address`. If no frame matches, the
+    // stack is left alone and the innermost frame is marked as suspect.
+    pub fn ret(&mut self, address: u16) {
+        match self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_address == address)
+        {
+            Some(index) => self.frames.truncate(index),
+            None => {
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.suspect = true;
+                }
+            }
+        }
+    }
+
+    /// Active frames, innermost first.
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter().rev()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_and_ret() {
+        let mut call_stack = CallStack::new();
+        call_stack.call(0x3000, 0x3010, 0x3001);
+        call_stack.call(0x3010, 0x3020, 0x3011);
+
+        call_stack.ret(0x3011);
+
+        assert_eq!(
+            call_stack.frames().collect::<Vec<&Frame>>(),
+            vec![&Frame {
+                call_site: 0x3000,
+                entry: 0x3010,
+                return_address: 0x3001,
+                suspect: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_ret_unwinds_to_matching_frame() {
+        let mut call_stack = CallStack::new();
+        call_stack.call(0x3000, 0x3010, 0x3001);
+        call_stack.call(0x3010, 0x3020, 0x3011);
+
+        call_stack.ret(0x3001);
+
+        assert!(call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_ret_without_call() {
+        let mut call_stack = CallStack::new();
+
+        call_stack.ret(0x3001);
+
+        assert!(call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_mismatched_ret_marks_frame_as_suspect() {
+        let mut call_stack = CallStack::new();
+        call_stack.call(0x3000, 0x3010, 0x3001);
+
+        call_stack.ret(0x4000);
+
+        let frames: Vec<&Frame> = call_stack.frames().collect();
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].suspect);
+    }
+}
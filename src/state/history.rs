@@ -0,0 +1,102 @@
+use crate::instruction::Instruction;
+use std::collections::VecDeque;
+
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// A ring buffer of the most recently executed instructions.
+#[derive(Debug)]
+pub struct History {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub pc: u16,
+    pub word: u16,
+    pub instruction: Instruction,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, pc: u16, word: u16, instruction: Instruction) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(Entry {
+            pc,
+            word,
+            instruction,
+        });
+    }
+
+    /// Changes the capacity, keeping the most recent entries.
+    pub fn resize(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+        self.capacity = capacity;
+    }
+
+    /// The last `n` entries, oldest first.
+    pub fn last(&self, n: usize) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .skip(self.entries.len().saturating_sub(n))
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Register::*;
+
+    fn pcs<'a>(entries: impl Iterator<Item = &'a Entry>) -> Vec<u16> {
+        entries.map(|entry| entry.pc).collect()
+    }
+
+    #[test]
+    fn test_record_wraps_around() {
+        let mut history = History::new(3);
+
+        for pc in 0x3000..0x3005 {
+            history.record(pc, 0x1261, Instruction::ADDIMM(R1, R1, 1));
+        }
+
+        assert_eq!(pcs(history.last(10)), vec![0x3002, 0x3003, 0x3004]);
+        assert_eq!(pcs(history.last(2)), vec![0x3003, 0x3004]);
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut history = History::new(4);
+        for pc in 0x3000..0x3004 {
+            history.record(pc, 0x1261, Instruction::ADDIMM(R1, R1, 1));
+        }
+
+        history.resize(2);
+        history.record(0x3004, 0x1261, Instruction::ADDIMM(R1, R1, 1));
+
+        assert_eq!(pcs(history.last(10)), vec![0x3003, 0x3004]);
+    }
+}
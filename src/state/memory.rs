@@ -1,86 +1,657 @@
-use nix::sys::select::{select, FdSet};
-use nix::sys::time::{TimeVal, TimeValLike};
-use std::io::{self, Read};
+pub mod device;
+mod hexdump;
+
+pub use hexdump::HexdumpLines;
+
+use crate::console::SharedConsole;
+use crate::instruction::Instruction;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+use core::ops::{RangeBounds, RangeInclusive};
+use device::{Device, Display, Keyboard, MachineControl};
 
 // Keyboard status register. The ready bit (bit [15]) indicates if the keyboard has received a new
 // character.
-const KBSR: u16 = 0xfe00;
+pub const KBSR: u16 = 0xfe00;
 
 // Keyboard data register. Bits [7:0] contain the last character typed on the keyboard.
-const KBDR: u16 = 0xfe02;
+pub const KBDR: u16 = 0xfe02;
 
 // Display status register. The ready bit (bit [15]) indicates if the display device is ready to
 // receive another character to print on the screen.
-const DSR: u16 = 0xfe04;
+pub const DSR: u16 = 0xfe04;
 
 // Display data register. A character written in the low byte of this register will be displayed on
 // the screen.
-const DDR: u16 = 0xfe06;
+pub const DDR: u16 = 0xfe06;
 
 // Machine control register. Bit [15] is the clock enable bit. When cleared, instruction processing
 // stops.
-const MCR: u16 = 0xfffe;
+pub const MCR: u16 = 0xfffe;
+
+/// Called with the address, old value and new value of a memory write, see
+/// [`crate::Vm::set_memory_write_hook`].
+pub type WriteHook = Box<dyn FnMut(u16, u16, u16) + Send>;
+
+struct Mapping {
+    range: RangeInclusive<u16>,
+    device: Box<dyn Device>,
+}
+
+const SIZE: usize = u16::MAX as usize + 1;
 
 pub struct Memory {
-    memory: [u16; u16::max_value() as usize],
+    // Boxed so the machine is cheap to move and clones copy straight from heap to heap.
+    memory: Box<[u16; SIZE]>,
+    devices: Vec<Mapping>,
+    // A bit for each 256 word page with a device in it, so most accesses skip the device search.
+    device_pages: [u64; 4],
+    // Called for the program's stores, and for host writes too when `hook_host_writes` is set.
+    pub(crate) write_hook: Option<WriteHook>,
+    pub(crate) hook_host_writes: bool,
+    // Set whenever a device is read or written, so a step only checks the console for errors
+    // when it could have used it.
+    pub(crate) device_accessed: bool,
+    // Write-protected ranges, sorted and neither overlapping nor touching. Checked for the
+    // program's stores by `State::step`, while host writes ignore them.
+    pub(crate) protected: Vec<RangeInclusive<u16>>,
+    // The instruction decoded from each word that's been executed, `None` until it is and again
+    // once the word is written, see `set_decode_cache`.
+    decoded: Option<Box<[Option<Instruction>]>>,
 }
 
 impl Memory {
-    pub fn new() -> Self {
-        let mut memory = [0; u16::max_value() as usize];
-        memory[DSR as usize] = 1 << 15;
-        memory[MCR as usize] = 1 << 15;
+    /// Memory with the keyboard and display, using `console`, and the MCR mapped.
+    pub fn new(console: &SharedConsole) -> Self {
+        let mut memory = Self {
+            memory: zeroed(),
+            devices: Vec::new(),
+            device_pages: [0; 4],
+            write_hook: None,
+            hook_host_writes: false,
+            device_accessed: false,
+            protected: Vec::new(),
+            decoded: None,
+        };
+
+        let devices: [(RangeInclusive<u16>, Box<dyn Device>); 3] = [
+            (
+                Keyboard::START..=Keyboard::END,
+                Box::new(Keyboard::new(console.clone())),
+            ),
+            (
+                Display::START..=Display::END,
+                Box::new(Display::new(console.clone())),
+            ),
+            (
+                MachineControl::ADDRESS..=MachineControl::ADDRESS,
+                Box::new(MachineControl::new()),
+            ),
+        ];
+        for (range, device) in devices {
+            memory
+                .map_device(range, device)
+                .expect("standard devices don't overlap");
+        }
+
+        memory
+    }
 
-        Self { memory }
+    /// Hands reads and writes in `range` to `device`. Fails if the range overlaps another
+    /// device's.
+    pub fn map_device(
+        &mut self,
+        range: RangeInclusive<u16>,
+        device: Box<dyn Device>,
+    ) -> Result<(), String> {
+        if range.is_empty() {
+            return Err(format!(
+                "Device range {:#06x}-{:#06x} is empty",
+                range.start(),
+                range.end()
+            ));
+        }
+        if let Some(mapping) = self.devices.iter().find(|mapping| {
+            range.start() <= mapping.range.end() && mapping.range.start() <= range.end()
+        }) {
+            return Err(format!(
+                "Device range {:#06x}-{:#06x} overlaps {:#06x}-{:#06x}",
+                range.start(),
+                range.end(),
+                mapping.range.start(),
+                mapping.range.end()
+            ));
+        }
+
+        for page in (range.start() >> 8)..=(range.end() >> 8) {
+            self.device_pages[usize::from(page >> 6)] |= 1 << (page & 63);
+        }
+        self.devices.push(Mapping { range, device });
+
+        Ok(())
+    }
+
+    /// Write-protects `range` from the program's stores, see [`Memory::is_protected`]. Loading
+    /// programs and other host writes can still change it.
+    pub fn protect(&mut self, range: RangeInclusive<u16>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let mut start = *range.start();
+        let mut end = *range.end();
+        // Absorb the ranges this one overlaps or touches.
+        self.protected.retain(|other| {
+            let apart = u32::from(*other.end()) + 1 < u32::from(start)
+                || u32::from(end) + 1 < u32::from(*other.start());
+            if !apart {
+                start = start.min(*other.start());
+                end = end.max(*other.end());
+            }
+            apart
+        });
+        let index = self
+            .protected
+            .partition_point(|other| other.start() < &start);
+        self.protected.insert(index, start..=end);
+    }
+
+    /// Lifts the write protection from `range`, splitting any protected range it falls inside.
+    pub fn unprotect(&mut self, range: RangeInclusive<u16>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let (start, end) = (*range.start(), *range.end());
+        let mut protected = Vec::with_capacity(self.protected.len() + 1);
+        for other in self.protected.drain(..) {
+            if *other.end() < start || end < *other.start() {
+                protected.push(other);
+                continue;
+            }
+            if *other.start() < start {
+                protected.push(*other.start()..=start - 1);
+            }
+            if end < *other.end() {
+                protected.push(end + 1..=*other.end());
+            }
+        }
+        self.protected = protected;
+    }
+
+    /// Whether the program storing to `address` is an error.
+    pub fn is_protected(&self, address: u16) -> bool {
+        self.protected.iter().any(|range| range.contains(&address))
+    }
+
+    /// Copies `other`'s words into this memory without reallocating. Devices, protection and the
+    /// write hook are left as they are.
+    pub fn restore_from(&mut self, other: &Memory) {
+        self.memory.copy_from_slice(&other.memory[..]);
+        if let Some(decoded) = &mut self.decoded {
+            decoded.fill(None);
+        }
+    }
+
+    /// Keeps each instruction executed decoded, so loops don't decode the same words over and
+    /// over, at the cost of a table the size of memory. Off to begin with.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        if enabled != self.decoded.is_some() {
+            self.decoded = Some(empty_decode_cache()).filter(|_| enabled);
+        }
+    }
+
+    /// Decodes the words in `range` ahead of time, turning the decode cache on if it isn't, so
+    /// even the first run through them skips decoding. Device registers are left to be decoded if
+    /// they're ever executed, and a store over a word drops its entry as usual.
+    pub fn predecode(&mut self, range: RangeInclusive<u16>) {
+        self.set_decode_cache(true);
+        for address in range {
+            if !self.maybe_device(address) {
+                let word = self.memory[usize::from(address)];
+                if let Some(decoded) = &mut self.decoded {
+                    decoded[usize::from(address)] = Some(Instruction::decode(word));
+                }
+            }
+        }
+    }
+
+    /// Whether [`Memory::set_decode_cache`] turned the cache on.
+    pub fn has_decode_cache(&self) -> bool {
+        self.decoded.is_some()
+    }
+
+    // Decodes `word`, just fetched from `address`, going through the cache if it's on. Device
+    // registers aren't cached as reading them gives a different word each time.
+    pub(crate) fn decode(&mut self, address: u16, word: u16) -> Instruction {
+        let device = self.maybe_device(address);
+        match &mut self.decoded {
+            Some(decoded) if !device => decoded[usize::from(address)]
+                .get_or_insert_with(|| Instruction::decode(word))
+                .clone(),
+            _ => Instruction::decode(word),
+        }
+    }
+
+    /// The addresses and values of the non-zero words, skipping devices.
+    pub fn non_zero(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        (0..=u16::MAX)
+            .zip(self.memory.iter())
+            .filter(|(_, value)| **value != 0)
+            .map(|(address, value)| (address, *value))
+    }
+
+    /// The words in `range`, 8 to a line after the address, with an ASCII gutter for printable
+    /// low bytes. Lines start from the start of the range rather than a multiple of 8. Devices
+    /// are peeked rather than read.
+    ///
+    /// ```
+    /// use lc3::State;
+    ///
+    /// let mut state = State::new();
+    /// state.memory.write(0x3000, u16::from(b'A'));
+    /// assert_eq!(
+    ///     state.memory.hexdump(0x3000..0x3002),
+    ///     "0x3000: 0x0041 0x0000                                           |A.|"
+    /// );
+    /// ```
+    pub fn hexdump(&self, range: impl RangeBounds<u16>) -> String {
+        self.hexdump_lines(range)
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// [`Memory::hexdump`] a line at a time.
+    pub fn hexdump_lines(&self, range: impl RangeBounds<u16>) -> HexdumpLines<'_> {
+        HexdumpLines::new(self, range, |word| format!("{:#06x}", word))
+    }
+
+    // A hexdump with the values formatted by `format`, e.g. in the debugger's chosen format.
+    pub(crate) fn hexdump_lines_with<'a>(
+        &'a self,
+        range: impl RangeBounds<u16>,
+        format: impl Fn(u16) -> String + 'a,
+    ) -> HexdumpLines<'a> {
+        HexdumpLines::new(self, range, format)
+    }
+
+    // Swaps the mapped devices with `other`'s.
+    pub(crate) fn swap_devices(&mut self, other: &mut Memory) {
+        core::mem::swap(&mut self.devices, &mut other.devices);
+        core::mem::swap(&mut self.device_pages, &mut other.device_pages);
     }
 
     pub fn read(&mut self, address: u16) -> u16 {
-        if KBSR == address {
-            let value = if check_key() { 1 << 15 } else { 0 };
-            self.memory[KBSR as usize] = value;
-            value
-        } else if KBDR == address {
-            let kbsr = self.memory[KBSR as usize];
-            if ((kbsr >> 15) & 0x1) == 1 {
-                get_char()
-            } else {
-                0
+        match self.device(address) {
+            Some((offset, device)) => device.read(offset),
+            None => self.memory[address as usize],
+        }
+    }
+
+    // Returns the stored word without triggering any device side effects, for the debugger and
+    // other inspection. Guest programs go through `read`.
+    pub fn peek(&self, address: u16) -> u16 {
+        if self.maybe_device(address) {
+            if let Some(mapping) = self
+                .devices
+                .iter()
+                .find(|mapping| mapping.range.contains(&address))
+            {
+                return mapping.device.peek(address - mapping.range.start());
             }
-        } else if DSR == address {
-            unimplemented!("DSR")
-        } else if DDR == address {
-            let value = self.memory[DDR as usize];
-            print!("{}", char::from(value as u8));
-            value
-        } else if MCR == address {
-            unimplemented!("MCR")
-        } else {
-            self.memory[address as usize]
         }
+
+        self.memory[address as usize]
     }
 
+    // A write by the host, e.g. loading a program or the debugger setting memory.
     pub fn write(&mut self, address: u16, value: u16) {
-        self.memory[address as usize] = value;
+        if self.hook_host_writes {
+            self.store(address, value);
+        } else {
+            self.write_through(address, value);
+        }
+    }
+
+    // A store by the guest program.
+    pub fn store(&mut self, address: u16, value: u16) {
+        if self.write_hook.is_some() {
+            let old = self.peek(address);
+            if let Some(hook) = &mut self.write_hook {
+                hook(address, old, value);
+            }
+        }
+
+        self.write_through(address, value);
+    }
+
+    fn write_through(&mut self, address: u16, value: u16) {
+        match self.device(address) {
+            Some((offset, device)) => device.write(offset, value),
+            None => {
+                self.memory[address as usize] = value;
+                // Self-modifying code gets the new instruction.
+                if let Some(decoded) = &mut self.decoded {
+                    decoded[address as usize] = None;
+                }
+            }
+        }
+    }
+
+    // The device mapped at `address` and the offset into it, if there is one.
+    fn device(&mut self, address: u16) -> Option<(u16, &mut (dyn Device + 'static))> {
+        if !self.maybe_device(address) {
+            return None;
+        }
+
+        self.device_accessed = true;
+        self.devices
+            .iter_mut()
+            .find(|mapping| mapping.range.contains(&address))
+            .map(|mapping| (address - mapping.range.start(), mapping.device.as_mut()))
+    }
+
+    fn maybe_device(&self, address: u16) -> bool {
+        let page = address >> 8;
+        (self.device_pages[usize::from(page >> 6)] >> (page & 63)) & 1 == 1
+    }
+}
+
+// A clone has copies of the devices that support it, see `Device::try_clone`, but no write hook.
+impl Clone for Memory {
+    fn clone(&self) -> Self {
+        let mut memory = Self {
+            memory: zeroed(),
+            devices: Vec::new(),
+            device_pages: [0; 4],
+            write_hook: None,
+            hook_host_writes: self.hook_host_writes,
+            device_accessed: false,
+            protected: self.protected.clone(),
+            decoded: self.decoded.as_ref().map(|_| empty_decode_cache()),
+        };
+        memory.restore_from(self);
+
+        for mapping in &self.devices {
+            if let Some(device) = mapping.device.try_clone() {
+                memory
+                    .map_device(mapping.range.clone(), device)
+                    .expect("cloned devices don't overlap");
+            }
+        }
+
+        memory
+    }
+}
+
+// Compares the words. Devices and hooks aren't part of it.
+impl PartialEq for Memory {
+    fn eq(&self, other: &Self) -> bool {
+        self.memory[..] == other.memory[..]
     }
 }
 
-fn check_key() -> bool {
-    const STDIN_FILENO: i32 = 0;
+// Only the non-zero words, rather than all 65536.
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.non_zero()
+                    .map(|(address, value)| (Word(address), Word(value))),
+            )
+            .finish()
+    }
+}
 
-    let mut readfds = FdSet::new();
-    readfds.insert(STDIN_FILENO);
+// Formats as hex in `Debug` output.
+pub(crate) struct Word(pub u16);
 
-    match select(None, &mut readfds, None, None, &mut TimeVal::zero()) {
-        Ok(value) => value == 1,
-        Err(_) => false,
+impl fmt::Debug for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#06x}", self.0)
     }
 }
 
-fn get_char() -> u16 {
-    let mut buffer = [0; 1];
-    io::stdin()
-        .read_exact(&mut buffer)
-        .expect("unable to read from STDIN");
+// Allocated directly on the heap, as a 128 KB array would be built on the stack first.
+fn zeroed() -> Box<[u16; SIZE]> {
+    vec![0; SIZE]
+        .into_boxed_slice()
+        .try_into()
+        .expect("the vector has SIZE words")
+}
+
+fn empty_decode_cache() -> Box<[Option<Instruction>]> {
+    vec![None; SIZE].into_boxed_slice()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::{BufferConsole, Echo};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_peek_leaves_devices_alone() {
+        let mut memory = Memory::new(&SharedConsole::default());
+        memory.write(KBSR, 1 << 15);
+        memory.write(KBDR, u16::from(b'q'));
+
+        // Neither polls the keyboard nor reads STDIN, which would block or fail under test.
+        assert_eq!(memory.peek(KBSR), 1 << 15);
+        assert_eq!(memory.peek(KBDR), u16::from(b'q'));
+        assert_eq!(memory.peek(DSR), 1 << 15);
+        assert_eq!(memory.peek(MCR), 1 << 15);
+        assert_eq!(memory.peek(KBDR), u16::from(b'q'));
+    }
+
+    #[test]
+    fn test_keyboard_reads_from_the_console() {
+        let console = BufferConsole::new(b"ab");
+        let mut memory = Memory::new(&SharedConsole::new(Box::new(console)));
+
+        assert_eq!(memory.read(KBSR), 1 << 15);
+        // The key stays put until KBDR is read.
+        assert_eq!(memory.read(KBSR), 1 << 15);
+        assert_eq!(memory.read(KBDR), u16::from(b'a'));
+        assert_eq!(memory.read(KBDR), 0);
+
+        assert_eq!(memory.read(KBSR), 1 << 15);
+        assert_eq!(memory.read(KBDR), u16::from(b'b'));
+        assert_eq!(memory.read(KBSR), 0);
+    }
+
+    #[test]
+    fn test_display_writes_to_the_console() {
+        let console = BufferConsole::default();
+        let mut memory = Memory::new(&SharedConsole::new(Box::new(console.clone())));
+
+        assert_eq!(memory.read(DSR), 1 << 15);
+        memory.write(DDR, u16::from(b'!'));
+        assert_eq!(memory.read(DDR), u16::from(b'!'));
+        assert_eq!(console.output(), b"!");
+    }
+
+    #[test]
+    fn test_keyboard_echo() {
+        let console = BufferConsole::new(b"ab");
+        let shared = SharedConsole::new(Box::new(console.clone()));
+        let mut memory = Memory::new(&shared);
+
+        memory.read(KBSR);
+        memory.read(KBDR);
+        assert!(console.output().is_empty());
+
+        // Only the read that takes the key echoes it.
+        shared.set_echo(Echo::On);
+        memory.read(KBSR);
+        memory.read(KBDR);
+        memory.read(KBDR);
+        assert_eq!(console.output(), b"b");
+    }
+
+    // Counts its reads and writes, and reads back the offset.
+    struct Counter {
+        reads: Arc<AtomicUsize>,
+        writes: Arc<AtomicUsize>,
+    }
+
+    impl Device for Counter {
+        fn read(&mut self, offset: u16) -> u16 {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            offset
+        }
+
+        fn write(&mut self, _offset: u16, _value: u16) {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_map_device() {
+        let mut memory = Memory::new(&SharedConsole::default());
+        let reads = Arc::new(AtomicUsize::new(0));
+        let writes = Arc::new(AtomicUsize::new(0));
+        let counter = Counter {
+            reads: Arc::clone(&reads),
+            writes: Arc::clone(&writes),
+        };
+        memory
+            .map_device(0x40fe..=0x4101, Box::new(counter))
+            .unwrap();
+
+        assert_eq!(memory.read(0x40fe), 0);
+        assert_eq!(memory.read(0x4101), 3);
+        memory.store(0x4100, 7);
+        memory.write(0x4100, 8);
+        assert_eq!(memory.peek(0x4100), 0);
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+        assert_eq!(writes.load(Ordering::SeqCst), 2);
+
+        // Either side of the device is ordinary memory.
+        memory.store(0x40fd, 1);
+        memory.store(0x4102, 2);
+        assert_eq!(memory.read(0x40fd), 1);
+        assert_eq!(memory.read(0x4102), 2);
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+        assert_eq!(writes.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_map_device_rejects_overlaps() {
+        let mut memory = Memory::new(&SharedConsole::default());
+        let counter = || {
+            Box::new(Counter {
+                reads: Arc::default(),
+                writes: Arc::default(),
+            })
+        };
+
+        assert!(memory.map_device(0x4000..=0x4003, counter()).is_ok());
+        assert_eq!(
+            memory.map_device(0x4003..=0x4004, counter()),
+            Err("Device range 0x4003-0x4004 overlaps 0x4000-0x4003".to_string())
+        );
+        assert_eq!(
+            memory.map_device(0xfe06..=0xfe10, counter()),
+            Err("Device range 0xfe06-0xfe10 overlaps 0xfe04-0xfe06".to_string())
+        );
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = 0x5000..=0x4000;
+        assert_eq!(
+            memory.map_device(empty, counter()),
+            Err("Device range 0x5000-0x4000 is empty".to_string())
+        );
+        assert!(memory.map_device(0x4004..=0x4004, counter()).is_ok());
+    }
+
+    #[test]
+    fn test_decode_cache() {
+        let mut memory = Memory::new(&SharedConsole::default());
+        memory.set_decode_cache(true);
+        memory.write(0x3000, 0x1021); // ADD R0, R0, #1
+        let add = Instruction::decode(0x1021);
+
+        assert_eq!(memory.decode(0x3000, 0x1021), add.clone());
+        // Served from the cache.
+        assert_eq!(memory.decode(0x3000, 0xf025), add.clone());
+
+        memory.store(0x3000, 0xf025);
+        assert_eq!(memory.decode(0x3000, 0xf025), Instruction::decode(0xf025));
+
+        let snapshot = memory.clone();
+        assert!(snapshot.has_decode_cache());
+        memory.restore_from(&Memory::new(&SharedConsole::default()));
+        assert_eq!(memory.decode(0x3000, 0x1021), add);
+
+        // Device registers give a different word on each read.
+        assert_eq!(memory.decode(KBSR, 0x1021), Instruction::decode(0x1021));
+        assert_eq!(memory.decode(KBSR, 0xf025), Instruction::decode(0xf025));
+
+        memory.set_decode_cache(false);
+        assert!(!memory.has_decode_cache());
+    }
+
+    #[test]
+    fn test_predecode() {
+        let mut memory = Memory::new(&SharedConsole::default());
+        memory.write(0x3000, 0x1021); // ADD R0, R0, #1
+        memory.write(0x3001, 0xf0ff); // TRAP xFF
+        memory.predecode(0x3000..=0x3001);
+        assert!(memory.has_decode_cache());
 
-    u16::from(buffer[0])
+        // Decoded without looking at the word passed in.
+        let add = Instruction::decode(0x1021);
+        assert_eq!(memory.decode(0x3000, 0xf025), add);
+        assert_eq!(
+            memory.decode(0x3001, 0x1021),
+            Instruction::UNKNOWNTRAP(0xff)
+        );
+
+        memory.store(0x3000, 0xf025);
+        assert_eq!(memory.decode(0x3000, 0xf025), Instruction::decode(0xf025));
+
+        // Device registers are skipped.
+        memory.predecode(KBSR..=KBSR);
+        assert_eq!(memory.decode(KBSR, 0x1021), Instruction::decode(0x1021));
+    }
+
+    #[test]
+    fn test_protect_and_unprotect() {
+        let mut memory = Memory::new(&SharedConsole::default());
+        memory.protect(0x3000..=0x300f);
+        memory.protect(0x3020..=0x302f);
+        // Touching the first, so they merge.
+        memory.protect(0x3010..=0x3018);
+        memory.protect(0xfff0..=0xffff);
+        assert_eq!(
+            memory.protected,
+            [0x3000..=0x3018, 0x3020..=0x302f, 0xfff0..=0xffff]
+        );
+
+        // Bridging the gap.
+        memory.protect(0x3010..=0x3020);
+        assert_eq!(memory.protected, [0x3000..=0x302f, 0xfff0..=0xffff]);
+        assert!(memory.is_protected(0x3000));
+        assert!(!memory.is_protected(0x3030));
+
+        memory.unprotect(0x3010..=0x301f);
+        memory.unprotect(0xffff..=0xffff);
+        assert_eq!(
+            memory.protected,
+            [0x3000..=0x300f, 0x3020..=0x302f, 0xfff0..=0xfffe]
+        );
+        memory.unprotect(0x0000..=0xffff);
+        assert!(memory.protected.is_empty());
+
+        // Host writes aren't protected.
+        memory.protect(0x3000..=0x3000);
+        memory.write(0x3000, 0x1234);
+        assert_eq!(memory.peek(0x3000), 0x1234);
+    }
 }
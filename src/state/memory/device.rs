@@ -0,0 +1,170 @@
+use super::{DDR, DSR, KBDR, KBSR, MCR};
+use crate::console::{Echo, SharedConsole};
+use alloc::boxed::Box;
+
+/// Memory-mapped hardware, see [`crate::Vm::map_device`]. Offsets are relative to the start of
+/// the range the device is mapped at.
+pub trait Device: Send {
+    /// A read by the program, which may have side effects.
+    fn read(&mut self, offset: u16) -> u16;
+
+    fn write(&mut self, offset: u16, value: u16);
+
+    /// What a read would return, without the side effects, for the debugger and other
+    /// inspection. Devices without readable state can leave this as 0.
+    fn peek(&self, offset: u16) -> u16 {
+        let _ = offset;
+        0
+    }
+
+    /// A copy for a cloned machine, see [`crate::State`]'s `Clone`. Devices that can't be copied
+    /// return `None`, leaving plain memory in their range in the clone.
+    fn try_clone(&self) -> Option<Box<dyn Device>> {
+        None
+    }
+}
+
+// The keyboard status and data registers, KBSR and KBDR.
+#[derive(Clone)]
+pub struct Keyboard {
+    console: SharedConsole,
+    registers: [u16; 3],
+}
+
+impl Keyboard {
+    pub const START: u16 = KBSR;
+    pub const END: u16 = KBDR;
+
+    const STATUS: usize = 0;
+    const DATA: usize = (KBDR - KBSR) as usize;
+
+    pub fn new(console: SharedConsole) -> Self {
+        Self {
+            console,
+            registers: [0; 3],
+        }
+    }
+
+    fn key_ready(&self) -> bool {
+        (self.registers[Self::STATUS] >> 15) & 0x1 == 1
+    }
+}
+
+impl Device for Keyboard {
+    fn read(&mut self, offset: u16) -> u16 {
+        match offset as usize {
+            Self::STATUS => {
+                // A typed key waits in KBDR until the program reads it.
+                if !self.key_ready() {
+                    if let Some(key) = self.console.lock().poll_key() {
+                        self.registers[Self::DATA] = u16::from(key);
+                        self.registers[Self::STATUS] = 1 << 15;
+                    }
+                }
+                self.registers[Self::STATUS]
+            }
+            Self::DATA => {
+                if self.key_ready() {
+                    self.registers[Self::STATUS] = 0;
+                    if self.console.echo() == Echo::On {
+                        self.console
+                            .lock()
+                            .write(&[self.registers[Self::DATA] as u8]);
+                    }
+                    self.registers[Self::DATA]
+                } else {
+                    0
+                }
+            }
+            offset => self.registers[offset],
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        self.registers[offset as usize] = value;
+    }
+
+    fn peek(&self, offset: u16) -> u16 {
+        self.registers[offset as usize]
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Device>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// The display status and data registers, DSR and DDR.
+#[derive(Clone)]
+pub struct Display {
+    console: SharedConsole,
+    registers: [u16; 3],
+}
+
+impl Display {
+    pub const START: u16 = DSR;
+    pub const END: u16 = DDR;
+
+    const STATUS: usize = 0;
+    const DATA: usize = (DDR - DSR) as usize;
+
+    pub fn new(console: SharedConsole) -> Self {
+        let mut registers = [0; 3];
+        registers[Self::STATUS] = 1 << 15;
+
+        Self { console, registers }
+    }
+}
+
+impl Device for Display {
+    fn read(&mut self, offset: u16) -> u16 {
+        self.registers[offset as usize]
+    }
+
+    // Output is written straight away, so the display is always ready for more.
+    fn write(&mut self, offset: u16, value: u16) {
+        if offset as usize == Self::DATA {
+            self.console.lock().write(&[value as u8]);
+        }
+        self.registers[offset as usize] = value;
+    }
+
+    fn peek(&self, offset: u16) -> u16 {
+        self.registers[offset as usize]
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Device>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// The machine control register, MCR.
+#[derive(Clone)]
+pub struct MachineControl {
+    register: u16,
+}
+
+impl MachineControl {
+    pub const ADDRESS: u16 = MCR;
+
+    pub fn new() -> Self {
+        Self { register: 1 << 15 }
+    }
+}
+
+impl Device for MachineControl {
+    fn read(&mut self, _offset: u16) -> u16 {
+        self.register
+    }
+
+    fn write(&mut self, _offset: u16, value: u16) {
+        self.register = value;
+    }
+
+    fn peek(&self, _offset: u16) -> u16 {
+        self.register
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Device>> {
+        Some(Box::new(self.clone()))
+    }
+}
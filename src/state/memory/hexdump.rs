@@ -0,0 +1,136 @@
+use super::Memory;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::{Bound, RangeBounds};
+
+const WORDS_PER_LINE: u32 = 8;
+
+/// The lines of a [`Memory::hexdump`], see [`Memory::hexdump_lines`].
+pub struct HexdumpLines<'a> {
+    memory: &'a Memory,
+    // The next line's address and the end of the range, as u32 so 0xffff can be included.
+    next: u32,
+    end: u32,
+    format: Box<dyn Fn(u16) -> String + 'a>,
+}
+
+impl<'a> HexdumpLines<'a> {
+    pub(super) fn new(
+        memory: &'a Memory,
+        range: impl RangeBounds<u16>,
+        format: impl Fn(u16) -> String + 'a,
+    ) -> Self {
+        let next = match range.start_bound() {
+            Bound::Included(start) => u32::from(*start),
+            Bound::Excluded(start) => u32::from(*start) + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => u32::from(*end) + 1,
+            Bound::Excluded(end) => u32::from(*end),
+            Bound::Unbounded => 0x10000,
+        };
+
+        HexdumpLines {
+            memory,
+            next,
+            end,
+            format: Box::new(format),
+        }
+    }
+}
+
+impl Iterator for HexdumpLines<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.next >= self.end {
+            return None;
+        }
+
+        let start = self.next;
+        self.next = self.end.min(start + WORDS_PER_LINE);
+        let words: Vec<u16> = (start..self.next)
+            .map(|address| self.memory.peek(address as u16))
+            .collect();
+
+        let values = words
+            .iter()
+            .map(|word| format!("{:>6}", (self.format)(*word)))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let ascii: String = words
+            .iter()
+            .map(|word| {
+                let c = char::from(*word as u8);
+                if c.is_ascii_graphic() || c == ' ' {
+                    c
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        Some(format!("{:#06x}: {:<55} |{}|", start, values, ascii))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::console::SharedConsole;
+    use crate::state::memory::Memory;
+
+    // "Hello, World!" from x3000, with a word whose high byte is set after it.
+    fn fixture() -> Memory {
+        let mut memory = Memory::new(&SharedConsole::default());
+        for (i, c) in "Hello, World!".bytes().enumerate() {
+            memory.write(0x3000 + i as u16, u16::from(c));
+        }
+        memory.write(0x300e, 0xff41);
+        memory.write(0xffff, 0x1234);
+        memory
+    }
+
+    #[test]
+    fn test_hexdump() {
+        assert_eq!(
+            fixture().hexdump(0x3000..0x3014),
+            [
+                "0x3000: 0x0048 0x0065 0x006c 0x006c 0x006f 0x002c 0x0020 0x0057 |Hello, W|",
+                "0x3008: 0x006f 0x0072 0x006c 0x0064 0x0021 0x0000 0xff41 0x0000 |orld!.A.|",
+                "0x3010: 0x0000 0x0000 0x0000 0x0000                             |....|",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_hexdump_unaligned() {
+        assert_eq!(
+            fixture().hexdump(0x3003..=0x300d),
+            [
+                "0x3003: 0x006c 0x006f 0x002c 0x0020 0x0057 0x006f 0x0072 0x006c |lo, Worl|",
+                "0x300b: 0x0064 0x0021 0x0000                                    |d!.|",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_hexdump_to_the_end_of_memory() {
+        // The MCR is peeked, so the clock stays enabled.
+        assert_eq!(
+            fixture().hexdump(0xfff8..),
+            "0xfff8: 0x0000 0x0000 0x0000 0x0000 0x0000 0x0000 0x8000 0x1234 |.......4|"
+        );
+        assert_eq!(fixture().hexdump_lines(..).count(), 0x10000 / 8);
+    }
+
+    #[test]
+    fn test_hexdump_empty() {
+        assert_eq!(fixture().hexdump(0x3000..0x3000), "");
+        assert_eq!(fixture().hexdump_lines(0x3000..0x3000).next(), None);
+    }
+}
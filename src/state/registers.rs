@@ -1,5 +1,16 @@
 use crate::instruction::Register;
+use core::ops::{Index, IndexMut};
 
+/// R0 to R7, indexed by [`Register`].
+///
+/// ```
+/// use lc3::{Register, Registers};
+///
+/// let mut registers = Registers::new();
+/// registers[Register::R3] = 7;
+/// assert_eq!(registers[Register::R3], 7);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Registers {
     registers: [u16; 8],
 }
@@ -10,14 +21,79 @@ impl Registers {
     }
 
     pub fn read(&self, register: Register) -> u16 {
-        self.registers[register as usize]
+        self[register]
     }
 
     pub fn write(&mut self, register: Register, value: u16) {
-        self.registers[register as usize] = value
+        self[register] = value
+    }
+
+    /// Each register with its value, R0 to R7.
+    pub fn iter(&self) -> impl Iterator<Item = (Register, u16)> + '_ {
+        self.registers
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (Register::from(i as u16), *value))
     }
 
     pub fn registers(&self) -> [u16; 8] {
         self.registers
     }
+
+    pub fn restore(&mut self, registers: [u16; 8]) {
+        self.registers = registers
+    }
+}
+
+impl Index<Register> for Registers {
+    type Output = u16;
+
+    fn index(&self, register: Register) -> &u16 {
+        &self.registers[register as usize]
+    }
+}
+
+impl IndexMut<Register> for Registers {
+    fn index_mut(&mut self, register: Register) -> &mut u16 {
+        &mut self.registers[register as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index() {
+        let mut registers = Registers::default();
+        registers.write(Register::R2, 2);
+        assert_eq!(registers[Register::R2], 2);
+
+        registers[Register::R7] = 0x3000;
+        registers[Register::R2] += 1;
+        assert_eq!(registers.read(Register::R7), 0x3000);
+        assert_eq!(registers.read(Register::R2), 3);
+        assert_eq!(registers.registers(), [0, 0, 3, 0, 0, 0, 0, 0x3000]);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut registers = Registers::new();
+        registers.restore([10, 11, 12, 13, 14, 15, 16, 17]);
+
+        let all: Vec<(Register, u16)> = registers.iter().collect();
+        assert_eq!(
+            all,
+            [
+                (Register::R0, 10),
+                (Register::R1, 11),
+                (Register::R2, 12),
+                (Register::R3, 13),
+                (Register::R4, 14),
+                (Register::R5, 15),
+                (Register::R6, 16),
+                (Register::R7, 17),
+            ]
+        );
+    }
 }
@@ -0,0 +1,364 @@
+use super::{Condition, State, TrapMode};
+use crate::cpu::sign_extend;
+use crate::instruction::{Instruction, Register, TrapVector};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+const OPCODES: [&str; 16] = [
+    "BR", "ADD", "LD", "ST", "JSR", "AND", "LDR", "STR", "RTI", "NOT", "LDI", "STI", "JMP", "RES",
+    "LEA", "TRAP",
+];
+
+// How many of the busiest addresses the summary lists.
+const SUMMARY_ADDRESSES: usize = 10;
+
+/// Counts of what the program has done since the machine was created or reset, see
+/// [`crate::Vm::stats`].
+///
+/// Memory accesses are the instruction fetch and the loads and stores the instruction makes
+/// itself, counted per address. The words a built-in TRAP routine like PUTS reads aren't
+/// included, though its use of R0 is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExecStats {
+    reads: HashMap<u16, u64>,
+    writes: HashMap<u16, u64>,
+    register_reads: [u64; 8],
+    register_writes: [u64; 8],
+    opcodes: [u64; 16],
+    traps: BTreeMap<u8, u64>,
+    branches_taken: u64,
+    branches_not_taken: u64,
+}
+
+impl ExecStats {
+    /// How many times `address` has been read, including as an instruction.
+    pub fn reads(&self, address: u16) -> u64 {
+        self.reads.get(&address).copied().unwrap_or(0)
+    }
+
+    /// How many times the program has stored to `address`.
+    pub fn writes(&self, address: u16) -> u64 {
+        self.writes.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn register_reads(&self, register: Register) -> u64 {
+        self.register_reads[register as usize]
+    }
+
+    pub fn register_writes(&self, register: Register) -> u64 {
+        self.register_writes[register as usize]
+    }
+
+    /// Up to `n` of the most accessed addresses, with their reads plus writes, busiest first and
+    /// then by address.
+    pub fn hottest_addresses(&self, n: usize) -> Vec<(u16, u64)> {
+        let mut accesses = self.reads.clone();
+        for (address, count) in &self.writes {
+            *accesses.entry(*address).or_insert(0) += count;
+        }
+
+        let mut hottest: Vec<(u16, u64)> = accesses.into_iter().collect();
+        hottest.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        hottest.truncate(n);
+        hottest
+    }
+
+    /// Each opcode executed and how many times, most frequent first and then in opcode order.
+    /// JSRR counts as JSR and RET as JMP.
+    pub fn opcode_histogram(&self) -> Vec<(&'static str, u64)> {
+        let mut histogram: Vec<(&'static str, u64)> = OPCODES
+            .iter()
+            .zip(self.opcodes.iter())
+            .filter(|(_, count)| **count > 0)
+            .map(|(name, count)| (*name, *count))
+            .collect();
+        // Stable, so equal counts stay in opcode order.
+        histogram.sort_by(|(_, a), (_, b)| b.cmp(a));
+        histogram
+    }
+
+    /// Each TRAP vector executed and how many times, in vector order.
+    pub fn traps(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        self.traps.iter().map(|(vector, count)| (*vector, *count))
+    }
+
+    /// How many BRs jumped. An unconditional BRnzp always does.
+    pub fn branches_taken(&self) -> u64 {
+        self.branches_taken
+    }
+
+    /// How many BRs fell through to the next instruction.
+    pub fn branches_not_taken(&self) -> u64 {
+        self.branches_not_taken
+    }
+
+    // Counts the instruction about to execute, from the state before it does.
+    pub(crate) fn record(&mut self, state: &State, word: u16, instruction: &Instruction) {
+        self.opcodes[usize::from(word >> 12)] += 1;
+        self.read(state.pc);
+
+        let pc = state.pc.wrapping_add(1);
+        let registers = &state.registers;
+        match instruction {
+            Instruction::ADD(r0, r1, r2) | Instruction::AND(r0, r1, r2) => {
+                self.read_registers(&[*r1, *r2]);
+                self.write_register(*r0);
+            }
+            Instruction::ADDIMM(r0, r1, _)
+            | Instruction::ANDIMM(_, r0, r1)
+            | Instruction::NOT(r0, r1) => {
+                self.read_registers(&[*r1]);
+                self.write_register(*r0);
+            }
+            Instruction::LD(r0, pc_offset) => {
+                self.read(pc.wrapping_add(sign_extend(*pc_offset, 9)));
+                self.write_register(*r0);
+            }
+            Instruction::LDI(r0, pc_offset) => {
+                let pointer = pc.wrapping_add(sign_extend(*pc_offset, 9));
+                self.read(pointer);
+                self.read(state.memory.peek(pointer));
+                self.write_register(*r0);
+            }
+            Instruction::LDR(r0, base_r, offset) => {
+                self.read_registers(&[*base_r]);
+                self.read(registers[*base_r].wrapping_add(sign_extend(*offset, 6)));
+                self.write_register(*r0);
+            }
+            Instruction::LEA(r0, _) => self.write_register(*r0),
+            Instruction::ST(sr, _) | Instruction::STI(sr, _) | Instruction::STR(sr, _, _) => {
+                self.read_registers(&[*sr]);
+                match instruction {
+                    Instruction::STI(_, pc_offset) => {
+                        self.read(pc.wrapping_add(sign_extend(*pc_offset, 9)))
+                    }
+                    Instruction::STR(_, base_r, _) => self.read_registers(&[*base_r]),
+                    _ => {}
+                }
+                if let Some(address) = state.store_address(instruction) {
+                    *self.writes.entry(address).or_insert(0) += 1;
+                }
+            }
+            Instruction::JSR(_) => self.write_register(Register::R7),
+            Instruction::JSRR(base_r) => {
+                self.read_registers(&[*base_r]);
+                self.write_register(Register::R7);
+            }
+            Instruction::JMP(base_r) => self.read_registers(&[*base_r]),
+            // The built-in routines return straight to the next instruction, leaving R7 alone.
+            // An OS's are counted as they run.
+            Instruction::TRAP(trap_vector) => {
+                *self.traps.entry(trap_vector.vector()).or_insert(0) += 1;
+                if state.trap_mode == TrapMode::Os {
+                    self.write_register(Register::R7);
+                    return;
+                }
+                match trap_vector {
+                    TrapVector::GETC | TrapVector::IN => self.write_register(Register::R0),
+                    TrapVector::OUT | TrapVector::PUTS | TrapVector::PUTSP => {
+                        self.read_registers(&[Register::R0])
+                    }
+                    TrapVector::HALT => {}
+                }
+            }
+            Instruction::BR(condition, _) => {
+                let taken = match state.condition {
+                    Condition::N => condition.n,
+                    Condition::Z => condition.z,
+                    Condition::P => condition.p,
+                };
+                if taken {
+                    self.branches_taken += 1;
+                } else {
+                    self.branches_not_taken += 1;
+                }
+            }
+            Instruction::UNUSED | Instruction::RESERVED | Instruction::UNKNOWNTRAP(_) => {}
+        }
+    }
+
+    // Counts a TRAP run by a registered handler, or one for an OS without a built-in routine.
+    // Only the call itself is known.
+    pub(crate) fn record_host_trap(&mut self, pc: u16, vector: u8) {
+        self.opcodes[0xf] += 1;
+        *self.traps.entry(vector).or_insert(0) += 1;
+        self.read(pc);
+        self.write_register(Register::R7);
+    }
+
+    fn read(&mut self, address: u16) {
+        *self.reads.entry(address).or_insert(0) += 1;
+    }
+
+    fn read_registers(&mut self, registers: &[Register]) {
+        for register in registers {
+            self.register_reads[*register as usize] += 1;
+        }
+    }
+
+    fn write_register(&mut self, register: Register) {
+        self.register_writes[register as usize] += 1;
+    }
+}
+
+// The counts `--stats` prints, in a fixed order so runs can be compared.
+impl fmt::Display for ExecStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Opcodes:")?;
+        for (name, count) in self.opcode_histogram() {
+            writeln!(f, "  {:<5} {:>10}", name, count)?;
+        }
+
+        writeln!(f, "Traps:")?;
+        for (vector, count) in self.traps() {
+            let name = TrapVector::try_decode(u16::from(vector))
+                .map(|trap_vector| format!("{:?}", trap_vector))
+                .unwrap_or_default();
+            writeln!(f, "  x{:02X} {:<5} {:>7}", vector, name, count)?;
+        }
+
+        writeln!(
+            f,
+            "Branches: {} taken, {} not taken",
+            self.branches_taken, self.branches_not_taken
+        )?;
+
+        writeln!(f, "Busiest addresses:")?;
+        for (address, count) in self.hottest_addresses(SUMMARY_ADDRESSES) {
+            writeln!(
+                f,
+                "  x{:04X} {:>10} ({} reads, {} writes)",
+                address,
+                count,
+                self.reads(address),
+                self.writes(address)
+            )?;
+        }
+
+        writeln!(f, "Registers:")?;
+        for i in 0..8 {
+            writeln!(
+                f,
+                "  R{}    {:>10} reads {:>10} writes",
+                i, self.register_reads[i], self.register_writes[i]
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::Register;
+    use crate::state::State;
+
+    // .ORIG x3000
+    //       AND R0, R0, #0
+    //       LD R1, COUNT
+    // LOOP  ADD R0, R0, #2
+    //       ADD R1, R1, #-1
+    //       BRp LOOP
+    //       ST R0, RESULT
+    //       HALT
+    // COUNT .FILL #3
+    // RESULT .BLKW 1
+    fn run() -> State {
+        let mut state = State::new();
+        state
+            .load_rom(&mut [
+                0x3000, 0x5020, 0x2205, 0x1022, 0x127f, 0x03fd, 0x3002, 0xf025, 0x0003, 0x0000,
+            ])
+            .unwrap();
+        while state.running {
+            state.step();
+        }
+        state
+    }
+
+    #[test]
+    fn test_counts() {
+        let state = run();
+        let stats = &state.stats;
+
+        assert_eq!(state.instructions, 13);
+        assert_eq!(
+            stats.opcode_histogram(),
+            [
+                ("ADD", 6),
+                ("BR", 3),
+                ("LD", 1),
+                ("ST", 1),
+                ("AND", 1),
+                ("TRAP", 1)
+            ]
+        );
+
+        assert_eq!(stats.reads(0x3002), 3);
+        assert_eq!(stats.reads(0x3007), 1);
+        assert_eq!(stats.writes(0x3008), 1);
+        assert_eq!(stats.reads(0x3008), 0);
+        assert_eq!(state.memory.peek(0x3008), 6);
+
+        assert_eq!(stats.register_reads(Register::R0), 5);
+        assert_eq!(stats.register_writes(Register::R0), 4);
+        assert_eq!(stats.register_reads(Register::R1), 3);
+        assert_eq!(stats.register_writes(Register::R1), 4);
+        assert_eq!(stats.register_writes(Register::R7), 0);
+    }
+
+    #[test]
+    fn test_hottest_addresses() {
+        let stats = run().stats;
+
+        assert_eq!(
+            stats.hottest_addresses(4),
+            [(0x3002, 3), (0x3003, 3), (0x3004, 3), (0x3000, 1)]
+        );
+        assert_eq!(stats.hottest_addresses(100).len(), 9);
+    }
+
+    #[test]
+    fn test_summary() {
+        let stats = run().stats;
+
+        assert_eq!(stats.traps().collect::<Vec<_>>(), [(0x25, 1)]);
+        assert_eq!((stats.branches_taken(), stats.branches_not_taken()), (2, 1));
+        assert_eq!(
+            stats.to_string(),
+            [
+                "Opcodes:",
+                "  ADD            6",
+                "  BR             3",
+                "  LD             1",
+                "  ST             1",
+                "  AND            1",
+                "  TRAP           1",
+                "Traps:",
+                "  x25 HALT        1",
+                "Branches: 2 taken, 1 not taken",
+                "Busiest addresses:",
+                "  x3002          3 (3 reads, 0 writes)",
+                "  x3003          3 (3 reads, 0 writes)",
+                "  x3004          3 (3 reads, 0 writes)",
+                "  x3000          1 (1 reads, 0 writes)",
+                "  x3001          1 (1 reads, 0 writes)",
+                "  x3005          1 (1 reads, 0 writes)",
+                "  x3006          1 (1 reads, 0 writes)",
+                "  x3007          1 (1 reads, 0 writes)",
+                "  x3008          1 (0 reads, 1 writes)",
+                "Registers:",
+                "  R0             5 reads          4 writes",
+                "  R1             3 reads          4 writes",
+                "  R2             0 reads          0 writes",
+                "  R3             0 reads          0 writes",
+                "  R4             0 reads          0 writes",
+                "  R5             0 reads          0 writes",
+                "  R6             0 reads          0 writes",
+                "  R7             0 reads          0 writes",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use super::memory::KBDR;
+use super::{Condition, State};
+use crate::cpu::sign_extend;
+use crate::instruction::{Instruction, TrapVector};
+use alloc::collections::VecDeque;
+
+pub const DEFAULT_DEPTH: usize = 4096;
+
+/// A bounded log of the machine state overwritten by each step, so steps can be undone.
+///
+/// Keyboard input consumed by a step can't be given back, so stepping back over GETC, IN, or a
+/// KBDR read restores the registers but marks the step as irreversible: executing it again won't
+/// see the same character. The debugger's call stack and history aren't rewound.
+#[derive(Debug, Clone)]
+pub struct UndoLog {
+    deltas: VecDeque<Delta>,
+    depth: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delta {
+    pub pc: u16,
+    pub condition: Condition,
+    pub running: bool,
+    pub registers: [u16; 8],
+    // The address and previous value of the memory word written by the step, if any.
+    pub memory: Option<(u16, u16)>,
+    pub irreversible: bool,
+}
+
+impl Delta {
+    /// Captures what executing `instruction` is about to overwrite.
+    pub fn capture(state: &State, instruction: &Instruction) -> Self {
+        let pc = state.pc.wrapping_add(1);
+        let store_address = state.store_address(instruction);
+        let load_address = match instruction {
+            Instruction::LD(_, pc_offset) => Some(pc.wrapping_add(sign_extend(*pc_offset, 9))),
+            Instruction::LDR(_, base_r, offset) => Some(
+                state
+                    .registers
+                    .read(*base_r)
+                    .wrapping_add(sign_extend(*offset, 6)),
+            ),
+            Instruction::LDI(_, pc_offset) => Some(
+                state
+                    .memory
+                    .peek(pc.wrapping_add(sign_extend(*pc_offset, 9))),
+            ),
+            _ => None,
+        };
+        let irreversible = match instruction {
+            Instruction::TRAP(TrapVector::GETC) | Instruction::TRAP(TrapVector::IN) => true,
+            _ => load_address == Some(KBDR),
+        };
+
+        Self {
+            pc: state.pc,
+            condition: state.condition.clone(),
+            running: state.running,
+            registers: state.registers(),
+            memory: store_address.map(|address| (address, state.memory.peek(address))),
+            irreversible,
+        }
+    }
+
+    /// For a step whose effects aren't known, restoring only the registers and flags.
+    pub fn irreversible(state: &State) -> Self {
+        Self {
+            pc: state.pc,
+            condition: state.condition.clone(),
+            running: state.running,
+            registers: state.registers(),
+            memory: None,
+            irreversible: true,
+        }
+    }
+
+    pub fn restore(&self, state: &mut State) {
+        state.pc = self.pc;
+        state.condition = self.condition.clone();
+        state.running = self.running;
+        state.instructions = state.instructions.saturating_sub(1);
+        state.registers.restore(self.registers);
+
+        if let Some((address, value)) = self.memory {
+            state.memory.write(address, value);
+        }
+    }
+}
+
+impl UndoLog {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            deltas: VecDeque::new(),
+            depth,
+        }
+    }
+
+    pub fn record(&mut self, delta: Delta) {
+        if self.depth == 0 {
+            return;
+        }
+        if self.deltas.len() == self.depth {
+            self.deltas.pop_front();
+        }
+
+        self.deltas.push_back(delta);
+    }
+
+    pub fn pop(&mut self) -> Option<Delta> {
+        self.deltas.pop_back()
+    }
+
+    /// Changes the depth, keeping the most recent deltas.
+    pub fn resize(&mut self, depth: usize) {
+        while self.deltas.len() > depth {
+            self.deltas.pop_front();
+        }
+        self.depth = depth;
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+impl Default for UndoLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEPTH)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+    use crate::instruction::Register::*;
+
+    fn new_state() -> State {
+        let mut state = State::new();
+        state.pc = 0x3000;
+        state.undo = Some(UndoLog::default());
+        state
+    }
+
+    fn step_back(mut state: State) -> State {
+        let delta = state.undo.as_mut().unwrap().pop().unwrap();
+        delta.restore(&mut state);
+        state
+    }
+
+    #[test]
+    fn test_reverse_add() {
+        let mut state = new_state();
+        state.registers.write(R1, 5);
+        state.memory.write(0x3000, 0b0001_001_001_1_11111); // ADD R1, R1, #-1
+
+        state.step();
+        assert_eq!(state.registers.read(R1), 4);
+
+        state = step_back(state);
+        assert_eq!(state.pc, 0x3000);
+        assert_eq!(state.registers.read(R1), 5);
+        assert_eq!(state.condition, Condition::P);
+    }
+
+    #[test]
+    fn test_reverse_st() {
+        let mut state = new_state();
+        state.registers.write(R2, 42);
+        state.memory.write(0x3000, 0b0011_010_000000100); // ST R2, #4
+        state.memory.write(0x3005, 7);
+
+        state.step();
+        assert_eq!(state.memory.peek(0x3005), 42);
+
+        state = step_back(state);
+        assert_eq!(state.pc, 0x3000);
+        assert_eq!(state.memory.peek(0x3005), 7);
+    }
+
+    #[test]
+    fn test_reverse_branch() {
+        let mut state = new_state();
+        state.condition = Condition::Z;
+        state.memory.write(0x3000, 0b0000_010_000010000); // BRz #16
+
+        state.step();
+        assert_eq!(state.pc, 0x3011);
+
+        state = step_back(state);
+        assert_eq!(state.pc, 0x3000);
+    }
+
+    #[test]
+    fn test_reverse_getc_is_irreversible() {
+        let mut state = new_state();
+        state.console.replace(Box::new(BufferConsole::new(b"A")));
+        state.registers.write(R0, 5);
+        state.memory.write(0x3000, 0xf020); // GETC
+
+        state.step();
+        assert_eq!(state.registers.read(R0), u16::from(b'A'));
+
+        let delta = state.undo.as_mut().unwrap().pop().unwrap();
+        delta.restore(&mut state);
+        assert!(delta.irreversible);
+        assert_eq!(state.registers.read(R0), 5);
+        assert_eq!(state.pc, 0x3000);
+    }
+
+    #[test]
+    fn test_depth_is_bounded() {
+        let mut state = new_state();
+        state.undo = Some(UndoLog::new(2));
+        for address in 0x3000..0x3003 {
+            state.memory.write(address, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        }
+
+        for _ in 0..3 {
+            state.step();
+        }
+
+        let mut undo = state.undo.take().unwrap();
+        assert!(undo.pop().is_some());
+        assert!(undo.pop().is_some());
+        assert!(undo.pop().is_none());
+    }
+}
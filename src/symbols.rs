@@ -0,0 +1,224 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+// Offsets from a symbol at or beyond this are shown as plain addresses, since attributing them
+// to a distant label is likely to be misleading.
+const MAX_SYMBOL_OFFSET: u16 = 16;
+
+/// Labels and their addresses, as produced by `lc3as` in a `.sym` file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable {
+    // Sorted by address.
+    symbols: Vec<(String, u16)>,
+    origin: Option<u16>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the `lc3as` symbol file format. Lines that don't look like a `NAME ADDRESS` pair
+    /// (headers, separators) are ignored, apart from an `Origin:` one.
+    ///
+    /// ```text
+    /// // Symbol table
+    /// // Origin: x3000
+    /// // Scope level 0:
+    /// //    Symbol Name       Page Address
+    /// //    ----------------  ------------
+    /// //    LOOP              3002
+    /// ```
+    pub fn parse(contents: &str) -> Self {
+        let mut origin = None;
+        let mut symbols: Vec<(String, u16)> = contents
+            .lines()
+            .filter_map(|line| {
+                let line = line.trim_start_matches("//");
+                match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                    [name, address] => {
+                        let address = address.trim_start_matches('x');
+                        let address = u16::from_str_radix(address, 16).ok()?;
+                        // Not a label, as a label can't have a colon in it.
+                        if *name == "Origin:" {
+                            origin = Some(address);
+                            return None;
+                        }
+                        Some((name.to_string(), address))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        symbols.sort_by_key(|(_, address)| *address);
+
+        Self { symbols, origin }
+    }
+
+    /// Adds a symbol, after any others at the same address.
+    pub(crate) fn insert(&mut self, name: &str, address: u16) {
+        let index = self
+            .symbols
+            .partition_point(|(_, symbol_address)| *symbol_address <= address);
+        self.symbols.insert(index, (name.to_string(), address));
+    }
+
+    /// The `.ORIG` address of the program the symbols came from, labelled or not.
+    pub fn origin(&self) -> Option<u16> {
+        self.origin
+    }
+
+    pub(crate) fn set_origin(&mut self, origin: u16) {
+        self.origin = Some(origin);
+    }
+
+    /// Looks up the address of a symbol, ignoring case.
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.symbols
+            .iter()
+            .find(|(symbol, _)| symbol.eq_ignore_ascii_case(name))
+            .map(|(_, address)| *address)
+    }
+
+    /// Names an address relative to the closest symbol at or below it, e.g. `LOOP+2`.
+    pub fn symbolize(&self, address: u16) -> Option<String> {
+        let (name, symbol_address) = self
+            .symbols
+            .iter()
+            .rev()
+            .find(|(_, symbol_address)| *symbol_address <= address)?;
+
+        match address - symbol_address {
+            0 => Some(name.clone()),
+            offset if offset < MAX_SYMBOL_OFFSET => Some(format!("{}+{}", name, offset)),
+            _ => None,
+        }
+    }
+
+    /// The symbol at exactly `address`, if there is one.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|(_, symbol_address)| *symbol_address == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Symbolizes the address, falling back to hex.
+    pub fn format_address(&self, address: u16) -> String {
+        self.symbolize(address)
+            .unwrap_or_else(|| format!("x{:04X}", address))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, u16)> {
+        self.symbols.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+// The `lc3as` format, so other tools can read what the assembler writes.
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "// Symbol table")?;
+        if let Some(origin) = self.origin {
+            writeln!(f, "// Origin: x{:04X}", origin)?;
+        }
+        writeln!(f, "// Scope level 0:")?;
+        writeln!(f, "//\tSymbol Name       Page Address")?;
+        writeln!(f, "//\t----------------  ------------")?;
+        for (name, address) in &self.symbols {
+            writeln!(f, "//\t{:<16}  {:04X}", name, address)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYMBOLS: &str = "// Symbol table
+// Scope level 0:
+//\tSymbol Name       Page Address
+//\t----------------  ------------
+//\tSTART             3000
+//\tDONE              3010
+//\tLOOP              3002
+";
+
+    #[test]
+    fn test_parse() {
+        let symbols = SymbolTable::parse(SYMBOLS);
+
+        assert_eq!(
+            symbols.iter().cloned().collect::<Vec<(String, u16)>>(),
+            vec![
+                ("START".to_string(), 0x3000),
+                ("LOOP".to_string(), 0x3002),
+                ("DONE".to_string(), 0x3010),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let symbols = SymbolTable::parse(SYMBOLS);
+
+        // In address order.
+        assert_eq!(
+            symbols.to_string(),
+            "// Symbol table
+// Scope level 0:
+//\tSymbol Name       Page Address
+//\t----------------  ------------
+//\tSTART             3000
+//\tLOOP              3002
+//\tDONE              3010
+"
+        );
+        assert_eq!(SymbolTable::parse(&symbols.to_string()), symbols);
+    }
+
+    #[test]
+    fn test_origin() {
+        assert_eq!(SymbolTable::parse(SYMBOLS).origin(), None);
+
+        let mut symbols = SymbolTable::parse(SYMBOLS);
+        symbols.set_origin(0x3000);
+        assert!(symbols
+            .to_string()
+            .starts_with("// Symbol table\n// Origin: x3000\n"));
+
+        let parsed = SymbolTable::parse(&symbols.to_string());
+        assert_eq!(parsed.origin(), Some(0x3000));
+        assert_eq!(parsed.address("ORIGIN:"), None);
+        assert_eq!(parsed, symbols);
+    }
+
+    #[test]
+    fn test_address() {
+        let symbols = SymbolTable::parse(SYMBOLS);
+
+        assert_eq!(symbols.address("LOOP"), Some(0x3002));
+        assert_eq!(symbols.address("loop"), Some(0x3002));
+        assert_eq!(symbols.address("MISSING"), None);
+    }
+
+    #[test]
+    fn test_symbolize() {
+        let symbols = SymbolTable::parse(SYMBOLS);
+
+        assert_eq!(symbols.symbolize(0x2fff), None);
+        assert_eq!(symbols.symbolize(0x3000), Some("START".to_string()));
+        assert_eq!(symbols.symbolize(0x3001), Some("START+1".to_string()));
+        assert_eq!(symbols.symbolize(0x3005), Some("LOOP+3".to_string()));
+        assert_eq!(symbols.symbolize(0x3011), Some("DONE+1".to_string()));
+        assert_eq!(symbols.symbolize(0x3020), None);
+        assert_eq!(symbols.format_address(0x3020), "x3020");
+    }
+}
@@ -0,0 +1,56 @@
+//! The two things the VM needs from the terminal it runs in: taking STDIN out of line buffering so
+//! keys arrive as they're pressed, and checking for a key without blocking. termios and
+//! `select(2)` on Unix, the console API on Windows.
+
+use std::io::{self, Read};
+use std::time::Duration;
+
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+pub(crate) type Native = self::unix::Unix;
+#[cfg(windows)]
+pub(crate) type Native = self::windows::Windows;
+
+pub(crate) trait Terminal {
+    /// The settings to put back afterwards, see [`Terminal::restore`].
+    type Mode;
+
+    /// Turns off line buffering and echo on STDIN, returning the previous settings, or `None`
+    /// when STDIN isn't a terminal.
+    fn enable_raw_mode() -> io::Result<Option<Self::Mode>>;
+
+    fn restore(mode: &Self::Mode) -> io::Result<()>;
+
+    /// Turns line buffering and echo on STDIN on or off, e.g. around the debugger's prompt. Does
+    /// nothing when STDIN isn't a terminal.
+    fn set_line_buffering(enabled: bool) -> io::Result<()>;
+
+    /// Whether a key can be read without blocking, waiting up to `timeout` for one.
+    fn key_ready(timeout: Duration) -> bool;
+
+    fn read_key() -> io::Result<u8> {
+        let mut buffer = [0; 1];
+        io::stdin().read_exact(&mut buffer)?;
+        Ok(buffer[0])
+    }
+}
+
+/// The terminal in raw mode, from [`RawMode::enable`] until [`RawMode::restore`].
+pub struct RawMode(<Native as Terminal>::Mode);
+
+impl RawMode {
+    /// Puts the terminal in raw mode, so keys reach the program as they're pressed without being
+    /// echoed. Returns `None`, changing nothing, when STDIN isn't a terminal, e.g. it's piped in.
+    pub fn enable() -> io::Result<Option<RawMode>> {
+        Ok(Native::enable_raw_mode()?.map(RawMode))
+    }
+
+    /// Puts back the settings from before [`RawMode::enable`].
+    pub fn restore(self) -> io::Result<()> {
+        Native::restore(&self.0)
+    }
+}
@@ -0,0 +1,64 @@
+use super::Terminal;
+use nix::sys::select::{select, FdSet};
+use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg, Termios};
+use nix::sys::time::{TimeVal, TimeValLike};
+use nix::unistd::isatty;
+use std::io;
+use std::time::Duration;
+
+const STDIN_FILENO: i32 = 0;
+
+pub(crate) struct Unix;
+
+impl Terminal for Unix {
+    type Mode = Termios;
+
+    fn enable_raw_mode() -> io::Result<Option<Termios>> {
+        // Input piped in from a file or another process, or none at all, has no terminal settings
+        // to change.
+        if !isatty(STDIN_FILENO).unwrap_or(false) {
+            return Ok(None);
+        }
+
+        let original = tcgetattr(STDIN_FILENO).map_err(to_io_error)?;
+        let mut termios = original.clone();
+        termios.local_flags &= !(LocalFlags::ICANON | LocalFlags::ECHO);
+        tcsetattr(STDIN_FILENO, SetArg::TCSANOW, &termios).map_err(to_io_error)?;
+
+        Ok(Some(original))
+    }
+
+    fn restore(termios: &Termios) -> io::Result<()> {
+        tcsetattr(STDIN_FILENO, SetArg::TCSANOW, termios).map_err(to_io_error)
+    }
+
+    fn set_line_buffering(enabled: bool) -> io::Result<()> {
+        if !isatty(STDIN_FILENO).map_err(to_io_error)? {
+            return Ok(());
+        }
+
+        let mut termios = tcgetattr(STDIN_FILENO).map_err(to_io_error)?;
+        if enabled {
+            termios.local_flags |= LocalFlags::ICANON | LocalFlags::ECHO;
+        } else {
+            termios.local_flags &= !(LocalFlags::ICANON | LocalFlags::ECHO);
+        }
+
+        tcsetattr(STDIN_FILENO, SetArg::TCSANOW, &termios).map_err(to_io_error)
+    }
+
+    fn key_ready(timeout: Duration) -> bool {
+        let mut readfds = FdSet::new();
+        readfds.insert(STDIN_FILENO);
+        let mut timeout = TimeVal::microseconds(timeout.as_micros() as i64);
+
+        match select(None, &mut readfds, None, None, &mut timeout) {
+            Ok(value) => value == 1,
+            Err(_) => false,
+        }
+    }
+}
+
+fn to_io_error(error: nix::Error) -> io::Error {
+    io::Error::other(error.to_string())
+}
@@ -0,0 +1,186 @@
+use super::Terminal;
+use std::io::{self, Read};
+use std::mem;
+use std::thread;
+use std::time::{Duration, Instant};
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::Storage::FileSystem::{GetFileType, FILE_TYPE_PIPE};
+use windows_sys::Win32::System::Console::{
+    GetConsoleMode, GetStdHandle, PeekConsoleInputW, ReadConsoleInputW, SetConsoleMode,
+    CONSOLE_MODE, ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT, INPUT_RECORD, KEY_EVENT, STD_INPUT_HANDLE,
+};
+use windows_sys::Win32::System::Pipes::PeekNamedPipe;
+
+// There's no select(2) for console or pipe handles, so waiting for a key checks this often.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// How many pending console events are looked through for a key at a time.
+const EVENTS: usize = 64;
+
+pub(crate) struct Windows;
+
+impl Terminal for Windows {
+    type Mode = CONSOLE_MODE;
+
+    fn enable_raw_mode() -> io::Result<Option<CONSOLE_MODE>> {
+        // Input piped in from a file or another process has no console mode to change.
+        let original = match console_mode(stdin()) {
+            Some(mode) => mode,
+            None => return Ok(None),
+        };
+
+        // Processed input stays on, so Ctrl-C still reaches the handler in `interrupt`.
+        set_console_mode(original & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT))?;
+
+        Ok(Some(original))
+    }
+
+    fn restore(mode: &CONSOLE_MODE) -> io::Result<()> {
+        set_console_mode(*mode)
+    }
+
+    fn set_line_buffering(enabled: bool) -> io::Result<()> {
+        let mode = match console_mode(stdin()) {
+            Some(mode) => mode,
+            None => return Ok(()),
+        };
+
+        if enabled {
+            set_console_mode(mode | ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT)
+        } else {
+            set_console_mode(mode & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT))
+        }
+    }
+
+    fn key_ready(timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if input_waiting() {
+                return true;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    // The console gives a carriage return for Enter, where a Unix terminal gives a line feed.
+    fn read_key() -> io::Result<u8> {
+        let mut buffer = [0; 1];
+        io::stdin().read_exact(&mut buffer)?;
+
+        match buffer[0] {
+            b'\r' if console_mode(stdin()).is_some() => Ok(b'\n'),
+            byte => Ok(byte),
+        }
+    }
+}
+
+fn stdin() -> HANDLE {
+    unsafe { GetStdHandle(STD_INPUT_HANDLE) }
+}
+
+// `None` when the handle isn't a console, e.g. STDIN is redirected.
+fn console_mode(handle: HANDLE) -> Option<CONSOLE_MODE> {
+    let mut mode = 0;
+    if unsafe { GetConsoleMode(handle, &mut mode) } == 0 {
+        None
+    } else {
+        Some(mode)
+    }
+}
+
+fn set_console_mode(mode: CONSOLE_MODE) -> io::Result<()> {
+    if unsafe { SetConsoleMode(stdin(), mode) } == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn input_waiting() -> bool {
+    let input = stdin();
+    if let Some(mode) = console_mode(input) {
+        return console_key_waiting(input, mode);
+    }
+
+    if unsafe { GetFileType(input) } == FILE_TYPE_PIPE {
+        let mut available = 0;
+        let peeked = unsafe {
+            PeekNamedPipe(
+                input,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null_mut(),
+                &mut available,
+                std::ptr::null_mut(),
+            )
+        };
+        // A closed pipe counts as ready too, so the read reports the end of input.
+        return peeked == 0 || available > 0;
+    }
+
+    // A file can always be read, if only to find its end.
+    true
+}
+
+// With line input on, a read waits for Enter, so only a finished line counts. Otherwise any key
+// pressed will do, and the mouse, focus and key release events in front of it are dropped so
+// they don't pile up.
+fn console_key_waiting(input: HANDLE, mode: CONSOLE_MODE) -> bool {
+    let mut records: [INPUT_RECORD; EVENTS] = unsafe { mem::zeroed() };
+    let mut count = 0;
+    if unsafe { PeekConsoleInputW(input, records.as_mut_ptr(), EVENTS as u32, &mut count) } == 0 {
+        return false;
+    }
+
+    let records = &records[..count as usize];
+    if mode & ENABLE_LINE_INPUT != 0 {
+        return records
+            .iter()
+            .filter_map(character)
+            .any(|c| c == u16::from(b'\r'));
+    }
+
+    match records
+        .iter()
+        .position(|record| character(record).is_some())
+    {
+        Some(0) => true,
+        Some(skipped) => {
+            discard(input, skipped);
+            true
+        }
+        None => {
+            discard(input, records.len());
+            false
+        }
+    }
+}
+
+fn character(record: &INPUT_RECORD) -> Option<u16> {
+    if u32::from(record.EventType) != KEY_EVENT {
+        return None;
+    }
+
+    let key = unsafe { record.Event.KeyEvent };
+    let c = unsafe { key.uChar.UnicodeChar };
+    if key.bKeyDown != 0 && c != 0 {
+        Some(c)
+    } else {
+        None
+    }
+}
+
+fn discard(input: HANDLE, count: usize) {
+    if count == 0 {
+        return;
+    }
+
+    let mut records: [INPUT_RECORD; EVENTS] = unsafe { mem::zeroed() };
+    let mut read = 0;
+    unsafe { ReadConsoleInputW(input, records.as_mut_ptr(), count as u32, &mut read) };
+}
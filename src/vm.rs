@@ -0,0 +1,1251 @@
+#[cfg(feature = "tokio")]
+mod asynchronous;
+mod builder;
+mod handle;
+pub(crate) mod throttle;
+mod trace;
+
+#[cfg(feature = "tokio")]
+pub use asynchronous::AsyncConsole;
+pub use builder::VmBuilder;
+pub use handle::VmHandle;
+pub use trace::{Trace, TraceEntry};
+
+use crate::asm::{self, AsmError};
+#[cfg(feature = "native-term")]
+use crate::console::CaptureConsole;
+use crate::console::{BufferConsole, Console, Echo};
+use crate::error::{FileError, VmError};
+use crate::file;
+use crate::instruction::{Instruction, Register};
+use crate::interrupt;
+use crate::state::memory::device::Device;
+use crate::state::memory::MCR;
+pub use crate::state::{HaltReason, StepResult};
+
+#[cfg(feature = "stats")]
+use crate::state::stats::ExecStats;
+use crate::state::{Condition, HookAction, State, TrapMode};
+use std::io::{self, ErrorKind};
+use std::iter;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use throttle::Throttle;
+
+/// The machine at the end of a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub registers: [u16; 8],
+    pub pc: u16,
+    pub condition: Condition,
+    /// How many instructions were executed.
+    pub instructions: u64,
+    /// False when the run ended with the program still going, e.g. a debugger script finished
+    /// without continuing to the end.
+    pub halted: bool,
+    /// Whether TRAPs went to an OS in memory or the built-in routines.
+    pub trap_mode: TrapMode,
+    /// How long the instructions took to run, not counting time stopped in the debugger. Only
+    /// the run methods are timed, not single steps.
+    pub duration: Duration,
+}
+
+impl RunSummary {
+    pub(crate) fn of(state: &State, duration: Duration) -> Self {
+        RunSummary {
+            registers: state.registers(),
+            pc: state.pc,
+            condition: state.condition.clone(),
+            instructions: state.instructions,
+            halted: !state.running,
+            trap_mode: state.trap_mode,
+            duration,
+        }
+    }
+
+    /// Millions of instructions a second over [`RunSummary::duration`], or zero when nothing was
+    /// timed.
+    pub fn mips(&self) -> f64 {
+        mips(self.instructions, self.duration)
+    }
+}
+
+pub(crate) fn mips(instructions: u64, duration: Duration) -> f64 {
+    let seconds = duration.as_secs_f64();
+    if seconds > 0.0 {
+        instructions as f64 / seconds / 1_000_000.0
+    } else {
+        0.0
+    }
+}
+
+/// An LC-3 machine for applications that want to own the execution loop.
+///
+/// ```
+/// use lc3::{StepResult, Vm};
+///
+/// // .ORIG x3000, ADD R0, R0, #7, HALT
+/// let program = [0x30, 0x00, 0x10, 0x27, 0xf0, 0x25];
+///
+/// let mut vm = Vm::new();
+/// vm.capture_output();
+/// vm.load_program(&program).unwrap();
+///
+/// assert_eq!(vm.step(), StepResult::Continue);
+/// assert_eq!(vm.registers()[0], 7);
+/// assert!(matches!(vm.step(), StepResult::Halted { .. }));
+/// ```
+pub struct Vm {
+    state: State,
+    // Loaded program images, reapplied by `reset`.
+    programs: Vec<Vec<u16>>,
+    // Which of `programs` is the OS, left out of `protect_programs`.
+    os: Option<usize>,
+    // Checked by `run_with_limit`, raised by Ctrl-C once the handler is installed.
+    interrupt: &'static AtomicBool,
+    // Where console output goes when capturing.
+    output: Option<BufferConsole>,
+    // Where execution starts instead of the last program's origin, reapplied by `reset`.
+    entry: Option<u16>,
+    // Limits for `run`.
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+    // Instructions per second for `run` and friends, unthrottled when `None`.
+    hz: Option<u64>,
+    // Whether images are decoded as they're loaded, see `set_predecode`.
+    predecode: bool,
+    // Time spent in the run methods since the last reset.
+    run_time: Duration,
+    // Instructions `run` and friends execute between checks for Ctrl-C and the timeout.
+    batch_size: u32,
+}
+
+/// How many instructions run between checks for Ctrl-C, the timeout and anything else that
+/// stops a run from outside, unless changed with [`Vm::set_batch_size`].
+pub const DEFAULT_BATCH_SIZE: u32 = 4096;
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm {
+            state: State::new(),
+            programs: Vec::new(),
+            os: None,
+            interrupt: &interrupt::REQUESTED,
+            output: None,
+            entry: None,
+            max_steps: None,
+            timeout: None,
+            hz: None,
+            predecode: false,
+            run_time: Duration::ZERO,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Configures a machine in one go, including its program and limits.
+    pub fn builder() -> VmBuilder {
+        VmBuilder::default()
+    }
+
+    /// Loads an object file image: a big-endian origin word followed by the words to place
+    /// there. The PC is set to the origin.
+    pub fn load_program(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let rom = file::from_bytes(bytes)?;
+        self.load(rom)
+    }
+
+    /// Loads an OS image, like [`Vm::load_program`], and sends TRAPs through its trap vector
+    /// table from now on instead of the built-in routines. Load it before the program so the PC
+    /// is left at the program's origin.
+    ///
+    /// [`crate::BUNDLED_OS`] is one that behaves like the built-in routines. Its HALT stops the
+    /// machine by clearing the clock enable bit of the MCR, as any OS's can.
+    pub fn load_os(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.load_program(bytes)?;
+        self.os = Some(self.programs.len() - 1);
+        self.state.trap_mode = TrapMode::Os;
+
+        Ok(())
+    }
+
+    /// Whether TRAPs go to an OS in memory, see [`Vm::load_os`].
+    pub fn trap_mode(&self) -> TrapMode {
+        self.state.trap_mode
+    }
+
+    /// Like [`Vm::load_program`], reading the image from a file.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<(), FileError> {
+        let path = path.as_ref();
+        let rom = file::read_rom(path)?;
+        self.load(rom).map_err(|e| FileError::new(path, e))
+    }
+
+    /// Assembles `source` and loads it like [`Vm::load_program`], so a program can be written
+    /// inline, e.g. in a test. Nothing touches the filesystem, and each error gives its line and
+    /// column.
+    ///
+    /// ```
+    /// let mut vm = lc3::Vm::new();
+    /// vm.load_assembly(".ORIG x3000\nADD R1, R1, #1\nHALT\n.END").unwrap();
+    /// ```
+    pub fn load_assembly(&mut self, source: &str) -> Result<(), Vec<AsmError>> {
+        let program = asm::assemble(source)?;
+        let rom = iter::once(program.origin).chain(program.words).collect();
+        // The assembler already checked the program fits in memory.
+        self.load(rom).expect("assembled program loads");
+
+        Ok(())
+    }
+
+    fn load(&mut self, mut rom: Vec<u16>) -> io::Result<()> {
+        self.state
+            .load_rom(&mut rom)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        self.programs.push(rom);
+        if self.predecode {
+            self.predecode_programs();
+        }
+
+        Ok(())
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> StepResult {
+        self.state.step()
+    }
+
+    /// Runs the machine on a new thread, controlled through the returned handle. Its console is
+    /// replaced so keys come from [`VmHandle::send_key`] and output goes to
+    /// [`VmHandle::output`].
+    pub fn spawn(self) -> VmHandle {
+        VmHandle::spawn(self)
+    }
+
+    /// Executes instructions as the returned iterator is advanced, yielding each one with the
+    /// registers it left behind.
+    ///
+    /// The iterator ends once the machine halts, after yielding the HALT, or on Ctrl-C or a pause
+    /// from the pre-step hook, or before an instruction that can't be executed, which
+    /// [`Vm::step`] then reports. It has no step limit of its own, use `take` for that, and
+    /// [`Vm::is_halted`] tells whether the program finished.
+    ///
+    /// ```
+    /// use lc3::{Instruction, Vm};
+    ///
+    /// // .ORIG x3000, ADD R0, R0, #7, HALT
+    /// let program = [0x30, 0x00, 0x10, 0x27, 0xf0, 0x25];
+    ///
+    /// let mut vm = Vm::new();
+    /// vm.load_program(&program).unwrap();
+    ///
+    /// let traps = vm
+    ///     .trace_iter()
+    ///     .take(10_000)
+    ///     .filter(|entry| matches!(entry.instruction, Instruction::TRAP(_)))
+    ///     .count();
+    /// assert_eq!(traps, 1);
+    /// assert!(vm.is_halted());
+    /// ```
+    pub fn trace_iter(&mut self) -> Trace<'_> {
+        Trace::new(self)
+    }
+
+    /// Steps until the machine halts, the pre-step hook pauses or an instruction fails. Returns
+    /// straight away if it has already halted.
+    pub fn run_until_halt(&mut self) {
+        let started = Instant::now();
+        while self.state.running
+            && matches!(
+                self.step(),
+                StepResult::Continue | StepResult::Trapped { .. }
+            )
+        {}
+        self.run_time += started.elapsed();
+    }
+
+    /// Steps until the machine halts, within the limits given to the [`VmBuilder`] if any.
+    pub fn run(&mut self) -> Result<(), VmError> {
+        self.run_with_limits(self.max_steps, self.timeout)
+    }
+
+    /// Steps until the machine halts or `max_steps` instructions have been executed, whichever
+    /// comes first. Every instruction counts, including TRAPs. Also stops on Ctrl-C when the
+    /// binary's handler is installed, and returns `Ok` early if the pre-step hook pauses.
+    pub fn run_with_limit(&mut self, max_steps: u64) -> Result<(), VmError> {
+        self.run_with_limits(Some(max_steps), None)
+    }
+
+    /// Steps until the machine halts or `timeout` has passed, including time spent blocked
+    /// waiting for keyboard input.
+    pub fn run_with_timeout(&mut self, timeout: Duration) -> Result<(), VmError> {
+        self.run_with_limits(None, Some(timeout))
+    }
+
+    /// Combines [`Vm::run_with_limit`] and [`Vm::run_with_timeout`], stopping at whichever limit
+    /// is reached first.
+    pub fn run_with_limits(
+        &mut self,
+        max_steps: Option<u64>,
+        timeout: Option<Duration>,
+    ) -> Result<(), VmError> {
+        let started = Instant::now();
+        let deadline = timeout.map(|timeout| started + timeout);
+        self.state.deadline = deadline;
+        let result = self.run_until(max_steps, deadline);
+        self.state.deadline = None;
+        self.run_time += started.elapsed();
+        // Whatever stopped it, e.g. a limit, in the middle of output.
+        self.state.console.lock().flush();
+
+        result
+    }
+
+    fn run_until(
+        &mut self,
+        max_steps: Option<u64>,
+        deadline: Option<Instant>,
+    ) -> Result<(), VmError> {
+        let mut steps = 0;
+        let mut throttle = self.hz.map(Throttle::new);
+
+        while self.state.running {
+            if Some(steps) == max_steps {
+                let pc = self.pc();
+                return Err(VmError::StepLimitExceeded {
+                    limit: steps,
+                    pc,
+                    word: self.read_memory(pc),
+                });
+            }
+            if self.interrupt.load(Ordering::Relaxed) {
+                return Err(VmError::Interrupted {
+                    pc: self.pc(),
+                    instructions: self.state.instructions,
+                });
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(VmError::Timeout { pc: self.pc() });
+            }
+
+            // The step limit is exact, so a batch never runs past it.
+            let batch = match max_steps {
+                Some(max_steps) => u64::from(self.batch_size).min(max_steps - steps),
+                None => u64::from(self.batch_size),
+            };
+            for _ in 0..batch {
+                if let Some(throttle) = &mut throttle {
+                    throttle.take();
+                }
+
+                match self.step() {
+                    StepResult::Breakpoint => return Ok(()),
+                    StepResult::Error(e) => return Err(e),
+                    _ => {}
+                }
+                steps += 1;
+                if !self.state.running {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts over with a fresh machine and the same programs loaded, in the same order. The
+    /// console, registered traps and hooks are kept. Reloading the programs doesn't call the
+    /// memory write hook.
+    pub fn reset(&mut self) {
+        let mut state = State::new();
+        for rom in &mut self.programs {
+            // Already loaded successfully once.
+            state.load_rom(rom).expect("program loads");
+        }
+        state.trap_mode = self.state.trap_mode;
+        // The keyboard and display hold the console, so they move across together.
+        std::mem::swap(&mut state.console, &mut self.state.console);
+        state.memory.swap_devices(&mut self.state.memory);
+        // An OS's HALT stopped the clock, which starts again.
+        state.memory.write(MCR, 1 << 15);
+        if let Some(entry) = self.entry {
+            state.pc = entry;
+        }
+        std::mem::swap(&mut state.traps, &mut self.state.traps);
+        std::mem::swap(&mut state.pre_step, &mut self.state.pre_step);
+        std::mem::swap(&mut state.post_step, &mut self.state.post_step);
+        let memory = &mut self.state.memory;
+        state.memory.write_hook = memory.write_hook.take();
+        state.memory.hook_host_writes = memory.hook_host_writes;
+        state.memory.protected = std::mem::take(&mut memory.protected);
+        state.memory.set_decode_cache(memory.has_decode_cache());
+
+        self.state = state;
+        self.run_time = Duration::ZERO;
+        if self.predecode {
+            self.predecode_programs();
+        }
+    }
+
+    pub fn registers(&self) -> [u16; 8] {
+        self.state.registers()
+    }
+
+    pub fn register(&self, register: Register) -> u16 {
+        self.state.registers.read(register)
+    }
+
+    pub fn set_register(&mut self, register: Register, value: u16) {
+        self.state.registers.write(register, value);
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.state.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.state.pc = pc;
+    }
+
+    pub fn condition(&self) -> Condition {
+        self.state.condition.clone()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        !self.state.running
+    }
+
+    /// Memory, register and opcode counts since the machine was created or last reset.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> &ExecStats {
+        &self.state.stats
+    }
+
+    /// Registers, PC, flags, instruction count and run time, e.g. to check a program's results.
+    pub fn summary(&self) -> RunSummary {
+        RunSummary::of(&self.state, self.run_time)
+    }
+
+    /// Reads a word without any device side effects.
+    pub fn read_memory(&self, address: u16) -> u16 {
+        self.state.memory.peek(address)
+    }
+
+    pub fn write_memory(&mut self, address: u16, value: u16) {
+        self.state.memory.write(address, value);
+    }
+
+    /// Makes the program storing into `range` an error, [`VmError::WriteToProtected`], rather
+    /// than changing it. Loading programs and [`Vm::write_memory`] aren't affected.
+    pub fn protect(&mut self, range: RangeInclusive<u16>) {
+        self.state.memory.protect(range);
+    }
+
+    /// Lets the program store into `range` again.
+    pub fn unprotect(&mut self, range: RangeInclusive<u16>) {
+        self.state.memory.unprotect(range);
+    }
+
+    /// Protects each of the images loaded so far, e.g. so a stray store into the program's own
+    /// instructions stops it there rather than running whatever it wrote. An OS is left out, as
+    /// it may keep its data in its own image.
+    pub fn protect_programs(&mut self) {
+        let ranges: Vec<_> = self
+            .images()
+            .filter(|(i, _)| Some(*i) != self.os)
+            .map(|(_, range)| range)
+            .collect();
+        for range in ranges {
+            self.protect(range);
+        }
+    }
+
+    // Where each non-empty image in `programs` was loaded, with its index.
+    fn images(&self) -> impl Iterator<Item = (usize, RangeInclusive<u16>)> + '_ {
+        self.programs
+            .iter()
+            .enumerate()
+            .filter(|(_, rom)| rom.len() > 1)
+            .map(|(i, rom)| (i, rom[0]..=rom[0] + (rom.len() - 2) as u16))
+    }
+
+    /// Runs `handler` whenever the program executes `TRAP vector`, instead of the built-in
+    /// routine if there is one. Vectors without a handler or a built-in routine are an error as
+    /// before. R7 is set to the return address and execution carries on from there afterwards,
+    /// unless the handler changes the PC.
+    pub fn register_trap(&mut self, vector: u8, handler: impl FnMut(&mut State) + Send + 'static) {
+        self.state.traps.insert(vector, Box::new(handler));
+    }
+
+    /// Calls `hook` before each instruction executes, with the machine, the word at the PC and
+    /// its decoding. Returning [`HookAction::Pause`] stops before the instruction, e.g. for a
+    /// breakpoint; the next step executes it without asking again. TRAPs with a registered
+    /// handler aren't shown to the hook.
+    pub fn set_pre_step_hook(
+        &mut self,
+        hook: impl FnMut(&State, u16, &Instruction) -> HookAction + Send + 'static,
+    ) {
+        let state = &mut self.state;
+        state.pre_step = Some(Box::new(hook));
+        state.paused = false;
+    }
+
+    pub fn remove_pre_step_hook(&mut self) {
+        let state = &mut self.state;
+        state.pre_step = None;
+        state.paused = false;
+    }
+
+    /// Calls `hook` after each instruction executes, with the machine, the address and word of
+    /// the instruction and its decoding, e.g. to trace the registers it changed. Like the pre-step
+    /// hook, TRAPs with a registered handler aren't shown to it.
+    pub fn set_post_step_hook(
+        &mut self,
+        hook: impl FnMut(&State, u16, u16, &Instruction) + Send + 'static,
+    ) {
+        self.state.post_step = Some(Box::new(hook));
+    }
+
+    pub fn remove_post_step_hook(&mut self) {
+        self.state.post_step = None;
+    }
+
+    /// Calls `hook` with the address, old value and new value of every store the program makes,
+    /// including to device registers. The address is the resolved one, e.g. for STI the address
+    /// read from memory.
+    pub fn set_memory_write_hook(&mut self, hook: impl FnMut(u16, u16, u16) + Send + 'static) {
+        self.state.memory.write_hook = Some(Box::new(hook));
+    }
+
+    pub fn remove_memory_write_hook(&mut self) {
+        self.state.memory.write_hook = None;
+    }
+
+    /// Also calls the memory write hook for writes from outside the program, such as
+    /// [`Vm::write_memory`] and loading programs. Off by default.
+    pub fn hook_host_writes(&mut self, enabled: bool) {
+        self.state.memory.hook_host_writes = enabled;
+    }
+
+    /// Maps `device` at `range`, so the program's reads and writes there go to it instead of
+    /// memory. Fails if the range overlaps another device, including the keyboard (xFE00-xFE02),
+    /// display (xFE04-xFE06) and MCR (xFFFE). Devices are kept on reset.
+    pub fn map_device(
+        &mut self,
+        range: RangeInclusive<u16>,
+        device: impl Device + 'static,
+    ) -> Result<(), String> {
+        self.state.memory.map_device(range, Box::new(device))
+    }
+
+    /// Replaces the terminal as the program's keyboard and display.
+    pub fn set_console(&mut self, console: impl Console + 'static) {
+        self.state.console.replace(Box::new(console));
+        self.output = None;
+    }
+
+    /// Which keys the program reads are written back to the console. By default only IN echoes,
+    /// as the trap is specified, so a program reading with GETC or KBDR doesn't show what's typed.
+    /// The setting stays with the machine when its console is replaced.
+    pub fn set_echo(&mut self, echo: Echo) {
+        self.state.console.set_echo(echo);
+    }
+
+    /// Keeps instructions decoded between steps, which speeds up loops for the price of a table
+    /// the size of memory, see [`crate::Memory::set_decode_cache`]. Stores over code are picked up, so
+    /// self-modifying programs still work. Off by default.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.state.memory.set_decode_cache(enabled);
+    }
+
+    /// How many instructions [`Vm::run`] and friends execute between checks for Ctrl-C and the
+    /// timeout, [`DEFAULT_BATCH_SIZE`] to begin with. Breakpoints and the step limit still stop
+    /// the run on the exact instruction, and the debugger uses the same size when nothing needs
+    /// checking after each instruction. Zero is taken as one.
+    pub fn set_batch_size(&mut self, batch_size: u32) {
+        self.batch_size = batch_size.max(1);
+    }
+
+    pub fn batch_size(&self) -> u32 {
+        self.batch_size
+    }
+
+    /// Decodes the images loaded so far, the OS included, and each one loaded or reloaded by
+    /// [`Vm::reset`] from now on, so not even the first pass through a program decodes. This
+    /// turns the decode cache on, which is what picks up stores over code, see
+    /// [`crate::Memory::predecode`]. Turning it off again leaves the cache on. Off by default.
+    pub fn set_predecode(&mut self, enabled: bool) {
+        self.predecode = enabled;
+        if enabled {
+            self.predecode_programs();
+        }
+    }
+
+    fn predecode_programs(&mut self) {
+        let ranges: Vec<_> = self.images().map(|(_, range)| range).collect();
+        for range in ranges {
+            self.state.memory.predecode(range);
+        }
+    }
+
+    /// Collects console output instead of printing it to STDOUT. Keys are still read from STDIN,
+    /// or never come without the `native-term` feature.
+    pub fn capture_output(&mut self) {
+        if self.output.is_none() {
+            let output = BufferConsole::default();
+            #[cfg(feature = "native-term")]
+            let console = CaptureConsole::new(output.clone());
+            #[cfg(not(feature = "native-term"))]
+            let console = output.clone();
+            self.state.console.replace(Box::new(console));
+            self.output = Some(output);
+        }
+    }
+
+    /// Output collected since the last call, when capturing.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output
+            .as_ref()
+            .map(BufferConsole::take_output)
+            .unwrap_or_default()
+    }
+
+    /// Whether `address` is in one of the loaded images.
+    pub fn is_loaded(&self, address: u16) -> bool {
+        self.programs.iter().any(|rom| {
+            let origin = u32::from(rom[0]);
+            let end = origin + rom.len() as u32 - 1;
+            (origin..end).contains(&u32::from(address))
+        })
+    }
+
+    // The images loaded, in order, each starting with its origin.
+    pub(crate) fn programs(&self) -> &[Vec<u16>] {
+        &self.programs
+    }
+
+    pub(crate) fn into_state(self) -> State {
+        self.state
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // .ORIG x3000
+    // LEA R0, #3
+    // PUTS
+    // ADD R1, R1, #2
+    // HALT
+    // .STRINGZ "Hi"
+    const PROGRAM: [u8; 16] = [
+        0x30, 0x00, 0xe0, 0x03, 0xf0, 0x22, 0x12, 0x62, 0xf0, 0x25, 0x00, 0x48, 0x00, 0x69, 0x00,
+        0x00,
+    ];
+
+    fn vm() -> Vm {
+        let mut vm = Vm::new();
+        vm.capture_output();
+        vm.load_program(&PROGRAM).unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_step() {
+        let mut vm = vm();
+        assert_eq!(vm.pc(), 0x3000);
+
+        assert_eq!(vm.step(), StepResult::Continue);
+        assert_eq!(vm.register(Register::R0), 0x3004);
+        assert_eq!(vm.condition(), Condition::P);
+
+        assert_eq!(vm.step(), StepResult::Trapped { vector: 0x22 });
+        assert_eq!(vm.take_output(), b"Hi");
+        assert_eq!(vm.step(), StepResult::Continue);
+        assert_eq!(vm.register(Register::R1), 2);
+
+        assert_eq!(
+            vm.step(),
+            StepResult::Halted {
+                reason: HaltReason::Halt
+            }
+        );
+        assert!(vm.is_halted());
+    }
+
+    #[test]
+    fn test_run_until_halt_and_reset() {
+        let mut vm = vm();
+        vm.run_until_halt();
+
+        assert!(vm.is_halted());
+        let summary = vm.summary();
+        assert!(summary.duration > Duration::ZERO);
+        assert_eq!(
+            summary,
+            RunSummary {
+                registers: [0x3004, 2, 0, 0, 0, 0, 0, 0],
+                pc: 0x3004,
+                condition: Condition::P,
+                instructions: 4,
+                halted: true,
+                trap_mode: TrapMode::Builtin,
+                duration: summary.duration,
+            }
+        );
+        assert_eq!(vm.registers(), [0x3004, 2, 0, 0, 0, 0, 0, 0]);
+        assert!(vm.take_output().starts_with(b"Hi"));
+
+        vm.write_memory(0x3002, 0x1263); // ADD R1, R1, #3
+        vm.reset();
+        assert_eq!(vm.pc(), 0x3000);
+        assert_eq!(vm.read_memory(0x3002), 0x1262);
+        assert!(!vm.is_halted());
+        assert_eq!(vm.summary().duration, Duration::ZERO);
+
+        vm.set_register(Register::R1, 40);
+        vm.run_until_halt();
+        assert_eq!(vm.register(Register::R1), 42);
+    }
+
+    #[test]
+    fn test_run_with_limit() {
+        let mut vm = Vm::new();
+        vm.load_program(&[0x30, 0x00, 0x0f, 0xff]).unwrap(); // BRnzp #-1
+
+        assert_eq!(
+            vm.run_with_limit(1000),
+            Err(VmError::StepLimitExceeded {
+                limit: 1000,
+                pc: 0x3000,
+                word: 0x0fff
+            })
+        );
+        assert_eq!(vm.summary().instructions, 1000);
+        assert_eq!(
+            vm.run_with_limit(5).unwrap_err().to_string(),
+            "Step limit of 5 reached at PC 0x3000: BRnzp x3000"
+        );
+
+        // Halting on the last instruction of the budget is fine.
+        let mut vm = self::vm();
+        assert_eq!(vm.run_with_limit(4), Ok(()));
+        assert_eq!(vm.summary().instructions, 4);
+        assert!(vm.is_halted());
+    }
+
+    #[test]
+    fn test_run_stops_on_an_illegal_opcode() {
+        let mut vm = Vm::new();
+        vm.load_program(&[0x30, 0x00, 0x10, 0x27, 0xd0, 0x00])
+            .unwrap(); // ADD, RESERVED
+
+        assert_eq!(
+            vm.run(),
+            Err(VmError::IllegalOpcode {
+                pc: 0x3001,
+                word: 0xd000
+            })
+        );
+        assert_eq!(
+            vm.run().unwrap_err().to_string(),
+            "Illegal opcode 0xd000 at PC 0x3001"
+        );
+        assert_eq!(vm.summary().instructions, 1);
+    }
+
+    #[test]
+    fn test_interrupt_stops_the_run() {
+        static INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+        let mut vm = Vm::new();
+        vm.interrupt = &INTERRUPT;
+        vm.load_program(&[0x30, 0x00, 0x0f, 0xff]).unwrap(); // BRnzp #-1
+        assert_eq!(
+            vm.run_with_limit(3),
+            Err(VmError::StepLimitExceeded {
+                limit: 3,
+                pc: 0x3000,
+                word: 0x0fff
+            })
+        );
+
+        INTERRUPT.store(true, Ordering::SeqCst);
+        let e = vm.run().unwrap_err();
+        assert_eq!(
+            e,
+            VmError::Interrupted {
+                pc: 0x3000,
+                instructions: 3
+            }
+        );
+        assert_eq!(
+            e.to_string(),
+            "Interrupted at PC 0x3000 after 3 instructions"
+        );
+        assert_eq!(vm.summary().instructions, 3);
+    }
+
+    #[test]
+    fn test_batches_stop_exactly() {
+        let mut vm = Vm::new();
+        vm.set_batch_size(4);
+        vm.load_assembly(
+            ".ORIG x3000
+    LOOP    ADD R0, R0, #1
+            BRnzp LOOP
+            .END",
+        )
+        .unwrap();
+
+        // Part way through the third batch.
+        assert!(matches!(
+            vm.run_with_limit(10),
+            Err(VmError::StepLimitExceeded { limit: 10, .. })
+        ));
+        assert_eq!(vm.summary().instructions, 10);
+
+        vm.set_pre_step_hook(|state, _, _| {
+            if state.registers.read(Register::R0) == 7 {
+                HookAction::Pause
+            } else {
+                HookAction::Continue
+            }
+        });
+        assert_eq!(vm.run_with_limit(100), Ok(()));
+        assert_eq!(vm.summary().instructions, 13);
+
+        vm.set_batch_size(0);
+        assert_eq!(vm.batch_size(), 1);
+    }
+
+    #[test]
+    fn test_run_with_timeout() {
+        let mut vm = Vm::new();
+        vm.load_program(&[0x30, 0x00, 0x0f, 0xff]).unwrap(); // BRnzp #-1
+
+        assert_eq!(
+            vm.run_with_timeout(Duration::from_millis(20)),
+            Err(VmError::Timeout { pc: 0x3000 })
+        );
+        assert_eq!(
+            vm.run_with_limits(Some(5), Some(Duration::from_secs(60))),
+            Err(VmError::StepLimitExceeded {
+                limit: 5,
+                pc: 0x3000,
+                word: 0x0fff
+            })
+        );
+
+        let mut vm = self::vm();
+        assert_eq!(vm.run_with_timeout(Duration::from_secs(60)), Ok(()));
+        assert!(vm.is_halted());
+    }
+
+    #[test]
+    fn test_set_console() {
+        // .ORIG x3000
+        // GETC
+        // OUT
+        // HALT
+        let mut vm = Vm::new();
+        let console = BufferConsole::new(b"x");
+        vm.set_console(console.clone());
+        vm.load_program(&[0x30, 0x00, 0xf0, 0x20, 0xf0, 0x21, 0xf0, 0x25])
+            .unwrap();
+
+        vm.run_until_halt();
+        assert_eq!(console.output(), b"x");
+
+        vm.reset();
+        console.push_input(b"y");
+        vm.run_until_halt();
+        assert_eq!(console.output(), b"xy");
+    }
+
+    #[test]
+    fn test_register_trap() {
+        // .ORIG x3000
+        //       ADD R0, R0, #3
+        // LOOP  TRAP x30
+        //       ADD R0, R0, #-1
+        //       BRp LOOP
+        //       HALT
+        let program = [
+            0x30, 0x00, 0x10, 0x23, 0xf0, 0x30, 0x10, 0x3f, 0x03, 0xfd, 0xf0, 0x25,
+        ];
+        let mut vm = Vm::new();
+        vm.load_program(&program).unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&calls);
+        vm.register_trap(0x30, move |state| {
+            let r0 = state.registers.read(Register::R0);
+            let r1 = state.registers.read(Register::R1);
+            state.registers.write(Register::R1, r1 + r0);
+            seen.lock().unwrap().push(r0);
+        });
+
+        vm.run_until_halt();
+        assert_eq!(*calls.lock().unwrap(), [3, 2, 1]);
+        assert_eq!(vm.register(Register::R1), 6);
+        assert_eq!(vm.register(Register::R7), 0x3002);
+        assert_eq!(vm.summary().instructions, 11);
+    }
+
+    #[test]
+    fn test_register_trap_replaces_built_in() {
+        // .ORIG x3000
+        // OUT
+        // HALT
+        let mut vm = Vm::new();
+        vm.capture_output();
+        vm.load_program(&[0x30, 0x00, 0xf0, 0x21, 0xf0, 0x25])
+            .unwrap();
+        vm.register_trap(0x21, |state| state.registers.write(Register::R2, 1));
+
+        vm.run_until_halt();
+        assert_eq!(vm.register(Register::R2), 1);
+        assert!(vm.take_output().is_empty());
+    }
+
+    #[test]
+    fn test_assembled_unknown_trap() {
+        let source = ".ORIG x3000\nTRAP x80\nHALT\n.END";
+        for predecode in [false, true] {
+            let mut vm = Vm::new();
+            vm.load_assembly(source).unwrap();
+            vm.set_predecode(predecode);
+
+            assert_eq!(
+                vm.step(),
+                StepResult::Error(VmError::BadTrapVector {
+                    pc: 0x3000,
+                    vector: 0x80
+                })
+            );
+            assert_eq!(vm.pc(), 0x3000);
+
+            vm.register_trap(0x80, |state| state.registers.write(Register::R2, 1));
+            vm.run_until_halt();
+            assert!(vm.is_halted());
+            assert_eq!(vm.register(Register::R2), 1);
+        }
+    }
+
+    #[test]
+    fn test_pre_step_hook_traces_execution() {
+        let mut vm = vm();
+        let trace = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&trace);
+        vm.set_pre_step_hook(move |state, word, instruction| {
+            recorded
+                .lock()
+                .unwrap()
+                .push((state.pc, word, instruction.to_string()));
+            HookAction::Continue
+        });
+
+        vm.run_until_halt();
+        assert_eq!(
+            *trace.lock().unwrap(),
+            [
+                (0x3000, 0xe003, "LEA R0, #3".to_string()),
+                (0x3001, 0xf022, "PUTS".to_string()),
+                (0x3002, 0x1262, "ADD R1, R1, #2".to_string()),
+                (0x3003, 0xf025, "HALT".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_post_step_hook_sees_the_result() {
+        let mut vm = vm();
+        let trace = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&trace);
+        vm.set_post_step_hook(move |state, pc, _, instruction| {
+            recorded.lock().unwrap().push((
+                pc,
+                instruction.to_string(),
+                state.pc,
+                state.registers()[1],
+            ));
+        });
+
+        vm.run_until_halt();
+        assert_eq!(
+            trace.lock().unwrap()[2..],
+            [
+                (0x3002, "ADD R1, R1, #2".to_string(), 0x3003, 2),
+                (0x3003, "HALT".to_string(), 0x3004, 2),
+            ]
+        );
+
+        vm.remove_post_step_hook();
+        vm.reset();
+        vm.run_until_halt();
+        assert_eq!(trace.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_pre_step_hook_pauses() {
+        let mut vm = vm();
+        vm.set_pre_step_hook(|_, _, instruction| match instruction {
+            Instruction::TRAP(_) => HookAction::Pause,
+            _ => HookAction::Continue,
+        });
+
+        vm.run_until_halt();
+        assert_eq!(vm.pc(), 0x3001);
+        assert_eq!(vm.summary().instructions, 1);
+        assert!(vm.take_output().is_empty());
+
+        // The paused instruction goes ahead on the next step.
+        assert_eq!(vm.step(), StepResult::Trapped { vector: 0x22 });
+        assert_eq!(vm.take_output(), b"Hi");
+        assert_eq!(vm.run_with_limit(100), Ok(()));
+        assert_eq!(vm.pc(), 0x3003);
+
+        vm.remove_pre_step_hook();
+        vm.run_until_halt();
+        assert!(vm.is_halted());
+    }
+
+    #[test]
+    fn test_memory_write_hook() {
+        // .ORIG x3000
+        //       ADD R1, R1, #5
+        //       ST R1, DATA
+        //       LEA R2, DATA
+        //       STR R1, R2, #1
+        //       STI R1, PTR
+        //       HALT
+        // DATA  .FILL #1
+        //       .FILL #2
+        // PTR   .FILL x4000
+        let program = [
+            0x30, 0x00, 0x12, 0x65, 0x32, 0x04, 0xe4, 0x03, 0x72, 0x81, 0xb2, 0x03, 0xf0, 0x25,
+            0x00, 0x01, 0x00, 0x02, 0x40, 0x00,
+        ];
+        let mut vm = Vm::new();
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&writes);
+        vm.set_memory_write_hook(move |address, old, new| {
+            recorded.lock().unwrap().push((address, old, new));
+        });
+        vm.load_program(&program).unwrap();
+        vm.write_memory(0x5000, 1);
+
+        vm.run_until_halt();
+        assert_eq!(
+            *writes.lock().unwrap(),
+            [(0x3006, 1, 5), (0x3007, 2, 5), (0x4000, 0, 5)]
+        );
+
+        writes.lock().unwrap().clear();
+        vm.hook_host_writes(true);
+        vm.write_memory(0x5000, 2);
+        assert_eq!(*writes.lock().unwrap(), [(0x5000, 1, 2)]);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let mut vm = Vm::new();
+
+        assert_eq!(
+            vm.load_program(&[0x30]).unwrap_err().to_string(),
+            "input must be a multiple of 2"
+        );
+        assert_eq!(
+            vm.load_program(&[]).unwrap_err().to_string(),
+            "ROM must be at least 2 bytes."
+        );
+
+        let path = std::env::temp_dir().join(format!("lc3-odd-{}.obj", std::process::id()));
+        std::fs::write(&path, [0x30]).unwrap();
+        let e = vm.load_file(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            e.to_string(),
+            format!("{}: input must be a multiple of 2", path.display())
+        );
+    }
+
+    #[test]
+    fn test_load_os() {
+        let mut vm = Vm::new();
+        vm.capture_output();
+        vm.load_os(crate::BUNDLED_OS).unwrap();
+        vm.load_program(&PROGRAM).unwrap();
+        // The OS saves registers in its own image, so it's left writable.
+        vm.protect_programs();
+        assert_eq!(vm.pc(), 0x3000);
+
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.take_output(), b"Hi");
+        assert_eq!(vm.register(Register::R1), 2);
+        assert_eq!(vm.summary().trap_mode, TrapMode::Os);
+
+        // Still there after a reset.
+        vm.reset();
+        assert_eq!(vm.trap_mode(), TrapMode::Os);
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.take_output(), b"Hi");
+
+        assert_eq!(self::vm().trap_mode(), TrapMode::Builtin);
+    }
+
+    #[test]
+    fn test_decode_cache_sees_self_modifying_code() {
+        let mut vm = Vm::new();
+        vm.set_decode_cache(true);
+        vm.load_assembly(
+            ".ORIG x3000
+            ADD R2, R2, #2
+    LOOP    ADD R0, R0, #1
+            LD R1, PATCH
+            ST R1, LOOP
+            ADD R2, R2, #-1
+            BRp LOOP
+            HALT
+    PATCH   ADD R0, R0, #5
+            .END",
+        )
+        .unwrap();
+
+        // The second time round runs the patched instruction, not the one decoded the first time.
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.register(Register::R0), 6);
+
+        // So do host writes, and the cache stays on over a reset.
+        vm.reset();
+        vm.write_memory(0x3001, 0x1022); // ADD R0, R0, #2
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.register(Register::R0), 7);
+        assert!(vm.state.memory.has_decode_cache());
+    }
+
+    #[test]
+    fn test_predecode_sees_self_modifying_code() {
+        // NEXT is patched before it first runs, so only predecoding has decoded it by then, and
+        // LOOP is patched after its first run.
+        let program = asm::assemble(
+            ".ORIG x3000
+            ADD R2, R2, #3
+    LOOP    ADD R0, R0, #1
+            LD R1, PATCH
+            ST R1, NEXT
+    NEXT    ADD R3, R3, #1
+            ST R1, LOOP
+            ADD R2, R2, #-1
+            BRp LOOP
+            HALT
+    PATCH   ADD R0, R0, #5
+            .END",
+        )
+        .unwrap();
+
+        let mut results = Vec::new();
+        for (decode_cache, predecode) in [(false, false), (true, false), (false, true)] {
+            let mut vm = Vm::builder()
+                .program_bytes(&program.to_bytes())
+                .decode_cache(decode_cache)
+                .predecode(predecode)
+                .build()
+                .unwrap();
+            assert_eq!(vm.run(), Ok(()));
+            let first = (
+                vm.registers(),
+                vm.read_memory(0x3001),
+                vm.read_memory(0x3004),
+            );
+
+            // Reset reloads the unpatched program, decoded afresh.
+            vm.reset();
+            assert_eq!(vm.run(), Ok(()));
+            let second = (
+                vm.registers(),
+                vm.read_memory(0x3001),
+                vm.read_memory(0x3004),
+            );
+            assert_eq!(first, second);
+            results.push(first);
+        }
+
+        assert_eq!(results[0].0[0], 26);
+        assert_eq!(results[0].0[3], 0);
+        assert_eq!(results[1], results[0]);
+        assert_eq!(results[2], results[0]);
+    }
+
+    #[test]
+    fn test_load_assembly() {
+        let mut vm = Vm::new();
+        vm.load_assembly(
+            ".ORIG x3000
+            LD R1, COUNT
+            ADD R1, R1, #1
+            HALT
+    COUNT   .FILL #41
+            .END",
+        )
+        .unwrap();
+        assert_eq!(vm.pc(), 0x3000);
+
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.register(Register::R1), 42);
+
+        let errors = Vm::new()
+            .load_assembly(".ORIG x3000\n  ADD R1, R1, #99\n")
+            .unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "2:3: Immediate #99 out of range for imm5 (-16 to 15)"
+        );
+    }
+
+    #[test]
+    fn test_protect_programs() {
+        // .ORIG x3000
+        // ST R1, #1
+        // HALT
+        // .FILL x0000
+        let program = [0x30, 0x00, 0x32, 0x01, 0xf0, 0x25, 0x00, 0x00];
+        let mut vm = Vm::new();
+        vm.capture_output();
+        vm.load_program(&program).unwrap();
+        vm.protect_programs();
+        vm.set_register(Register::R1, 0xbeef);
+
+        let error = VmError::WriteToProtected {
+            address: 0x3002,
+            pc: 0x3000,
+        };
+        assert_eq!(vm.step(), StepResult::Error(error));
+        assert_eq!(
+            vm.run().unwrap_err().to_string(),
+            "Write to protected address 0x3002 at PC 0x3000"
+        );
+        assert_eq!(vm.read_memory(0x3002), 0);
+        assert_eq!(vm.pc(), 0x3000);
+
+        // The host can still patch the image, and protection survives a reset.
+        vm.write_memory(0x3002, 1);
+        vm.reset();
+        assert!(vm.run().is_err());
+        assert_eq!(vm.read_memory(0x3002), 0);
+
+        vm.unprotect(0x3002..=0x3002);
+        vm.set_register(Register::R1, 0xbeef);
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.read_memory(0x3002), 0xbeef);
+    }
+}
@@ -0,0 +1,223 @@
+use super::{StepResult, Vm};
+use crate::console::Console;
+use crate::error::VmError;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
+
+// How many instructions run before yielding to the runtime.
+const BATCH: usize = 10_000;
+
+/// Keys and output for [`Vm::run_async`], as async channels.
+pub struct AsyncConsole {
+    keys: Receiver<u8>,
+    output: UnboundedSender<Vec<u8>>,
+}
+
+impl AsyncConsole {
+    /// Keys are read from `keys`, and each write by the program is sent to `output`.
+    pub fn new(keys: Receiver<u8>, output: UnboundedSender<Vec<u8>>) -> Self {
+        AsyncConsole { keys, output }
+    }
+}
+
+impl Vm {
+    /// Like [`Vm::run`], but yields to the runtime between batches of instructions and awaits
+    /// keys rather than blocking the thread when the program waits for input. The console is
+    /// swapped for `console` while it runs, and the step and time limits don't apply.
+    ///
+    /// Ends with an error if the program waits for a key after the sending side of the keys
+    /// channel is dropped. Also returns `Ok` early if the pre-step hook pauses.
+    pub async fn run_async(&mut self, mut console: AsyncConsole) -> Result<(), VmError> {
+        let input = Arc::new(Mutex::new(Input::default()));
+        let previous = self.state.console.replace(Box::new(Bridge {
+            input: Arc::clone(&input),
+            output: console.output.clone(),
+        }));
+
+        let started = Instant::now();
+        let result = self.run_batches(&mut console, &input).await;
+        self.run_time += started.elapsed();
+        self.state.console.replace(previous);
+
+        result
+    }
+
+    async fn run_batches(
+        &mut self,
+        console: &mut AsyncConsole,
+        input: &Mutex<Input>,
+    ) -> Result<(), VmError> {
+        let mut closed = false;
+
+        while self.state.running {
+            // Keys typed since the last batch, for programs polling KBSR.
+            loop {
+                match console.keys.try_recv() {
+                    Ok(key) => input.lock().unwrap().keys.push_back(key),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        closed = true;
+                        break;
+                    }
+                }
+            }
+
+            for _ in 0..BATCH {
+                match self.step() {
+                    StepResult::Halted { .. } | StepResult::Breakpoint => return Ok(()),
+                    StepResult::Error(e) => return Err(e),
+                    _ => {}
+                }
+                if input.lock().unwrap().starved {
+                    break;
+                }
+            }
+
+            let starved = std::mem::take(&mut input.lock().unwrap().starved);
+            if !starved {
+                tokio::task::yield_now().await;
+            } else if closed {
+                return Err(VmError::Io(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "The keys channel closed while the program was waiting for input",
+                )));
+            } else {
+                match console.keys.recv().await {
+                    Some(key) => input.lock().unwrap().keys.push_back(key),
+                    None => closed = true,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Input {
+    keys: VecDeque<u8>,
+    // Set when a GETC or IN found no key, so the run loop awaits one before retrying it.
+    starved: bool,
+}
+
+// The console the machine sees while running async, fed keys by the run loop.
+struct Bridge {
+    input: Arc<Mutex<Input>>,
+    output: UnboundedSender<Vec<u8>>,
+}
+
+impl Console for Bridge {
+    fn poll_key(&mut self) -> Option<u8> {
+        self.input.lock().unwrap().keys.pop_front()
+    }
+
+    fn read_key_blocking(&mut self, _deadline: Option<Instant>) -> Option<u8> {
+        let mut input = self.input.lock().unwrap();
+        let key = input.keys.pop_front();
+        input.starved = key.is_none();
+        key
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Nobody listening is fine, the program carries on.
+        let _ = self.output.send(bytes.to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+    use tokio::sync::mpsc;
+
+    // .ORIG x3000
+    // LOOP  GETC
+    //       OUT
+    //       ADD R1, R0, #-10
+    //       BRnp LOOP
+    //       HALT
+    const ECHO: [u8; 12] = [
+        0x30, 0x00, 0xf0, 0x20, 0xf0, 0x21, 0x12, 0x36, 0x0b, 0xfc, 0xf0, 0x25,
+    ];
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_run_async() {
+        let mut vm = Vm::new();
+        let console = BufferConsole::default();
+        vm.set_console(console.clone());
+        vm.load_program(&ECHO).unwrap();
+
+        let (keys, key_receiver) = mpsc::channel(4);
+        let (output_sender, mut output) = mpsc::unbounded_channel();
+
+        let runtime = runtime();
+        let result = runtime.block_on(async move {
+            // The program waits on the first key before any is sent.
+            let feed = tokio::spawn(async move {
+                for key in b"hey\n" {
+                    tokio::task::yield_now().await;
+                    keys.send(*key).await.unwrap();
+                }
+            });
+            let result = vm
+                .run_async(AsyncConsole::new(key_receiver, output_sender))
+                .await;
+            feed.await.unwrap();
+            (vm, result)
+        });
+
+        let (mut vm, result) = result;
+        assert_eq!(result, Ok(()));
+        assert!(vm.is_halted());
+
+        let mut echoed = Vec::new();
+        while let Ok(bytes) = output.try_recv() {
+            echoed.extend(bytes);
+        }
+        assert_eq!(echoed, b"hey\n");
+
+        // The machine's own console is back afterwards.
+        vm.write_memory(0x3005, 0xf021); // OUT
+        vm.set_pc(0x3005);
+        vm.step();
+        assert_eq!(console.output(), b"\n");
+    }
+
+    #[test]
+    fn test_run_async_is_send() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let mut vm = Vm::new();
+        let (_keys, key_receiver) = mpsc::channel(1);
+        let (output_sender, _output) = mpsc::unbounded_channel();
+        assert_send(&vm.run_async(AsyncConsole::new(key_receiver, output_sender)));
+    }
+
+    #[test]
+    fn test_run_async_without_more_input() {
+        let mut vm = Vm::new();
+        vm.load_program(&ECHO).unwrap();
+
+        let (keys, key_receiver) = mpsc::channel(4);
+        let (output_sender, _output) = mpsc::unbounded_channel();
+        drop(keys);
+
+        let result =
+            runtime().block_on(vm.run_async(AsyncConsole::new(key_receiver, output_sender)));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The keys channel closed while the program was waiting for input"
+        );
+        assert_eq!(vm.pc(), 0x3000);
+    }
+}
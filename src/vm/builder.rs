@@ -0,0 +1,353 @@
+use super::{trace, Vm};
+use crate::console::{Console, Echo};
+use crate::error::BuildError;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+enum Program {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+/// Configures a [`Vm`], see [`Vm::builder`].
+///
+/// ```
+/// use lc3::Vm;
+///
+/// // .ORIG x3000, ADD R0, R0, #7, HALT
+/// let program = [0x30, 0x00, 0x10, 0x27, 0xf0, 0x25];
+///
+/// let mut vm = Vm::builder()
+///     .program_bytes(&program)
+///     .max_steps(100)
+///     .build()
+///     .unwrap();
+/// vm.run().unwrap();
+/// assert_eq!(vm.registers()[0], 7);
+/// ```
+#[derive(Default)]
+pub struct VmBuilder {
+    os_image: Option<Vec<u8>>,
+    program_bytes: Option<Vec<u8>>,
+    program_file: Option<PathBuf>,
+    entry: Option<u16>,
+    console: Option<Box<dyn Console>>,
+    echo: Echo,
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+    hz: Option<u64>,
+    trace: bool,
+    trace_output: Option<Box<dyn Write + Send>>,
+    protect_text: bool,
+    decode_cache: bool,
+    predecode: bool,
+}
+
+impl VmBuilder {
+    /// An object file image to load, see [`Vm::load_program`].
+    pub fn program_bytes(mut self, bytes: &[u8]) -> Self {
+        self.program_bytes = Some(bytes.to_vec());
+        self
+    }
+
+    /// An object file to load, instead of [`VmBuilder::program_bytes`].
+    pub fn program_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.program_file = Some(path.into());
+        self
+    }
+
+    /// An operating system image to carry out TRAPs, see [`Vm::load_os`]. It's loaded before the
+    /// program so the program's origin is still where execution starts.
+    pub fn os_image(mut self, bytes: &[u8]) -> Self {
+        self.os_image = Some(bytes.to_vec());
+        self
+    }
+
+    /// Where execution starts instead of the program's origin. Must be inside a loaded image.
+    pub fn entry(mut self, address: u16) -> Self {
+        self.entry = Some(address);
+        self
+    }
+
+    /// Replaces the terminal, see [`Vm::set_console`].
+    pub fn console(mut self, console: Box<dyn Console>) -> Self {
+        self.console = Some(console);
+        self
+    }
+
+    /// Which keys the program reads are echoed, see [`Vm::set_echo`].
+    pub fn echo(mut self, echo: Echo) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Limits [`Vm::run`] to `max_steps` instructions.
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Limits [`Vm::run`] to `timeout` of wall-clock time.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Paces [`Vm::run`] to about `hz` instructions a second, e.g. for programs that use busy
+    /// loops as timers. Zero is unthrottled, the default.
+    pub fn hz(mut self, hz: u64) -> Self {
+        self.hz = Some(hz).filter(|hz| *hz > 0);
+        self
+    }
+
+    /// Prints a line to STDERR for each instruction executed, with the registers it wrote, see
+    /// [`VmBuilder::trace_output`]. Going to STDERR keeps it apart from the program's output.
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Writes the trace to `output` instead of STDERR, e.g. a file.
+    pub fn trace_output(mut self, output: Box<dyn Write + Send>) -> Self {
+        self.trace = true;
+        self.trace_output = Some(output);
+        self
+    }
+
+    /// Write-protects the loaded images, see [`Vm::protect_programs`].
+    pub fn protect_text(mut self, protect_text: bool) -> Self {
+        self.protect_text = protect_text;
+        self
+    }
+
+    /// Keeps instructions decoded between steps, see [`Vm::set_decode_cache`].
+    pub fn decode_cache(mut self, decode_cache: bool) -> Self {
+        self.decode_cache = decode_cache;
+        self
+    }
+
+    /// Decodes the images as they're loaded, see [`Vm::set_predecode`].
+    pub fn predecode(mut self, predecode: bool) -> Self {
+        self.predecode = predecode;
+        self
+    }
+
+    pub fn build(self) -> Result<Vm, BuildError> {
+        let program = match (self.program_bytes, self.program_file) {
+            (Some(_), Some(_)) => return Err(BuildError::ConflictingPrograms),
+            (Some(bytes), None) => Some(Program::Bytes(bytes)),
+            (None, Some(path)) => Some(Program::File(path)),
+            (None, None) => None,
+        };
+        if program.is_none() && self.os_image.is_none() {
+            return Err(BuildError::NoProgram);
+        }
+
+        let mut vm = Vm::new();
+        vm.set_decode_cache(self.decode_cache);
+        vm.set_predecode(self.predecode);
+        if let Some(console) = self.console {
+            vm.state.console.replace(console);
+        }
+        vm.set_echo(self.echo);
+        if let Some(os_image) = &self.os_image {
+            vm.load_os(os_image).map_err(BuildError::Load)?;
+        }
+        match &program {
+            Some(Program::Bytes(bytes)) => vm.load_program(bytes).map_err(BuildError::Load)?,
+            Some(Program::File(path)) => vm.load_file(path).map_err(BuildError::File)?,
+            None => {}
+        }
+
+        if let Some(entry) = self.entry {
+            if !vm.is_loaded(entry) {
+                return Err(BuildError::EntryOutsideProgram(entry));
+            }
+            vm.entry = Some(entry);
+            vm.set_pc(entry);
+        }
+
+        if self.protect_text {
+            vm.protect_programs();
+        }
+        vm.max_steps = self.max_steps;
+        vm.timeout = self.timeout;
+        vm.hz = self.hz;
+        if self.trace {
+            let mut output = self.trace_output.unwrap_or_else(|| Box::new(io::stderr()));
+            vm.set_post_step_hook(move |state, pc, word, instruction| {
+                let _ = writeln!(
+                    output,
+                    "{}",
+                    trace::trace_line(state, pc, word, instruction)
+                );
+            });
+        }
+
+        Ok(vm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+    use crate::error::VmError;
+    use std::env;
+    use std::fs;
+    use std::time::Instant;
+
+    // .ORIG x3000
+    // LEA R0, #3
+    // PUTS
+    // ADD R1, R1, #2
+    // HALT
+    // .STRINGZ "Hi"
+    const PROGRAM: [u8; 16] = [
+        0x30, 0x00, 0xe0, 0x03, 0xf0, 0x22, 0x12, 0x62, 0xf0, 0x25, 0x00, 0x48, 0x00, 0x69, 0x00,
+        0x00,
+    ];
+
+    #[test]
+    fn test_program_bytes_and_console() {
+        let console = BufferConsole::default();
+        let mut vm = Vm::builder()
+            .program_bytes(&PROGRAM)
+            .console(Box::new(console.clone()))
+            .build()
+            .unwrap();
+
+        assert_eq!(vm.pc(), 0x3000);
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(console.output(), b"Hi");
+    }
+
+    #[test]
+    fn test_program_file() {
+        let path = env::temp_dir().join(format!("lc3-builder-{}.obj", std::process::id()));
+        fs::write(&path, PROGRAM).unwrap();
+        let vm = Vm::builder().program_file(&path).build();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(vm.unwrap().read_memory(0x3002), 0x1262);
+        assert!(matches!(
+            Vm::builder().program_file(&path).build(),
+            Err(BuildError::File(_))
+        ));
+    }
+
+    #[test]
+    fn test_entry_and_limits() {
+        // .ORIG x4000
+        // ADD R2, R2, #1
+        // BRnzp #-2
+        let os = [0x40, 0x00, 0x14, 0xa1, 0x0f, 0xfe];
+        let mut vm = Vm::builder()
+            .os_image(&os)
+            .program_bytes(&PROGRAM)
+            .entry(0x4000)
+            .max_steps(10)
+            .build()
+            .unwrap();
+
+        assert_eq!(vm.pc(), 0x4000);
+        assert_eq!(
+            vm.run(),
+            Err(VmError::StepLimitExceeded {
+                limit: 10,
+                pc: 0x4000,
+                word: 0x14a1
+            })
+        );
+        assert_eq!(vm.register(crate::Register::R2), 5);
+
+        // The entry point survives a reset.
+        vm.reset();
+        assert_eq!(vm.pc(), 0x4000);
+    }
+
+    #[test]
+    fn test_hz() {
+        // .ORIG x3000
+        // BRnzp #-1
+        let program = [0x30, 0x00, 0x0f, 0xff];
+        let mut vm = Vm::builder()
+            .program_bytes(&program)
+            .max_steps(300)
+            .hz(2_000)
+            .build()
+            .unwrap();
+
+        // The first batch of 20 goes straight away, the rest at 2kHz.
+        let started = Instant::now();
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::StepLimitExceeded { limit: 300, .. })
+        ));
+        assert!(started.elapsed() >= Duration::from_millis(140));
+
+        let vm = Vm::builder().program_bytes(&program).hz(0).build().unwrap();
+        assert_eq!(vm.hz, None);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(
+            Vm::builder()
+                .program_bytes(&PROGRAM)
+                .program_file("program.obj")
+                .build()
+                .err()
+                .map(|e| e.to_string()),
+            Some("Both program bytes and a program file were given".to_string())
+        );
+        assert_eq!(
+            Vm::builder().build().err().map(|e| e.to_string()),
+            Some("No program to load".to_string())
+        );
+        assert_eq!(
+            Vm::builder()
+                .program_bytes(&PROGRAM)
+                .entry(0x3007)
+                .build()
+                .err()
+                .map(|e| e.to_string()),
+            Some("Entry point 0x3007 is outside the loaded program".to_string())
+        );
+        assert!(Vm::builder()
+            .program_bytes(&PROGRAM)
+            .entry(0x3006)
+            .build()
+            .is_ok());
+        assert_eq!(
+            Vm::builder()
+                .program_bytes(&[0x30])
+                .build()
+                .err()
+                .map(|e| e.to_string()),
+            Some("input must be a multiple of 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_protect_text() {
+        // .ORIG x3000
+        // ST R0, #-1
+        let program = [0x30, 0x00, 0x31, 0xff];
+        let mut vm = Vm::builder()
+            .program_bytes(&program)
+            .protect_text(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            vm.run(),
+            Err(VmError::WriteToProtected {
+                address: 0x3000,
+                pc: 0x3000
+            })
+        );
+        assert_eq!(vm.read_memory(0x3000), 0x31ff);
+    }
+}
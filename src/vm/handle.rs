@@ -0,0 +1,301 @@
+use super::{StepResult, Vm};
+use crate::console::Console;
+use crate::error::VmError;
+use crate::state::State;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// How many instructions run between checks for control messages.
+const BATCH: usize = 10_000;
+
+// How long a blocked GETC waits for a key before letting the control loop check its messages.
+const KEY_WAIT: Duration = Duration::from_millis(10);
+
+enum Control {
+    Pause,
+    Resume,
+    Stop,
+    Snapshot(Sender<State>),
+}
+
+/// A machine running on its own thread, see [`Vm::spawn`].
+///
+/// ```
+/// use lc3::Vm;
+///
+/// // .ORIG x3000
+/// // LOOP  GETC
+/// //       OUT
+/// //       ADD R1, R0, #-10
+/// //       BRnp LOOP
+/// //       HALT
+/// let program = [
+///     0x30, 0x00, 0xf0, 0x20, 0xf0, 0x21, 0x12, 0x36, 0x0b, 0xfc, 0xf0, 0x25,
+/// ];
+/// let mut vm = Vm::new();
+/// vm.load_program(&program).unwrap();
+///
+/// let handle = vm.spawn();
+/// for key in b"hi\n" {
+///     handle.send_key(*key);
+/// }
+/// let output: Vec<u8> = handle.output().iter().take(3).flatten().collect();
+/// assert_eq!(output, b"hi\n");
+///
+/// let (vm, result) = handle.join();
+/// assert!(result.is_ok() && vm.is_halted());
+/// ```
+pub struct VmHandle {
+    control: Sender<Control>,
+    keys: Sender<u8>,
+    output: Receiver<Vec<u8>>,
+    thread: JoinHandle<(Vm, Result<(), VmError>)>,
+}
+
+impl VmHandle {
+    pub(super) fn spawn(mut vm: Vm) -> Self {
+        let (control, control_receiver) = mpsc::channel();
+        let (keys, key_receiver) = mpsc::channel();
+        let (output_sender, output) = mpsc::channel();
+        let starved = Arc::new(AtomicBool::new(false));
+
+        vm.set_console(ChannelConsole {
+            keys: key_receiver,
+            output: output_sender,
+            starved: Arc::clone(&starved),
+        });
+        let thread = thread::spawn(move || {
+            let result = control_loop(&mut vm, &control_receiver, &starved);
+            (vm, result)
+        });
+
+        VmHandle {
+            control,
+            keys,
+            output,
+            thread,
+        }
+    }
+
+    /// Types a key, for the program to read through GETC, IN or KBSR and KBDR.
+    pub fn send_key(&self, key: u8) {
+        // Nobody to read it once the machine has stopped.
+        let _ = self.keys.send(key);
+    }
+
+    /// What the program writes to the console, a message per write.
+    pub fn output(&self) -> &Receiver<Vec<u8>> {
+        &self.output
+    }
+
+    /// Stops executing instructions until [`VmHandle::resume`].
+    pub fn pause(&self) {
+        let _ = self.control.send(Control::Pause);
+    }
+
+    pub fn resume(&self) {
+        let _ = self.control.send(Control::Resume);
+    }
+
+    /// A copy of the machine between instructions, or `None` once it has stopped.
+    pub fn snapshot(&self) -> Option<State> {
+        let (sender, receiver) = mpsc::channel();
+        self.control.send(Control::Snapshot(sender)).ok()?;
+        receiver.recv().ok()
+    }
+
+    /// Whether the machine has halted, failed or been stopped.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Stops the machine wherever it is and hands it back.
+    pub fn stop(self) -> (Vm, Result<(), VmError>) {
+        let _ = self.control.send(Control::Stop);
+        self.join()
+    }
+
+    /// Waits for the program to halt or fail, and hands the machine back. Its console is left
+    /// connected to this handle's channels, so replace it with [`Vm::set_console`] to run it
+    /// further.
+    pub fn join(self) -> (Vm, Result<(), VmError>) {
+        match self.thread.join() {
+            Ok(finished) => finished,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+// Runs batches of instructions, checking for control messages in between. A pause from the
+// pre-step hook pauses the loop too, until it's resumed.
+fn control_loop(
+    vm: &mut Vm,
+    control: &Receiver<Control>,
+    starved: &AtomicBool,
+) -> Result<(), VmError> {
+    let mut paused = false;
+
+    loop {
+        let message = if paused {
+            control.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            control.try_recv()
+        };
+        match message {
+            Ok(Control::Pause) => paused = true,
+            Ok(Control::Resume) => paused = false,
+            // Dropping the handle stops the machine too.
+            Ok(Control::Stop) | Err(TryRecvError::Disconnected) => return Ok(()),
+            Ok(Control::Snapshot(sender)) => {
+                let _ = sender.send(vm.state.clone());
+            }
+            Err(TryRecvError::Empty) => {}
+        }
+        if paused {
+            continue;
+        }
+
+        starved.store(false, Ordering::Relaxed);
+        for _ in 0..BATCH {
+            match vm.step() {
+                StepResult::Halted { .. } => return Ok(()),
+                StepResult::Error(e) => return Err(e),
+                StepResult::Breakpoint => paused = true,
+                _ => {}
+            }
+            // Waiting on a key, so see if there's anything to do meanwhile.
+            if paused || starved.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+    }
+}
+
+// Keys from the handle and output to it.
+struct ChannelConsole {
+    keys: Receiver<u8>,
+    output: Sender<Vec<u8>>,
+    // Set when a blocking read gave up, so the control loop doesn't wait out a whole batch.
+    starved: Arc<AtomicBool>,
+}
+
+impl Console for ChannelConsole {
+    fn poll_key(&mut self) -> Option<u8> {
+        self.keys.try_recv().ok()
+    }
+
+    fn read_key_blocking(&mut self, deadline: Option<Instant>) -> Option<u8> {
+        let mut wait = KEY_WAIT;
+        if let Some(deadline) = deadline {
+            wait = wait.min(deadline.saturating_duration_since(Instant::now()));
+        }
+
+        match self.keys.recv_timeout(wait) {
+            Ok(key) => Some(key),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                self.starved.store(true, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let _ = self.output.send(bytes.to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Register;
+
+    fn spawn(program: &[u8]) -> VmHandle {
+        let mut vm = Vm::new();
+        vm.load_program(program).unwrap();
+        vm.spawn()
+    }
+
+    fn collect(handle: &VmHandle, length: usize) -> Vec<u8> {
+        let mut output = Vec::new();
+        while output.len() < length {
+            output.extend(
+                handle
+                    .output()
+                    .recv_timeout(Duration::from_secs(10))
+                    .unwrap(),
+            );
+        }
+        output
+    }
+
+    #[test]
+    fn test_echo_with_getc() {
+        // .ORIG x3000
+        // LOOP  GETC
+        //       OUT
+        //       ADD R1, R0, #-10
+        //       BRnp LOOP
+        //       HALT
+        let handle = spawn(&[
+            0x30, 0x00, 0xf0, 0x20, 0xf0, 0x21, 0x12, 0x36, 0x0b, 0xfc, 0xf0, 0x25,
+        ]);
+
+        handle.send_key(b'o');
+        assert_eq!(collect(&handle, 1), b"o");
+        handle.send_key(b'k');
+        handle.send_key(b'\n');
+        assert_eq!(collect(&handle, 2), b"k\n");
+
+        let (vm, result) = handle.join();
+        assert_eq!(result, Ok(()));
+        assert!(vm.is_halted());
+        assert_eq!(vm.register(Register::R0), u16::from(b'\n'));
+    }
+
+    #[test]
+    fn test_echo_with_the_keyboard_registers() {
+        // .ORIG x3000
+        // POLL  LDI R0, KBSR
+        //       BRzp POLL
+        //       LDI R0, KBDR
+        //       OUT
+        //       BRnzp POLL
+        // KBSR  .FILL xFE00
+        // KBDR  .FILL xFE02
+        let handle = spawn(&[
+            0x30, 0x00, 0xa0, 0x04, 0x07, 0xfe, 0xa0, 0x03, 0xf0, 0x21, 0x0f, 0xfb, 0xfe, 0x00,
+            0xfe, 0x02,
+        ]);
+
+        for key in b"abc" {
+            handle.send_key(*key);
+        }
+        assert_eq!(collect(&handle, 3), b"abc");
+
+        let (vm, result) = handle.stop();
+        assert_eq!(result, Ok(()));
+        assert!(!vm.is_halted());
+    }
+
+    #[test]
+    fn test_pause_and_snapshot() {
+        // .ORIG x3000
+        // LOOP  ADD R1, R1, #1
+        //       BRnzp LOOP
+        let handle = spawn(&[0x30, 0x00, 0x12, 0x61, 0x0f, 0xfe]);
+
+        handle.pause();
+        let first = handle.snapshot().unwrap();
+        let second = handle.snapshot().unwrap();
+        assert_eq!(first, second);
+
+        handle.resume();
+        while handle.snapshot().unwrap() == first {}
+
+        let (vm, _) = handle.stop();
+        assert!(vm.summary().instructions > first.instructions);
+    }
+}
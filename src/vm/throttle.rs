@@ -0,0 +1,110 @@
+//! Paces execution to a clock speed with a token bucket: each instruction takes a token, and
+//! tokens come back at the clock rate.
+
+use std::convert::TryFrom;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The bucket is only refilled once it runs dry, so instructions go at full speed in batches of
+// about this long, with a sleep between each.
+const BATCH: Duration = Duration::from_millis(10);
+// How long the bucket can save up for, e.g. while the program is blocked waiting for a key, so a
+// short burst afterwards isn't slowed down.
+const BURST: Duration = Duration::from_millis(100);
+
+pub(crate) struct Throttle {
+    hz: u64,
+    tokens: u64,
+    batch: u64,
+    capacity: u64,
+    // When the tokens were last counted, less any part of a token not yet earned.
+    refilled: Instant,
+}
+
+impl Throttle {
+    pub(crate) fn new(hz: u64) -> Self {
+        let batch = tokens_in(hz, BATCH).max(1);
+
+        Throttle {
+            hz,
+            tokens: batch,
+            batch,
+            capacity: tokens_in(hz, BURST).max(batch),
+            refilled: Instant::now(),
+        }
+    }
+
+    // Takes the token for one instruction, sleeping for a batch's worth if there are none.
+    pub(crate) fn take(&mut self) {
+        while self.tokens == 0 {
+            self.refill();
+            if self.tokens == 0 {
+                thread::sleep(BATCH.min(self.duration_of(self.batch)));
+                self.refill();
+            }
+        }
+
+        self.tokens -= 1;
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let earned = tokens_in(self.hz, now - self.refilled);
+        if self.tokens + earned >= self.capacity {
+            self.tokens = self.capacity;
+            self.refilled = now;
+        } else {
+            self.tokens += earned;
+            // Keeps the fraction of a token towards the next refill.
+            self.refilled += self.duration_of(earned);
+        }
+    }
+
+    fn duration_of(&self, tokens: u64) -> Duration {
+        let nanos = u128::from(tokens) * 1_000_000_000 / u128::from(self.hz);
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+}
+
+fn tokens_in(hz: u64, duration: Duration) -> u64 {
+    let tokens = duration.as_nanos() * u128::from(hz) / 1_000_000_000;
+    u64::try_from(tokens).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Throttle;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_take_paces_to_the_rate() {
+        let mut throttle = Throttle::new(1_000);
+        let started = Instant::now();
+        for _ in 0..200 {
+            throttle.take();
+        }
+
+        // The first batch of 10 is free.
+        assert!(started.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[test]
+    fn test_bursts_are_saved_up() {
+        let mut throttle = Throttle::new(1_000);
+        for _ in 0..10 {
+            throttle.take();
+        }
+        std::thread::sleep(Duration::from_millis(150));
+
+        // Up to 100ms of instructions can go at once after a wait, but no more.
+        let started = Instant::now();
+        for _ in 0..100 {
+            throttle.take();
+        }
+        assert!(started.elapsed() < Duration::from_millis(50));
+        for _ in 0..50 {
+            throttle.take();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(40));
+    }
+}
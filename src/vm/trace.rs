@@ -0,0 +1,202 @@
+use super::{StepResult, Vm};
+use crate::instruction::{Instruction, Register, TrapVector};
+use crate::state::{State, TrapMode};
+use std::sync::atomic::Ordering;
+
+/// An executed instruction, see [`Vm::trace_iter`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    /// Where the instruction was.
+    pub pc: u16,
+    /// The instruction word.
+    pub raw: u16,
+    /// The decoded instruction, `UNKNOWNTRAP` for a TRAP to a vector only a host handler or an
+    /// OS knows.
+    pub instruction: Instruction,
+    pub registers_after: [u16; 8],
+}
+
+/// Steps the machine as it's iterated, see [`Vm::trace_iter`].
+pub struct Trace<'a> {
+    vm: &'a mut Vm,
+}
+
+impl<'a> Trace<'a> {
+    pub(super) fn new(vm: &'a mut Vm) -> Self {
+        Trace { vm }
+    }
+}
+
+impl Iterator for Trace<'_> {
+    type Item = TraceEntry;
+
+    fn next(&mut self) -> Option<TraceEntry> {
+        if self.vm.is_halted() || self.vm.interrupt.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let pc = self.vm.pc();
+        let raw = self.vm.read_memory(pc);
+        match self.vm.step() {
+            // Nothing executed.
+            StepResult::Breakpoint | StepResult::Error(_) => None,
+            _ => Some(TraceEntry {
+                pc,
+                raw,
+                instruction: Instruction::decode(raw),
+                registers_after: self.vm.registers(),
+            }),
+        }
+    }
+}
+
+/// A `--trace` line for the instruction at `pc` that `state` has just executed: its number, the
+/// address, the word, the instruction and then the registers it wrote and the flags, e.g.
+/// `14  x3005  x16A1  ADD R3, R2, #1   ; R3=x0010  P`.
+pub(crate) fn trace_line(state: &State, pc: u16, word: u16, instruction: &Instruction) -> String {
+    let written: Vec<String> = written_registers(instruction, state.trap_mode)
+        .iter()
+        .map(|register| format!("{:?}=x{:04X}", register, state.registers[*register]))
+        .collect();
+    let effects = if written.is_empty() {
+        format!("{:?}", state.condition)
+    } else {
+        format!("{}  {:?}", written.join(" "), state.condition)
+    };
+
+    format!(
+        "{}  x{:04X}  x{:04X}  {:<16} ; {}",
+        state.instructions,
+        pc,
+        word,
+        instruction.to_string(),
+        effects
+    )
+}
+
+fn written_registers(instruction: &Instruction, trap_mode: TrapMode) -> Vec<Register> {
+    match instruction {
+        // An OS's routine writes what it writes as it runs.
+        Instruction::TRAP(_) if trap_mode == TrapMode::Os => vec![Register::R7],
+        // Only a host handler or an OS runs these.
+        Instruction::UNKNOWNTRAP(_) => vec![Register::R7],
+        Instruction::ADD(r0, _, _)
+        | Instruction::ADDIMM(r0, _, _)
+        | Instruction::AND(r0, _, _)
+        | Instruction::ANDIMM(_, r0, _)
+        | Instruction::NOT(r0, _)
+        | Instruction::LD(r0, _)
+        | Instruction::LDI(r0, _)
+        | Instruction::LDR(r0, _, _)
+        | Instruction::LEA(r0, _) => vec![*r0],
+        Instruction::JSR(_) | Instruction::JSRR(_) => vec![Register::R7],
+        // The built-in routines leave R7 alone.
+        Instruction::TRAP(TrapVector::GETC) | Instruction::TRAP(TrapVector::IN) => {
+            vec![Register::R0]
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Register;
+    use crate::state::HookAction;
+
+    // Multiplies 6 by 7 into R0.
+    //
+    // .ORIG x3000
+    //       AND R0, R0, #0
+    //       AND R1, R1, #0
+    //       ADD R1, R1, #7
+    // LOOP  ADD R0, R0, #6
+    //       ADD R1, R1, #-1
+    //       BRp LOOP
+    //       HALT
+    const PROGRAM: [u8; 16] = [
+        0x30, 0x00, 0x50, 0x20, 0x52, 0x60, 0x12, 0x67, 0x10, 0x26, 0x12, 0x7f, 0x03, 0xfd, 0xf0,
+        0x25,
+    ];
+
+    fn vm() -> Vm {
+        let mut vm = Vm::new();
+        vm.capture_output();
+        vm.load_program(&PROGRAM).unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_trace_a_loop() {
+        let mut vm = vm();
+        let trace: Vec<TraceEntry> = vm.trace_iter().collect();
+
+        assert_eq!(trace.len(), 25);
+        assert_eq!(
+            trace
+                .iter()
+                .filter(|entry| matches!(entry.instruction, Instruction::BR(..)))
+                .count(),
+            7
+        );
+        assert_eq!(
+            trace[3],
+            TraceEntry {
+                pc: 0x3003,
+                raw: 0x1026,
+                instruction: Instruction::ADDIMM(Register::R0, Register::R0, 6),
+                registers_after: [6, 7, 0, 0, 0, 0, 0, 0],
+            }
+        );
+        let last = trace.last().unwrap();
+        assert_eq!((last.pc, last.registers_after[0]), (0x3006, 42));
+
+        assert!(vm.is_halted());
+        assert_eq!(vm.trace_iter().next(), None);
+    }
+
+    #[test]
+    fn test_trace_line() {
+        let mut state = State::new();
+        state.pc = 0x3001;
+        state.instructions = 1;
+        let getc = Instruction::TRAP(TrapVector::GETC);
+        state.registers.write(Register::R0, 0x61);
+        state.registers.write(Register::R7, 0x3001);
+
+        assert_eq!(
+            trace_line(&state, 0x3000, 0xf020, &getc),
+            "1  x3000  xF020  GETC             ; R0=x0061  P"
+        );
+        state.trap_mode = TrapMode::Os;
+        assert_eq!(
+            trace_line(&state, 0x3000, 0xf020, &getc),
+            "1  x3000  xF020  GETC             ; R7=x3001  P"
+        );
+    }
+
+    #[test]
+    fn test_trace_is_lazy() {
+        let mut vm = vm();
+        let pcs: Vec<u16> = vm.trace_iter().take(4).map(|entry| entry.pc).collect();
+
+        assert_eq!(pcs, [0x3000, 0x3001, 0x3002, 0x3003]);
+        assert_eq!(vm.pc(), 0x3004);
+        assert!(!vm.is_halted());
+    }
+
+    #[test]
+    fn test_trace_ends_on_pause() {
+        let mut vm = vm();
+        vm.set_pre_step_hook(|state, _, _| {
+            if state.pc == 0x3005 {
+                HookAction::Pause
+            } else {
+                HookAction::Continue
+            }
+        });
+
+        assert_eq!(vm.trace_iter().count(), 5);
+        assert_eq!(vm.pc(), 0x3005);
+    }
+}
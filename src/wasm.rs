@@ -0,0 +1,107 @@
+//! A facade for running the machine from JavaScript, with keys and output passed in and out
+//! explicitly rather than through a terminal.
+
+use crate::console::BufferConsole;
+use crate::vm::{StepResult, Vm};
+use wasm_bindgen::prelude::*;
+
+/// A machine for the browser. Programs are loaded from `.obj` bytes, run a number of
+/// instructions at a time, and talk to the page through [`WasmVm::key_pressed`] and
+/// [`WasmVm::take_output`].
+#[wasm_bindgen]
+pub struct WasmVm {
+    vm: Vm,
+    console: BufferConsole,
+}
+
+#[wasm_bindgen]
+impl WasmVm {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let mut vm = Vm::new();
+        let console = BufferConsole::default();
+        vm.set_console(console.clone());
+
+        WasmVm { vm, console }
+    }
+
+    /// Loads an object file, e.g. a `Uint8Array` from a fetch or file input.
+    pub fn load_program(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.vm.load_program(bytes).map_err(|e| e.to_string())
+    }
+
+    /// Runs up to `n` instructions, returning whether the machine is still running. A program
+    /// waiting for a key retries until one is pressed, so keep calling this, e.g. once a frame.
+    pub fn step(&mut self, n: u32) -> Result<bool, String> {
+        for _ in 0..n {
+            match self.vm.step() {
+                StepResult::Halted { .. } => return Ok(false),
+                StepResult::Error(e) => return Err(e.to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(!self.vm.is_halted())
+    }
+
+    /// Queues a key for GETC, IN or the keyboard registers.
+    pub fn key_pressed(&mut self, key: u8) {
+        self.console.push_input(&[key]);
+    }
+
+    /// What the program has written since the last call.
+    pub fn take_output(&mut self) -> String {
+        String::from_utf8_lossy(&self.console.take_output()).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // .ORIG x3000
+    // LOOP  GETC
+    //       OUT
+    //       ADD R1, R0, #-10
+    //       BRnp LOOP
+    //       HALT
+    const ECHO: [u8; 12] = [
+        0x30, 0x00, 0xf0, 0x20, 0xf0, 0x21, 0x12, 0x36, 0x0b, 0xfc, 0xf0, 0x25,
+    ];
+
+    #[test]
+    fn test_echo() {
+        let mut vm = WasmVm::new();
+        vm.load_program(&ECHO).unwrap();
+
+        // Waiting on the first key.
+        assert_eq!(vm.step(100), Ok(true));
+        assert_eq!(vm.take_output(), "");
+
+        for key in b"hi" {
+            vm.key_pressed(*key);
+        }
+        assert_eq!(vm.step(100), Ok(true));
+        assert_eq!(vm.take_output(), "hi");
+
+        vm.key_pressed(b'\n');
+        assert_eq!(vm.step(100), Ok(false));
+        assert!(vm.take_output().starts_with('\n'));
+        assert_eq!(vm.take_output(), "");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = WasmVm::new();
+        assert!(vm.load_program(&[0x30]).is_err());
+
+        // .ORIG x3000
+        // .FILL xD000 ; RESERVED
+        vm.load_program(&[0x30, 0x00, 0xd0, 0x00]).unwrap();
+        assert_eq!(
+            vm.step(1),
+            Err("Illegal opcode 0xd000 at PC 0x3000".to_string())
+        );
+    }
+}
@@ -0,0 +1,136 @@
+#![cfg(feature = "native-term")]
+
+use lc3::SymbolTable;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+const SOURCE: &str = "; Prints a greeting.
+        .ORIG x3000
+        LEA R0, x3003
+        PUTS
+        HALT
+        .STRINGZ \"Hi\\n\"
+        .END
+";
+
+fn fixture(name: &str, contents: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("lc3-asm-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("program.asm");
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn lc3(args: &[&std::ffi::OsStr]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lc3"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn assembles_an_object_that_runs() {
+    let source = fixture("object", SOURCE);
+    let output = lc3(&["asm".as_ref(), source.as_ref()]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let object = source.with_extension("obj");
+    assert_eq!(
+        fs::read(&object).unwrap(),
+        [
+            0x30, 0x00, 0xe0, 0x02, 0xf0, 0x22, 0xf0, 0x25, 0x00, 0x48, 0x00, 0x69, 0x00, 0x0a,
+            0x00, 0x00
+        ]
+    );
+    assert!(fs::read_to_string(source.with_extension("sym"))
+        .unwrap()
+        .starts_with("// Symbol table\n"));
+
+    let output = lc3(&[object.as_ref()]);
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Hi\n");
+}
+
+#[test]
+fn writes_the_labels_to_the_sym_file() {
+    let source = fixture(
+        "sym",
+        "START   .ORIG x3000
+        LEA R0, MESSAGE
+        PUTS
+DONE    HALT
+MESSAGE .STRINGZ \"Hi\"
+        .END
+",
+    );
+    let output = lc3(&["asm".as_ref(), source.as_ref()]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let symbols = fs::read_to_string(source.with_extension("sym")).unwrap();
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+
+    let symbols = SymbolTable::parse(&symbols);
+    assert_eq!(symbols.address("START"), Some(0x3000));
+    assert_eq!(symbols.address("DONE"), Some(0x3002));
+    assert_eq!(symbols.address("MESSAGE"), Some(0x3003));
+}
+
+#[test]
+fn output_and_no_sym() {
+    let source = fixture("output", SOURCE);
+    let object = source.with_file_name("out.obj");
+    let output = lc3(&[
+        "asm".as_ref(),
+        source.as_ref(),
+        "-o".as_ref(),
+        object.as_ref(),
+        "--no-sym".as_ref(),
+    ]);
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(object.exists());
+    assert!(!source.with_extension("obj").exists());
+    assert!(!object.with_extension("sym").exists());
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn run_assembles_in_memory() {
+    let source = fixture("run", SOURCE);
+    let output = lc3(&["asm".as_ref(), source.as_ref(), "--run".as_ref()]);
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(output.stdout, b"Hi\n");
+    assert!(!source.with_extension("obj").exists());
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn errors_name_the_position() {
+    let source = fixture("errors", ".ORIG x3000\nFOO R1\n  ADD R1, R1, #99\n");
+    let output = lc3(&["asm".as_ref(), source.as_ref()]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "{path}:2:1: Unknown mnemonic \"FOO\"\n\
+             {path}:3:3: Immediate #99 out of range for imm5 (-16 to 15)\n\
+             Error: Unable to assemble {path}: 2 errors\n",
+            path = source.display()
+        )
+    );
+    assert!(!source.with_extension("obj").exists());
+
+    // A file that can't be read is an I/O error.
+    let missing = source.with_file_name("missing.asm");
+    let output = lc3(&["asm".as_ref(), missing.as_ref()]);
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,96 @@
+//! Runs every program in `tests/conformance/` against the library and checks what it prints.
+//!
+//! A case is three files sharing a name: the program, either assembly (`NAME.asm`, assembled here)
+//! or an object file (`NAME.obj`), the keys typed at it (`NAME.in`) and everything it should
+//! print (`NAME.out`), compared byte for byte. Adding one is a matter of dropping those in the
+//! directory. `hello.obj` is hand-assembled:
+//!
+//! ```text
+//! .ORIG x3000
+//! LEA R0, MESSAGE   ; xE002
+//! PUTS              ; xF022
+//! HALT              ; xF025
+//! MESSAGE .STRINGZ "Hello, World!\n"
+//! ```
+#![cfg(feature = "std")]
+
+use lc3::{BufferConsole, Vm};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Enough for any case here many times over, so a program that no longer halts fails rather than
+// hanging the run.
+const MAX_STEPS: u64 = 100_000;
+
+fn cases() -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let mut programs: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| matches!(extension(path), "asm" | "obj"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+// What the program at `path` prints given its input, or why it couldn't be run.
+fn run(path: &Path) -> Result<Vec<u8>, String> {
+    let read = |extension| {
+        let path = path.with_extension(extension);
+        fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+
+    let mut vm = Vm::new();
+    match extension(path) {
+        "asm" => {
+            let source = String::from_utf8(read("asm")?).map_err(|e| e.to_string())?;
+            vm.load_assembly(&source).map_err(|errors| {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                errors.join(", ")
+            })?;
+        }
+        _ => vm.load_program(&read("obj")?).map_err(|e| e.to_string())?,
+    }
+
+    let console = BufferConsole::new(&read("in")?);
+    vm.set_console(console.clone());
+    vm.run_with_limit(MAX_STEPS).map_err(|e| e.to_string())?;
+    if !vm.is_halted() {
+        return Err("stopped without halting".to_string());
+    }
+
+    Ok(console.output())
+}
+
+#[test]
+fn conformance() {
+    let cases = cases();
+    assert!(!cases.is_empty());
+
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy();
+            let expected = match fs::read(path.with_extension("out")) {
+                Ok(expected) => expected,
+                Err(e) => return Some(format!("{}: {}.out: {}", name, name, e)),
+            };
+            match run(path) {
+                Ok(output) if output == expected => None,
+                Ok(output) => Some(format!(
+                    "{}: printed \"{}\", expected \"{}\"",
+                    name,
+                    output.escape_ascii(),
+                    expected.escape_ascii()
+                )),
+                Err(e) => Some(format!("{}: {}", name, e)),
+            }
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,17 @@
+; Counts down from 9 to 0, one digit a line.
+        .ORIG x3000
+        LD R1, ZERO
+        ADD R2, R1, #9
+LOOP    ADD R0, R2, #0
+        OUT
+        LD R0, NEWLINE
+        OUT
+        ADD R2, R2, #-1
+        NOT R3, R1
+        ADD R3, R3, #1
+        ADD R3, R2, R3
+        BRzp LOOP
+        HALT
+ZERO    .FILL x0030
+NEWLINE .FILL x000A
+        .END
@@ -0,0 +1,8 @@
+; Echoes each key typed, up to and including a newline.
+        .ORIG x3000
+LOOP    GETC
+        OUT
+        ADD R1, R0, #-10
+        BRnp LOOP
+        HALT
+        .END
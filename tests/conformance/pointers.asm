@@ -0,0 +1,15 @@
+; Stores a key through one pointer, then prints it back through another to the same place.
+        .ORIG x3000
+        GETC
+        STI R0, STORE
+        AND R0, R0, #0
+        LDI R0, LOAD
+        OUT
+        LDI R0, LOAD
+        ADD R0, R0, #1
+        OUT
+        HALT
+STORE   .FILL CELL
+LOAD    .FILL CELL
+CELL    .BLKW #1
+        .END
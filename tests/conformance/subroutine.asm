@@ -0,0 +1,30 @@
+; Prints each line of a list through a subroutine, which saves R7 around its own calls.
+        .ORIG x3000
+        LEA R0, FIRST
+        JSR LINE
+        LEA R0, SECOND
+        JSR LINE
+        HALT
+
+; Prints the string at R0 and a newline.
+LINE    ST R7, SAVE
+        PUTS
+        JSR NEWLINE
+        LD R7, SAVE
+        RET
+
+NEWLINE ST R0, SAVE_R0
+        ST R7, SAVE_R7
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        OUT
+        LD R0, SAVE_R0
+        LD R7, SAVE_R7
+        RET
+
+SAVE    .BLKW #1
+SAVE_R0 .BLKW #1
+SAVE_R7 .BLKW #1
+FIRST   .STRINGZ "called"
+SECOND  .STRINGZ "returned"
+        .END
@@ -0,0 +1,45 @@
+#![cfg(feature = "native-term")]
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// .ORIG x3000
+// ADD R1, R1, #1
+// ADD R1, R1, #1
+// HALT
+const PROGRAM: [u16; 4] = [0x3000, 0x1261, 0x1261, 0xf025];
+
+#[test]
+fn scripted_local_session() {
+    let path = env::temp_dir().join(format!("lc3-debug-local-{}.obj", std::process::id()));
+    let bytes: Vec<u8> = PROGRAM.iter().flat_map(|word| word.to_be_bytes()).collect();
+    fs::write(&path, bytes).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lc3"))
+        .arg(&path)
+        .arg("--debug-local")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"break 0x3001\nc\nr\ns\nr\nexit\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("(lc3) "), "{}", stderr);
+    assert!(stderr.contains("R1: 0x0001"), "{}", stderr);
+    assert!(stderr.contains("R1: 0x0002"), "{}", stderr);
+    assert!(stderr.contains("Exiting..."), "{}", stderr);
+}
@@ -0,0 +1,105 @@
+#![cfg(feature = "native-term")]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+// .ORIG x3000
+// ADD R1, R1, #1
+// ADD R1, R1, #1
+// LEA R0, #2
+// PUTS
+// HALT
+// .STRINGZ "A"
+const PROGRAM: [u16; 8] = [
+    0x3000, 0x1261, 0x1261, 0xe002, 0xf022, 0xf025, 0x0041, 0x0000,
+];
+
+fn fixture(name: &str, contents: &[u8]) -> PathBuf {
+    let path = env::temp_dir().join(format!("lc3-debug-script-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn run_script(name: &str, script: &str) -> Output {
+    let bytes: Vec<u8> = PROGRAM.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let program = fixture(&format!("{}.obj", name), &bytes);
+    let script = fixture(&format!("{}.txt", name), script.as_bytes());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lc3"))
+        .arg(&program)
+        .arg("--debug-script")
+        .arg(&script)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    fs::remove_file(&program).unwrap();
+    fs::remove_file(&script).unwrap();
+    output
+}
+
+#[test]
+fn script_in_batch_mode() {
+    let output = run_script(
+        "batch",
+        "# Stop before the HALT and look around.\nbreak 0x3003\n\ncontinue\nr\ndump 0x3005 +1\nexit\n",
+    );
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        [
+            "Breakpoint set at 0x3003",
+            "PC 0x3000",
+            "R0: x0000 -> x3005, R1: x0000 -> x0002, PC: x3000 -> x3003",
+            "R0: 0x3005",
+            "R1: 0x0002",
+            "R2: 0x0000",
+            "R3: 0x0000",
+            "R4: 0x0000",
+            "R5: 0x0000",
+            "R6: 0x0000",
+            "R7: 0x0000",
+            "0x3005: 0x0041                                                  |A|",
+            "Exiting...",
+            "",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn script_errors_name_the_line() {
+    let output = run_script("error", "break 0x3001\n\nbreak NOPE\ncontinue\n");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("Breakpoint set at 0x3001\n"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("PC 0x3000"), "{}", stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("error.txt line 3: Unknown identifier \"NOPE\""),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn detach_lets_the_program_finish() {
+    let output = run_script("detach", "break 0x3002\ncontinue\ndetach\n");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.ends_with("Detaching, the program continues without the debugger\nA"),
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "native-term")]
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+
+// .ORIG x3000
+// ADD R1, R1, #1
+// HALT
+const PROGRAM: [u16; 3] = [0x3000, 0x1261, 0xf025];
+
+#[test]
+fn port_zero_announces_the_chosen_port() {
+    let path = env::temp_dir().join(format!("lc3-debug-tcp-{}.obj", std::process::id()));
+    let bytes: Vec<u8> = PROGRAM.iter().flat_map(|word| word.to_be_bytes()).collect();
+    fs::write(&path, bytes).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lc3"))
+        .arg(&path)
+        .args(["--debug-port", "0", "--no-raw-mode"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    let address = line
+        .strip_prefix("Waiting for connection on ")
+        .and_then(|rest| rest.strip_suffix("...\n"))
+        .unwrap_or_else(|| panic!("{:?}", line));
+    assert!(
+        address.starts_with("127.0.0.1:") && !address.ends_with(":0"),
+        "{}",
+        address
+    );
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(b"s\nr\nexit\n").unwrap();
+    let mut responses = String::new();
+    stream.read_to_string(&mut responses).unwrap();
+
+    assert!(child.wait().unwrap().success());
+    fs::remove_file(&path).unwrap();
+    assert!(responses.contains("R1: 0x0001"), "{}", responses);
+    assert!(responses.ends_with("Exiting...\n"), "{}", responses);
+}
+
+#[test]
+fn debug_addr_and_port_conflict() {
+    let output = Command::new(env!("CARGO_BIN_EXE_lc3"))
+        .args([
+            "program.obj",
+            "--debug-addr",
+            "127.0.0.1:0",
+            "--debug-port",
+            "0",
+        ])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("cannot be used with"), "{}", stderr);
+}
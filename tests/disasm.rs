@@ -0,0 +1,142 @@
+#![cfg(feature = "native-term")]
+
+use lc3::asm::assemble;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+// .ORIG x3000
+// START AND R1, R1, #0
+//       LD R0, COUNT
+// LOOP  ADD R1, R1, #1
+//       ADD R0, R0, #-1
+//       BRp LOOP
+//       HALT
+// COUNT .FILL 5
+//       .FILL xD000
+const PROGRAM: [u16; 9] = [
+    0x3000, 0x5260, 0x2004, 0x1261, 0x103f, 0x03fd, 0xf025, 0x0005, 0xd000,
+];
+
+const SYMBOLS: &str = "//\tSTART 3000\n//\tLOOP 3002\n//\tCOUNT 3006\n";
+
+fn fixture(name: &str, contents: &[u8]) -> PathBuf {
+    let path = env::temp_dir().join(format!("lc3-disasm-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn disasm(name: &str, args: &[&str]) -> String {
+    disasm_image(name, &PROGRAM, SYMBOLS, args)
+}
+
+fn disasm_image(name: &str, image: &[u16], symbols: &str, args: &[&str]) -> String {
+    let bytes: Vec<u8> = image.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let program = fixture(&format!("{}.obj", name), &bytes);
+    let symbols = fixture(&format!("{}.sym", name), symbols.as_bytes());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lc3"))
+        .arg("disasm")
+        .arg(&program)
+        .arg("--sym")
+        .arg(&symbols)
+        .args(args)
+        .output()
+        .unwrap();
+    fs::remove_file(&program).unwrap();
+    fs::remove_file(&symbols).unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn lists_every_word() {
+    assert_eq!(
+        disasm("all", &[]),
+        [
+            "x3000  x5260  START  AND R1, R1, #0",
+            "x3001  x2004         LD R0, COUNT",
+            "x3002  x1261  LOOP   ADD R1, R1, #1",
+            "x3003  x103F         ADD R0, R0, #-1",
+            "x3004  x03FD         BRp LOOP",
+            "x3005  xF025         HALT",
+            "x3006  x0005  COUNT  NOP",
+            "x3007  xD000         .FILL xD000",
+            "",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn verbose_shows_raw_offsets() {
+    assert_eq!(
+        disasm("verbose", &["--range", "x3001-x3004", "--verbose"]),
+        [
+            "x3001  x2004        LD R0, COUNT (#4)",
+            "x3002  x1261  LOOP  ADD R1, R1, #1",
+            "x3003  x103F        ADD R0, R0, #-1",
+            "x3004  x03FD        BRp LOOP (#-3)",
+            "",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn range_without_addresses() {
+    assert_eq!(
+        disasm("range", &["--range", "x3002-x3004", "--no-addresses"]),
+        [
+            "x1261  LOOP  ADD R1, R1, #1",
+            "x103F        ADD R0, R0, #-1",
+            "x03FD        BRp LOOP",
+            "",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn analyze_separates_code_from_strings() {
+    let program = assemble(include_str!("fixtures/greetings.asm")).unwrap();
+    let mut image = vec![program.origin];
+    image.extend(&program.words);
+
+    assert_eq!(
+        disasm_image(
+            "analyze",
+            &image,
+            &program.symbols.to_string(),
+            &["--analyze", "--no-addresses"]
+        ),
+        [
+            "xE009           LEA R0, HELLO",
+            "xF022           PUTS",
+            "x2206           LD R1, COUNT",
+            "x0401           BRz DONE",
+            "x4801           JSR BYE",
+            "xF025  DONE     HALT",
+            "xE00B  BYE      LEA R0, GOODBYE",
+            "xF022           PUTS",
+            "xC1C0           RET",
+            "x0001  COUNT    .FILL x0001",
+            "x0048  HELLO    .STRINGZ \"Hello!\\n\"",
+            "x0065",
+            "x006C",
+            "x006C",
+            "x006F",
+            "x0021",
+            "x000A",
+            "x0000",
+            "x0042  GOODBYE  .STRINGZ \"Bye\"",
+            "x0079",
+            "x0065",
+            "x0000",
+            "",
+        ]
+        .join("\n")
+    );
+}
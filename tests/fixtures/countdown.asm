@@ -0,0 +1,16 @@
+; A loop calling a subroutine, with a counter kept in memory.
+        .ORIG x3000
+        LD R1, COUNT
+LOOP    JSR PRINT
+        ADD R1, R1, #-1
+        BRp LOOP
+        ST R1, COUNT
+        HALT
+PRINT   LEA R0, DIGIT
+        LDR R0, R0, #0
+        ADD R0, R0, R1
+        OUT
+        RET
+COUNT   .FILL #3
+DIGIT   .FILL x0030
+        .END
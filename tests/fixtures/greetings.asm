@@ -0,0 +1,15 @@
+; Code followed by a table of strings, which a plain listing shows as NOPs.
+        .ORIG x3000
+        LEA R0, HELLO
+        PUTS
+        LD R1, COUNT
+        BRz DONE
+        JSR BYE
+DONE    HALT
+BYE     LEA R0, GOODBYE
+        PUTS
+        RET
+COUNT   .FILL #1
+HELLO   .STRINGZ "Hello!\n"
+GOODBYE .STRINGZ "Bye"
+        .END
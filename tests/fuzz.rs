@@ -0,0 +1,41 @@
+//! Runs the inputs saved in `tests/fuzz/TARGET/` through the fuzz target of that name, see
+//! `fuzz/src/lib.rs`. Copying a crash from `fuzz/artifacts/TARGET/` there keeps it fixed.
+#![cfg(feature = "std")]
+
+#[path = "../fuzz/src/lib.rs"]
+mod targets;
+
+use std::fs;
+use std::path::Path;
+
+fn replay(target: &str, check: fn(&[u8])) {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fuzz")
+        .join(target);
+    let mut inputs: Vec<_> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty());
+
+    for input in inputs {
+        eprintln!("{}", input.display());
+        check(&fs::read(&input).unwrap());
+    }
+}
+
+#[test]
+fn decode() {
+    replay("decode", targets::decode);
+}
+
+#[test]
+fn load() {
+    replay("load", targets::load);
+}
+
+#[test]
+fn execute() {
+    replay("execute", targets::execute);
+}
@@ -0,0 +1,57 @@
+# Run against the built module, e.g. `maturin develop && pytest tests/python`.
+
+import lc3
+import pytest
+
+# .ORIG x3000
+# LOOP  GETC
+#       OUT
+#       ADD R1, R0, #-10
+#       BRnp LOOP
+#       HALT
+ECHO = bytes([0x30, 0x00, 0xF0, 0x20, 0xF0, 0x21, 0x12, 0x36, 0x0B, 0xFC, 0xF0, 0x25])
+
+
+def test_echo():
+    vm = lc3.Lc3Vm()
+    vm.load(ECHO)
+
+    vm.send_input("hi")
+    assert vm.run() is False
+    assert vm.output == "hi"
+
+    vm.send_input("\n")
+    assert vm.run(max_steps=1000) is True
+    assert vm.halted
+    assert vm.output.startswith("hi\n")
+    assert vm.registers[0] == ord("\n")
+    assert len(vm.registers) == 8
+
+
+def test_memory():
+    vm = lc3.Lc3Vm()
+    vm.load(ECHO)
+    assert vm.read_mem(0x3000, 2) == [0xF020, 0xF021]
+
+    # ADD R2, R2, #5
+    vm.write_mem(0x3000, [0x14A5, 0x14A5])
+    assert vm.step(2) is True
+    assert vm.registers[2] == 10
+    assert vm.pc == 0x3002
+
+
+def test_errors():
+    vm = lc3.Lc3Vm()
+    with pytest.raises(ValueError):
+        vm.load(b"\x30")
+
+    # .ORIG x3000
+    # LOOP  BRnzp LOOP
+    vm.load(bytes([0x30, 0x00, 0x0F, 0xFF]))
+    with pytest.raises(lc3.StepLimitExceeded, match="Step limit of 100"):
+        vm.run(max_steps=100)
+
+    vm.write_mem(0x3000, [0xD000])
+    with pytest.raises(lc3.IllegalInstruction):
+        vm.step()
+    assert issubclass(lc3.IllegalInstruction, lc3.Lc3Error)
@@ -0,0 +1,854 @@
+#![cfg(feature = "native-term")]
+
+use lc3::{Condition, OsImage, RunOptions, RunSummary, TrapMode, VmError};
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Multiplies 6 by 7 into R0.
+//
+// .ORIG x3000
+//       AND R0, R0, #0
+//       AND R1, R1, #0
+//       ADD R1, R1, #7
+// LOOP  ADD R0, R0, #6
+//       ADD R1, R1, #-1
+//       BRp LOOP
+//       HALT
+const PROGRAM: [u16; 8] = [
+    0x3000, 0x5020, 0x5260, 0x1267, 0x1026, 0x127f, 0x03fd, 0xf025,
+];
+
+fn fixture(name: &str, program: &[u16]) -> PathBuf {
+    let path = env::temp_dir().join(format!("lc3-run-{}-{}.obj", std::process::id(), name));
+    let bytes: Vec<u8> = program.iter().flat_map(|word| word.to_be_bytes()).collect();
+    fs::write(&path, bytes).unwrap();
+    path
+}
+
+// The binary. STDIN is never a terminal here, so it's left alone without a note about it.
+fn lc3() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_lc3"));
+    command.arg("--no-raw-mode");
+    command
+}
+
+fn run(name: &str, program: &[u16], max_steps: Option<u64>) -> Result<RunSummary, String> {
+    let path = fixture(name, program);
+    let options = RunOptions {
+        program: path.clone(),
+        max_steps,
+        ..RunOptions::default()
+    };
+    let result = lc3::run(options).map_err(|e| e.to_string());
+    fs::remove_file(&path).unwrap();
+
+    result
+}
+
+#[test]
+fn run_returns_the_final_state() {
+    let summary = run("multiply", &PROGRAM, Some(25)).unwrap();
+    assert!(summary.duration > Duration::ZERO);
+    assert!(summary.mips() > 0.0);
+    assert_eq!(
+        summary,
+        RunSummary {
+            registers: [42, 0, 0, 0, 0, 0, 0, 0],
+            pc: 0x3007,
+            condition: Condition::Z,
+            instructions: 25,
+            halted: true,
+            trap_mode: TrapMode::Builtin,
+            duration: summary.duration,
+        }
+    );
+}
+
+#[test]
+fn time_reports_the_run() {
+    let path = fixture("time", &PROGRAM);
+    let output = lc3().arg(&path).arg("--time").output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Ran 25 instructions in "), "{}", stderr);
+    assert!(stderr.ends_with(" MIPS\n"), "{}", stderr);
+}
+
+#[test]
+fn run_options_can_be_built_in_code() {
+    let path = fixture("options", &PROGRAM);
+    let options = RunOptions {
+        program: path.clone(),
+        timeout: Some(Duration::from_secs(10)),
+        ..RunOptions::default()
+    };
+    assert_eq!(options.debug, None);
+    assert!(!options.decode_cache && !options.predecode);
+    let summary = lc3::run(options);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(summary.unwrap().registers[0], 42);
+
+    let missing = RunOptions {
+        program: env::temp_dir().join("lc3-run-missing.obj"),
+        ..RunOptions::default()
+    };
+    assert!(matches!(lc3::run(missing), Err(VmError::Build(_))));
+}
+
+// Stores `ADD R0, R0, #10` over the `ADD R0, R0, #1` it has just run, then runs it again, so a
+// stale decode leaves R0 at 2 rather than 11.
+//
+//       AND R0, R0, #0
+//       AND R2, R2, #0
+//       ADD R2, R2, #2
+//       LD R1, NEW
+// LOOP  ADD R0, R0, #1
+//       ST R1, LOOP
+//       ADD R2, R2, #-1
+//       BRp LOOP
+//       HALT
+// NEW   ADD R0, R0, #10
+const SELF_MODIFYING: [u16; 11] = [
+    0x3000, 0x5020, 0x54a0, 0x14a2, 0x2205, 0x1021, 0x33fe, 0x14bf, 0x03fc, 0xf025, 0x102a,
+];
+
+#[test]
+fn decode_cache_options_pick_up_stores_over_code() {
+    let path = fixture("decode-cache", &SELF_MODIFYING);
+    for (decode_cache, predecode) in [(false, false), (true, false), (false, true)] {
+        let options = RunOptions {
+            program: path.clone(),
+            decode_cache,
+            predecode,
+            ..RunOptions::default()
+        };
+        let summary = lc3::run(options).unwrap();
+        assert_eq!(summary.registers[0], 11, "{} {}", decode_cache, predecode);
+    }
+
+    let output = lc3()
+        .args(["--decode-cache", "--predecode"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn a_missing_program_is_named() {
+    let output = lc3().arg("missing.obj").output().unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Error: missing.obj: No such file or directory (os error 2)\n"
+    );
+}
+
+#[test]
+#[allow(deprecated)]
+fn the_positional_run_still_works() {
+    let path = fixture("positional", &PROGRAM);
+    let filename = path.to_string_lossy().into_owned();
+    let result = lc3::run_file(filename, false).map_err(|e| e.to_string());
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn max_steps_stops_a_runaway_program() {
+    // .ORIG x3000
+    // BRnzp #-1
+    let program = [0x3000, 0x0fff];
+
+    assert_eq!(
+        run("loop", &program, Some(100)),
+        Err("Step limit of 100 reached at PC 0x3000: BRnzp x3000".to_string())
+    );
+
+    let path = fixture("loop-cli", &program);
+    let output = lc3()
+        .arg(&path)
+        .args(["--max-steps", "10"])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        [
+            "R0: x0000  R1: x0000  R2: x0000  R3: x0000",
+            "R4: x0000  R5: x0000  R6: x0000  R7: x0000",
+            "Error: Step limit of 10 reached at PC 0x3000: BRnzp x3000",
+            "",
+        ]
+        .join("\n")
+    );
+}
+
+#[test]
+fn verbosity_levels_change_only_the_vm_messages() {
+    // .ORIG x3000
+    // BRnzp #-1
+    let path = fixture("verbosity", &[0x3000, 0x0fff]);
+    let stderr = |flags: &[&str]| {
+        let output = lc3()
+            .arg(&path)
+            .args(["--max-steps", "10"])
+            .args(flags)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(4));
+        assert!(output.stdout.is_empty());
+        String::from_utf8(output.stderr).unwrap()
+    };
+    let registers = [
+        "R0: x0000  R1: x0000  R2: x0000  R3: x0000",
+        "R4: x0000  R5: x0000  R6: x0000  R7: x0000",
+    ];
+    let error = "Error: Step limit of 10 reached at PC 0x3000: BRnzp x3000";
+
+    // Hard errors are always printed.
+    assert_eq!(stderr(&["--quiet"]), format!("{}\n", error));
+    assert_eq!(
+        stderr(&[]),
+        format!("{}\n{}\n{}\n", registers[0], registers[1], error)
+    );
+
+    let verbose = stderr(&["--verbose"]);
+    let lines: Vec<&str> = verbose.lines().collect();
+    assert_eq!(lines.len(), 5, "{}", verbose);
+    assert_eq!(
+        lines[0],
+        format!("Loaded {}: 1 words at 0x3000-0x3000", path.display())
+    );
+    assert!(
+        lines[1].starts_with("Ran 10 instructions in "),
+        "{}",
+        verbose
+    );
+    assert_eq!(lines[2..], [registers[0], registers[1], error]);
+
+    let output = lc3().arg(&path).args(["-q", "-v"]).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn max_steps_of_zero_is_unlimited() {
+    let path = fixture("unlimited", &PROGRAM);
+    let output = lc3()
+        .arg(&path)
+        .args(["--max-steps", "0"])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        run("unlimited-options", &PROGRAM, Some(0))
+            .unwrap()
+            .instructions,
+        25
+    );
+}
+
+#[test]
+fn exit_codes_tell_failures_apart() {
+    let exit_code = |path: &PathBuf, args: &[&str]| {
+        let output = lc3().arg(path).args(args).output().unwrap();
+        (
+            output.status.code(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    // .ORIG x3000
+    // .FILL xD000 ; RESERVED
+    let illegal = fixture("illegal", &[0x3000, 0xd000]);
+    // .ORIG x3000
+    // TRAP x30
+    let bad_trap = fixture("bad-trap", &[0x3000, 0xf030]);
+    let halts = fixture("halts", &PROGRAM);
+
+    assert_eq!(exit_code(&halts, &[]), (Some(0), String::new()));
+    assert_eq!(
+        exit_code(&PathBuf::from("missing.obj"), &[]),
+        (
+            Some(2),
+            "Error: missing.obj: No such file or directory (os error 2)\n".to_string()
+        )
+    );
+    assert_eq!(
+        exit_code(&halts, &["--stdin-file", "missing.txt"]),
+        (
+            Some(2),
+            "Error: missing.txt: No such file or directory (os error 2)\n".to_string()
+        )
+    );
+    assert_eq!(
+        exit_code(&halts, &["--pc", "x1FFFF"]),
+        (Some(2), "Error: Invalid --pc \"x1FFFF\"\n".to_string())
+    );
+    assert_eq!(exit_code(&halts, &["--no-such-flag"]).0, Some(2));
+    assert_eq!(
+        exit_code(&illegal, &[]),
+        (
+            Some(3),
+            "Error: Illegal opcode 0xd000 at PC 0x3000\n".to_string()
+        )
+    );
+    assert_eq!(
+        exit_code(&bad_trap, &[]),
+        (
+            Some(3),
+            "Error: Unknown TRAP vector 0x30 at PC 0x3000\n".to_string()
+        )
+    );
+
+    for path in [illegal, bad_trap, halts] {
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+// Runs the binary with a STDIN pipe that nothing is ever written to, so no key arrives but reads
+// don't hit EOF either. Returns what it wrote to STDERR.
+fn run_without_input(path: &PathBuf, args: &[&str]) -> (ExitStatus, String) {
+    let mut child = lc3()
+        .arg(path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stderr = String::new();
+    let mut pipe = child.stderr.take().unwrap();
+    pipe.read_to_string(&mut stderr).unwrap();
+
+    (child.wait().unwrap(), stderr)
+}
+
+#[test]
+fn closed_stdin_is_an_error() {
+    // .ORIG x3000
+    // GETC
+    // HALT
+    let path = fixture("eof", &[0x3000, 0xf020, 0xf025]);
+    let output = lc3().arg(&path).stdin(Stdio::null()).output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Error: Unable to read from STDIN: failed to fill whole buffer\n"
+    );
+}
+
+#[test]
+fn output_is_flushed_before_waiting_for_a_key() {
+    // Written straight to the display, so it's only flushed because GETC waits.
+    //
+    // .ORIG x3000
+    //        LD R0, PROMPT
+    //        STI R0, DDR
+    //        GETC
+    //        HALT
+    // PROMPT .FILL x3F
+    // DDR    .FILL xFE06
+    let path = fixture(
+        "prompt",
+        &[0x3000, 0x2003, 0xb003, 0xf020, 0xf025, 0x003f, 0xfe06],
+    );
+    let mut child = lc3()
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut byte = [0; 1];
+        while stdout.read_exact(&mut byte).is_ok() {
+            let _ = sender.send(byte[0]);
+        }
+    });
+
+    // The prompt shows while GETC is still waiting.
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(b'?'));
+    child.stdin.take().unwrap().write_all(b"k").unwrap();
+    assert!(child.wait().unwrap().success());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn idle_polling_loop_still_gets_keys() {
+    // .ORIG x3000
+    // POLL  LDI R0, KBSR
+    //       BRzp POLL
+    //       LDI R0, KBDR
+    //       OUT
+    //       HALT
+    // KBSR  .FILL xFE00
+    // KBDR  .FILL xFE02
+    let path = fixture(
+        "idle-poll",
+        &[
+            0x3000, 0xa004, 0x07fe, 0xa003, 0xf021, 0xf025, 0xfe00, 0xfe02,
+        ],
+    );
+    let mut child = lc3()
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Long enough without a key for the polling to back off.
+    thread::sleep(Duration::from_millis(300));
+    child.stdin.take().unwrap().write_all(b"k").unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "k");
+}
+
+#[test]
+fn timeout_stops_a_program_waiting_for_input() {
+    // .ORIG x3000
+    // POLL  LDI R0, KBSR
+    //       BRzp POLL
+    //       HALT
+    // KBSR  .FILL xFE00
+    let path = fixture("poll", &[0x3000, 0xa002, 0x07fe, 0xf025, 0xfe00]);
+    let started = Instant::now();
+    let (status, stderr) = run_without_input(&path, &["--timeout", "0.2"]);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(status.code(), Some(5));
+    assert!(started.elapsed() < Duration::from_secs(10));
+    // Stopped somewhere in the loop.
+    assert!(
+        stderr == "Error: Timed out at PC 0x3000\n" || stderr == "Error: Timed out at PC 0x3001\n",
+        "{}",
+        stderr
+    );
+
+    // GETC blocks in the read itself.
+    //
+    // .ORIG x3000
+    // GETC
+    // HALT
+    let path = fixture("getc", &[0x3000, 0xf020, 0xf025]);
+    let (status, stderr) = run_without_input(&path, &["--timeout", "0.2"]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(!status.success());
+    assert_eq!(stderr, "Error: Timed out at PC 0x3000\n");
+}
+
+#[test]
+fn entry_overrides_the_origin() {
+    // .ORIG x3000
+    // BRnzp #-1
+    // .BLKW 3
+    // HALT
+    let program = [0x3000, 0x0fff, 0x0000, 0x0000, 0x0000, 0xf025];
+    let path = fixture("entry", &program);
+    let options = RunOptions {
+        program: path.clone(),
+        entry: Some(0x3004),
+        max_steps: Some(10),
+        ..RunOptions::default()
+    };
+    let summary = lc3::run(options);
+    assert_eq!(summary.unwrap().instructions, 1);
+
+    for pc in ["0x3004", "x3004", "12292"] {
+        let output = lc3()
+            .arg(&path)
+            .args(["--pc", pc, "--max-steps", "10"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+    }
+
+    // Outside the program is allowed, with a warning.
+    let output = lc3()
+        .arg(&path)
+        .args(["--pc", "0x4000", "--max-steps", "10"])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Warning: Starting at 0x4000, outside the loaded program\n"));
+    assert!(stderr.ends_with("Error: Step limit of 10 reached at PC 0x400a: NOP\n"));
+}
+
+// Counts down from 2, adding 5 each time.
+//
+// .ORIG x3000
+//        AND R0, R0, #0
+//        ADD R1, R0, #2
+// LOOP   ADD R0, R0, #5
+//        ADD R1, R1, #-1
+//        BRp LOOP
+//        ST R0, RESULT
+//        HALT
+// RESULT .BLKW 1
+const TEN_STEPS: [u16; 9] = [
+    0x3000, 0x5020, 0x1222, 0x1025, 0x127f, 0x03fd, 0x3001, 0xf025, 0x0000,
+];
+
+#[test]
+fn trace_prints_each_instruction() {
+    let path = fixture("trace", &TEN_STEPS);
+    let output = lc3().arg(&path).arg("--trace").output().unwrap();
+
+    assert!(output.status.success());
+    let trace = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        trace,
+        [
+            "1  x3000  x5020  AND R0, R0, #0   ; R0=x0000  Z",
+            "2  x3001  x1222  ADD R1, R0, #2   ; R1=x0002  P",
+            "3  x3002  x1025  ADD R0, R0, #5   ; R0=x0005  P",
+            "4  x3003  x127F  ADD R1, R1, #-1  ; R1=x0001  P",
+            "5  x3004  x03FD  BRp #-3          ; P",
+            "6  x3002  x1025  ADD R0, R0, #5   ; R0=x000A  P",
+            "7  x3003  x127F  ADD R1, R1, #-1  ; R1=x0000  Z",
+            "8  x3004  x03FD  BRp #-3          ; Z",
+            "9  x3005  x3001  ST R0, #1        ; Z",
+            "10  x3006  xF025  HALT             ; Z",
+            "",
+        ]
+        .join("\n")
+    );
+
+    let trace_path = env::temp_dir().join(format!("lc3-run-{}-trace.txt", std::process::id()));
+    let output = lc3()
+        .arg(&path)
+        .arg("--trace-file")
+        .arg(&trace_path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    assert_eq!(fs::read_to_string(&trace_path).unwrap(), trace);
+    fs::remove_file(&trace_path).unwrap();
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn stats_summarise_the_run() {
+    let path = fixture("stats", &PROGRAM);
+    let output = lc3().arg(&path).arg("--stats").output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("Instructions: 25\nWall time: "),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains(
+            "Opcodes:
+  ADD           15
+  BR             7
+  AND            2
+  TRAP           1
+Traps:
+  x25 HALT        1
+Branches: 6 taken, 1 not taken
+"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn stdin_file_feeds_the_program() {
+    // .ORIG x3000
+    // LOOP  GETC
+    //       OUT
+    //       ADD R1, R0, #-10
+    //       BRnp LOOP
+    //       HALT
+    let path = fixture("echo", &[0x3000, 0xf020, 0xf021, 0x1236, 0x0bfc, 0xf025]);
+    let input = env::temp_dir().join(format!("lc3-run-{}-input.txt", std::process::id()));
+    fs::write(&input, "hello\nunread").unwrap();
+
+    let output = lc3()
+        .arg(&path)
+        .arg("--stdin-file")
+        .arg(&input)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "hello\n");
+
+    // Running out of input is an error, as with STDIN.
+    fs::write(&input, "hi").unwrap();
+    let output = lc3()
+        .arg(&path)
+        .arg("--stdin-file")
+        .arg(&input)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&input).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "hi");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Error: Unable to read input: failed to fill whole buffer\n"
+    );
+}
+
+#[test]
+fn echo_flags_control_which_keys_are_shown() {
+    // .ORIG x3000
+    // GETC
+    // IN
+    // HALT
+    let path = fixture("echo-flags", &[0x3000, 0xf020, 0xf023, 0xf025]);
+    let input = env::temp_dir().join(format!("lc3-run-{}-echo.txt", std::process::id()));
+    fs::write(&input, "ab").unwrap();
+
+    let run = |flags: &[&str]| {
+        lc3()
+            .arg(&path)
+            .arg("--stdin-file")
+            .arg(&input)
+            .args(flags)
+            .output()
+            .unwrap()
+    };
+    let stdout = |flags: &[&str]| String::from_utf8(run(flags).stdout).unwrap();
+
+    assert_eq!(stdout(&[]), "Enter a character: b");
+    assert_eq!(stdout(&["--echo"]), "aEnter a character: b");
+    assert_eq!(stdout(&["--no-echo"]), "Enter a character: ");
+    assert_eq!(run(&["--echo", "--no-echo"]).status.code(), Some(2));
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&input).unwrap();
+}
+
+#[test]
+fn stdout_file_captures_only_the_program_output() {
+    // .ORIG x3000
+    //     LEA R0, MSG
+    //     PUTS
+    //     HALT
+    // MSG .STRINGZ "Hello, World!\n"
+    let mut program = vec![0x3000, 0xe002, 0xf022, 0xf025];
+    program.extend("Hello, World!\n".bytes().map(u16::from));
+    program.push(0);
+    let path = fixture("hello", &program);
+    let output_path = env::temp_dir().join(format!("lc3-run-{}-output.txt", std::process::id()));
+
+    let output = lc3()
+        .arg(&path)
+        .arg("--stdout-file")
+        .arg(&output_path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(fs::read(&output_path).unwrap(), b"Hello, World!\n");
+    fs::remove_file(&output_path).unwrap();
+
+    let output = lc3()
+        .arg(&path)
+        .args(["--stdout-file", "-"])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Hello, World!\n");
+}
+
+#[test]
+fn hz_paces_the_run() {
+    let path = fixture("hz", &PROGRAM);
+    let options = RunOptions {
+        program: path.clone(),
+        hz: Some(100),
+        ..RunOptions::default()
+    };
+    let started = Instant::now();
+    let summary = lc3::run(options).unwrap();
+
+    // 25 instructions at 100Hz, less the first that goes straight away, with some slack.
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert!(summary.duration >= Duration::from_millis(200));
+    // The same run otherwise.
+    let unthrottled = run("hz-unthrottled", &PROGRAM, None).unwrap();
+    assert_eq!(
+        RunSummary {
+            duration: unthrottled.duration,
+            ..summary
+        },
+        unthrottled
+    );
+
+    // Zero is full speed, the same as without the flag.
+    let output = lc3().arg(&path).args(["--hz", "0"]).output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stderr, b"");
+}
+
+#[test]
+fn hz_keeps_polling_the_keyboard() {
+    // .ORIG x3000
+    // POLL  LDI R0, KBSR
+    //       BRzp POLL
+    //       LDI R0, KBDR
+    //       OUT
+    //       HALT
+    // KBSR  .FILL xFE00
+    // KBDR  .FILL xFE02
+    let path = fixture(
+        "hz-poll",
+        &[
+            0x3000, 0xa004, 0x07fe, 0xa003, 0xf021, 0xf025, 0xfe00, 0xfe02,
+        ],
+    );
+    let input = env::temp_dir().join(format!("lc3-run-{}-hz-input.txt", std::process::id()));
+    fs::write(&input, "k").unwrap();
+
+    let output = lc3()
+        .arg(&path)
+        .args(["--hz", "1000", "--stdin-file"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&input).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "k");
+}
+
+#[test]
+fn stdin_can_be_redirected_from_a_file() {
+    // .ORIG x3000
+    // GETC
+    // OUT
+    // HALT
+    let path = fixture("redirected", &[0x3000, 0xf020, 0xf021, 0xf025]);
+    let input = env::temp_dir().join(format!("lc3-run-{}-redirected.txt", std::process::id()));
+    fs::write(&input, "k").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lc3"))
+        .arg(&path)
+        .stdin(fs::File::open(&input).unwrap())
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&input).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"k");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Note: STDIN isn't a terminal, leaving it in its normal mode (--no-raw-mode skips this check)\n"
+    );
+}
+
+// Says hello, reads a key with GETC and one with IN, then prints a packed string.
+//
+// .ORIG x3000
+//         LEA R0, HELLO
+//         PUTS
+//         GETC
+//         OUT
+//         IN
+//         ADD R1, R0, #0
+//         LEA R0, PACKED
+//         PUTSP
+//         AND R0, R0, #0
+//         ADD R0, R0, #10
+//         OUT
+//         HALT
+// HELLO   .STRINGZ "Hello, World!\n"
+// PACKED  .FILL x6548
+//         .FILL x6C6C
+//         .FILL x006F
+//         .FILL x0000
+const HELLO: [u16; 32] = [
+    0x3000, 0xe00b, 0xf022, 0xf020, 0xf021, 0xf023, 0x1220, 0xe014, 0xf024, 0x5020, 0x102a, 0xf021,
+    0xf025, 0x0048, 0x0065, 0x006c, 0x006c, 0x006f, 0x002c, 0x0020, 0x0057, 0x006f, 0x0072, 0x006c,
+    0x0064, 0x0021, 0x000a, 0x0000, 0x6548, 0x6c6c, 0x006f, 0x0000,
+];
+
+#[test]
+fn an_os_prints_the_same_as_the_built_in_traps() {
+    let path = fixture("hello", &HELLO);
+    let input = env::temp_dir().join(format!("lc3-run-{}-hello.txt", std::process::id()));
+    fs::write(&input, "ab").unwrap();
+    let os = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("os/lc3os.obj");
+
+    let stdout = |flags: &[&str]| {
+        let output = lc3()
+            .arg(&path)
+            .arg("--stdin-file")
+            .arg(&input)
+            .args(flags)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let expected = "Hello, World!\naEnter a character: bHello\n";
+    assert_eq!(stdout(&[]), expected);
+    assert_eq!(stdout(&["--no-os"]), expected);
+    assert_eq!(stdout(&["--os"]), expected);
+    assert_eq!(stdout(&[&format!("--os={}", os.display())]), expected);
+
+    let options = RunOptions {
+        program: path.clone(),
+        os: OsImage::Bundled,
+        stdin_file: Some(input.clone()),
+        stdout_file: Some(
+            env::temp_dir().join(format!("lc3-run-{}-hello.out", std::process::id())),
+        ),
+        ..RunOptions::default()
+    };
+    let out = options.stdout_file.clone().unwrap();
+    let summary = lc3::run(options).unwrap();
+    assert_eq!(summary.trap_mode, TrapMode::Os);
+    assert!(summary.halted);
+    assert_eq!(fs::read_to_string(&out).unwrap(), expected);
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&input).unwrap();
+    fs::remove_file(&out).unwrap();
+}
@@ -0,0 +1,92 @@
+//! Runs every program in `tests/snapshots/` and compares everything it did with its `.snap` file:
+//! what it printed, the registers, PC and condition it finished with, and how many instructions it
+//! took. Unlike `tests/conformance`, a change to any of that fails, so a change in behavior shows up
+//! in review as a diff of the snapshots.
+//!
+//! A case is `NAME.asm` and the keys typed at it, `NAME.in`. `UPDATE_SNAPSHOTS=1 cargo test --test
+//! snapshots` writes `NAME.snap` for each instead of comparing, e.g. for a new case or when the
+//! behavior was meant to change.
+#![cfg(feature = "std")]
+
+use lc3::{BufferConsole, Vm};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A program that stops halting fails rather than hanging the run.
+const MAX_STEPS: u64 = 100_000;
+
+fn cases() -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let mut programs: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "asm"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+// Runs the program at `path` with its input and describes what it did, one thing a line. The
+// output is quoted and escaped, a line of it per line printed.
+fn snapshot(path: &Path) -> String {
+    let source = fs::read_to_string(path).unwrap();
+    let input = fs::read(path.with_extension("in")).unwrap();
+
+    let mut vm = Vm::new();
+    vm.load_assembly(&source).unwrap();
+    let console = BufferConsole::new(&input);
+    vm.set_console(console.clone());
+    let result = vm.run_with_limit(MAX_STEPS);
+    let summary = vm.summary();
+
+    let mut snapshot = String::new();
+    if let Err(e) = result {
+        writeln!(snapshot, "error: {}", e).unwrap();
+    }
+    writeln!(snapshot, "output:").unwrap();
+    for line in console.output().split_inclusive(|&byte| byte == b'\n') {
+        writeln!(snapshot, "  \"{}\"", line.escape_ascii()).unwrap();
+    }
+    for (i, value) in summary.registers.iter().enumerate() {
+        writeln!(snapshot, "R{}: x{:04X}", i, value).unwrap();
+    }
+    writeln!(snapshot, "PC: x{:04X}", summary.pc).unwrap();
+    writeln!(snapshot, "condition: {:?}", summary.condition).unwrap();
+    writeln!(snapshot, "instructions: {}", summary.instructions).unwrap();
+    writeln!(snapshot, "halted: {}", summary.halted).unwrap();
+    snapshot
+}
+
+#[test]
+fn snapshots() {
+    let cases = cases();
+    assert!(!cases.is_empty());
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+
+    let mut failures = Vec::new();
+    for path in &cases {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let actual = snapshot(path);
+        let snap = path.with_extension("snap");
+        if update {
+            fs::write(&snap, &actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&snap) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => {
+                failures.push(format!("{}: expected\n{}\ngot\n{}", name, expected, actual))
+            }
+            Err(e) => failures.push(format!("{}: {}: {}", name, snap.display(), e)),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "\n{}\nRun with UPDATE_SNAPSHOTS=1 to accept the changes.",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,16 @@
+; Leaves the 10th Fibonacci number in R0 and prints nothing, ending on a negative comparison.
+        .ORIG x3000
+        AND R0, R0, #0
+        AND R1, R1, #0
+        ADD R1, R1, #1
+        AND R2, R2, #0
+        ADD R2, R2, #10
+LOOP    ADD R3, R0, R1
+        ADD R0, R1, #0
+        ADD R1, R3, #0
+        ADD R2, R2, #-1
+        BRp LOOP
+        NOT R4, R1
+        ADD R4, R4, R0
+        HALT
+        .END
@@ -0,0 +1,20 @@
+; Upper-cases keys read by polling KBSR/KBDR until a newline, writing each through DSR/DDR.
+        .ORIG x3000
+        LD R2, CASE
+POLL    LDI R0, KBSR
+        BRzp POLL
+        LDI R0, KBDR
+        ADD R1, R0, #-10
+        BRz DONE
+        ADD R0, R0, R2
+WAIT    LDI R3, DSR
+        BRzp WAIT
+        STI R0, DDR
+        BRnzp POLL
+DONE    HALT
+CASE    .FILL #-32
+KBSR    .FILL xFE00
+KBDR    .FILL xFE02
+DSR     .FILL xFE04
+DDR     .FILL xFE06
+        .END
@@ -0,0 +1,23 @@
+; Goes through the built-in TRAPs that print: PUTS, OUT, PUTSP and IN's prompt and echo.
+        .ORIG x3000
+        LEA R0, GREETING
+        PUTS
+        LD R0, BANG
+        OUT
+        LEA R0, PACKED
+        PUTSP
+        IN
+        ADD R1, R0, #0
+        LD R0, NEWLINE
+        OUT
+        ADD R0, R1, #1
+        OUT
+        HALT
+BANG    .FILL x0021
+NEWLINE .FILL x000A
+GREETING .STRINGZ "Traps"
+; "ok\n", two characters a word, low byte first.
+PACKED  .FILL x6B6F
+        .FILL x000A
+        .FILL x0000
+        .END
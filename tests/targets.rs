@@ -0,0 +1,36 @@
+//! Needs the targets installed, e.g. `rustup target add wasm32-unknown-unknown
+//! thumbv7m-none-eabi`, so these only run when asked for with `cargo test -- --ignored`.
+
+use std::env;
+use std::process::Command;
+
+fn check(target: &str, features: &str) {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["check", "--lib", "--no-default-features"])
+        .args(["--target", target])
+        .args(["--features", features])
+        .args(["--target-dir", env!("CARGO_TARGET_TMPDIR")])
+        .status()
+        .unwrap();
+    assert!(
+        status.success(),
+        "target: {}, features: {:?}",
+        target,
+        features
+    );
+}
+
+#[test]
+#[ignore]
+fn the_library_builds_for_wasm32() {
+    check("wasm32-unknown-unknown", "std");
+    check("wasm32-unknown-unknown", "wasm");
+}
+
+#[test]
+#[ignore]
+fn the_core_builds_without_std() {
+    check("thumbv7m-none-eabi", "");
+}